@@ -2,8 +2,14 @@
 //! This module implements final (i.e. parsed and validated) game configuration
 //! objects and their building from persistent configuration.
 
+use std::time::Duration;
+
 use anyhow::{ensure, Context, Error, Result};
 use async_std::path::Path;
+use bevy::{
+    prelude::KeyCode,
+    reflect::{DynamicEnum, DynamicVariant, FromReflect, TypeInfo, Typed},
+};
 use conf_macros::Config;
 use de_uom::{LogicalPixel, Metre};
 use serde::Deserialize;
@@ -19,6 +25,11 @@ use crate::bundle_config;
 pub struct MultiplayerConf {
     #[ensure(server.scheme() == "http", "Only `http` scheme is allowed for `server`.")]
     pub server: Url,
+
+    #[is_finite]
+    #[ensure(*interpolation_delay >= 0., "`interpolation_delay` must not be negative.")]
+    #[ensure(*interpolation_delay <= 1., "`interpolation_delay` must be smaller or equal to 1.0.")]
+    pub interpolation_delay: f32,
 }
 
 #[derive(Deserialize, Config, Debug, Clone)]
@@ -43,6 +54,22 @@ pub struct Camera {
     #[ensure(*rotation_sensitivity > 0., "`rotation_sensitivity` must be greater than 0.0.")]
     pub rotation_sensitivity: f32,
 }
+
+#[derive(Deserialize, Config, Debug, Clone)]
+pub struct Menu {
+    #[ensure(parse_key(back_key).is_some(), "`back_key` is not a valid key name.")]
+    #[ensure(parse_key(back_key) != Some(KeyCode::Back), "`back_key` must not be `Back`, it is used by text inputs.")]
+    pub back_key: String,
+
+    #[is_finite]
+    #[ensure(*transition_duration >= 0., "`transition_duration` must not be negative.")]
+    #[ensure(*transition_duration <= 2., "`transition_duration` must be smaller or equal to 2.0.")]
+    pub transition_duration: f32,
+
+    pub reduced_motion: bool,
+
+    pub map_preview: bool,
+}
 // --------------------
 
 // ---- default implementations ----
@@ -51,6 +78,18 @@ impl Default for MultiplayerConf {
     fn default() -> Self {
         Self {
             server: Url::parse("http://lobby.de_game.org").unwrap(),
+            interpolation_delay: 0.1,
+        }
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self {
+            back_key: "Escape".to_owned(),
+            transition_duration: 0.2,
+            reduced_motion: false,
+            map_preview: true,
         }
     }
 }
@@ -89,6 +128,34 @@ impl TryInto<CameraConf> for Camera {
     }
 }
 
+impl TryInto<MenuConf> for Menu {
+    type Error = Error;
+
+    fn try_into(self) -> Result<MenuConf> {
+        let back_key = parse_key(&self.back_key).context("Invalid `back_key`.")?;
+        let transition = if self.reduced_motion || self.transition_duration == 0. {
+            None
+        } else {
+            Some(Duration::from_secs_f32(self.transition_duration))
+        };
+        Ok(MenuConf {
+            back_key,
+            transition,
+            map_preview: self.map_preview,
+        })
+    }
+}
+
+/// Parses a key name, for example `Escape` or `F1`. Key names are identical to
+/// variant names of [`KeyCode`].
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let TypeInfo::Enum(info) = KeyCode::type_info() else { unreachable!() };
+    if !info.contains_variant(name) {
+        return None;
+    }
+    KeyCode::from_reflect(&DynamicEnum::new("KeyCode", name, DynamicVariant::Unit))
+}
+
 #[derive(Debug, Clone)]
 pub struct CameraConf {
     move_margin: LogicalPixel,
@@ -137,15 +204,102 @@ impl CameraConf {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct MenuConf {
+    back_key: KeyCode,
+    transition: Option<Duration>,
+    map_preview: bool,
+}
+
+impl MenuConf {
+    /// Pressing this key in menu navigates back to the main menu.
+    pub fn back_key(&self) -> KeyCode {
+        self.back_key
+    }
+
+    /// Duration of animated transitions between menu screens. None if the
+    /// transitions are disabled.
+    pub fn transition(&self) -> Option<Duration> {
+        self.transition
+    }
+
+    /// Whether the 3D preview of maps is available in map selection.
+    pub fn map_preview(&self) -> bool {
+        self.map_preview
+    }
+}
+
 impl MultiplayerConf {
     /// Server URL for lobby connections.
     pub fn server(&self) -> &Url {
         &self.server
     }
+
+    /// Delay by which remote state is rendered in the past. It is further
+    /// limited relative to the tick rate of the game server, see
+    /// `de_net::InterpolationDelay`.
+    pub fn interpolation_delay(&self) -> Duration {
+        Duration::from_secs_f32(self.interpolation_delay)
+    }
 }
 
 // Bundle configuration neatly into a single struct
 bundle_config!(
     camera: CameraConf: Camera, // Conf file -> Camera -> CameraConf
+    menu: MenuConf: Menu, // Conf file -> Menu -> MenuConf
     multiplayer: MultiplayerConf: MultiplayerConf  // Conf file -> MultiplayerConf
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("Escape"), Some(KeyCode::Escape));
+        assert_eq!(parse_key("F10"), Some(KeyCode::F10));
+        assert_eq!(parse_key("Q"), Some(KeyCode::Q));
+        assert_eq!(parse_key("NoSuchKey"), None);
+        assert_eq!(parse_key("escape"), None);
+    }
+
+    #[test]
+    fn test_menu_check() {
+        let menu = Menu {
+            back_key: "Back".to_owned(),
+            ..Menu::default()
+        };
+        assert!(menu.check().is_err());
+
+        let menu = Menu {
+            back_key: "Home".to_owned(),
+            ..Menu::default()
+        };
+        assert!(menu.check().is_ok());
+
+        let menu = Menu {
+            transition_duration: -0.1,
+            ..Menu::default()
+        };
+        assert!(menu.check().is_err());
+    }
+
+    #[test]
+    fn test_multiplayer_check() {
+        let multiplayer = MultiplayerConf {
+            interpolation_delay: 0.25,
+            ..MultiplayerConf::default()
+        };
+        assert!(multiplayer.check().is_ok());
+        assert_eq!(
+            multiplayer.interpolation_delay(),
+            Duration::from_millis(250)
+        );
+
+        let multiplayer = MultiplayerConf {
+            interpolation_delay: 1.5,
+            ..MultiplayerConf::default()
+        };
+        assert!(multiplayer.check().is_err());
+    }
+}
@@ -25,6 +25,7 @@ pub(crate) async fn load_conf_text(path: &Path) -> Result<Option<String>> {
 #[cfg(test)]
 mod tests {
     use async_std::{path::PathBuf, task};
+    use bevy::prelude::KeyCode;
     use de_uom::Metre;
 
     use crate::conf::Configuration;
@@ -46,5 +47,7 @@ mod tests {
         );
         assert_eq!(conf.camera().min_distance(), Metre::new(12.5));
         assert_eq!(conf.camera().max_distance(), Metre::new(250.));
+        assert_eq!(conf.menu().back_key(), KeyCode::F10);
+        assert_eq!(conf.menu().transition(), None);
     }
 }
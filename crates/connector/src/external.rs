@@ -0,0 +1,21 @@
+use std::{
+    net::SocketAddrV4,
+    sync::{Arc, Mutex},
+};
+
+/// External address of the game server as forwarded by the gateway (home
+/// router) of the local network, shared between the port forwarding and the
+/// game processor. It is None while the port is not mapped.
+#[derive(Clone, Default)]
+pub(crate) struct ExternalAddr(Arc<Mutex<Option<SocketAddrV4>>>);
+
+impl ExternalAddr {
+    pub(crate) fn get(&self) -> Option<SocketAddrV4> {
+        *self.0.lock().unwrap()
+    }
+
+    #[cfg_attr(not(feature = "portmap"), allow(dead_code))]
+    pub(crate) fn set(&self, addr: Option<SocketAddrV4>) {
+        *self.0.lock().unwrap() = addr;
+    }
+}
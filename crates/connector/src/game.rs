@@ -1,31 +1,52 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
 use ahash::AHashSet;
 use anyhow::Context;
 use async_std::{channel::TryRecvError, prelude::FutureExt as StdFutureExt};
-use de_net::{self, Communicator, InMessage, Network, OutMessage, Peers};
-use tracing::info;
+use de_net::{
+    self, Communicator, FromGame, InMessage, NetConf, Network, OutMessage, Peers, SocketOptions,
+    ToGame,
+};
+use tracing::{info, warn};
+
+use crate::{external::ExternalAddr, slots::Slots};
+
+/// Maximum time given to the players to confirm the game closure before the
+/// server finishes.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
 
 pub(crate) struct GameProcessor {
     communicator: Communicator,
     players: AHashSet<SocketAddr>,
+    slots: Slots,
+    external: ExternalAddr,
 }
 
 impl GameProcessor {
-    pub(crate) async fn start(port: u16) -> anyhow::Result<()> {
-        let net = Network::bind(Some(port))
+    pub(crate) async fn start(port: u16, external: ExternalAddr) -> anyhow::Result<()> {
+        // Players connect from other machines.
+        let options = SocketOptions::default().with_address(Ipv4Addr::UNSPECIFIED);
+        let net = Network::bind_with(Some(port), options)
             .await
             .with_context(|| format!("Failed to bind on port {port}"))?;
         info!("Listening on port {}", port);
 
-        let processor = Self {
-            communicator: de_net::startup(net),
-            players: AHashSet::new(),
-        };
+        Self::new(net, external).run().await
+    }
 
-        processor.run().await
+    fn new(net: Network, external: ExternalAddr) -> Self {
+        Self {
+            communicator: de_net::startup(net, NetConf::default()),
+            players: AHashSet::new(),
+            slots: Slots::new(),
+            external,
+        }
     }
 
+    /// Processes messages until the game is closed.
     async fn run(mut self) -> anyhow::Result<()> {
         loop {
             if let Ok(input_result) = self
@@ -41,7 +62,11 @@ impl GameProcessor {
                     Peers::Players => {
                         self.handle_players(message).await?;
                     }
-                    Peers::Server => todo!("Not yet implemented"),
+                    Peers::Server => {
+                        if self.handle_server(message).await? {
+                            return Ok(());
+                        }
+                    }
                 }
             }
 
@@ -58,7 +83,7 @@ impl GameProcessor {
     async fn handle_players(&mut self, message: InMessage) -> anyhow::Result<()> {
         let reliable = message.reliable();
 
-        let targets = self
+        let targets: Vec<SocketAddr> = self
             .players
             .iter()
             .cloned()
@@ -75,4 +100,217 @@ impl GameProcessor {
             .await
             .context("Data sending failed")
     }
+
+    /// Returns true if the game was closed.
+    async fn handle_server(&mut self, message: InMessage) -> anyhow::Result<bool> {
+        for request in message.decode::<ToGame>() {
+            match request {
+                Ok(ToGame::Join) => self.join(message.source()).await?,
+                Ok(ToGame::CloseGame) => {
+                    if self.close(message.source()).await? {
+                        return Ok(true);
+                    }
+                }
+                Ok(ToGame::Ping(id)) => self.pong(message.source(), id, message.reliable()).await?,
+                Ok(ToGame::PortMapping) => self.port_mapping(message.source()).await?,
+                Err(error) => {
+                    warn!("Invalid message from {}: {error}", message.source());
+                    break;
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Assigns a player slot to the joining client and informs the client
+    /// about it.
+    async fn join(&mut self, source: SocketAddr) -> anyhow::Result<()> {
+        let response = match self.slots.assign(source) {
+            Some(player) => {
+                info!("Player {player} joined from {source}");
+                FromGame::Joined { player }
+            }
+            None => FromGame::GameFull,
+        };
+
+        let message = OutMessage::encode_single(&response, true, Peers::Server, vec![source])
+            .context("Failed to encode join response")?;
+        self.communicator
+            .send(message)
+            .await
+            .context("Data sending failed")
+    }
+
+    /// Responds to a ping with the same ID and reliability as the ping.
+    async fn pong(&mut self, source: SocketAddr, id: u32, reliable: bool) -> anyhow::Result<()> {
+        let message =
+            OutMessage::encode_single(&FromGame::Pong(id), reliable, Peers::Server, vec![source])
+                .context("Failed to encode pong")?;
+        self.communicator
+            .send(message)
+            .await
+            .context("Data sending failed")
+    }
+
+    /// Informs the client about the external address of the server.
+    async fn port_mapping(&mut self, source: SocketAddr) -> anyhow::Result<()> {
+        let response = FromGame::PortMapping(self.external.get());
+        let message = OutMessage::encode_single(&response, true, Peers::Server, vec![source])
+            .context("Failed to encode port mapping response")?;
+        self.communicator
+            .send(message)
+            .await
+            .context("Data sending failed")
+    }
+
+    /// Closes the game on request of its host: all players are informed
+    /// about the closure and given a chance to receive the message before
+    /// the server finishes.
+    ///
+    /// Returns true if the game was closed.
+    async fn close(&mut self, source: SocketAddr) -> anyhow::Result<bool> {
+        if !self.slots.is_host(source) {
+            warn!("Ignoring game closure request from non-host {source}");
+            return Ok(false);
+        }
+
+        info!("Game closed by its host {source}");
+        let targets: Vec<SocketAddr> = self.players.iter().cloned().collect();
+        let message =
+            OutMessage::encode_single(&FromGame::GameClosed, true, Peers::Server, targets)
+                .context("Failed to encode game closure notification")?;
+        self.communicator
+            .send(message)
+            .await
+            .context("Data sending failed")?;
+
+        if !self.communicator.flush(CLOSE_TIMEOUT).await {
+            warn!("Game closure was not confirmed by all players");
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+
+    use super::*;
+
+    async fn request(client: &mut Communicator, server: SocketAddr, request: ToGame) {
+        let message =
+            OutMessage::encode_single(&request, true, Peers::Server, vec![server]).unwrap();
+        client.send(message).await.unwrap();
+    }
+
+    async fn response(client: &mut Communicator) -> FromGame {
+        let message = client
+            .recv()
+            .timeout(Duration::from_secs(2))
+            .await
+            .unwrap()
+            .unwrap();
+        message.decode::<FromGame>().next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_ping() {
+        task::block_on(async {
+            let net = Network::bind(None).await.unwrap();
+            let server = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), net.port().unwrap());
+            let game = task::spawn(GameProcessor::new(net, ExternalAddr::default()).run());
+
+            let mut client =
+                de_net::startup(Network::bind(None).await.unwrap(), NetConf::default());
+            request(&mut client, server, ToGame::Ping(42)).await;
+            assert!(matches!(response(&mut client).await, FromGame::Pong(42)));
+            request(&mut client, server, ToGame::Ping(43)).await;
+            assert!(matches!(response(&mut client).await, FromGame::Pong(43)));
+
+            // The game keeps running.
+            request(&mut client, server, ToGame::Join).await;
+            assert!(matches!(
+                response(&mut client).await,
+                FromGame::Joined { player: 0 }
+            ));
+            request(&mut client, server, ToGame::CloseGame).await;
+            assert!(matches!(response(&mut client).await, FromGame::GameClosed));
+            game.timeout(CLOSE_TIMEOUT + Duration::from_secs(1))
+                .await
+                .unwrap()
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_port_mapping() {
+        task::block_on(async {
+            let net = Network::bind(None).await.unwrap();
+            let server = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), net.port().unwrap());
+            let external = ExternalAddr::default();
+            let game = task::spawn(GameProcessor::new(net, external.clone()).run());
+
+            let mut client =
+                de_net::startup(Network::bind(None).await.unwrap(), NetConf::default());
+            request(&mut client, server, ToGame::PortMapping).await;
+            assert!(matches!(
+                response(&mut client).await,
+                FromGame::PortMapping(None)
+            ));
+
+            let addr = "78.28.42.7:8082".parse().unwrap();
+            external.set(Some(addr));
+            request(&mut client, server, ToGame::PortMapping).await;
+            match response(&mut client).await {
+                FromGame::PortMapping(Some(received)) => assert_eq!(received, addr),
+                _ => panic!("Unexpected response."),
+            }
+
+            request(&mut client, server, ToGame::Join).await;
+            assert!(matches!(
+                response(&mut client).await,
+                FromGame::Joined { player: 0 }
+            ));
+            request(&mut client, server, ToGame::CloseGame).await;
+            assert!(matches!(response(&mut client).await, FromGame::GameClosed));
+            game.timeout(CLOSE_TIMEOUT + Duration::from_secs(1))
+                .await
+                .unwrap()
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_close() {
+        task::block_on(async {
+            let net = Network::bind(None).await.unwrap();
+            let server = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), net.port().unwrap());
+            let game = task::spawn(GameProcessor::new(net, ExternalAddr::default()).run());
+
+            let mut host = de_net::startup(Network::bind(None).await.unwrap(), NetConf::default());
+            let mut guest = de_net::startup(Network::bind(None).await.unwrap(), NetConf::default());
+
+            request(&mut host, server, ToGame::Join).await;
+            assert!(matches!(
+                response(&mut host).await,
+                FromGame::Joined { player: 0 }
+            ));
+            request(&mut guest, server, ToGame::Join).await;
+            assert!(matches!(
+                response(&mut guest).await,
+                FromGame::Joined { player: 1 }
+            ));
+
+            // Only the host might close the game.
+            request(&mut guest, server, ToGame::CloseGame).await;
+            request(&mut host, server, ToGame::CloseGame).await;
+            assert!(matches!(response(&mut host).await, FromGame::GameClosed));
+            assert!(matches!(response(&mut guest).await, FromGame::GameClosed));
+
+            game.timeout(CLOSE_TIMEOUT + Duration::from_secs(1))
+                .await
+                .unwrap()
+                .unwrap();
+        });
+    }
 }
@@ -1,9 +1,13 @@
 use async_std::task;
 use tracing::{error, info};
 
-use crate::game::GameProcessor;
+use crate::{external::ExternalAddr, game::GameProcessor};
 
+mod external;
 mod game;
+#[cfg(feature = "portmap")]
+mod portmap;
+mod slots;
 
 const PORT: u16 = 8082;
 
@@ -11,8 +15,20 @@ pub fn start() {
     info!("Starting...");
 
     task::block_on(task::spawn(async {
-        if let Err(error) = GameProcessor::start(PORT).await {
-            error!("{:?}", error);
+        let external = ExternalAddr::default();
+        #[cfg(feature = "portmap")]
+        let forward = portmap::PortForward::start(PORT, external.clone());
+
+        // The processor finishes once the game is closed by its host or
+        // after an error.
+        let result = GameProcessor::start(PORT, external).await;
+
+        #[cfg(feature = "portmap")]
+        forward.stop().await;
+
+        match result {
+            Ok(()) => info!("Game finished"),
+            Err(error) => error!("{:?}", error),
         }
     }));
 }
@@ -0,0 +1,89 @@
+use std::{net::SocketAddrV4, time::Duration};
+
+use async_std::{
+    channel::{self, Receiver, Sender},
+    prelude::FutureExt,
+    task::{self, JoinHandle},
+};
+use de_net::PortMapping;
+use tracing::{info, warn};
+
+use crate::external::ExternalAddr;
+
+/// Requested lifetime of the port mapping. The mapping is renewed after
+/// half of its lifetime so that a crashed server does not leave it open
+/// for long.
+const LIFETIME: Duration = Duration::from_secs(600);
+const MIN_RENEWAL: Duration = Duration::from_secs(30);
+
+/// Forwarding of the server port on the local gateway (home router). The
+/// mapping is kept open until [`PortForward::stop`] is called, which happens
+/// when the game server finishes (see [`crate::start`]). A mapping of a
+/// killed server expires after its lifetime.
+///
+/// The external address of the mapping is kept up to date in an
+/// [`ExternalAddr`].
+pub(crate) struct PortForward {
+    stop: Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PortForward {
+    pub(crate) fn start(port: u16, external: ExternalAddr) -> Self {
+        let (stop, stopped) = channel::bounded(1);
+        Self {
+            stop,
+            task: task::spawn(run(port, external, stopped)),
+        }
+    }
+
+    /// Closes the port mapping.
+    pub(crate) async fn stop(self) {
+        // The task might have already finished.
+        let _ = self.stop.send(()).await;
+        self.task.await;
+    }
+}
+
+async fn run(port: u16, external: ExternalAddr, stopped: Receiver<()>) {
+    let mut mapping = match PortMapping::open(port, LIFETIME).await {
+        Ok(mapping) => {
+            info!("Port mapping opened: {mapping}");
+            mapping
+        }
+        Err(error) if error.is_unavailable() => {
+            info!(
+                "Port mapping is not available ({error}), UDP port {port} has to be \
+                 forwarded manually for players outside of the local network"
+            );
+            return;
+        }
+        Err(error) => {
+            warn!("Port mapping failed: {error}");
+            return;
+        }
+    };
+
+    loop {
+        external.set(Some(address(&mapping)));
+
+        let renewal = (mapping.lifetime() / 2).max(MIN_RENEWAL);
+        // Both a stop request and a dropped sender stop the forwarding.
+        if stopped.recv().timeout(renewal).await.is_ok() {
+            break;
+        }
+        if let Err(error) = mapping.renew().await {
+            warn!("Port mapping renewal failed: {error}");
+        }
+    }
+
+    external.set(None);
+    match mapping.close().await {
+        Ok(()) => info!("Port mapping closed"),
+        Err(error) => warn!("Port mapping closing failed: {error}"),
+    }
+}
+
+fn address(mapping: &PortMapping) -> SocketAddrV4 {
+    SocketAddrV4::new(mapping.external_ip(), mapping.external_port())
+}
@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+
+use ahash::AHashMap;
+
+/// Maximum number of players in a single game.
+const MAX_PLAYERS: u8 = 4;
+
+/// Assignment of player slots to connections.
+///
+/// Slots are never released during a game so that a player re-joining from
+/// the same address (e.g. after a connection failure) is assigned the same
+/// slot. The player in the first slot, i.e. the first player to join, is the
+/// host of the game.
+pub(crate) struct Slots {
+    assigned: AHashMap<SocketAddr, u8>,
+}
+
+impl Slots {
+    pub(crate) fn new() -> Self {
+        Self {
+            assigned: AHashMap::new(),
+        }
+    }
+
+    /// Returns the slot of a connection. The lowest free slot is assigned to
+    /// connections without a slot. None is returned if all slots are taken.
+    pub(crate) fn assign(&mut self, addr: SocketAddr) -> Option<u8> {
+        if let Some(&player) = self.assigned.get(&addr) {
+            return Some(player);
+        }
+
+        let player = (0..MAX_PLAYERS).find(|slot| !self.assigned.values().any(|p| p == slot))?;
+        self.assigned.insert(addr, player);
+        Some(player)
+    }
+
+    /// Returns true if the connection is the host of the game.
+    pub(crate) fn is_host(&self, addr: SocketAddr) -> bool {
+        self.assigned.get(&addr) == Some(&0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let addr = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let mut slots = Slots::new();
+
+        assert_eq!(slots.assign(addr(1000)), Some(0));
+        assert_eq!(slots.assign(addr(1001)), Some(1));
+        // A re-joining player keeps its slot.
+        assert_eq!(slots.assign(addr(1000)), Some(0));
+        assert_eq!(slots.assign(addr(1002)), Some(2));
+        assert_eq!(slots.assign(addr(1003)), Some(3));
+        assert_eq!(slots.assign(addr(1004)), None);
+        assert_eq!(slots.assign(addr(1001)), Some(1));
+
+        assert!(slots.is_host(addr(1000)));
+        assert!(!slots.is_host(addr(1001)));
+        assert!(!slots.is_host(addr(1004)));
+    }
+}
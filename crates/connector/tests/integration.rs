@@ -20,6 +20,11 @@ fn test() {
 
     async fn first(client: &mut Network) {
         let mut buffer = [0u8; 1024];
+
+        // Confirmation of the very first datagram is sent immediately.
+        let (n, _) = client.recv(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[0..n], &[128, 0, 0, 0, 3, 3, 7]);
+
         let (n, _) = client.recv(&mut buffer).await.unwrap();
         assert_eq!(&buffer[4..n], &[5, 6, 7, 8]);
 
@@ -39,7 +44,7 @@ fn test() {
 
         // Confirmation
         let (n, _) = client.recv(&mut buffer).await.unwrap();
-        assert_eq!(&buffer[0..n], &[128, 0, 0, 0, 3, 3, 7, 22, 22, 22]);
+        assert_eq!(&buffer[0..n], &[128, 0, 0, 0, 22, 22, 22]);
 
         // Try to send invalid data -- wrong header
         client
@@ -75,6 +80,11 @@ fn test() {
 
     async fn second(client: &mut Network) {
         let mut buffer = [0u8; 1024];
+
+        // Confirmation of the very first datagram is sent immediately.
+        let (n, _) = client.recv(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[0..n], &[128, 0, 0, 0, 0, 8, 7]);
+
         let (n, _) = client.recv(&mut buffer).await.unwrap();
 
         // First 4 bytes are interpreted as datagram ID.
@@ -95,10 +105,6 @@ fn test() {
             .await
             .unwrap();
 
-        // Confirmation
-        let (n, _) = client.recv(&mut buffer).await.unwrap();
-        assert_eq!(&buffer[0..n], &[128, 0, 0, 0, 0, 8, 7]);
-
         assert!(client
             .recv(&mut buffer)
             .timeout(Duration::from_secs(2))
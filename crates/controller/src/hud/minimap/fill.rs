@@ -3,6 +3,7 @@ use de_core::{
     baseset::GameSet, gamestate::GameState, gconfig::GameConfig, objects::ObjectType,
     player::Player, projection::ToFlat,
 };
+use de_gui::{MINIMAP_ENEMY_COLOR, MINIMAP_PLAYER_COLOR, MINIMAP_TERRAIN_COLOR};
 use de_map::size::MapBounds;
 use de_objects::SolidObjects;
 use de_terrain::TerrainCollider;
@@ -15,9 +16,6 @@ use parry2d::{
 use super::draw::DrawingParam;
 use crate::ray::ScreenRay;
 
-const TERRAIN_COLOR: Color = Color::rgb(0.61, 0.46, 0.32);
-const PLAYER_COLOR: Color = Color::rgb(0.1, 0.1, 0.9);
-const ENEMY_COLOR: Color = Color::rgb(0.9, 0.1, 0.1);
 const MIN_ENTITY_SIZE: Vec2 = Vec2::splat(0.02);
 const CAMERA_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
 
@@ -74,7 +72,7 @@ impl<'w> UiCoords<'w> {
 
 fn clear_system(mut drawing: DrawingParam) {
     let mut drawing = drawing.drawing();
-    drawing.fill(TERRAIN_COLOR);
+    drawing.fill(MINIMAP_TERRAIN_COLOR);
 }
 
 fn draw_entities_system(
@@ -89,9 +87,9 @@ fn draw_entities_system(
     for (transform, &player, &object_type) in entities.iter() {
         let minimap_position = ui_coords.flat_to_rel(transform.translation.to_flat());
         let color = if game.is_local_player(player) {
-            PLAYER_COLOR
+            MINIMAP_PLAYER_COLOR
         } else {
-            ENEMY_COLOR
+            MINIMAP_ENEMY_COLOR
         };
 
         let radius = solids.get(object_type).ichnography().radius();
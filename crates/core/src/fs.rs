@@ -8,6 +8,11 @@ pub fn conf_dir() -> Result<AsyncPathBuf, DirError> {
     dir(dirs::config_dir)
 }
 
+/// Returns DE cache directory.
+pub fn cache_dir() -> Result<AsyncPathBuf, DirError> {
+    dir(dirs::cache_dir)
+}
+
 /// Returns DE logging directory.
 pub fn logs_dir() -> Result<AsyncPathBuf, DirError> {
     dir(dirs::cache_dir).map(|d| d.join("logs"))
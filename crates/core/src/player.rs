@@ -100,7 +100,11 @@ impl Iterator for PlayerRange {
     fn next(&mut self) -> Option<Self::Item> {
         match self.current {
             Some(current) => {
-                self.current = current.next();
+                self.current = if current < self.stop {
+                    current.next()
+                } else {
+                    None
+                };
                 Some(current)
             }
             None => {
@@ -138,5 +142,11 @@ mod tests {
         assert_eq!(range.next(), Some(Player::Player3));
         assert_eq!(range.next(), Some(Player::Player4));
         assert_eq!(range.next(), None);
+
+        let mut range = PlayerRange::up_to(Player::Player2);
+        assert_eq!(range.len(), 2);
+        assert_eq!(range.next(), Some(Player::Player1));
+        assert_eq!(range.next(), Some(Player::Player2));
+        assert_eq!(range.next(), None);
     }
 }
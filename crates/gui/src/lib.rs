@@ -9,6 +9,7 @@ pub use commands::GuiCommands;
 use focus::FocusPlugin;
 pub use focus::SetFocusEvent;
 pub use label::LabelCommands;
+pub use minimap::{Drawing, MINIMAP_ENEMY_COLOR, MINIMAP_PLAYER_COLOR, MINIMAP_TERRAIN_COLOR};
 pub use style::OuterStyle;
 use text::TextPlugin;
 use textbox::TextBoxPlugin;
@@ -20,6 +21,7 @@ mod button;
 mod commands;
 mod focus;
 mod label;
+mod minimap;
 mod style;
 mod text;
 mod textbox;
@@ -16,10 +16,12 @@ pub(crate) struct TextBoxPlugin;
 
 impl Plugin for TextBoxPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(focus_system).add_system(
-            input_system
-                .run_if(on_event::<ReceivedCharacter>().or_else(on_event::<KeyboardInput>())),
-        );
+        app.add_system(focus_system)
+            .add_system(
+                input_system
+                    .run_if(on_event::<ReceivedCharacter>().or_else(on_event::<KeyboardInput>())),
+            )
+            .add_system(update_system.after(input_system));
     }
 }
 
@@ -69,13 +71,20 @@ impl<'w, 's> TextBoxCommands<'w, 's> for GuiCommands<'w, 's> {
 
 #[derive(SystemParam)]
 pub struct TextBoxQuery<'w, 's> {
-    query: Query<'w, 's, &'static TextBox>,
+    query: Query<'w, 's, &'static mut TextBox>,
 }
 
 impl<'w, 's> TextBoxQuery<'w, 's> {
     pub fn text(&self, entity: Entity) -> Option<Cow<'_, str>> {
         self.query.get(entity).map(|e| e.text()).ok()
     }
+
+    /// Replaces text of a text box. Control characters are ignored.
+    pub fn set_text(&mut self, entity: Entity, text: &str) -> Result<(), &'static str> {
+        let Ok(mut text_box) = self.query.get_mut(entity) else { return Err("Text box does not exist.") };
+        text_box.set(text);
+        Ok(())
+    }
 }
 
 #[derive(Component)]
@@ -104,6 +113,10 @@ impl TextBox {
         }
     }
 
+    fn set(&mut self, text: &str) {
+        self.text = text.chars().filter(|c| !c.is_control()).collect();
+    }
+
     fn push(&mut self, input: char) {
         debug_assert!(!input.is_control());
         self.text.push(input);
@@ -170,3 +183,18 @@ fn input_system(
 
     text.sections[0].value = text_box.ui_text();
 }
+
+/// Updates displayed text of text boxes changed other than by user input.
+fn update_system(
+    text_boxes: Query<(&TextBox, &Children), Changed<TextBox>>,
+    mut texts: Query<&mut Text>,
+) {
+    for (text_box, children) in text_boxes.iter() {
+        for &child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.sections[0].value = text_box.ui_text();
+                break;
+            }
+        }
+    }
+}
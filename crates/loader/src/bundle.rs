@@ -0,0 +1,88 @@
+use async_std::fs;
+use de_core::{
+    fs::{cache_dir, DirError},
+    gconfig::GameConfig,
+    player::Player,
+};
+use de_map::map::Map;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Name of the file (in DE cache directory) with the bundle of the last
+/// started game.
+const BUNDLE_FILE_NAME: &str = "last_game.json";
+
+/// Setup of a game captured at its start. It contains everything needed to
+/// recreate the game setup, for example when reproducing a bug.
+#[derive(Serialize)]
+pub(crate) struct StartBundle {
+    version: &'static str,
+    map_name: String,
+    map_path: String,
+    map_hash: String,
+    local_player: Player,
+    players: Vec<Player>,
+}
+
+impl StartBundle {
+    pub(crate) fn new(config: &GameConfig, map: &Map) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            map_name: map.metadata().name().to_owned(),
+            map_path: config.map_path().to_string_lossy().into_owned(),
+            map_hash: map.compute_hash().to_hex(),
+            local_player: config.player(),
+            players: config.players().collect(),
+        }
+    }
+
+    /// Stores the bundle to DE cache directory, any previously stored bundle
+    /// is overwritten.
+    pub(crate) async fn store(&self) -> Result<(), BundleError> {
+        let dir = cache_dir()?;
+        fs::create_dir_all(&dir).await?;
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(dir.join(BUNDLE_FILE_NAME), json).await?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum BundleError {
+    #[error(transparent)]
+    Dir(#[from] DirError),
+    #[error("failed to serialize the game start bundle")]
+    Serialization(#[from] serde_json::Error),
+    #[error("failed to write the game start bundle")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec2;
+    use de_map::{meta::MapMetadata, size::MapBounds};
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_serialization() {
+        let map = Map::empty(MapMetadata::new(
+            "Test Map".into(),
+            MapBounds::new(Vec2::new(100., 200.)),
+            Player::Player3,
+        ));
+        let config = GameConfig::new("maps/test.dem.tar", Player::Player2, Player::Player3);
+
+        let json: Value = serde_json::to_value(StartBundle::new(&config, &map)).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["map_name"], "Test Map");
+        assert_eq!(json["map_path"], "maps/test.dem.tar");
+        assert_eq!(json["map_hash"], map.compute_hash().to_hex());
+        assert_eq!(json["local_player"], "Player2");
+        assert_eq!(
+            json["players"],
+            serde_json::json!(["Player1", "Player2", "Player3"])
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 use map::MapLoaderPlugin;
 
+mod bundle;
 mod map;
 
 pub struct LoaderPluginGroup;
@@ -25,6 +25,8 @@ use de_terrain::TerrainBundle;
 use futures_lite::future;
 use iyes_progress::prelude::*;
 
+use crate::bundle::StartBundle;
+
 pub(crate) struct MapLoaderPlugin;
 
 impl Plugin for MapLoaderPlugin {
@@ -87,6 +89,15 @@ fn spawn_map(
         }
     };
 
+    let bundle = StartBundle::new(game_config.as_ref(), &map);
+    IoTaskPool::get()
+        .spawn(async move {
+            if let Err(err) = bundle.store().await {
+                log_full_error!(err);
+            }
+        })
+        .detach();
+
     let initial_focus = map
         .content()
         .objects()
@@ -1,4 +1,6 @@
-use sqlx::sqlite::SqliteRow;
+use anyhow::{Context, Result};
+use log::info;
+use sqlx::{query, sqlite::SqliteRow, Pool, Row, Sqlite};
 
 pub const SQLITE_CONSTRAINT_PRIMARYKEY: &str = "1555";
 pub const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
@@ -25,3 +27,69 @@ where
 
     fn try_from_row(row: SqliteRow) -> Result<Self, Self::Error>;
 }
+
+/// Brings the DB schema of a component (e.g. games) up to date by applying
+/// its not yet applied migrations. The number of applied migrations of each
+/// component is stored in the DB, thus each migration is applied exactly
+/// once. Migrations of a component must never be removed or reordered, new
+/// ones are appended.
+pub(crate) async fn migrate(
+    pool: &'static Pool<Sqlite>,
+    component: &str,
+    migrations: &[&str],
+) -> Result<()> {
+    query(
+        "CREATE TABLE IF NOT EXISTS schema_versions (\
+         component TEXT NOT NULL PRIMARY KEY, \
+         version INTEGER NOT NULL);",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create schema versions table")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to start a DB transaction")?;
+
+    let version: u32 = query("SELECT version FROM schema_versions WHERE component = ?;")
+        .bind(component)
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to retrieve schema version")?
+        .map(|row| row.try_get("version"))
+        .transpose()
+        .context("Failed to retrieve schema version")?
+        .unwrap_or(0);
+
+    let applied = usize::try_from(version).unwrap();
+    if applied > migrations.len() {
+        anyhow::bail!(
+            "Schema version {version} of {component} is newer than the latest known version {}",
+            migrations.len()
+        );
+    }
+
+    for (index, migration) in migrations.iter().enumerate().skip(applied) {
+        info!("Migrating {component} schema to version {}...", index + 1);
+        query(migration)
+            .execute(&mut transaction)
+            .await
+            .with_context(|| format!("Migration {} of {component} failed", index + 1))?;
+    }
+
+    query(
+        "INSERT INTO schema_versions (component, version) VALUES (?, ?) \
+         ON CONFLICT(component) DO UPDATE SET version = excluded.version;",
+    )
+    .bind(component)
+    .bind(u32::try_from(migrations.len()).unwrap())
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store schema version")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit a DB transaction")
+}
@@ -1,21 +1,27 @@
 use anyhow::{Context, Result};
 use de_lobby_model::{
-    Game, GameConfig, GameListing, GameMap, GamePartial, MAP_HASH_LEN, MAX_GAME_NAME_LEN,
-    MAX_MAP_NAME_LEN, MAX_USERNAME_LEN,
+    Game, GameConfig, GameListing, GameMap, GamePartial, GamePlayer, MAP_HASH_LEN,
+    MAX_GAME_NAME_LEN, MAX_MAP_NAME_LEN, MAX_USERNAME_LEN,
 };
 use futures_util::TryStreamExt;
 use log::info;
-use sqlx::{query, sqlite::SqliteRow, Pool, Row, Sqlite, SqliteExecutor};
+use sqlx::{query, sqlite::SqliteRow, Pool, Row, Sqlite, SqliteExecutor, Transaction};
 use thiserror::Error;
 
 use crate::{
     db::{
-        FromRow, SQLITE_CONSTRAINT_FOREIGNKEY, SQLITE_CONSTRAINT_PRIMARYKEY,
+        migrate, FromRow, SQLITE_CONSTRAINT_FOREIGNKEY, SQLITE_CONSTRAINT_PRIMARYKEY,
         SQLITE_CONSTRAINT_UNIQUE,
     },
     db_error,
 };
 
+/// Schema changes applied on top of `init.sql`, see [`migrate`].
+const MIGRATIONS: [&str; 2] = [
+    include_str!("migrations/1_started.sql"),
+    include_str!("migrations/2_slots.sql"),
+];
+
 #[derive(Clone)]
 pub(super) struct Games {
     pool: &'static Pool<Sqlite>,
@@ -23,23 +29,16 @@ pub(super) struct Games {
 
 impl Games {
     /// This method sets up the database by creating required tables if they do
-    /// not already exist.
+    /// not already exist and by migrating them to the latest schema.
     ///
     /// It is supposed users were already setup.
     pub(super) async fn init(pool: &'static Pool<Sqlite>) -> Result<Self> {
-        let init_query = format!(
-            include_str!("init.sql"),
-            username_len = MAX_USERNAME_LEN,
-            game_name_len = MAX_GAME_NAME_LEN,
-            map_name_lenght = MAX_MAP_NAME_LEN,
-            map_hash_lenght = MAP_HASH_LEN,
-        );
-
         info!("Initializing games...");
-        query(&init_query)
+        query(&init_query())
             .execute(pool)
             .await
             .context("DB initialization failed")?;
+        migrate(pool, "games", &MIGRATIONS).await?;
         Ok(Self { pool })
     }
 
@@ -64,6 +63,47 @@ impl Games {
         Ok(games)
     }
 
+    /// Returns the full state of a game, including all its players (with
+    /// their slots) ordered by the time they joined the game, or None if the
+    /// game does not exist.
+    pub(super) async fn get(&self, game: &str) -> Result<Option<Game>> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start a DB transaction")?;
+
+        let Some(row) = query("SELECT * FROM games WHERE name = ?;")
+            .bind(game)
+            .fetch_optional(&mut transaction)
+            .await
+            .context("Failed to retrieve a game from the DB")?
+        else {
+            return Ok(None);
+        };
+        let config = GameConfig::try_from_row(row)?;
+
+        let mut players = Vec::new();
+        let mut rows = query("SELECT username, slot FROM players WHERE game = ? ORDER BY ordinal;")
+            .bind(game)
+            .fetch(&mut transaction);
+        while let Some(row) = rows
+            .try_next()
+            .await
+            .context("Failed to retrieve a game player from the DB")?
+        {
+            players.push(GamePlayer::try_from_row(row)?);
+        }
+        drop(rows);
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit a DB transaction")?;
+
+        Ok(Some(Game::from_players(config, players)))
+    }
+
     /// This method creates a new game in the DB and places all users to it.
     pub(super) async fn create(&self, game: Game) -> Result<(), CreationError> {
         let game_config = game.config();
@@ -86,10 +126,15 @@ impl Games {
         result.map_err(CreationError::Database)?;
 
         let mut author = true;
-        for username in game.players() {
-            Self::add_player_inner(&mut transaction, author, username, game_config.name())
-                .await
-                .map_err(CreationError::AdditionError)?;
+        for player in game.players() {
+            Self::add_player_inner(
+                &mut transaction,
+                author,
+                player.username(),
+                game_config.name(),
+            )
+            .await
+            .map_err(CreationError::AdditionError)?;
             author = false;
         }
 
@@ -102,24 +147,48 @@ impl Games {
     }
 
     pub(super) async fn add_player(&self, username: &str, game: &str) -> Result<(), AdditionError> {
-        Self::add_player_inner(self.pool, false, username, game).await
+        let mut transaction = self.pool.begin().await.map_err(AdditionError::Database)?;
+        Self::add_player_inner(&mut transaction, false, username, game).await?;
+        transaction
+            .commit()
+            .await
+            .map_err(AdditionError::Database)?;
+        Ok(())
     }
 
-    async fn add_player_inner<'c, E>(
-        executor: E,
+    /// Adds a player to a game. The player is placed to the lowest free slot.
+    async fn add_player_inner(
+        transaction: &mut Transaction<'_, Sqlite>,
         author: bool,
         username: &str,
         game: &str,
-    ) -> Result<(), AdditionError>
-    where
-        E: SqliteExecutor<'c>,
-    {
-        let result = query("INSERT INTO players (author, username, game) VALUES (?, ?, ?);")
-            .bind(author)
-            .bind(username)
+    ) -> Result<(), AdditionError> {
+        let Some(max_players) = Self::max_players(&mut *transaction, game)
+            .await
+            .map_err(AdditionError::Database)?
+        else {
+            return Err(AdditionError::UserOrGameDoesNotExist);
+        };
+
+        let taken: Vec<u8> = query("SELECT slot FROM players WHERE game = ?;")
             .bind(game)
-            .execute(executor)
-            .await;
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(AdditionError::Database)?
+            .iter()
+            .map(|row| row.try_get("slot"))
+            .collect::<Result<_, _>>()
+            .map_err(AdditionError::Database)?;
+        let Some(slot) = free_slot(&taken, max_players) else { return Err(AdditionError::GameFull) };
+
+        let result =
+            query("INSERT INTO players (author, username, game, slot) VALUES (?, ?, ?, ?);")
+                .bind(author)
+                .bind(username)
+                .bind(game)
+                .bind(slot)
+                .execute(&mut *transaction)
+                .await;
 
         db_error!(
             result,
@@ -145,12 +214,14 @@ impl Games {
     ) -> Result<(), RemovalError> {
         let mut transaction = self.pool.begin().await.map_err(RemovalError::Database)?;
 
-        let mut rows = query("SELECT author FROM players WHERE username = ? AND game = ?;")
+        let row = query("SELECT author FROM players WHERE username = ? AND game = ?;")
             .bind(username)
             .bind(game)
-            .fetch(self.pool);
+            .fetch_optional(&mut transaction)
+            .await
+            .map_err(RemovalError::Database)?;
 
-        let action = match rows.try_next().await.map_err(RemovalError::Database)? {
+        let action = match row {
             Some(row) => {
                 let author: bool = row.try_get("author").map_err(RemovalError::Database)?;
                 if author {
@@ -202,6 +273,156 @@ impl Games {
 
         Ok(())
     }
+
+    /// Moves a player of a game to another slot. The slot must not be taken
+    /// by another player of the game.
+    pub(super) async fn set_slot(
+        &self,
+        username: &str,
+        game: &str,
+        slot: u8,
+    ) -> Result<(), SlotError> {
+        let mut transaction = self.pool.begin().await.map_err(SlotError::Database)?;
+
+        let Some(max_players) = Self::max_players(&mut transaction, game)
+            .await
+            .map_err(SlotError::Database)?
+        else {
+            return Err(SlotError::NotInTheGame);
+        };
+        if slot == 0 || slot > max_players {
+            return Err(SlotError::OutOfRange(max_players));
+        }
+
+        let result = query("UPDATE players SET slot = ? WHERE username = ? AND game = ?;")
+            .bind(slot)
+            .bind(username)
+            .bind(game)
+            .execute(&mut transaction)
+            .await;
+        db_error!(result, SlotError::Taken, SQLITE_CONSTRAINT_UNIQUE);
+
+        let rows_affected = result.map_err(SlotError::Database)?.rows_affected();
+        assert!(rows_affected <= 1);
+        if rows_affected == 0 {
+            return Err(SlotError::NotInTheGame);
+        }
+
+        transaction.commit().await.map_err(SlotError::Database)?;
+        Ok(())
+    }
+
+    /// Returns maximum number of players of a game or None if the game does
+    /// not exist.
+    async fn max_players<'c, E>(executor: E, game: &str) -> Result<Option<u8>, sqlx::Error>
+    where
+        E: SqliteExecutor<'c>,
+    {
+        let row = query("SELECT max_players FROM games WHERE name = ?;")
+            .bind(game)
+            .fetch_optional(executor)
+            .await?;
+        row.map(|row| row.try_get("max_players")).transpose()
+    }
+
+    /// Changes the map of a game. Only the author of the game is allowed to
+    /// do this and only before the game is started.
+    pub(super) async fn change_map(
+        &self,
+        username: &str,
+        game: &str,
+        map: &GameMap,
+    ) -> Result<(), MapChangeError> {
+        let mut transaction = self.pool.begin().await.map_err(MapChangeError::Database)?;
+
+        let query_result = query(
+            "UPDATE games SET map_hash = ?, map_name = ? \
+             WHERE name = ? AND NOT started AND EXISTS ( \
+                 SELECT 1 FROM players \
+                 WHERE players.game = games.name AND username = ? AND author \
+             );",
+        )
+        .bind(map.hash())
+        .bind(map.name())
+        .bind(game)
+        .bind(username)
+        .execute(&mut transaction)
+        .await
+        .map_err(MapChangeError::Database)?;
+
+        let rows_affected = query_result.rows_affected();
+        assert!(rows_affected <= 1);
+        if rows_affected == 0 {
+            let started = Self::started(&mut transaction, game)
+                .await
+                .map_err(MapChangeError::Database)?;
+            return Err(if started == Some(true) {
+                MapChangeError::Started
+            } else {
+                MapChangeError::NotAuthor
+            });
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(MapChangeError::Database)?;
+        Ok(())
+    }
+
+    /// Marks a game as started. Only the author of the game is allowed to do
+    /// this. Starting of an already started game is not an error.
+    pub(super) async fn start(&self, username: &str, game: &str) -> Result<(), StartError> {
+        let query_result = query(
+            "UPDATE games SET started = TRUE \
+             WHERE name = ? AND EXISTS ( \
+                 SELECT 1 FROM players \
+                 WHERE players.game = games.name AND username = ? AND author \
+             );",
+        )
+        .bind(game)
+        .bind(username)
+        .execute(self.pool)
+        .await
+        .map_err(StartError::Database)?;
+
+        let rows_affected = query_result.rows_affected();
+        assert!(rows_affected <= 1);
+        if rows_affected == 0 {
+            return Err(StartError::NotAuthor);
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a game is started or None if the game does not exist.
+    async fn started<'c, E>(executor: E, game: &str) -> Result<Option<bool>, sqlx::Error>
+    where
+        E: SqliteExecutor<'c>,
+    {
+        let row = query("SELECT started FROM games WHERE name = ?;")
+            .bind(game)
+            .fetch_optional(executor)
+            .await?;
+        row.map(|row| row.try_get("started")).transpose()
+    }
+}
+
+/// Returns the initial DB schema, see also [`MIGRATIONS`].
+fn init_query() -> String {
+    format!(
+        include_str!("init.sql"),
+        username_len = MAX_USERNAME_LEN,
+        game_name_len = MAX_GAME_NAME_LEN,
+        map_name_lenght = MAX_MAP_NAME_LEN,
+        map_hash_lenght = MAP_HASH_LEN,
+    )
+}
+
+/// Returns the lowest slot not present in `taken` or None if all slots of a
+/// game with `max_players` are taken.
+fn free_slot(taken: &[u8], max_players: u8) -> Option<u8> {
+    (1..=max_players).find(|slot| !taken.contains(slot))
 }
 
 /// Action taken during removal of a player from a game.
@@ -230,6 +451,8 @@ pub(super) enum AdditionError {
     AlreadyInAGame,
     #[error("The user or the game does not exist")]
     UserOrGameDoesNotExist,
+    #[error("All slots of the game are taken")]
+    GameFull,
     #[error("A database error encountered")]
     Database(#[source] sqlx::Error),
     #[error(transparent)]
@@ -244,6 +467,36 @@ pub(super) enum RemovalError {
     Database(#[source] sqlx::Error),
 }
 
+#[derive(Error, Debug)]
+pub(super) enum SlotError {
+    #[error("User is not in the game or the game does not exist")]
+    NotInTheGame,
+    #[error("The slot is not between 1 and {0}")]
+    OutOfRange(u8),
+    #[error("The slot is already taken")]
+    Taken,
+    #[error("A database error encountered")]
+    Database(#[source] sqlx::Error),
+}
+
+#[derive(Error, Debug)]
+pub(super) enum MapChangeError {
+    #[error("User is not the author of the game or the game does not exist")]
+    NotAuthor,
+    #[error("The game is already started")]
+    Started,
+    #[error("A database error encountered")]
+    Database(#[source] sqlx::Error),
+}
+
+#[derive(Error, Debug)]
+pub(super) enum StartError {
+    #[error("User is not the author of the game or the game does not exist")]
+    NotAuthor,
+    #[error("A database error encountered")]
+    Database(#[source] sqlx::Error),
+}
+
 impl FromRow for GamePartial {
     type Error = anyhow::Error;
 
@@ -254,6 +507,16 @@ impl FromRow for GamePartial {
     }
 }
 
+impl FromRow for GamePlayer {
+    type Error = anyhow::Error;
+
+    fn try_from_row(row: SqliteRow) -> Result<Self, Self::Error> {
+        let username: String = row.try_get("username")?;
+        let slot: u8 = row.try_get("slot")?;
+        Ok(Self::new(username, slot))
+    }
+}
+
 impl FromRow for GameConfig {
     type Error = anyhow::Error;
 
@@ -274,3 +537,217 @@ impl FromRow for GameMap {
         Ok(Self::new(hash, name))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn games() -> Games {
+        Games::init(users().await).await.unwrap()
+    }
+
+    /// Returns a new DB with users but without any games tables.
+    async fn users() -> &'static Pool<Sqlite> {
+        // Each connection to an in-memory DB is a separate DB.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool: &'static Pool<Sqlite> = Box::leak(Box::new(pool));
+
+        query("CREATE TABLE users (username TEXT NOT NULL PRIMARY KEY);")
+            .execute(pool)
+            .await
+            .unwrap();
+        for username in ["alice", "bob", "carol"] {
+            query("INSERT INTO users (username) VALUES (?);")
+                .bind(username)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    async fn slots(games: &Games) -> Vec<(String, u8)> {
+        games
+            .get("Game")
+            .await
+            .unwrap()
+            .unwrap()
+            .players()
+            .iter()
+            .map(|p| (p.username().to_owned(), p.slot()))
+            .collect()
+    }
+
+    #[test]
+    fn test_free_slot() {
+        assert_eq!(free_slot(&[], 2), Some(1));
+        assert_eq!(free_slot(&[1, 3], 4), Some(2));
+        assert_eq!(free_slot(&[2, 1], 2), None);
+    }
+
+    #[actix_web::test]
+    async fn test_slots() {
+        let games = games().await;
+        let config = GameConfig::new(
+            "Game".to_owned(),
+            3,
+            GameMap::new("a".repeat(64), "Map".to_owned()),
+        );
+        games
+            .create(Game::new(config, "alice".to_owned()))
+            .await
+            .unwrap();
+        games.add_player("bob", "Game").await.unwrap();
+        assert_eq!(
+            slots(&games).await,
+            vec![("alice".to_owned(), 1), ("bob".to_owned(), 2)]
+        );
+
+        // Both request slot 3, the second request is rejected and the player
+        // keeps the originally assigned slot.
+        games.set_slot("alice", "Game", 3).await.unwrap();
+        assert!(matches!(
+            games.set_slot("bob", "Game", 3).await,
+            Err(SlotError::Taken)
+        ));
+        assert!(matches!(
+            games.set_slot("bob", "Game", 4).await,
+            Err(SlotError::OutOfRange(3))
+        ));
+        assert!(matches!(
+            games.set_slot("carol", "Game", 1).await,
+            Err(SlotError::NotInTheGame)
+        ));
+        assert_eq!(
+            slots(&games).await,
+            vec![("alice".to_owned(), 3), ("bob".to_owned(), 2)]
+        );
+
+        // Slot freed by a leaving player is reused.
+        games.remove_player("bob", "Game").await.unwrap();
+        games.add_player("carol", "Game").await.unwrap();
+        games.add_player("bob", "Game").await.unwrap();
+        assert_eq!(
+            slots(&games).await,
+            vec![
+                ("alice".to_owned(), 3),
+                ("carol".to_owned(), 1),
+                ("bob".to_owned(), 2)
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_change_map() {
+        let games = games().await;
+        let map = |name: &str| GameMap::new("a".repeat(64), name.to_owned());
+        let map_name = |game: Option<Game>| game.unwrap().config().map().name().to_owned();
+
+        let config = GameConfig::new("Game".to_owned(), 3, map("Old"));
+        games
+            .create(Game::new(config, "alice".to_owned()))
+            .await
+            .unwrap();
+        games.add_player("bob", "Game").await.unwrap();
+
+        // Only the author is allowed to change the map.
+        assert!(matches!(
+            games.change_map("bob", "Game", &map("Bob's")).await,
+            Err(MapChangeError::NotAuthor)
+        ));
+        assert!(matches!(
+            games.change_map("alice", "Other", &map("New")).await,
+            Err(MapChangeError::NotAuthor)
+        ));
+        assert!(matches!(
+            games.start("bob", "Game").await,
+            Err(StartError::NotAuthor)
+        ));
+        assert_eq!(map_name(games.get("Game").await.unwrap()), "Old");
+
+        // All players see the new map.
+        games
+            .change_map("alice", "Game", &map("New"))
+            .await
+            .unwrap();
+        assert_eq!(map_name(games.get("Game").await.unwrap()), "New");
+        let listing = games.list().await.unwrap();
+        assert_eq!(listing.games()[0].config().map().name(), "New");
+
+        // The map cannot be changed once the game is running.
+        games.start("alice", "Game").await.unwrap();
+        assert!(matches!(
+            games.change_map("alice", "Game", &map("Newer")).await,
+            Err(MapChangeError::Started)
+        ));
+        assert_eq!(map_name(games.get("Game").await.unwrap()), "New");
+    }
+
+    #[actix_web::test]
+    async fn test_migration() {
+        let pool = users().await;
+        // A DB created before the migrations were introduced.
+        query(&init_query()).execute(pool).await.unwrap();
+        query("INSERT INTO games (name, max_players, map_hash, map_name) VALUES (?, 3, ?, 'Map');")
+            .bind("Game")
+            .bind("a".repeat(64))
+            .execute(pool)
+            .await
+            .unwrap();
+        for (author, username) in [(true, "bob"), (false, "alice")] {
+            query("INSERT INTO players (author, username, game) VALUES (?, ?, 'Game');")
+                .bind(author)
+                .bind(username)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+
+        let games = Games::init(pool).await.unwrap();
+        assert_eq!(
+            slots(&games).await,
+            vec![("bob".to_owned(), 1), ("alice".to_owned(), 2)]
+        );
+        assert!(matches!(
+            games.set_slot("alice", "Game", 1).await,
+            Err(SlotError::Taken)
+        ));
+        games
+            .change_map(
+                "bob",
+                "Game",
+                &GameMap::new("b".repeat(64), "New".to_owned()),
+            )
+            .await
+            .unwrap();
+        games.start("bob", "Game").await.unwrap();
+
+        // Already applied migrations are not applied again.
+        let games = Games::init(pool).await.unwrap();
+        assert!(matches!(
+            games
+                .change_map(
+                    "bob",
+                    "Game",
+                    &GameMap::new("c".repeat(64), "Newer".to_owned())
+                )
+                .await,
+            Err(MapChangeError::Started)
+        ));
+        games.add_player("carol", "Game").await.unwrap();
+        assert_eq!(
+            slots(&games).await,
+            vec![
+                ("bob".to_owned(), 1),
+                ("alice".to_owned(), 2),
+                ("carol".to_owned(), 3)
+            ]
+        );
+    }
+}
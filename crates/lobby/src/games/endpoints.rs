@@ -1,8 +1,10 @@
 use actix_web::{get, post, put, web, HttpResponse, Responder};
-use de_lobby_model::{Game, GameConfig, Validatable};
+use de_lobby_model::{Game, GameConfig, GameMap, Validatable};
 use log::{error, warn};
 
-use super::db::{AdditionError, CreationError, Games, RemovalError};
+use super::db::{
+    AdditionError, CreationError, Games, MapChangeError, RemovalError, SlotError, StartError,
+};
 use crate::auth::Claims;
 
 /// Registers all authentication endpoints.
@@ -11,8 +13,12 @@ pub(super) fn configure(cfg: &mut web::ServiceConfig) {
         web::scope("/games")
             .service(create)
             .service(list)
+            .service(get)
             .service(join)
-            .service(leave),
+            .service(leave)
+            .service(set_slot)
+            .service(change_map)
+            .service(start),
     );
 }
 
@@ -57,6 +63,23 @@ async fn list(games: web::Data<Games>) -> impl Responder {
     }
 }
 
+#[get("/{name}")]
+async fn get(games: web::Data<Games>, path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+
+    match games.get(name.as_str()).await {
+        Ok(Some(game)) => HttpResponse::Ok().json(game),
+        Ok(None) => {
+            warn!("Game retrieval error: the game does not exist.");
+            HttpResponse::NotFound().json("Game not found.")
+        }
+        Err(error) => {
+            error!("Game retrieval error: {:?}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 #[put("/{name}/join")]
 async fn join(
     claims: web::ReqData<Claims>,
@@ -75,6 +98,10 @@ async fn join(
             warn!("Game joining error: the game or the user does not exist");
             HttpResponse::NotFound().json("Game not found.")
         }
+        Err(AdditionError::GameFull) => {
+            warn!("Game joining error: the game is full.");
+            HttpResponse::Conflict().json("The game is full.")
+        }
         Err(error) => {
             error!("Error while adding a player to a game: {:?}", error);
             HttpResponse::InternalServerError().finish()
@@ -102,3 +129,91 @@ async fn leave(
         }
     }
 }
+
+#[put("/{name}/slot")]
+async fn set_slot(
+    claims: web::ReqData<Claims>,
+    games: web::Data<Games>,
+    path: web::Path<String>,
+    slot: web::Json<u8>,
+) -> impl Responder {
+    let name = path.into_inner();
+
+    match games
+        .set_slot(claims.username(), name.as_str(), slot.into_inner())
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().json(()),
+        Err(SlotError::NotInTheGame) => {
+            warn!("Slot change error: the user is not in the game.");
+            HttpResponse::Forbidden().json("The user is not in the game.")
+        }
+        Err(error @ SlotError::OutOfRange(_)) => {
+            warn!("Slot change error: {error}");
+            HttpResponse::BadRequest().json(format!("{error}."))
+        }
+        Err(SlotError::Taken) => {
+            warn!("Slot change error: the slot is already taken.");
+            HttpResponse::Conflict().json("The slot is already taken.")
+        }
+        Err(error) => {
+            error!("Error while changing slot of a player: {:?}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/{name}/map")]
+async fn change_map(
+    claims: web::ReqData<Claims>,
+    games: web::Data<Games>,
+    path: web::Path<String>,
+    map: web::Json<GameMap>,
+) -> impl Responder {
+    let name = path.into_inner();
+    let map = map.into_inner();
+    if let Err(error) = map.validate() {
+        warn!("Invalid game map: {:?}", error);
+        return HttpResponse::BadRequest().json(format!("{error}"));
+    }
+
+    match games
+        .change_map(claims.username(), name.as_str(), &map)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().json(()),
+        Err(MapChangeError::NotAuthor) => {
+            warn!("Map change error: the user is not the author of the game.");
+            HttpResponse::Forbidden().json("The user is not the author of the game.")
+        }
+        Err(MapChangeError::Started) => {
+            warn!("Map change error: the game is already started.");
+            HttpResponse::Conflict().json("The game is already started.")
+        }
+        Err(error) => {
+            error!("Error while changing map of a game: {:?}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/{name}/start")]
+async fn start(
+    claims: web::ReqData<Claims>,
+    games: web::Data<Games>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let name = path.into_inner();
+
+    match games.start(claims.username(), name.as_str()).await {
+        Ok(_) => HttpResponse::Ok().json(()),
+        Err(StartError::NotAuthor) => {
+            warn!("Game start error: the user is not the author of the game.");
+            HttpResponse::Forbidden().json("The user is not the author of the game.")
+        }
+        Err(error) => {
+            error!("Error while starting a game: {:?}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
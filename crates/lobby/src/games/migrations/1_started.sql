@@ -0,0 +1 @@
+ALTER TABLE games ADD COLUMN started BOOLEAN NOT NULL DEFAULT FALSE;
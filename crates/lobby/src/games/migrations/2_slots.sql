@@ -0,0 +1,9 @@
+ALTER TABLE players ADD COLUMN slot TINYINT NOT NULL DEFAULT 0;
+
+-- Players of existing games get slots in the order they joined.
+UPDATE players SET slot = (
+    SELECT count(*) FROM players AS earlier
+    WHERE earlier.game = players.game AND earlier.ordinal <= players.ordinal
+);
+
+CREATE UNIQUE INDEX players_game_slot ON players(game, slot);
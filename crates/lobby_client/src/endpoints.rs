@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 
-use de_lobby_model::{GameConfig, GameListing, Token, UserWithPassword, UsernameAndPassword};
+use de_lobby_model::{
+    Game, GameConfig, GameListing, GameMap, Token, UserWithPassword, UsernameAndPassword,
+};
 use reqwest::{header::HeaderValue, Method, Request};
 use serde::Serialize;
 use url::Url;
@@ -95,6 +97,28 @@ impl LobbyRequestCreator for ListGamesRequest {
     }
 }
 
+pub struct GetGameRequest(String);
+
+impl GetGameRequest {
+    pub fn new(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl LobbyRequest for GetGameRequest {
+    type Response = Game;
+}
+
+impl LobbyRequestCreator for GetGameRequest {
+    fn path(&self) -> Cow<str> {
+        encode(&["a", "games", self.0.as_str()])
+    }
+
+    fn create(&self, url: Url) -> Request {
+        Request::new(Method::GET, url)
+    }
+}
+
 pub struct JoinGameRequest(String);
 
 impl JoinGameRequest {
@@ -139,6 +163,82 @@ impl LobbyRequestCreator for LeaveGameRequest {
     }
 }
 
+pub struct ChangeMapRequest {
+    game: String,
+    map: GameMap,
+}
+
+impl ChangeMapRequest {
+    pub fn new(game: String, map: GameMap) -> Self {
+        Self { game, map }
+    }
+}
+
+impl LobbyRequest for ChangeMapRequest {
+    type Response = ();
+}
+
+impl LobbyRequestCreator for ChangeMapRequest {
+    fn path(&self) -> Cow<str> {
+        encode(&["a", "games", self.game.as_str(), "map"])
+    }
+
+    fn create(&self, url: Url) -> Request {
+        let mut request = Request::new(Method::PUT, url);
+        json(&mut request, &self.map);
+        request
+    }
+}
+
+pub struct StartGameRequest(String);
+
+impl StartGameRequest {
+    pub fn new(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl LobbyRequest for StartGameRequest {
+    type Response = ();
+}
+
+impl LobbyRequestCreator for StartGameRequest {
+    fn path(&self) -> Cow<str> {
+        encode(&["a", "games", self.0.as_str(), "start"])
+    }
+
+    fn create(&self, url: Url) -> Request {
+        Request::new(Method::PUT, url)
+    }
+}
+
+pub struct SetSlotRequest {
+    game: String,
+    slot: u8,
+}
+
+impl SetSlotRequest {
+    pub fn new(game: String, slot: u8) -> Self {
+        Self { game, slot }
+    }
+}
+
+impl LobbyRequest for SetSlotRequest {
+    type Response = ();
+}
+
+impl LobbyRequestCreator for SetSlotRequest {
+    fn path(&self) -> Cow<str> {
+        encode(&["a", "games", self.game.as_str(), "slot"])
+    }
+
+    fn create(&self, url: Url) -> Request {
+        let mut request = Request::new(Method::PUT, url);
+        json(&mut request, &self.slot);
+        request
+    }
+}
+
 fn json<T: Serialize>(request: &mut Request, content: &T) {
     request.headers_mut().insert(
         "Content-Type",
@@ -158,7 +258,7 @@ fn encode(parts: &[&str]) -> Cow<'static, str> {
 
 #[cfg(test)]
 mod tests {
-    use de_lobby_model::{GameMap, User};
+    use de_lobby_model::User;
 
     use super::*;
 
@@ -228,6 +328,15 @@ mod tests {
         assert_eq!(body, expected_body);
     }
 
+    #[test]
+    fn test_get() {
+        let request = GetGameRequest::new("Cool Game".to_owned());
+        assert_eq!(request.path().as_ref(), "/a/games/Cool%20Game");
+
+        let request = request.create(Url::parse("http://example.com/a/games/x").unwrap());
+        assert_eq!(request.method().as_str(), "GET");
+    }
+
     #[test]
     fn test_join() {
         let request = JoinGameRequest::new("Cool Game".to_owned());
@@ -239,4 +348,48 @@ mod tests {
         let request = LeaveGameRequest::new("První Hra".to_owned());
         assert_eq!(request.path().as_ref(), "/a/games/Prvn%C3%AD%20Hra/leave");
     }
+
+    #[test]
+    fn test_change_map() {
+        let request = ChangeMapRequest::new(
+            "Cool Game".to_owned(),
+            GameMap::new(
+                "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef".to_owned(),
+                "other".to_owned(),
+            ),
+        );
+        assert_eq!(request.path().as_ref(), "/a/games/Cool%20Game/map");
+
+        let request = request.create(Url::parse("http://example.com/a/games/x/map").unwrap());
+        assert_eq!(request.method().as_str(), "PUT");
+
+        let body = String::from_utf8(request.body().unwrap().as_bytes().unwrap().to_vec()).unwrap();
+        let expected_body = concat!(
+            r#"{"hash":"0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef","#,
+            r#""name":"other"}"#
+        );
+        assert_eq!(body, expected_body);
+    }
+
+    #[test]
+    fn test_start() {
+        let request = StartGameRequest::new("Cool Game".to_owned());
+        assert_eq!(request.path().as_ref(), "/a/games/Cool%20Game/start");
+
+        let request = request.create(Url::parse("http://example.com/a/games/x/start").unwrap());
+        assert_eq!(request.method().as_str(), "PUT");
+        assert!(request.body().is_none());
+    }
+
+    #[test]
+    fn test_set_slot() {
+        let request = SetSlotRequest::new("Cool Game".to_owned(), 3);
+        assert_eq!(request.path().as_ref(), "/a/games/Cool%20Game/slot");
+
+        let request = request.create(Url::parse("http://example.com/a/games/x/slot").unwrap());
+        assert_eq!(request.method().as_str(), "PUT");
+
+        let body = String::from_utf8(request.body().unwrap().as_bytes().unwrap().to_vec()).unwrap();
+        assert_eq!(body, "3");
+    }
 }
@@ -34,7 +34,11 @@ impl PluginGroup for LobbyClientPluginGroup {
             .add(EndpointPlugin::<SignInRequest>::default())
             .add(EndpointPlugin::<CreateGameRequest>::default())
             .add(EndpointPlugin::<ListGamesRequest>::default())
+            .add(EndpointPlugin::<GetGameRequest>::default())
             .add(EndpointPlugin::<JoinGameRequest>::default())
             .add(EndpointPlugin::<LeaveGameRequest>::default())
+            .add(EndpointPlugin::<ChangeMapRequest>::default())
+            .add(EndpointPlugin::<StartGameRequest>::default())
+            .add(EndpointPlugin::<SetSlotRequest>::default())
     }
 }
@@ -11,7 +11,7 @@ const MAX_PLAYERS: u8 = 4;
 #[serde(rename_all = "camelCase")]
 pub struct Game {
     config: GameConfig,
-    players: Vec<String>,
+    players: Vec<GamePlayer>,
 }
 
 impl Game {
@@ -19,19 +19,54 @@ impl Game {
     pub fn new(config: GameConfig, author: String) -> Self {
         Self {
             config,
-            players: vec![author],
+            players: vec![GamePlayer::new(author, 1)],
         }
     }
 
+    /// Creates a game with a given list of players. The author of the game
+    /// is the first player.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `players` is empty.
+    pub fn from_players(config: GameConfig, players: Vec<GamePlayer>) -> Self {
+        assert!(!players.is_empty());
+        Self { config, players }
+    }
+
     pub fn config(&self) -> &GameConfig {
         &self.config
     }
 
-    pub fn players(&self) -> &[String] {
+    pub fn players(&self) -> &[GamePlayer] {
         self.players.as_slice()
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamePlayer {
+    username: String,
+    slot: u8,
+}
+
+impl GamePlayer {
+    pub fn new(username: String, slot: u8) -> Self {
+        Self { username, slot }
+    }
+
+    pub fn username(&self) -> &str {
+        self.username.as_str()
+    }
+
+    /// Slot of the player in the game, i.e. a number between 1 and maximum
+    /// number of players in the game. The slot determines color and starting
+    /// location of the player. Each player in a game has a unique slot.
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameListing(Vec<GamePartial>);
@@ -3,7 +3,7 @@ use std::io;
 use async_std::{
     fs::{File, OpenOptions},
     io::{ReadExt, Write},
-    path::Path,
+    path::{Path, PathBuf},
     stream::StreamExt,
 };
 use async_tar::{Archive, Builder, Entry, EntryType, Header};
@@ -11,6 +11,7 @@ use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
 use crate::{
+    hash::MapHash,
     map::{Map, MapValidationError},
     meta::MapMetadata,
 };
@@ -133,6 +134,52 @@ pub enum MapLoadingError {
     Validation { source: MapValidationError },
 }
 
+/// Result of a comparison of a locally stored map with a map hash announced
+/// by another party, for example by a game host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapVerification {
+    /// The map is available locally and its content matches the hash.
+    Matching,
+    /// A map file named after the hash is available locally but its content
+    /// differs, i.e. it is a different version of the map.
+    Mismatching,
+    /// No map file named after the hash is available locally.
+    Missing,
+}
+
+/// Verifies that a map with a given hash is available in a directory.
+///
+/// The map is looked up by its canonical file name (see
+/// [`MapHash::construct_path`]). The content of the found map is loaded and
+/// hashed so that maps modified after they were stored are detected.
+///
+/// # Arguments
+///
+/// * `dir` - directory with locally stored maps.
+///
+/// * `hash` - expected hash of the map.
+pub async fn verify_map<P: Into<PathBuf>>(
+    dir: P,
+    hash: &MapHash,
+) -> LoadingResult<MapVerification> {
+    let path = hash.construct_path(dir);
+    if !path.exists().await {
+        return Ok(MapVerification::Missing);
+    }
+
+    match load_map(&path).await {
+        Ok(map) => {
+            if &map.compute_hash() == hash {
+                Ok(MapVerification::Matching)
+            } else {
+                Ok(MapVerification::Mismatching)
+            }
+        }
+        Err(MapLoadingError::Io { source }) => Err(MapLoadingError::Io { source }),
+        Err(_) => Ok(MapVerification::Mismatching),
+    }
+}
+
 /// Writes a map to a TAR file. Overwrites the file if it already exists.
 pub async fn store_map<P: AsRef<Path>>(map: &Map, path: P) -> StoringResult {
     let file = storing_io_error!(
@@ -234,6 +281,7 @@ mod test {
         let mut tmp_dir_path = PathBuf::from(tmp_dir.path());
         tmp_dir_path.push("test-map.dem.tar");
 
+        let hash = map.compute_hash();
         task::block_on(store_map(&map, tmp_dir_path.as_path())).unwrap();
         let loaded_map = task::block_on(load_map(tmp_dir_path.as_path())).unwrap();
 
@@ -241,6 +289,58 @@ mod test {
             loaded_map.metadata().bounds().aabb(),
             Aabb::new(Point::new(-500., -1000.), Point::new(500., 1000.))
         );
+        assert_eq!(loaded_map.compute_hash(), hash);
+
+        // The stored map is replaced by a map with tampered content.
+        map.insert_object(Object::new(
+            map.new_placement(Vec2::ZERO, 1.),
+            InnerObject::Active(ActiveObject::new(
+                ActiveObjectType::Building(BuildingType::Base),
+                Player::Player1,
+            )),
+        ));
+        task::block_on(store_map(&map, tmp_dir_path.as_path())).unwrap();
+        let tampered_map = task::block_on(load_map(tmp_dir_path.as_path())).unwrap();
+        assert_ne!(tampered_map.compute_hash(), hash);
+    }
+
+    #[test]
+    fn test_verify_map() {
+        let bounds = MapBounds::new(Vec2::new(1000., 2000.));
+        let map_a = Map::empty(MapMetadata::new("Map A".into(), bounds, Player::Player2));
+        let map_b = Map::empty(MapMetadata::new("Map B".into(), bounds, Player::Player2));
+        let hash_a = map_a.compute_hash();
+        let hash_b = map_b.compute_hash();
+
+        let tmp_dir = Builder::new().prefix("de_map_").tempdir().unwrap();
+        let tmp_dir_path = PathBuf::from(tmp_dir.path());
+
+        assert_eq!(
+            task::block_on(verify_map(tmp_dir_path.clone(), &hash_a)).unwrap(),
+            MapVerification::Missing
+        );
+
+        task::block_on(store_map(
+            &map_a,
+            hash_a.construct_path(tmp_dir_path.clone()),
+        ))
+        .unwrap();
+        // A different map stored under the name of map B, i.e. a different
+        // version of map B.
+        task::block_on(store_map(
+            &map_a,
+            hash_b.construct_path(tmp_dir_path.clone()),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            task::block_on(verify_map(tmp_dir_path.clone(), &hash_a)).unwrap(),
+            MapVerification::Matching
+        );
+        assert_eq!(
+            task::block_on(verify_map(tmp_dir_path, &hash_b)).unwrap(),
+            MapVerification::Mismatching
+        );
     }
 
     #[test]
@@ -72,7 +72,8 @@ impl Map {
         placement
     }
 
-    pub(crate) fn validate(&self) -> Result<(), MapValidationError> {
+    /// Validates the map metadata and all objects placed on the map.
+    pub fn validate(&self) -> Result<(), MapValidationError> {
         if let Err(error) = self.metadata.validate() {
             return Err(MapValidationError::Metadata { source: error });
         }
@@ -0,0 +1,235 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use de_gui::{GuiCommands, LabelCommands, OuterStyle};
+
+use crate::{menu::Menu, MenuState};
+
+/// Height of a single credits line in logical pixels.
+const LINE_HEIGHT: f32 = 40.;
+/// Maximum number of credits lines visible at once.
+const VISIBLE_LINES: usize = 8;
+
+pub(crate) struct AboutPlugin;
+
+impl Plugin for AboutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup.in_schedule(OnEnter(MenuState::About)))
+            .add_system(scroll_system.run_if(in_state(MenuState::About)));
+    }
+}
+
+/// Version and build information of the running game, displayed on the about
+/// screen.
+#[derive(Resource, Clone)]
+pub struct BuildInfo {
+    version: String,
+    build: String,
+}
+
+impl BuildInfo {
+    /// # Arguments
+    ///
+    /// * `version` - version of the game, for example
+    ///   `env!("CARGO_PKG_VERSION")`.
+    ///
+    /// * `build` - build identifier, for example hash of the built git commit.
+    pub fn new(version: &str, build: &str) -> Self {
+        Self {
+            version: version.trim().to_owned(),
+            build: build.trim().to_owned(),
+        }
+    }
+
+    fn version_text(&self) -> String {
+        let build = if self.build.is_empty() {
+            "unknown"
+        } else {
+            self.build.as_str()
+        };
+        format!("Version {} (build {build})", self.version)
+    }
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        Self::new(env!("CARGO_PKG_VERSION"), "")
+    }
+}
+
+#[derive(Component)]
+struct CreditsList {
+    offset: f32,
+    max_offset: f32,
+}
+
+fn setup(mut commands: GuiCommands, menu: Res<Menu>, info: Option<Res<BuildInfo>>) {
+    let column_node = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(Val::Percent(50.), Val::Percent(100.)),
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(menu.root_node()).add_child(column_node);
+
+    let info = info.map_or_else(BuildInfo::default, |info| info.clone());
+    let version = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(100.), Val::Px(LINE_HEIGHT)),
+                margin: UiRect::bottom(Val::Px(LINE_HEIGHT)),
+            },
+            info.version_text(),
+        )
+        .id();
+    commands.entity(column_node).add_child(version);
+
+    // Credits lines not fitting into the view are clipped and can be
+    // scrolled with the mouse wheel.
+    let view = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(
+                    Val::Percent(100.),
+                    Val::Px(LINE_HEIGHT * VISIBLE_LINES as f32),
+                ),
+                overflow: Overflow::Hidden,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(column_node).add_child(view);
+
+    let lines = credits();
+    let list = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                position_type: PositionType::Relative,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(CreditsList {
+            offset: 0.,
+            max_offset: LINE_HEIGHT * lines.len().saturating_sub(VISIBLE_LINES) as f32,
+        })
+        .id();
+    commands.entity(view).add_child(list);
+
+    for line in lines {
+        let label = commands
+            .spawn_label(
+                OuterStyle {
+                    size: Size::new(Val::Percent(100.), Val::Px(LINE_HEIGHT)),
+                    ..default()
+                },
+                line,
+            )
+            .id();
+        commands.entity(list).add_child(label);
+    }
+}
+
+fn scroll_system(
+    mut events: EventReader<MouseWheel>,
+    mut lists: Query<(&mut CreditsList, &mut Style)>,
+) {
+    let delta: f32 = events.iter().map(|event| event.y).sum();
+    if delta == 0. {
+        return;
+    }
+
+    for (mut list, mut style) in lists.iter_mut() {
+        list.offset = (list.offset - delta * LINE_HEIGHT).clamp(0., list.max_offset);
+        style.position.top = Val::Px(-list.offset);
+    }
+}
+
+/// Returns lines of the credits list.
+fn credits() -> Vec<String> {
+    let mut lines = vec!["Authors:".to_owned()];
+    lines.extend(
+        env!("CARGO_PKG_AUTHORS")
+            .split(':')
+            .filter(|author| !author.is_empty())
+            .map(|author| author.trim().to_owned()),
+    );
+    lines.push("and all Digital Extinction contributors".to_owned());
+    lines.push(String::new());
+    lines.push("Built with the Bevy game engine".to_owned());
+    lines.push(format!("Licensed under {}", env!("CARGO_PKG_LICENSE")));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{input::InputPlugin, window::WindowPlugin};
+    use de_core::state::AppState;
+    use de_gui::GuiPluginGroup;
+
+    use super::*;
+
+    #[test]
+    fn test_version_text() {
+        assert_eq!(
+            BuildInfo::new("0.1.0", "6ec5d41\n").version_text(),
+            "Version 0.1.0 (build 6ec5d41)"
+        );
+        assert_eq!(
+            BuildInfo::default().version_text(),
+            format!("Version {} (build unknown)", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn test_credits() {
+        let credits = credits();
+        assert_eq!(credits[0], "Authors:");
+        assert_eq!(credits[1], "Martin Indra <martin.indra@mgn.cz>");
+        assert_eq!(credits.last().unwrap(), "Licensed under GPL-3.0");
+    }
+
+    #[test]
+    fn test_setup() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(InputPlugin)
+            .add_plugin(WindowPlugin::default())
+            .add_asset::<Font>()
+            .add_plugins(GuiPluginGroup)
+            .add_state::<AppState>()
+            .add_state::<MenuState>()
+            .insert_resource(BuildInfo::new("0.1.0", "6ec5d41"))
+            .add_plugin(AboutPlugin);
+        let root_node = app.world.spawn(NodeBundle::default()).id();
+        let corner_node = app.world.spawn(NodeBundle::default()).id();
+        app.insert_resource(Menu::new(root_node, corner_node));
+        app.update();
+
+        app.world
+            .resource_mut::<NextState<MenuState>>()
+            .set(MenuState::About);
+        app.update();
+
+        let texts: Vec<String> = app
+            .world
+            .query::<&Text>()
+            .iter(&app.world)
+            .map(|text| text.sections[0].value.clone())
+            .collect();
+        assert!(texts.contains(&"Version 0.1.0 (build 6ec5d41)".to_owned()));
+        for line in credits() {
+            assert!(texts.contains(&line));
+        }
+    }
+}
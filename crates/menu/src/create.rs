@@ -1,15 +1,23 @@
-use bevy::prelude::*;
+use async_std::path::PathBuf;
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use de_core::assets::asset_path;
 use de_gui::{
     ButtonCommands, ButtonOps, GuiCommands, LabelCommands, OuterStyle, TextBoxCommands,
     TextBoxQuery, ToastEvent,
 };
 use de_lobby_client::CreateGameRequest;
 use de_lobby_model::{GameConfig, GameMap, Validatable};
-use de_map::hash::MapHash;
+use de_map::{hash::MapHash, io::MapVerification};
+use futures_lite::future;
 
 use crate::{
     mapselection::{MapSelectedEvent, SelectMapEvent},
     menu::Menu,
+    multiplayer::CurrentGame,
+    presets::{load_preset, presets_dir, store_preset, LoadedPreset, PresetError},
     requests::{Receiver, RequestsPlugin, Sender},
     MenuState,
 };
@@ -20,6 +28,7 @@ impl Plugin for CreateGamePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(RequestsPlugin::<CreateGameRequest>::new())
             .add_event::<CreateGameEvent>()
+            .add_event::<PresetEvent>()
             .add_system(setup.in_schedule(OnEnter(MenuState::GameCreation)))
             .add_system(cleanup.in_schedule(OnExit(MenuState::GameCreation)))
             .add_system(
@@ -39,6 +48,18 @@ impl Plugin for CreateGamePlugin {
                     .after(CreateSet::Buttons)
                     .after(CreateSet::MapSelected),
             )
+            .add_system(
+                preset_system
+                    .run_if(in_state(MenuState::GameCreation))
+                    .run_if(on_event::<PresetEvent>())
+                    .after(CreateSet::Buttons)
+                    .after(CreateSet::MapSelected),
+            )
+            .add_system(
+                preset_task_system
+                    .run_if(in_state(MenuState::GameCreation))
+                    .run_if(resource_exists::<PresetTask>()),
+            )
             .add_system(response_system.run_if(in_state(MenuState::GameCreation)));
     }
 }
@@ -52,6 +73,8 @@ pub(crate) enum CreateSet {
 #[derive(Component, Clone, Copy)]
 enum ButtonAction {
     SelectMap,
+    SavePreset,
+    LoadPreset,
     Create,
 }
 
@@ -60,6 +83,7 @@ struct Inputs {
     name: Entity,
     max_players: Entity,
     map: Entity,
+    preset: Entity,
 }
 
 #[derive(Resource)]
@@ -67,6 +91,19 @@ struct SelectedMap(GameMap);
 
 struct CreateGameEvent;
 
+enum PresetEvent {
+    Save,
+    Load,
+}
+
+enum PresetOutcome {
+    Stored,
+    Loaded(LoadedPreset),
+}
+
+#[derive(Resource)]
+struct PresetTask(Task<Result<PresetOutcome, PresetError>>);
+
 fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
     let column_id = column(&mut commands, menu.root_node());
 
@@ -80,12 +117,34 @@ fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
     let map_row_id = row(&mut commands, column_id);
     let map_id = map_button(&mut commands, map_row_id);
 
+    let preset_row_id = row(&mut commands, column_id);
+    let preset_id = text_input(&mut commands, preset_row_id, "Preset");
+
     commands.insert_resource(Inputs {
         name: name_id,
         max_players: max_players_id,
         map: map_id,
+        preset: preset_id,
     });
 
+    let presets_row_id = row(&mut commands, column_id);
+    for (caption, action) in [
+        ("Save Preset", ButtonAction::SavePreset),
+        ("Load Preset", ButtonAction::LoadPreset),
+    ] {
+        let button_id = commands
+            .spawn_button(
+                OuterStyle {
+                    size: Size::new(Val::Percent(48.), Val::Percent(100.)),
+                    ..default()
+                },
+                caption,
+            )
+            .insert(action)
+            .id();
+        commands.entity(presets_row_id).add_child(button_id);
+    }
+
     let buttons_row_id = row(&mut commands, column_id);
     let create_id = commands
         .spawn_button(
@@ -190,17 +249,21 @@ fn spawn_caption(commands: &mut GuiCommands, parent_id: Entity, caption: &str) {
 fn cleanup(mut commands: GuiCommands) {
     commands.remove_resource::<Inputs>();
     commands.remove_resource::<SelectedMap>();
+    commands.remove_resource::<PresetTask>();
 }
 
 fn button_system(
     interactions: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
     mut map_events: EventWriter<SelectMapEvent>,
     mut create_events: EventWriter<CreateGameEvent>,
+    mut preset_events: EventWriter<PresetEvent>,
 ) {
     for (&interaction, &action) in interactions.iter() {
         if let Interaction::Clicked = interaction {
             match action {
                 ButtonAction::SelectMap => map_events.send(SelectMapEvent),
+                ButtonAction::SavePreset => preset_events.send(PresetEvent::Save),
+                ButtonAction::LoadPreset => preset_events.send(PresetEvent::Load),
                 ButtonAction::Create => create_events.send(CreateGameEvent),
             }
         }
@@ -239,37 +302,133 @@ fn create_game_system(
     mut toasts: EventWriter<ToastEvent>,
     mut sender: Sender<CreateGameRequest>,
 ) {
-    let Some(selected_map) = selected_map else {
-        toasts.send(ToastEvent::new("No map selected."));
-        return;
-    };
+    match game_config(inputs.as_ref(), &texts, selected_map.as_deref()) {
+        Ok(game_config) => sender.send(CreateGameRequest::new(game_config)),
+        Err(error) => toasts.send(ToastEvent::new(error)),
+    }
+}
+
+/// Returns a validated game configuration constructed from the inputs.
+fn game_config(
+    inputs: &Inputs,
+    texts: &TextBoxQuery,
+    selected_map: Option<&SelectedMap>,
+) -> Result<GameConfig, String> {
+    let Some(selected_map) = selected_map else { return Err("No map selected.".to_owned()) };
 
     let name = texts.text(inputs.name).unwrap().to_string();
-    let max_players: u8 = match texts.text(inputs.max_players).unwrap().parse() {
-        Ok(value) => value,
+    let max_players: u8 = texts
+        .text(inputs.max_players)
+        .unwrap()
+        .parse()
+        .map_err(|error| format!("Invalid max players: {error}"))?;
+
+    let game_config = GameConfig::new(name, max_players, selected_map.0.clone());
+    game_config.validate().map_err(|error| format!("{error}"))?;
+    Ok(game_config)
+}
+
+fn preset_system(
+    mut commands: Commands,
+    mut events: EventReader<PresetEvent>,
+    inputs: Res<Inputs>,
+    texts: TextBoxQuery,
+    selected_map: Option<Res<SelectedMap>>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(event) = events.iter().last() else { return };
+    let name = texts.text(inputs.preset).unwrap().to_string();
+
+    let task = match event {
+        PresetEvent::Save => {
+            let game_config = match game_config(inputs.as_ref(), &texts, selected_map.as_deref()) {
+                Ok(game_config) => game_config,
+                Err(error) => {
+                    toasts.send(ToastEvent::new(error));
+                    return;
+                }
+            };
+
+            IoTaskPool::get().spawn(async move {
+                store_preset(&presets_dir()?, &name, &game_config).await?;
+                Ok(PresetOutcome::Stored)
+            })
+        }
+        PresetEvent::Load => IoTaskPool::get().spawn(async move {
+            let maps_dir: PathBuf = asset_path("maps").into();
+            let preset = load_preset(&presets_dir()?, &maps_dir, &name).await?;
+            Ok(PresetOutcome::Loaded(preset))
+        }),
+    };
+
+    commands.insert_resource(PresetTask(task));
+}
+
+fn preset_task_system(
+    mut commands: Commands,
+    mut task: ResMut<PresetTask>,
+    inputs: Res<Inputs>,
+    mut texts: TextBoxQuery,
+    mut buttons: ButtonOps,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<PresetTask>();
+
+    let preset = match result {
+        Ok(PresetOutcome::Stored) => {
+            toasts.send(ToastEvent::new("Preset saved."));
+            return;
+        }
+        Ok(PresetOutcome::Loaded(preset)) => preset,
         Err(error) => {
-            toasts.send(ToastEvent::new(format!("Invalid max players: {error}")));
+            toasts.send(ToastEvent::new(format!("Preset error: {error}")));
             return;
         }
     };
 
-    let game_config = GameConfig::new(name, max_players, selected_map.0.clone());
-    if let Err(error) = game_config.validate() {
-        toasts.send(ToastEvent::new(format!("{error}")));
-        return;
-    }
+    let config = preset.config();
+    texts.set_text(inputs.name, config.name()).unwrap();
+    texts
+        .set_text(inputs.max_players, &config.max_players().to_string())
+        .unwrap();
 
-    sender.send(CreateGameRequest::new(game_config));
+    let map = config.map();
+    match preset.map() {
+        MapVerification::Matching => {
+            buttons.set_text(inputs.map, map.name().to_owned()).unwrap();
+            commands.insert_resource(SelectedMap(map.clone()));
+        }
+        verification => {
+            let reason = match verification {
+                MapVerification::Missing => "is not available locally",
+                _ => "differs from the local version",
+            };
+            toasts.send(ToastEvent::new(format!(
+                "Map \"{}\" of the preset {reason}.",
+                map.name()
+            )));
+            buttons.set_text(inputs.map, "-".to_owned()).unwrap();
+            commands.remove_resource::<SelectedMap>();
+        }
+    }
 }
 
 fn response_system(
+    mut commands: Commands,
     mut next_state: ResMut<NextState<MenuState>>,
+    inputs: Res<Inputs>,
+    texts: TextBoxQuery,
     mut receiver: Receiver<CreateGameRequest>,
     mut toasts: EventWriter<ToastEvent>,
 ) {
     if let Some(result) = receiver.receive() {
         match result {
-            Ok(_) => next_state.set(MenuState::MultiPlayerGame),
+            Ok(_) => {
+                let name = texts.text(inputs.name).unwrap().to_string();
+                commands.insert_resource(CurrentGame::new(name));
+                next_state.set(MenuState::MultiPlayerGame);
+            }
             Err(error) => toasts.send(ToastEvent::new(error)),
         }
     }
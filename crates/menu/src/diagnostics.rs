@@ -0,0 +1,329 @@
+#[cfg(feature = "portmap")]
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use de_gui::{ButtonCommands, GuiCommands, LabelCommands, OuterStyle};
+#[cfg(feature = "portmap")]
+use de_net::check_port_mapping;
+use de_net::{check_bind, check_loopback, BindError, CheckError};
+use futures_lite::future;
+
+use crate::{menu::Menu, MenuState};
+
+pub(crate) struct DiagnosticsPlugin;
+
+impl Plugin for DiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup.in_schedule(OnEnter(MenuState::NetDiagnostics)))
+            .add_system(cleanup.in_schedule(OnExit(MenuState::NetDiagnostics)))
+            .add_system(button_system.run_if(in_state(MenuState::NetDiagnostics)))
+            .add_system(
+                results_system
+                    .run_if(in_state(MenuState::NetDiagnostics))
+                    .run_if(resource_exists::<Checks>()),
+            );
+    }
+}
+
+/// Running network checks. Dropping of the resource cancels the checks.
+#[derive(Resource)]
+struct Checks {
+    bind: Check<u16>,
+    loopback: Check<Duration>,
+    #[cfg(feature = "portmap")]
+    port_mapping: Check<SocketAddrV4>,
+}
+
+impl Checks {
+    fn start(labels: &Labels) -> Self {
+        let pool = IoTaskPool::get();
+        Self {
+            bind: Check::new(labels.bind, pool.spawn(check_bind(None))),
+            loopback: Check::new(labels.loopback, pool.spawn(check_loopback())),
+            #[cfg(feature = "portmap")]
+            port_mapping: Check::new(labels.port_mapping, pool.spawn(check_port_mapping())),
+        }
+    }
+}
+
+struct Check<T> {
+    label: Entity,
+    task: Option<Task<Result<T, CheckError>>>,
+}
+
+impl<T> Check<T> {
+    fn new(label: Entity, task: Task<Result<T, CheckError>>) -> Self {
+        Self {
+            label,
+            task: Some(task),
+        }
+    }
+
+    /// Returns the check result if the check has just finished.
+    fn poll(&mut self) -> Option<Result<T, CheckError>> {
+        let task = self.task.as_mut()?;
+        let result = future::block_on(future::poll_once(task))?;
+        self.task = None;
+        Some(result)
+    }
+}
+
+#[derive(Resource)]
+struct Labels {
+    bind: Entity,
+    loopback: Entity,
+    #[cfg(feature = "portmap")]
+    port_mapping: Entity,
+}
+
+#[derive(Component, Clone, Copy)]
+enum ButtonAction {
+    Rerun,
+}
+
+fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
+    let column_node = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(Val::Percent(50.), Val::Percent(100.)),
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(menu.root_node()).add_child(column_node);
+
+    let labels = Labels {
+        bind: label(&mut commands, column_node, bind_text(None)),
+        loopback: label(&mut commands, column_node, loopback_text(None)),
+        #[cfg(feature = "portmap")]
+        port_mapping: label(&mut commands, column_node, port_mapping_text(None)),
+    };
+
+    let button = commands
+        .spawn_button(
+            OuterStyle {
+                size: Size::new(Val::Percent(50.), Val::Percent(8.)),
+                margin: UiRect::top(Val::Percent(4.)),
+            },
+            "Run Again",
+        )
+        .insert(ButtonAction::Rerun)
+        .id();
+    commands.entity(column_node).add_child(button);
+
+    commands.insert_resource(Checks::start(&labels));
+    commands.insert_resource(labels);
+}
+
+fn label(commands: &mut GuiCommands, parent: Entity, caption: String) -> Entity {
+    let label = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(100.), Val::Percent(8.)),
+                margin: UiRect::bottom(Val::Percent(2.)),
+            },
+            caption,
+        )
+        .id();
+    commands.entity(parent).add_child(label);
+    label
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<Checks>();
+    commands.remove_resource::<Labels>();
+}
+
+fn button_system(
+    mut commands: Commands,
+    interactions: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    labels: Res<Labels>,
+    children: Query<&Children>,
+    mut texts: Query<&mut Text>,
+) {
+    for (&interaction, &action) in interactions.iter() {
+        if let Interaction::Clicked = interaction {
+            match action {
+                ButtonAction::Rerun => {
+                    set_text(labels.bind, bind_text(None), &children, &mut texts);
+                    set_text(labels.loopback, loopback_text(None), &children, &mut texts);
+                    #[cfg(feature = "portmap")]
+                    set_text(
+                        labels.port_mapping,
+                        port_mapping_text(None),
+                        &children,
+                        &mut texts,
+                    );
+                    // Replacing the resource cancels possibly still running
+                    // checks.
+                    commands.insert_resource(Checks::start(labels.as_ref()));
+                }
+            }
+        }
+    }
+}
+
+fn results_system(
+    mut checks: ResMut<Checks>,
+    children: Query<&Children>,
+    mut texts: Query<&mut Text>,
+) {
+    if let Some(result) = checks.bind.poll() {
+        set_text(
+            checks.bind.label,
+            bind_text(Some(result)),
+            &children,
+            &mut texts,
+        );
+    }
+    if let Some(result) = checks.loopback.poll() {
+        set_text(
+            checks.loopback.label,
+            loopback_text(Some(result)),
+            &children,
+            &mut texts,
+        );
+    }
+    #[cfg(feature = "portmap")]
+    if let Some(result) = checks.port_mapping.poll() {
+        set_text(
+            checks.port_mapping.label,
+            port_mapping_text(Some(result)),
+            &children,
+            &mut texts,
+        );
+    }
+}
+
+fn set_text(
+    label: Entity,
+    value: String,
+    children: &Query<&Children>,
+    texts: &mut Query<&mut Text>,
+) {
+    for &child in children.get(label).unwrap().iter() {
+        if let Ok(mut text) = texts.get_mut(child) {
+            text.sections[0].value = value;
+            break;
+        }
+    }
+}
+
+/// Returns a description of a UDP port binding check result or of a running
+/// check if `result` is None.
+fn bind_text(result: Option<Result<u16, CheckError>>) -> String {
+    match result {
+        None => "Port binding: running...".to_owned(),
+        Some(Ok(port)) => format!("Port binding: OK (port {port})"),
+        Some(Err(CheckError::Bind(BindError::AddrInUse(port)))) => format!(
+            "Port binding: failed. Port {port} is already in use, is another instance \
+             running? Try a different port."
+        ),
+        Some(Err(err)) => format!(
+            "Port binding: failed ({err}). A firewall or another application \
+             might be blocking UDP."
+        ),
+    }
+}
+
+/// Returns a description of a loopback round trip check result or of a
+/// running check if `result` is None.
+fn loopback_text(result: Option<Result<Duration, CheckError>>) -> String {
+    match result {
+        None => "Loopback round trip: running...".to_owned(),
+        Some(Ok(rtt)) => format!(
+            "Loopback round trip: OK ({:.1} ms)",
+            rtt.as_secs_f64() * 1000.
+        ),
+        Some(Err(err)) => format!(
+            "Loopback round trip: failed ({err}). UDP datagrams are not \
+             delivered even locally."
+        ),
+    }
+}
+
+/// Returns a description of a port mapping check result or of a running
+/// check if `result` is None.
+#[cfg(feature = "portmap")]
+fn port_mapping_text(result: Option<Result<SocketAddrV4, CheckError>>) -> String {
+    match result {
+        None => "Port mapping: running...".to_owned(),
+        Some(Ok(addr)) => format!(
+            "Port mapping: OK (external address {}). Players outside of the \
+             local network can connect to hosted games.",
+            addr.ip()
+        ),
+        Some(Err(CheckError::PortMap(err))) if err.is_unavailable() => format!(
+            "Port mapping: unavailable ({err}). Forward the game port on your \
+             router manually to host games for players outside of the local network."
+        ),
+        Some(Err(err)) => format!("Port mapping: failed ({err})."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texts() {
+        assert_eq!(bind_text(None), "Port binding: running...");
+        assert_eq!(bind_text(Some(Ok(8082))), "Port binding: OK (port 8082)");
+        assert_eq!(
+            bind_text(Some(Err(CheckError::Timeout))),
+            "Port binding: failed (the check did not finish in time). A firewall or another \
+             application might be blocking UDP."
+        );
+        assert_eq!(
+            bind_text(Some(Err(CheckError::Bind(BindError::AddrInUse(8082))))),
+            "Port binding: failed. Port 8082 is already in use, is another instance \
+             running? Try a different port."
+        );
+
+        assert_eq!(
+            loopback_text(Some(Ok(Duration::from_micros(1340)))),
+            "Loopback round trip: OK (1.3 ms)"
+        );
+        assert_eq!(
+            loopback_text(Some(Err(CheckError::UnexpectedDatagram))),
+            "Loopback round trip: failed (an unexpected datagram was received). UDP datagrams \
+             are not delivered even locally."
+        );
+    }
+
+    #[cfg(feature = "portmap")]
+    #[test]
+    fn test_port_mapping_text() {
+        use de_net::{PortMapError, ResultCode};
+
+        assert_eq!(
+            port_mapping_text(Some(Ok("78.28.42.7:8082".parse().unwrap()))),
+            "Port mapping: OK (external address 78.28.42.7). Players outside of the local \
+             network can connect to hosted games."
+        );
+        assert_eq!(
+            port_mapping_text(Some(Err(CheckError::PortMap(PortMapError::Rejected(
+                ResultCode::NotAuthorized
+            ))))),
+            "Port mapping: unavailable (the gateway rejected the request: port mapping is \
+             disabled). Forward the game port on your router manually to host games for \
+             players outside of the local network."
+        );
+        assert_eq!(
+            port_mapping_text(Some(Err(CheckError::PortMap(PortMapError::Rejected(
+                ResultCode::OutOfResources
+            ))))),
+            "Port mapping: failed (the gateway rejected the request: the gateway is out of \
+             resources)."
+        );
+    }
+}
@@ -0,0 +1,654 @@
+use async_std::path::PathBuf;
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use de_core::{
+    assets::asset_path,
+    objects::{ActiveObjectType, BuildingType, InactiveObjectType},
+    player::{Player, PlayerRange},
+};
+use de_gui::{
+    ButtonCommands, ButtonOps, GuiCommands, LabelCommands, OuterStyle, TextBoxCommands,
+    TextBoxQuery, ToastEvent,
+};
+use de_map::{
+    content::{ActiveObject, InactiveObject, InnerObject, Object},
+    io::{load_map, store_map, MapLoadingError, MapStoringError},
+    map::{Map, MapValidationError},
+    meta::{MapMetadata, MAX_MAP_NAME_LEN},
+    size::MapBounds,
+};
+use futures_lite::future;
+use thiserror::Error;
+
+use crate::{
+    mapindex::MapIndex,
+    mapselection::{MapSelectedEvent, SelectMapEvent},
+    menu::Menu,
+    MenuState,
+};
+
+/// Number of cells along each side of the edited map.
+const GRID_SIZE: usize = 8;
+/// Length of a side of a single grid cell in meters.
+const CELL_SIZE: f32 = 100.;
+
+pub(crate) struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup.in_schedule(OnEnter(MenuState::MapEditor)))
+            .add_system(cleanup.in_schedule(OnExit(MenuState::MapEditor)))
+            .add_system(
+                button_system
+                    .run_if(in_state(MenuState::MapEditor))
+                    .in_set(EditorSet::Input),
+            )
+            .add_system(
+                keyboard_system
+                    .run_if(in_state(MenuState::MapEditor))
+                    .in_set(EditorSet::Input),
+            )
+            .add_system(
+                map_selected_system
+                    .run_if(in_state(MenuState::MapEditor))
+                    .run_if(on_event::<MapSelectedEvent>()),
+            )
+            .add_system(
+                task_system
+                    .run_if(in_state(MenuState::MapEditor))
+                    .run_if(resource_exists::<EditorTask>())
+                    .in_set(EditorSet::Input),
+            )
+            .add_system(
+                grid_system
+                    .run_if(in_state(MenuState::MapEditor))
+                    .after(EditorSet::Input),
+            );
+    }
+}
+
+#[derive(Copy, Clone, Hash, Debug, PartialEq, Eq, SystemSet)]
+enum EditorSet {
+    Input,
+}
+
+/// Content of a single grid cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Tree,
+    /// Starting position (i.e. base) of a player.
+    Start(Player),
+}
+
+impl Cell {
+    fn caption(self) -> String {
+        match self {
+            Self::Empty => String::new(),
+            Self::Tree => "T".to_owned(),
+            Self::Start(player) => player.to_num().to_string(),
+        }
+    }
+
+    fn object(self, map: &Map, position: Vec2) -> Option<Object> {
+        let inner = match self {
+            Self::Empty => return None,
+            Self::Tree => InnerObject::Inactive(InactiveObject::new(InactiveObjectType::Tree)),
+            Self::Start(player) => InnerObject::Active(ActiveObject::new(
+                ActiveObjectType::Building(BuildingType::Base),
+                player,
+            )),
+        };
+        Some(Object::new(map.new_placement(position, 0.), inner))
+    }
+}
+
+/// A single undoable change of a cell.
+#[derive(Clone, Copy, Debug)]
+struct Edit {
+    index: usize,
+    before: Cell,
+    after: Cell,
+}
+
+/// Edited map represented as a square grid of cells.
+#[derive(Resource)]
+struct Grid {
+    cells: Vec<Cell>,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl Grid {
+    fn new() -> Self {
+        Self {
+            cells: vec![Cell::Empty; GRID_SIZE * GRID_SIZE],
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Creates a grid from a map. Each object is snapped to the cell
+    /// containing its position, objects other than trees and bases are
+    /// ignored.
+    fn from_map(map: &Map) -> Self {
+        let mut grid = Self::new();
+        let bounds = map.metadata().bounds();
+
+        for object in map.content().objects() {
+            let cell = match object.inner() {
+                InnerObject::Inactive(inactive) => match inactive.object_type() {
+                    InactiveObjectType::Tree => Cell::Tree,
+                },
+                InnerObject::Active(active) => match active.object_type() {
+                    ActiveObjectType::Building(BuildingType::Base) => Cell::Start(active.player()),
+                    _ => continue,
+                },
+            };
+
+            let relative = (object.placement().position() - bounds.min()) / bounds.size();
+            let [x, y] = relative
+                .to_array()
+                .map(|coord| ((coord * GRID_SIZE as f32) as usize).min(GRID_SIZE - 1));
+            grid.cells[Self::index(x, y)] = cell;
+        }
+
+        grid
+    }
+
+    fn index(x: usize, y: usize) -> usize {
+        assert!(x < GRID_SIZE);
+        assert!(y < GRID_SIZE);
+        y * GRID_SIZE + x
+    }
+
+    /// Returns content of a cell. Cell (0, 0) is the south-west corner of
+    /// the map.
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        self.cells[Self::index(x, y)]
+    }
+
+    /// Changes content of a cell. Returns false if the cell already had the
+    /// content.
+    fn place(&mut self, x: usize, y: usize, cell: Cell) -> bool {
+        let index = Self::index(x, y);
+        let before = self.cells[index];
+        if before == cell {
+            return false;
+        }
+
+        self.cells[index] = cell;
+        self.undo.push(Edit {
+            index,
+            before,
+            after: cell,
+        });
+        self.redo.clear();
+        true
+    }
+
+    /// Reverts the last edit. Returns false if there is nothing to undo.
+    fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo.pop() else { return false };
+        self.cells[edit.index] = edit.before;
+        self.redo.push(edit);
+        true
+    }
+
+    /// Re-applies the last undone edit. Returns false if there is nothing to
+    /// redo.
+    fn redo(&mut self) -> bool {
+        let Some(edit) = self.redo.pop() else { return false };
+        self.cells[edit.index] = edit.after;
+        self.undo.push(edit);
+        true
+    }
+
+    /// Creates a validated map from the grid.
+    ///
+    /// The maximum number of players is given by the highest placed player
+    /// start. All players up to it must have exactly one start.
+    fn to_map(&self, name: &str) -> Result<Map, EditorError> {
+        if name.is_empty() || name.len() > MAX_MAP_NAME_LEN {
+            return Err(EditorError::Name);
+        }
+
+        let max_player = self
+            .cells
+            .iter()
+            .filter_map(|cell| match cell {
+                Cell::Start(player) => Some(*player),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(Player::Player1)
+            .max(Player::Player2);
+        for player in PlayerRange::up_to(max_player) {
+            let starts = self
+                .cells
+                .iter()
+                .filter(|&&cell| cell == Cell::Start(player))
+                .count();
+            if starts != 1 {
+                return Err(EditorError::Starts { player, starts });
+            }
+        }
+
+        let bounds = MapBounds::new(Vec2::splat(GRID_SIZE as f32 * CELL_SIZE));
+        let mut map = Map::empty(MapMetadata::new(name.to_owned(), bounds, max_player));
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                let relative = (Vec2::new(x as f32, y as f32) + 0.5) / GRID_SIZE as f32;
+                let position = bounds.rel_to_abs(relative);
+                if let Some(object) = self.cell(x, y).object(&map, position) {
+                    map.insert_object(object);
+                }
+            }
+        }
+
+        map.validate()?;
+        Ok(map)
+    }
+}
+
+#[derive(Error, Debug)]
+enum EditorError {
+    #[error("map name must have between 1 and {MAX_MAP_NAME_LEN} characters")]
+    Name,
+    #[error("{player} must have exactly one start, got {starts}")]
+    Starts { player: Player, starts: usize },
+    #[error(transparent)]
+    Validation(#[from] MapValidationError),
+    #[error(transparent)]
+    Loading(#[from] MapLoadingError),
+    #[error(transparent)]
+    Storing(#[from] MapStoringError),
+}
+
+/// Grid cell position of the keyboard cursor.
+#[derive(Resource, Default)]
+struct Cursor {
+    x: usize,
+    y: usize,
+}
+
+/// Content placed by clicking a cell or by pressing Enter.
+#[derive(Resource)]
+struct Tool(Cell);
+
+#[derive(Resource)]
+struct NameInput(Entity);
+
+#[derive(Component, Clone, Copy)]
+enum ButtonAction {
+    Cell(usize, usize),
+    Tool(Cell),
+    Open,
+    Undo,
+    Redo,
+    Save,
+}
+
+enum EditorOutcome {
+    Loaded(Map),
+    Stored(PathBuf, MapMetadata),
+}
+
+#[derive(Resource)]
+struct EditorTask(Task<Result<EditorOutcome, EditorError>>);
+
+fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
+    let column_id = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(Val::Percent(50.), Val::Percent(100.)),
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(menu.root_node()).add_child(column_id);
+
+    let name_row_id = row(&mut commands, column_id, 8.);
+    let caption_id = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(35.), Val::Percent(100.)),
+                ..default()
+            },
+            "Name",
+        )
+        .id();
+    commands.entity(name_row_id).add_child(caption_id);
+    let name_id = commands
+        .spawn_text_box(
+            OuterStyle {
+                size: Size::new(Val::Percent(65.), Val::Percent(100.)),
+                ..default()
+            },
+            false,
+        )
+        .id();
+    commands.entity(name_row_id).add_child(name_id);
+
+    // North is at the top.
+    for y in (0..GRID_SIZE).rev() {
+        let row_id = row(&mut commands, column_id, 6.);
+        for x in 0..GRID_SIZE {
+            button(
+                &mut commands,
+                row_id,
+                ButtonAction::Cell(x, y),
+                "",
+                100. / GRID_SIZE as f32 - 1.,
+            );
+        }
+    }
+
+    let tools_row_id = row(&mut commands, column_id, 8.);
+    let mut tools = vec![
+        ("Erase".to_owned(), Cell::Empty),
+        ("Tree".to_owned(), Cell::Tree),
+    ];
+    tools.extend(
+        PlayerRange::up_to(Player::Player4)
+            .map(|player| (format!("P{}", player.to_num()), Cell::Start(player))),
+    );
+    let width = 100. / tools.len() as f32 - 1.;
+    for (caption, cell) in tools {
+        button(
+            &mut commands,
+            tools_row_id,
+            ButtonAction::Tool(cell),
+            &caption,
+            width,
+        );
+    }
+
+    let actions_row_id = row(&mut commands, column_id, 8.);
+    for (caption, action) in [
+        ("Open", ButtonAction::Open),
+        ("Undo", ButtonAction::Undo),
+        ("Redo", ButtonAction::Redo),
+        ("Save", ButtonAction::Save),
+    ] {
+        button(&mut commands, actions_row_id, action, caption, 24.);
+    }
+
+    commands.insert_resource(Grid::new());
+    commands.insert_resource(Cursor::default());
+    commands.insert_resource(Tool(Cell::Tree));
+    commands.insert_resource(NameInput(name_id));
+}
+
+fn row(commands: &mut GuiCommands, parent_id: Entity, height: f32) -> Entity {
+    let row_id = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                size: Size::new(Val::Percent(100.), Val::Percent(height)),
+                margin: UiRect::new(
+                    Val::Percent(0.),
+                    Val::Percent(0.),
+                    Val::Percent(0.5),
+                    Val::Percent(0.5),
+                ),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(parent_id).add_child(row_id);
+    row_id
+}
+
+fn button(
+    commands: &mut GuiCommands,
+    parent_id: Entity,
+    action: ButtonAction,
+    caption: &str,
+    width: f32,
+) {
+    let button_id = commands
+        .spawn_button(
+            OuterStyle {
+                size: Size::new(Val::Percent(width), Val::Percent(100.)),
+                ..default()
+            },
+            caption,
+        )
+        .insert(action)
+        .id();
+    commands.entity(parent_id).add_child(button_id);
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<Grid>();
+    commands.remove_resource::<Cursor>();
+    commands.remove_resource::<Tool>();
+    commands.remove_resource::<NameInput>();
+    commands.remove_resource::<EditorTask>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn button_system(
+    mut commands: Commands,
+    interactions: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    mut grid: ResMut<Grid>,
+    mut cursor: ResMut<Cursor>,
+    mut tool: ResMut<Tool>,
+    name: Res<NameInput>,
+    texts: TextBoxQuery,
+    mut map_events: EventWriter<SelectMapEvent>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    for (&interaction, &action) in interactions.iter() {
+        if !matches!(interaction, Interaction::Clicked) {
+            continue;
+        }
+
+        match action {
+            ButtonAction::Cell(x, y) => {
+                *cursor = Cursor { x, y };
+                grid.place(x, y, tool.0);
+            }
+            ButtonAction::Tool(cell) => tool.0 = cell,
+            ButtonAction::Open => map_events.send(SelectMapEvent),
+            ButtonAction::Undo => {
+                grid.undo();
+            }
+            ButtonAction::Redo => {
+                grid.redo();
+            }
+            ButtonAction::Save => {
+                let map_name = texts.text(name.0).unwrap();
+                match grid.to_map(&map_name) {
+                    Ok(map) => {
+                        let task = IoTaskPool::get().spawn(async move {
+                            let path = map.compute_hash().construct_path(asset_path("maps"));
+                            store_map(&map, &path).await?;
+                            Ok(EditorOutcome::Stored(path, map.metadata().clone()))
+                        });
+                        commands.insert_resource(EditorTask(task));
+                    }
+                    Err(error) => toasts.send(ToastEvent::new(format!("Invalid map: {error}"))),
+                }
+            }
+        }
+    }
+}
+
+/// Moves the cursor with arrow keys, places the current tool with Enter and
+/// undoes / redoes edits with Ctrl+Z / Ctrl+Y.
+fn keyboard_system(
+    keys: Res<Input<KeyCode>>,
+    mut grid: ResMut<Grid>,
+    mut cursor: ResMut<Cursor>,
+    tool: Res<Tool>,
+) {
+    let max = GRID_SIZE - 1;
+    for &key in keys.get_just_pressed() {
+        match key {
+            KeyCode::Left => cursor.x = cursor.x.saturating_sub(1),
+            KeyCode::Right => cursor.x = (cursor.x + 1).min(max),
+            KeyCode::Down => cursor.y = cursor.y.saturating_sub(1),
+            KeyCode::Up => cursor.y = (cursor.y + 1).min(max),
+            KeyCode::Return => {
+                grid.place(cursor.x, cursor.y, tool.0);
+            }
+            KeyCode::Z if ctrl_pressed(&keys) => {
+                grid.undo();
+            }
+            KeyCode::Y if ctrl_pressed(&keys) => {
+                grid.redo();
+            }
+            _ => (),
+        }
+    }
+}
+
+fn ctrl_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::LControl, KeyCode::RControl])
+}
+
+fn map_selected_system(mut commands: Commands, mut events: EventReader<MapSelectedEvent>) {
+    let Some(event) = events.iter().last() else { return };
+    let path: PathBuf = event.path().into();
+    let task = IoTaskPool::get().spawn(async move {
+        let map = load_map(path).await?;
+        Ok(EditorOutcome::Loaded(map))
+    });
+    commands.insert_resource(EditorTask(task));
+}
+
+fn task_system(
+    mut commands: Commands,
+    mut task: ResMut<EditorTask>,
+    name: Res<NameInput>,
+    mut texts: TextBoxQuery,
+    mut toasts: EventWriter<ToastEvent>,
+    index: Option<ResMut<MapIndex>>,
+) {
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<EditorTask>();
+
+    match result {
+        Ok(EditorOutcome::Loaded(map)) => {
+            texts.set_text(name.0, map.metadata().name()).unwrap();
+            commands.insert_resource(Grid::from_map(&map));
+        }
+        Ok(EditorOutcome::Stored(path, metadata)) => {
+            // The map is offered in map selection right away.
+            if let Some(mut index) = index {
+                index.insert(path.into(), metadata);
+            }
+            toasts.send(ToastEvent::new("Map saved."));
+        }
+        Err(error) => toasts.send(ToastEvent::new(format!("Map error: {error}"))),
+    }
+}
+
+/// Updates captions of the grid cells. The cell under the cursor is
+/// surrounded by brackets.
+fn grid_system(
+    grid: Res<Grid>,
+    cursor: Res<Cursor>,
+    cells: Query<(Entity, &ButtonAction)>,
+    mut buttons: ButtonOps,
+) {
+    if !grid.is_changed() && !cursor.is_changed() {
+        return;
+    }
+
+    for (entity, &action) in cells.iter() {
+        let ButtonAction::Cell(x, y) = action else { continue };
+        let caption = grid.cell(x, y).caption();
+        let caption = if cursor.x == x && cursor.y == y {
+            format!("[{caption}]")
+        } else {
+            caption
+        };
+        buttons.set_text(entity, caption).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut grid = Grid::new();
+        assert!(!grid.undo());
+        assert!(!grid.redo());
+
+        assert!(grid.place(1, 2, Cell::Tree));
+        assert!(!grid.place(1, 2, Cell::Tree));
+        assert!(grid.place(1, 2, Cell::Start(Player::Player1)));
+        assert!(grid.place(3, 0, Cell::Tree));
+
+        assert!(grid.undo());
+        assert_eq!(grid.cell(3, 0), Cell::Empty);
+        assert!(grid.undo());
+        assert_eq!(grid.cell(1, 2), Cell::Tree);
+
+        assert!(grid.redo());
+        assert_eq!(grid.cell(1, 2), Cell::Start(Player::Player1));
+
+        // A new edit discards the undone ones.
+        assert!(grid.place(0, 0, Cell::Tree));
+        assert!(!grid.redo());
+        assert_eq!(grid.cell(3, 0), Cell::Empty);
+
+        assert!(grid.undo());
+        assert!(grid.undo());
+        assert!(grid.undo());
+        assert!(!grid.undo());
+        assert!(grid.cells.iter().all(|&cell| cell == Cell::Empty));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut grid = Grid::new();
+        grid.place(0, 0, Cell::Start(Player::Player1));
+        grid.place(7, 7, Cell::Start(Player::Player2));
+        assert!(matches!(grid.to_map(""), Err(EditorError::Name)));
+
+        grid.place(7, 0, Cell::Start(Player::Player3));
+        grid.place(7, 4, Cell::Start(Player::Player3));
+        assert!(matches!(
+            grid.to_map("Test"),
+            Err(EditorError::Starts {
+                player: Player::Player3,
+                starts: 2
+            })
+        ));
+        grid.place(7, 4, Cell::Tree);
+        grid.place(3, 5, Cell::Tree);
+
+        let map = grid.to_map("Test").unwrap();
+        assert_eq!(map.metadata().name(), "Test");
+        assert_eq!(map.metadata().max_player(), Player::Player3);
+        assert_eq!(map.content().objects().len(), 5);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = map.compute_hash().construct_path(dir.path());
+        // Loading validates the map.
+        let loaded = task::block_on(async {
+            store_map(&map, &path).await.unwrap();
+            load_map(&path).await.unwrap()
+        });
+        assert_eq!(loaded.compute_hash(), map.compute_hash());
+        assert_eq!(Grid::from_map(&loaded).cells, grid.cells);
+    }
+}
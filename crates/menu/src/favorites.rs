@@ -0,0 +1,174 @@
+use async_std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use bevy::prelude::Resource;
+use de_core::fs::{conf_dir, DirError};
+use de_lobby_model::GamePartial;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::versioned::{decode, encode, Versioned, VersionedError};
+
+/// Returns the path of the file where favorite games are stored.
+pub(crate) fn favorites_path() -> Result<PathBuf, DirError> {
+    conf_dir().map(|d| d.join("favorites.json"))
+}
+
+/// Names of games starred by the player. The names are kept in the order in
+/// which they were starred.
+#[derive(Resource, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Favorites(Vec<String>);
+
+impl Favorites {
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|favorite| favorite == name)
+    }
+
+    /// Stars a game. It is a no-op if the game is already starred.
+    pub(crate) fn add(&mut self, name: String) {
+        if !self.contains(&name) {
+            self.0.push(name);
+        }
+    }
+
+    /// Un-stars a game. It is a no-op if the game is not starred.
+    pub(crate) fn remove(&mut self, name: &str) {
+        self.0.retain(|favorite| favorite != name);
+    }
+
+    /// Splits listed games to pinned favorites and other games.
+    ///
+    /// All favorites are returned in the order of starring. Favorites not
+    /// present among `games` are offline and are returned with None.
+    pub(crate) fn split<'a>(
+        &'a self,
+        games: &'a [GamePartial],
+    ) -> (
+        Vec<(&'a str, Option<&'a GamePartial>)>,
+        Vec<&'a GamePartial>,
+    ) {
+        let pinned = self
+            .0
+            .iter()
+            .map(|name| {
+                let game = games.iter().find(|game| game.config().name() == name);
+                (name.as_str(), game)
+            })
+            .collect();
+        let others = games
+            .iter()
+            .filter(|game| !self.contains(game.config().name()))
+            .collect();
+        (pinned, others)
+    }
+}
+
+impl Versioned for Favorites {
+    const VERSION: u32 = 2;
+}
+
+/// Stores favorite games to a file. Previously stored favorites are
+/// overwritten.
+pub(crate) async fn store_favorites(
+    path: &Path,
+    favorites: &Favorites,
+) -> Result<(), FavoritesError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let json = encode(favorites)?;
+    fs::write(path, json).await?;
+    Ok(())
+}
+
+/// Loads favorite games from a file. No favorites are returned if the file
+/// does not exist.
+pub(crate) async fn load_favorites(path: &Path) -> Result<Favorites, FavoritesError> {
+    let json = match fs::read(path).await {
+        Ok(json) => json,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Favorites::default()),
+        Err(error) => return Err(error.into()),
+    };
+    let names: Favorites = decode(&json)?;
+    let mut favorites = Favorites::default();
+    for name in names.0 {
+        favorites.add(name);
+    }
+    Ok(favorites)
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum FavoritesError {
+    #[error(transparent)]
+    Dir(#[from] DirError),
+    #[error("favorites I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid favorites format: {0}")]
+    Format(#[from] VersionedError),
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+    use de_lobby_model::{GameConfig, GameMap};
+    use tempfile::Builder;
+
+    use super::*;
+
+    fn game(name: &str) -> GamePartial {
+        let config = GameConfig::new(
+            name.to_owned(),
+            2,
+            GameMap::new("0".repeat(64), "Test Map".into()),
+        );
+        GamePartial::new(config, 1)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tmp_dir = Builder::new().prefix("de_menu_").tempdir().unwrap();
+        let path = PathBuf::from(tmp_dir.path())
+            .join("conf")
+            .join("favorites.json");
+
+        task::block_on(async {
+            let mut favorites = load_favorites(&path).await.unwrap();
+            assert_eq!(favorites, Favorites::default());
+
+            favorites.add("Tournament".into());
+            favorites.add("Offline".into());
+            favorites.add("Friday".into());
+            favorites.add("Tournament".into());
+            store_favorites(&path, &favorites).await.unwrap();
+
+            let mut loaded = load_favorites(&path).await.unwrap();
+            assert_eq!(loaded, favorites);
+
+            loaded.remove("Friday");
+            store_favorites(&path, &loaded).await.unwrap();
+            let loaded = load_favorites(&path).await.unwrap();
+            assert!(loaded.contains("Tournament"));
+            assert!(loaded.contains("Offline"));
+            assert!(!loaded.contains("Friday"));
+
+            let games = vec![game("Random"), game("Tournament")];
+            let (pinned, others) = loaded.split(&games);
+            let pinned: Vec<(&str, bool)> = pinned
+                .iter()
+                .map(|(name, game)| (*name, game.is_some()))
+                .collect();
+            assert_eq!(pinned, vec![("Tournament", true), ("Offline", false)]);
+            let others: Vec<&str> = others.iter().map(|game| game.config().name()).collect();
+            assert_eq!(others, vec!["Random"]);
+
+            // Favorites stored before the format was versioned.
+            fs::write(&path, br#"["Tournament", "Friday"]"#)
+                .await
+                .unwrap();
+            let loaded = load_favorites(&path).await.unwrap();
+            assert!(loaded.contains("Tournament"));
+            assert!(loaded.contains("Friday"));
+        });
+    }
+}
@@ -1,44 +1,97 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, time::Stopwatch};
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+    time::Stopwatch,
+};
+use de_core::assets::asset_path;
 use de_gui::{ButtonCommands, GuiCommands, LabelCommands, OuterStyle, ToastEvent, ToastSet};
-use de_lobby_client::{ListGamesRequest, RequestEvent, ResponseEvent};
+use de_lobby_client::{JoinGameRequest, ListGamesRequest, RequestEvent, ResponseEvent};
 use de_lobby_model::GamePartial;
+use de_map::{
+    hash::MapHash,
+    io::{verify_map, MapLoadingError, MapVerification},
+};
+use futures_lite::future;
 
-use crate::{menu::Menu, MenuState};
+use crate::{
+    favorites::{favorites_path, load_favorites, store_favorites, Favorites, FavoritesError},
+    menu::Menu,
+    multiplayer::CurrentGame,
+    requests::{Receiver, RequestsPlugin, Sender},
+    MenuState,
+};
 
 const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+const OFFLINE_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 0.3);
 
 pub(crate) struct GameListingPlugin;
 
 impl Plugin for GameListingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(setup.in_schedule(OnEnter(MenuState::GameListing)))
+        app.add_plugin(RequestsPlugin::<JoinGameRequest>::new())
+            .add_system(setup.in_schedule(OnEnter(MenuState::GameListing)))
             .add_system(cleanup.in_schedule(OnExit(MenuState::GameListing)))
             .add_system(refresh_system.run_if(in_state(MenuState::GameListing)))
+            .add_system(
+                load_favorites_system
+                    .run_if(in_state(MenuState::GameListing))
+                    .before(ToastSet::ProcessEvents),
+            )
+            .add_system(
+                store_favorites_system
+                    .run_if(in_state(MenuState::GameListing))
+                    .before(ToastSet::ProcessEvents),
+            )
             .add_system(
                 list_games_system
                     .run_if(in_state(MenuState::GameListing))
                     .before(ToastSet::ProcessEvents),
             )
-            .add_system(button_system.run_if(in_state(MenuState::GameListing)));
+            .add_system(button_system.run_if(in_state(MenuState::GameListing)))
+            .add_system(verification_system.run_if(in_state(MenuState::GameListing)))
+            .add_system(join_response_system.run_if(in_state(MenuState::GameListing)));
     }
 }
 
 #[derive(Resource)]
 struct GamesTable(Entity);
 
+/// Pending loading of favorite games. Games are listed only after it
+/// finishes so that starred games are pinned from the beginning.
+#[derive(Resource)]
+struct LoadFavoritesTask(Task<Result<Favorites, FavoritesError>>);
+
+/// Pending storing of favorite games after a game was (un-)starred.
+#[derive(Resource)]
+struct StoreFavoritesTask(Task<Result<(), FavoritesError>>);
+
+/// Name of a game whose joining was requested.
+#[derive(Resource)]
+struct JoiningGame(String);
+
+/// Pending verification of the local copy of a map of a game which is about
+/// to be joined.
+#[derive(Resource)]
+struct MapVerificationTask {
+    game: String,
+    map_name: String,
+    task: Task<Result<MapVerification, MapLoadingError>>,
+}
+
 #[derive(Component)]
 enum ButtonAction {
     Create,
-    Join,
+    ToggleFavorite(String),
+    Join {
+        game: String,
+        map_name: String,
+        map_hash: String,
+    },
 }
 
-fn setup(
-    mut commands: GuiCommands,
-    menu: Res<Menu>,
-    mut requests: EventWriter<RequestEvent<ListGamesRequest>>,
-) {
+fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
     let column_id = commands
         .spawn(NodeBundle {
             style: Style {
@@ -57,11 +110,20 @@ fn setup(
     create_game_button(&mut commands, column_id);
     let table_id = table(&mut commands, column_id);
     commands.insert_resource(GamesTable(table_id));
-    requests.send(RequestEvent::new("list-games", ListGamesRequest));
+
+    let task = IoTaskPool::get().spawn(async {
+        let path = favorites_path()?;
+        load_favorites(&path).await
+    });
+    commands.insert_resource(LoadFavoritesTask(task));
 }
 
 fn cleanup(mut commands: Commands) {
     commands.remove_resource::<GamesTable>();
+    commands.remove_resource::<LoadFavoritesTask>();
+    commands.remove_resource::<Favorites>();
+    commands.remove_resource::<MapVerificationTask>();
+    commands.remove_resource::<JoiningGame>();
 }
 
 fn create_game_button(commands: &mut GuiCommands, parent_node: Entity) {
@@ -96,25 +158,13 @@ fn table(commands: &mut GuiCommands, parent_node: Entity) -> Entity {
     table_id
 }
 
-fn row(commands: &mut GuiCommands, game: &GamePartial) -> Entity {
-    let row_id = commands
-        .spawn(NodeBundle {
-            style: Style {
-                flex_direction: FlexDirection::Row,
-                size: Size::new(Val::Percent(100.), Val::Percent(8.)),
-                margin: UiRect::vertical(Val::Percent(0.5)),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::FlexStart,
-                ..default()
-            },
-            ..default()
-        })
-        .id();
+fn row(commands: &mut GuiCommands, game: &GamePartial, favorite: bool) -> Entity {
+    let row_id = row_node(commands);
 
     let name_id = commands
         .spawn_label(
             OuterStyle {
-                size: Size::new(Val::Percent(80.), Val::Percent(100.)),
+                size: Size::new(Val::Percent(60.), Val::Percent(100.)),
                 margin: UiRect::right(Val::Percent(2.)),
             },
             format!(
@@ -127,6 +177,9 @@ fn row(commands: &mut GuiCommands, game: &GamePartial) -> Entity {
         .id();
     commands.entity(row_id).add_child(name_id);
 
+    let favorite_id = favorite_button(commands, game.config().name(), favorite);
+    commands.entity(row_id).add_child(favorite_id);
+
     if game.num_players() < game.config().max_players() {
         let button_id = commands
             .spawn_button(
@@ -136,7 +189,11 @@ fn row(commands: &mut GuiCommands, game: &GamePartial) -> Entity {
                 },
                 "Join",
             )
-            .insert(ButtonAction::Join)
+            .insert(ButtonAction::Join {
+                game: game.config().name().to_owned(),
+                map_name: game.config().map().name().to_owned(),
+                map_hash: game.config().map().hash().to_owned(),
+            })
             .id();
         commands.entity(row_id).add_child(button_id);
     }
@@ -144,6 +201,58 @@ fn row(commands: &mut GuiCommands, game: &GamePartial) -> Entity {
     row_id
 }
 
+/// Spawns a greyed out row of a favorite game which is not listed by the
+/// lobby server.
+fn offline_row(commands: &mut GuiCommands, name: &str) -> Entity {
+    let row_id = row_node(commands);
+
+    let name_id = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(60.), Val::Percent(100.)),
+                margin: UiRect::right(Val::Percent(2.)),
+            },
+            format!("{name} (offline)"),
+        )
+        .insert(BackgroundColor(OFFLINE_COLOR))
+        .id();
+    commands.entity(row_id).add_child(name_id);
+
+    let favorite_id = favorite_button(commands, name, true);
+    commands.entity(row_id).add_child(favorite_id);
+
+    row_id
+}
+
+fn favorite_button(commands: &mut GuiCommands, name: &str, favorite: bool) -> Entity {
+    commands
+        .spawn_button(
+            OuterStyle {
+                size: Size::new(Val::Percent(18.), Val::Percent(100.)),
+                margin: UiRect::right(Val::Percent(2.)),
+            },
+            if favorite { "Unstar" } else { "Star" },
+        )
+        .insert(ButtonAction::ToggleFavorite(name.to_owned()))
+        .id()
+}
+
+fn row_node(commands: &mut GuiCommands) -> Entity {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                size: Size::new(Val::Percent(100.), Val::Percent(8.)),
+                margin: UiRect::vertical(Val::Percent(0.5)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::FlexStart,
+                ..default()
+            },
+            ..default()
+        })
+        .id()
+}
+
 fn refresh_system(
     time: Res<Time>,
     mut stopwatch: Local<Stopwatch>,
@@ -156,19 +265,65 @@ fn refresh_system(
     }
 }
 
+fn load_favorites_system(
+    mut commands: Commands,
+    task: Option<ResMut<LoadFavoritesTask>>,
+    mut requests: EventWriter<RequestEvent<ListGamesRequest>>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(mut task) = task else { return };
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<LoadFavoritesTask>();
+
+    let favorites = match result {
+        Ok(favorites) => favorites,
+        Err(error) => {
+            toasts.send(ToastEvent::new(format!("Favorites loading error: {error}")));
+            Favorites::default()
+        }
+    };
+    commands.insert_resource(favorites);
+    // Listing of the games refreshes availability of the favorites.
+    requests.send(RequestEvent::new("list-games", ListGamesRequest));
+}
+
+fn store_favorites_system(
+    mut commands: Commands,
+    task: Option<ResMut<StoreFavoritesTask>>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(mut task) = task else { return };
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<StoreFavoritesTask>();
+
+    if let Err(error) = result {
+        toasts.send(ToastEvent::new(format!("Favorites storing error: {error}")));
+    }
+}
+
 fn list_games_system(
     mut commands: GuiCommands,
     table: Res<GamesTable>,
+    favorites: Option<Res<Favorites>>,
     mut events: EventReader<ResponseEvent<ListGamesRequest>>,
     mut toasts: EventWriter<ToastEvent>,
 ) {
     let Some(event) = events.iter().last() else { return };
+    let Some(favorites) = favorites else { return };
     commands.entity(table.0).despawn_descendants();
 
     match event.result() {
         Ok(games) => {
-            for game in games.games() {
-                let row_id = row(&mut commands, game);
+            let (pinned, others) = favorites.split(games.games());
+            for (name, game) in pinned {
+                let row_id = match game {
+                    Some(game) => row(&mut commands, game, true),
+                    None => offline_row(&mut commands, name),
+                };
+                commands.entity(table.0).add_child(row_id);
+            }
+            for game in others {
+                let row_id = row(&mut commands, game, false);
                 commands.entity(table.0).add_child(row_id);
             }
         }
@@ -177,18 +332,106 @@ fn list_games_system(
 }
 
 fn button_system(
+    mut commands: Commands,
     mut next_state: ResMut<NextState<MenuState>>,
+    mut favorites: Option<ResMut<Favorites>>,
     interactions: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    mut requests: EventWriter<RequestEvent<ListGamesRequest>>,
     mut toasts: EventWriter<ToastEvent>,
 ) {
     for (&interaction, action) in interactions.iter() {
         if let Interaction::Clicked = interaction {
             match action {
                 ButtonAction::Create => next_state.set(MenuState::GameCreation),
-                ButtonAction::Join => {
-                    toasts.send(ToastEvent::new("Not yet implemented (issue #301)."))
+                ButtonAction::ToggleFavorite(name) => {
+                    let Some(favorites) = favorites.as_mut() else { continue };
+                    if favorites.contains(name) {
+                        favorites.remove(name);
+                    } else {
+                        favorites.add(name.to_owned());
+                    }
+
+                    let favorites = Favorites::clone(favorites);
+                    let task = IoTaskPool::get().spawn(async move {
+                        let path = favorites_path()?;
+                        store_favorites(&path, &favorites).await
+                    });
+                    commands.insert_resource(StoreFavoritesTask(task));
+                    requests.send(RequestEvent::new("list-games", ListGamesRequest));
+                }
+                ButtonAction::Join {
+                    game,
+                    map_name,
+                    map_hash,
+                } => {
+                    let hash = match MapHash::from_hex(map_hash) {
+                        Ok(hash) => hash,
+                        Err(error) => {
+                            toasts.send(ToastEvent::new(format!("Invalid map hash: {error}")));
+                            continue;
+                        }
+                    };
+
+                    let task = IoTaskPool::get()
+                        .spawn(async move { verify_map(asset_path("maps"), &hash).await });
+                    commands.insert_resource(MapVerificationTask {
+                        game: game.to_owned(),
+                        map_name: map_name.to_owned(),
+                        task,
+                    });
                 }
             }
         }
     }
 }
+
+/// Joins the game only after the local copy of its map has been verified to
+/// match the map used by the game. This prevents desyncs caused by different
+/// versions of a map.
+fn verification_system(
+    mut commands: Commands,
+    task: Option<ResMut<MapVerificationTask>>,
+    mut sender: Sender<JoinGameRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(mut task) = task else { return };
+    let Some(result) = future::block_on(future::poll_once(&mut task.task)) else {
+        return
+    };
+    commands.remove_resource::<MapVerificationTask>();
+
+    match result {
+        Ok(MapVerification::Matching) => {
+            commands.insert_resource(JoiningGame(task.game.clone()));
+            sender.send(JoinGameRequest::new(task.game.clone()));
+        }
+        Ok(MapVerification::Mismatching) => toasts.send(ToastEvent::new(format!(
+            "Your copy of map \"{}\" differs from the map used by the game.",
+            task.map_name
+        ))),
+        Ok(MapVerification::Missing) => toasts.send(ToastEvent::new(format!(
+            "Map \"{}\" used by the game is not available.",
+            task.map_name
+        ))),
+        Err(error) => toasts.send(ToastEvent::new(format!("Map verification error: {error}"))),
+    }
+}
+
+fn join_response_system(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<MenuState>>,
+    joining: Option<Res<JoiningGame>>,
+    mut receiver: Receiver<JoinGameRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    if let Some(result) = receiver.receive() {
+        match result {
+            Ok(_) => {
+                let Some(joining) = joining else { return };
+                commands.insert_resource(CurrentGame::new(joining.0.clone()));
+                next_state.set(MenuState::MultiPlayerGame);
+            }
+            Err(error) => toasts.send(ToastEvent::new(error)),
+        }
+    }
+}
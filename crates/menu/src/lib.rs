@@ -1,3 +1,5 @@
+use about::AboutPlugin;
+pub use about::BuildInfo;
 use aftergame::AfterGamePlugin;
 use bevy::{app::PluginGroupBuilder, prelude::*};
 use create::CreateGamePlugin;
@@ -6,22 +8,40 @@ use de_core::{
     state::AppState,
     transition::{DeStateTransition, StateWithSet},
 };
+use diagnostics::DiagnosticsPlugin;
+use editor::EditorPlugin;
 use gamelisting::GameListingPlugin;
 use mainmenu::MainMenuPlugin;
+use mapindex::MapIndexPlugin;
 use mapselection::MapSelectionPlugin;
 use menu::MenuPlugin;
+use multiplayer::MultiPlayerPlugin;
 use signin::SignInPlugin;
 use singleplayer::SinglePlayerPlugin;
+use transition::TransitionPlugin;
 
+mod about;
 mod aftergame;
 mod create;
+mod diagnostics;
+mod editor;
+mod favorites;
 mod gamelisting;
 mod mainmenu;
+mod mapindex;
+mod mappreview;
 mod mapselection;
+#[cfg(feature = "hot-reload")]
+mod mapwatch;
 mod menu;
+mod minimap;
+mod multiplayer;
+mod presets;
 mod requests;
 mod signin;
 mod singleplayer;
+mod transition;
+mod versioned;
 
 pub struct MenuPluginGroup;
 
@@ -31,12 +51,18 @@ impl PluginGroup for MenuPluginGroup {
             .add(MenuSetupPlugin)
             .add(MenuPlugin)
             .add(MainMenuPlugin)
+            .add(MapIndexPlugin)
             .add(MapSelectionPlugin)
             .add(SignInPlugin)
             .add(GameListingPlugin)
             .add(SinglePlayerPlugin)
             .add(CreateGamePlugin)
+            .add(MultiPlayerPlugin)
             .add(AfterGamePlugin)
+            .add(DiagnosticsPlugin)
+            .add(AboutPlugin)
+            .add(EditorPlugin)
+            .add(TransitionPlugin)
     }
 }
 
@@ -62,6 +88,9 @@ pub(crate) enum MenuState {
     GameCreation,
     MultiPlayerGame,
     AfterGame,
+    NetDiagnostics,
+    About,
+    MapEditor,
 }
 
 impl StateWithSet for MenuState {
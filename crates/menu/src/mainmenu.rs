@@ -46,6 +46,24 @@ fn setup(mut commands: GuiCommands, menu: Res<Menu>) {
         ButtonAction::SwithState(MenuState::SignIn),
         "Multiplayer",
     );
+    button(
+        &mut commands,
+        column_node,
+        ButtonAction::SwithState(MenuState::MapEditor),
+        "Map Editor",
+    );
+    button(
+        &mut commands,
+        column_node,
+        ButtonAction::SwithState(MenuState::NetDiagnostics),
+        "Network Check",
+    );
+    button(
+        &mut commands,
+        column_node,
+        ButtonAction::SwithState(MenuState::About),
+        "About",
+    );
     button(&mut commands, column_node, ButtonAction::Quit, "Quit Game");
 }
 
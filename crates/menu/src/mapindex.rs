@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use async_std::{fs, io, stream::StreamExt};
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use de_core::assets::asset_path;
+use de_gui::ToastEvent;
+use de_map::{
+    io::{load_map, MapLoadingError, MAP_FILE_SUFFIX},
+    meta::MapMetadata,
+};
+use futures_lite::future;
+
+/// Builds the [`MapIndex`] of the maps directory in the background right
+/// after startup and whenever [`rescan_maps`] is called.
+pub(crate) struct MapIndexPlugin;
+
+impl Plugin for MapIndexPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system(index_system.run_if(resource_exists::<IndexTask>()));
+    }
+}
+
+#[derive(Resource)]
+struct IndexTask(Task<io::Result<MapIndex>>);
+
+/// Validated maps from the maps directory. The resource is inserted once a
+/// scan finishes and it is removed while a re-scan is in progress.
+#[derive(Resource, Default)]
+pub(crate) struct MapIndex {
+    /// Valid maps sorted by name followed by broken maps sorted by path.
+    maps: Vec<IndexedMap>,
+}
+
+struct IndexedMap {
+    path: PathBuf,
+    /// Map metadata or a description of the reason why the map is broken.
+    result: Result<MapMetadata, String>,
+}
+
+impl MapIndex {
+    /// Loads and validates all maps in a directory. Broken maps are recorded
+    /// as such, they do not fail the scan.
+    pub(crate) async fn build(dir: &Path) -> io::Result<Self> {
+        let mut index = Self::default();
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next().await {
+            let path = entry?.path();
+            if !path.is_file().await
+                || !path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .map_or(false, |n| n.ends_with(MAP_FILE_SUFFIX))
+            {
+                continue;
+            }
+
+            let path: PathBuf = path.into();
+            let result = Self::validate(&path)
+                .await
+                .map_err(|error| error.to_string());
+            index.maps.push(IndexedMap { path, result });
+        }
+        index.sort();
+        Ok(index)
+    }
+
+    /// Loads and validates a single map and returns its metadata. The whole
+    /// map is loaded so that its content is validated too.
+    pub(crate) async fn validate(path: &Path) -> Result<MapMetadata, MapLoadingError> {
+        load_map(path).await.map(|map| map.metadata().clone())
+    }
+
+    /// Returns all valid maps.
+    pub(crate) fn valid(&self) -> impl Iterator<Item = (&Path, &MapMetadata)> {
+        self.maps.iter().filter_map(|map| match map.result {
+            Ok(ref metadata) => Some((map.path.as_path(), metadata)),
+            Err(_) => None,
+        })
+    }
+
+    /// Returns all broken maps together with the reason why they are broken.
+    pub(crate) fn broken(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.maps.iter().filter_map(|map| match map.result {
+            Ok(_) => None,
+            Err(ref error) => Some((map.path.as_path(), error.as_str())),
+        })
+    }
+
+    /// Inserts a new (valid) map or updates an already indexed map.
+    pub(crate) fn insert(&mut self, path: PathBuf, metadata: MapMetadata) {
+        self.remove(&path);
+        self.maps.push(IndexedMap {
+            path,
+            result: Ok(metadata),
+        });
+        self.sort();
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.maps.retain(|map| map.path != path);
+    }
+
+    fn sort(&mut self) {
+        self.maps.sort_by(|a, b| match (&a.result, &b.result) {
+            (Ok(a), Ok(b)) => a.name().cmp(b.name()),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => a.path.cmp(&b.path),
+        });
+    }
+}
+
+/// Re-builds the [`MapIndex`] so that changes of the maps directory done
+/// after startup are picked up. The index is removed until the scan
+/// finishes.
+pub(crate) fn rescan_maps(commands: &mut Commands) {
+    commands.remove_resource::<MapIndex>();
+    let task = IoTaskPool::get().spawn(async { MapIndex::build(&asset_path("maps")).await });
+    commands.insert_resource(IndexTask(task));
+}
+
+fn setup(mut commands: Commands) {
+    rescan_maps(&mut commands);
+}
+
+fn index_system(
+    mut commands: Commands,
+    mut task: ResMut<IndexTask>,
+    mut toasts: EventWriter<ToastEvent>,
+    mut reported: Local<Vec<PathBuf>>,
+) {
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<IndexTask>();
+
+    let index = match result {
+        Ok(index) => index,
+        Err(error) => {
+            error!("Maps directory scan failed: {error}");
+            toasts.send(ToastEvent::new(format!(
+                "Maps could not be loaded: {error}"
+            )));
+            MapIndex::default()
+        }
+    };
+
+    // Each broken map is reported only once, not after each re-scan.
+    let mut broken = 0;
+    for (path, error) in index.broken() {
+        if reported.iter().any(|reported| reported == path) {
+            continue;
+        }
+        warn!("Map {} is broken: {error}", path.display());
+        reported.push(path.into());
+        broken += 1;
+    }
+    if broken > 0 {
+        toasts.send(ToastEvent::new(format!(
+            "{broken} broken map(s) will not be offered, see the log for details."
+        )));
+    }
+
+    commands.insert_resource(index);
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+    use de_core::player::Player;
+    use de_map::{io::store_map, map::Map, size::MapBounds};
+
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["Zeta", "Alpha"] {
+            let map = Map::empty(MapMetadata::new(
+                name.into(),
+                MapBounds::new(Vec2::new(100., 100.)),
+                Player::Player2,
+            ));
+            let path = dir.path().join(format!("{name}.dem.tar"));
+            task::block_on(store_map(&map, path)).unwrap();
+        }
+        let broken = dir.path().join("broken.dem.tar");
+        std::fs::write(&broken, b"not a map").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"not a map either").unwrap();
+
+        let mut index = task::block_on(MapIndex::build(dir.path())).unwrap();
+        let names: Vec<&str> = index.valid().map(|(_, meta)| meta.name()).collect();
+        assert_eq!(names, vec!["Alpha", "Zeta"]);
+        let broken_maps: Vec<(&Path, &str)> = index.broken().collect();
+        assert_eq!(broken_maps.len(), 1);
+        assert_eq!(broken_maps[0].0, broken.as_path());
+        assert!(!broken_maps[0].1.is_empty());
+
+        let alpha = dir.path().join("Alpha.dem.tar");
+        index.remove(&alpha);
+        assert_eq!(index.valid().count(), 1);
+        index.insert(
+            alpha,
+            MapMetadata::new(
+                "Beta".into(),
+                MapBounds::new(Vec2::new(100., 100.)),
+                Player::Player2,
+            ),
+        );
+        let names: Vec<&str> = index.valid().map(|(_, meta)| meta.name()).collect();
+        assert_eq!(names, vec!["Beta", "Zeta"]);
+    }
+}
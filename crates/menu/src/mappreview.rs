@@ -0,0 +1,392 @@
+use std::{f32::consts::FRAC_PI_2, path::PathBuf};
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::camera::Viewport,
+    tasks::{IoTaskPool, Task},
+    window::PrimaryWindow,
+};
+use de_conf::Configuration;
+use de_core::{objects::ActiveObjectType, projection::ToAltitude};
+use de_gui::ToastEvent;
+use de_map::{
+    content::InnerObject,
+    io::{load_map, MapLoadingError},
+    map::Map,
+};
+use futures_lite::future;
+
+use crate::mapselection::MapState;
+
+/// Minimum elevation of the preview camera above the map plane in radians.
+const MIN_ELEVATION: f32 = 0.1;
+/// Maximum elevation of the preview camera, i.e. almost top-down view.
+const MAX_ELEVATION: f32 = FRAC_PI_2 - 0.05;
+const MIN_DISTANCE: f32 = 10.;
+/// Orbit rotation in radians per logical pixel of mouse movement.
+const ROTATION_SENSITIVITY: f32 = 0.01;
+/// Relative change of the camera distance per mouse wheel line.
+const ZOOM_SENSITIVITY: f32 = 0.1;
+
+pub(crate) struct MapPreviewPlugin;
+
+impl Plugin for MapPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PreviewMapEvent>()
+            .add_system(cleanup.in_schedule(OnExit(MapState::On)))
+            .add_system(
+                start_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(on_event::<PreviewMapEvent>()),
+            )
+            .add_system(
+                spawn_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(resource_exists::<PreviewTask>()),
+            )
+            .add_system(
+                orbit_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(resource_exists::<Preview>()),
+            )
+            .add_system(
+                viewport_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(resource_exists::<Preview>()),
+            );
+    }
+}
+
+/// Send this event to display a 3D preview of a map. A previously displayed
+/// preview is replaced. The event is ignored if map previews are disabled in
+/// the configuration.
+pub(crate) struct PreviewMapEvent(PathBuf);
+
+impl PreviewMapEvent {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+/// Pending loading of a previewed map.
+#[derive(Resource)]
+struct PreviewTask {
+    path: PathBuf,
+    task: Task<Result<Map, MapLoadingError>>,
+}
+
+/// Currently displayed map preview.
+#[derive(Resource)]
+struct Preview {
+    path: PathBuf,
+    /// Root entity of the preview scene. All entities of the scene are its
+    /// descendants.
+    root: Entity,
+}
+
+/// Orbit controls of the preview camera.
+#[derive(Component)]
+struct Orbit {
+    target: Vec3,
+    azimuth: f32,
+    elevation: f32,
+    distance: f32,
+    max_distance: f32,
+}
+
+impl Orbit {
+    fn rotate(&mut self, delta: Vec2) {
+        self.azimuth -= delta.x * ROTATION_SENSITIVITY;
+        self.elevation =
+            (self.elevation + delta.y * ROTATION_SENSITIVITY).clamp(MIN_ELEVATION, MAX_ELEVATION);
+    }
+
+    fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * (1. - lines * ZOOM_SENSITIVITY))
+            .clamp(MIN_DISTANCE, self.max_distance);
+    }
+
+    fn transform(&self) -> Transform {
+        let rotation = Quat::from_euler(EulerRot::YXZ, self.azimuth, -self.elevation, 0.);
+        Transform::from_translation(self.target + rotation * Vec3::Z * self.distance)
+            .looking_at(self.target, Vec3::Y)
+    }
+}
+
+fn cleanup(mut commands: Commands, preview: Option<Res<Preview>>) {
+    commands.remove_resource::<PreviewTask>();
+    if let Some(preview) = preview {
+        commands.entity(preview.root).despawn_recursive();
+        commands.remove_resource::<Preview>();
+    }
+}
+
+fn start_system(
+    mut commands: Commands,
+    conf: Res<Configuration>,
+    preview: Option<Res<Preview>>,
+    mut events: EventReader<PreviewMapEvent>,
+) {
+    let Some(event) = events.iter().last() else { return };
+    if !conf.menu().map_preview() {
+        return;
+    }
+
+    if let Some(preview) = preview {
+        if preview.path == event.0 {
+            return;
+        }
+        commands.entity(preview.root).despawn_recursive();
+        commands.remove_resource::<Preview>();
+    }
+
+    let path = event.0.clone();
+    let task = IoTaskPool::get().spawn(async move { load_map(&path).await });
+    commands.insert_resource(PreviewTask {
+        path: event.0.clone(),
+        task,
+    });
+}
+
+fn spawn_system(
+    mut commands: Commands,
+    mut task: ResMut<PreviewTask>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(result) = future::block_on(future::poll_once(&mut task.task)) else { return };
+    commands.remove_resource::<PreviewTask>();
+
+    match result {
+        Ok(map) => {
+            let root = spawn_scene(&mut commands, &mut meshes, &mut materials, &map);
+            commands.insert_resource(Preview {
+                path: task.path.clone(),
+                root,
+            });
+        }
+        Err(error) => toasts.send(ToastEvent::new(format!("Map preview error: {error}"))),
+    }
+}
+
+/// Spawns a scene with the map terrain, objects, a light and an orbiting
+/// camera. Returns the root entity of the scene.
+fn spawn_scene(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    map: &Map,
+) -> Entity {
+    let bounds = map.metadata().bounds();
+    let size = bounds.size();
+    let center: Vec3 = ((bounds.min() + bounds.max()) / 2.).to_msl();
+    let max_distance = 2. * size.max_element();
+
+    let building_mesh = meshes.add(shape::Cube::new(12.).into());
+    let unit_mesh = meshes.add(shape::Cube::new(3.).into());
+    let inactive_mesh = meshes.add(shape::Cube::new(5.).into());
+    let active_material = materials.add(Color::ORANGE_RED.into());
+    let inactive_material = materials.add(Color::DARK_GREEN.into());
+
+    commands
+        .spawn(SpatialBundle::default())
+        .with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: meshes.add(shape::Box::new(size.x, 0.1, size.y).into()),
+                material: materials.add(Color::rgb(0.45, 0.4, 0.3).into()),
+                transform: Transform::from_translation(center - 0.05 * Vec3::Y),
+                ..default()
+            });
+
+            for object in map.content().objects() {
+                let (mesh, material) = match object.inner() {
+                    InnerObject::Active(active) => match active.object_type() {
+                        ActiveObjectType::Building(_) => (&building_mesh, &active_material),
+                        ActiveObjectType::Unit(_) => (&unit_mesh, &active_material),
+                    },
+                    InnerObject::Inactive(_) => (&inactive_mesh, &inactive_material),
+                };
+                parent.spawn(PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform: object.placement().to_transform(),
+                    ..default()
+                });
+            }
+
+            parent.spawn(DirectionalLightBundle {
+                transform: Transform::from_xyz(1., 2., 1.).looking_at(Vec3::ZERO, Vec3::Y),
+                ..default()
+            });
+
+            let orbit = Orbit {
+                target: center,
+                azimuth: 0.,
+                elevation: 0.8,
+                distance: max_distance / 2.,
+                max_distance,
+            };
+            parent.spawn((
+                Camera3dBundle {
+                    camera: Camera {
+                        // Rendered over the menu UI.
+                        order: 1,
+                        ..default()
+                    },
+                    camera_3d: Camera3d {
+                        clear_color: ClearColorConfig::Custom(Color::BLACK),
+                        ..default()
+                    },
+                    transform: orbit.transform(),
+                    ..default()
+                },
+                UiCameraConfig { show_ui: false },
+                orbit,
+            ));
+        })
+        .id()
+}
+
+/// Orbits the camera while the right mouse button is pressed and zooms with
+/// the mouse wheel.
+fn orbit_system(
+    buttons: Res<Input<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    mut cameras: Query<(&mut Transform, &mut Orbit)>,
+) {
+    let delta: Vec2 = motion.iter().map(|event| event.delta).sum();
+    let lines: f32 = wheel
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.,
+        })
+        .sum();
+
+    for (mut transform, mut orbit) in cameras.iter_mut() {
+        if buttons.pressed(MouseButton::Right) {
+            orbit.rotate(delta);
+        }
+        orbit.zoom(lines);
+        *transform = orbit.transform();
+    }
+}
+
+/// Keeps the preview in the right part of the window, next to the map list.
+fn viewport_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<&mut Camera, With<Orbit>>,
+) {
+    let Ok(window) = windows.get_single() else { return };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    let viewport = Viewport {
+        physical_position: UVec2::new(size.x * 65 / 100, size.y * 10 / 100),
+        physical_size: UVec2::new(size.x * 30 / 100, size.y * 40 / 100),
+        ..default()
+    };
+
+    for mut camera in cameras.iter_mut() {
+        let current = camera.viewport.as_ref();
+        if current.map_or(true, |current| {
+            current.physical_position != viewport.physical_position
+                || current.physical_size != viewport.physical_size
+        }) {
+            camera.viewport = Some(viewport.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use async_std::task;
+    use bevy::input::InputPlugin;
+    use de_core::player::Player;
+    use de_map::{io::store_map, meta::MapMetadata, size::MapBounds};
+
+    use super::*;
+
+    fn preview_root(app: &mut App, path: PathBuf) -> Entity {
+        app.world.send_event(PreviewMapEvent::new(path.clone()));
+        for _ in 0..1000 {
+            app.update();
+            if let Some(preview) = app.world.get_resource::<Preview>() {
+                assert_eq!(preview.path, path);
+                return preview.root;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("Preview of {} was not spawned.", path.display());
+    }
+
+    fn descendants(app: &App, root: Entity) -> Vec<Entity> {
+        let mut entities = vec![root];
+        let mut index = 0;
+        while index < entities.len() {
+            if let Some(children) = app.world.get::<Children>(entities[index]) {
+                entities.extend(children.iter().copied());
+            }
+            index += 1;
+        }
+        entities
+    }
+
+    #[test]
+    fn test_preview() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = ["a", "b"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(format!("{name}.dem.tar"));
+                let map = Map::empty(MapMetadata::new(
+                    name.to_string(),
+                    MapBounds::new(Vec2::new(100., 200.)),
+                    Player::Player2,
+                ));
+                task::block_on(store_map(&map, &path)).unwrap();
+                path
+            })
+            .collect();
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(InputPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_event::<ToastEvent>()
+            .insert_resource(Configuration::default())
+            .add_state::<MapState>()
+            .add_plugin(MapPreviewPlugin);
+        app.world
+            .resource_mut::<NextState<MapState>>()
+            .set(MapState::On);
+        app.update();
+
+        let first = preview_root(&mut app, paths[0].clone());
+        let first_scene = descendants(&app, first);
+        // Ground, light and camera.
+        assert_eq!(first_scene.len(), 4);
+        assert_eq!(app.world.query::<&Orbit>().iter(&app.world).count(), 1);
+
+        let second = preview_root(&mut app, paths[1].clone());
+        assert_ne!(first, second);
+        for entity in first_scene {
+            assert!(app.world.get_entity(entity).is_none());
+        }
+        assert_eq!(app.world.query::<&Orbit>().iter(&app.world).count(), 1);
+
+        app.world
+            .resource_mut::<NextState<MapState>>()
+            .set(MapState::Off);
+        app.update();
+        assert!(!app.world.contains_resource::<Preview>());
+        assert!(app.world.get_entity(second).is_none());
+        assert_eq!(app.world.query::<&Orbit>().iter(&app.world).count(), 0);
+    }
+}
@@ -1,40 +1,73 @@
 use std::path::{Path, PathBuf};
+#[cfg(feature = "hot-reload")]
+use std::time::Duration;
 
-use async_std::{fs, io, stream::StreamExt};
+#[cfg(feature = "hot-reload")]
+use async_std::io;
+use bevy::prelude::*;
+#[cfg(feature = "hot-reload")]
 use bevy::{
-    prelude::*,
     tasks::{IoTaskPool, Task},
+    time::Stopwatch,
 };
-use de_core::{assets::asset_path, log_full_error, state::AppState};
-use de_gui::{ButtonCommands, GuiCommands, OuterStyle};
-use de_map::{
-    io::{load_metadata, MapLoadingError, MAP_FILE_SUFFIX},
-    meta::MapMetadata,
-};
+use de_conf::Configuration;
+#[cfg(feature = "hot-reload")]
+use de_core::assets::asset_path;
+use de_core::state::AppState;
+#[cfg(feature = "hot-reload")]
+use de_gui::ButtonOps;
+use de_gui::{ButtonCommands, GuiCommands, LabelCommands, OuterStyle};
+use de_map::meta::MapMetadata;
+#[cfg(feature = "hot-reload")]
 use futures_lite::future;
-use thiserror::Error;
+
+#[cfg(feature = "hot-reload")]
+use crate::mapwatch::{scan_maps, MapChange, MapsScan, MapsWatcher};
+#[cfg(feature = "hot-reload")]
+use crate::minimap::Minimaps;
+use crate::{
+    mapindex::{rescan_maps, MapIndex},
+    mappreview::{MapPreviewPlugin, PreviewMapEvent},
+    minimap::{MinimapNode, MinimapPlugin, ShowMinimapEvent},
+};
+
+/// Interval between scans of the maps directory done to hot-reload changed
+/// maps.
+#[cfg(feature = "hot-reload")]
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 pub(crate) struct MapSelectionPlugin;
 
 impl Plugin for MapSelectionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<MapState>()
+        app.add_plugin(MapPreviewPlugin)
+            .add_plugin(MinimapPlugin)
+            .add_state::<MapState>()
             .add_event::<SelectMapEvent>()
             .add_event::<MapSelectedEvent>()
             .add_system(setup.in_schedule(OnEnter(MapState::On)))
             .add_system(cleanup.in_schedule(OnExit(MapState::On)))
             .add_system(init_buttons.run_if(in_state(MapState::On)))
             .add_system(button_system.run_if(in_state(MapState::On)))
+            .add_system(tooltip_system.run_if(in_state(MapState::On)))
+            .add_system(preview_button_system.run_if(in_state(MapState::On)))
             .add_system(
                 select_map_system
                     .run_if(in_state(AppState::InMenu))
                     .run_if(on_event::<SelectMapEvent>()),
             );
+
+        // Maps are hot-reloaded only with the hot-reload feature, which is
+        // meant for map development.
+        #[cfg(feature = "hot-reload")]
+        app.add_system(watch_system.run_if(in_state(MapState::On)))
+            .add_system(reload_system.run_if(in_state(MapState::On)))
+            .add_system(apply_reload_system.run_if(in_state(MapState::On)));
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, States)]
-enum MapState {
+pub(crate) enum MapState {
     On,
     #[default]
     Off,
@@ -69,8 +102,36 @@ impl MapSelectedEvent {
 #[derive(Resource)]
 struct PopUpNode(Entity);
 
+/// Label with description of currently hovered map.
 #[derive(Resource)]
-struct LoadingTask(Task<Result<Vec<MapEntry>, LoadingError>>);
+struct Tooltip(Entity);
+
+/// Path of the last hovered map.
+#[derive(Resource, Default)]
+struct Highlighted(Option<PathBuf>);
+
+#[derive(Component)]
+struct PreviewButton;
+
+/// Column with map buttons.
+#[derive(Resource)]
+struct MapsColumn(Entity);
+
+/// Pending scan of the maps directory.
+#[cfg(feature = "hot-reload")]
+#[derive(Resource)]
+struct ScanTask(Task<io::Result<MapsScan>>);
+
+/// Pending reloading of metadata of changed maps.
+#[cfg(feature = "hot-reload")]
+#[derive(Resource)]
+struct ReloadTask(Task<Vec<ReloadedMap>>);
+
+#[cfg(feature = "hot-reload")]
+enum ReloadedMap {
+    Updated(PathBuf, MapMetadata),
+    Removed(PathBuf),
+}
 
 #[derive(Component)]
 struct MapEntry(PathBuf, MapMetadata);
@@ -89,17 +150,12 @@ impl MapEntry {
     }
 }
 
-#[derive(Error, Debug)]
-pub enum LoadingError {
-    #[error(transparent)]
-    Io { source: io::Error },
-    #[error(transparent)]
-    Map { source: MapLoadingError },
-}
-
 fn setup(mut commands: Commands) {
-    let task = IoTaskPool::get().spawn(load_available_maps());
-    commands.insert_resource(LoadingTask(task));
+    // Maps might have been added or removed since the last scan.
+    rescan_maps(&mut commands);
+    commands.init_resource::<Highlighted>();
+    #[cfg(feature = "hot-reload")]
+    commands.insert_resource(MapsWatcher::default());
 
     let node_id = commands
         .spawn(NodeBundle {
@@ -117,25 +173,19 @@ fn setup(mut commands: Commands) {
     commands.insert_resource(PopUpNode(node_id));
 }
 
+/// Spawns the map buttons once the map index is available. Broken maps are
+/// not offered.
 fn init_buttons(
     mut commands: GuiCommands,
+    conf: Res<Configuration>,
     node: Res<PopUpNode>,
-    task: Option<ResMut<LoadingTask>>,
+    index: Option<Res<MapIndex>>,
+    column: Option<Res<MapsColumn>>,
 ) {
-    let Some(mut task) = task else { return };
-    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else {
-        return
-    };
-
-    let map_entries = match result {
-        Ok(entries) => entries,
-        Err(err) => {
-            log_full_error!(err);
-            panic!("{}", err);
-        }
-    };
-
-    commands.remove_resource::<LoadingTask>();
+    if column.is_some() {
+        return;
+    }
+    let Some(index) = index else { return };
 
     let column_node = commands
         .spawn(NodeBundle {
@@ -152,15 +202,54 @@ fn init_buttons(
         .id();
 
     commands.entity(node.0).add_child(column_node);
+    commands.insert_resource(MapsColumn(column_node));
 
-    for map in map_entries {
+    for (path, metadata) in index.valid() {
+        let map = MapEntry::new(path.into(), metadata.clone());
         let button = map_button(&mut commands, map);
         commands.entity(column_node).add_child(button);
     }
+
+    let tooltip_node = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(35.), Val::Percent(25.)),
+                margin: UiRect::all(Val::Auto),
+            },
+            "",
+        )
+        .id();
+    commands.entity(node.0).add_child(tooltip_node);
+    commands.insert_resource(Tooltip(tooltip_node));
+
+    let minimap_node = commands.spawn(MinimapNode::bundle()).id();
+    commands.entity(node.0).add_child(minimap_node);
+
+    if conf.menu().map_preview() {
+        let preview_button = commands
+            .spawn_button(
+                OuterStyle {
+                    size: Size::new(Val::Percent(15.), Val::Percent(8.)),
+                    margin: UiRect::all(Val::Auto),
+                },
+                "3D Preview",
+            )
+            .insert(PreviewButton)
+            .id();
+        commands.entity(node.0).add_child(preview_button);
+    }
 }
 
 fn cleanup(mut commands: Commands, node: Res<PopUpNode>) {
-    commands.remove_resource::<LoadingTask>();
+    commands.remove_resource::<Tooltip>();
+    commands.remove_resource::<MapsColumn>();
+    commands.remove_resource::<Highlighted>();
+    #[cfg(feature = "hot-reload")]
+    {
+        commands.remove_resource::<MapsWatcher>();
+        commands.remove_resource::<ScanTask>();
+        commands.remove_resource::<ReloadTask>();
+    }
     commands.entity(node.0).despawn_recursive();
 }
 
@@ -180,41 +269,62 @@ fn button_system(
     }
 }
 
-async fn load_available_maps() -> Result<Vec<MapEntry>, LoadingError> {
-    let maps_dir = asset_path("maps");
-
-    let mut map_entries = Vec::new();
-    let mut dir_entries = match fs::read_dir(maps_dir).await {
-        Ok(entries) => entries,
-        Err(err) => return Err(LoadingError::Io { source: err }),
-    };
+fn preview_button_system(
+    highlighted: Res<Highlighted>,
+    interactions: Query<&Interaction, (Changed<Interaction>, With<PreviewButton>)>,
+    mut events: EventWriter<PreviewMapEvent>,
+) {
+    let Some(path) = highlighted.0.as_ref() else { return };
+    for &interaction in interactions.iter() {
+        if let Interaction::Clicked = interaction {
+            events.send(PreviewMapEvent::new(path.clone()));
+        }
+    }
+}
 
-    while let Some(res) = dir_entries.next().await {
-        let path = match res {
-            Ok(entry) => entry.path(),
-            Err(err) => return Err(LoadingError::Io { source: err }),
-        };
+fn tooltip_system(
+    tooltip: Option<Res<Tooltip>>,
+    mut highlighted: ResMut<Highlighted>,
+    interactions: Query<(&Interaction, &MapEntry), Changed<Interaction>>,
+    children: Query<&Children>,
+    mut texts: Query<&mut Text>,
+    mut minimaps: EventWriter<ShowMinimapEvent>,
+) {
+    let Some(tooltip) = tooltip else { return };
 
-        if !path.is_file().await {
-            continue;
-        }
-        if !path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .map_or(false, |n| n.ends_with(MAP_FILE_SUFFIX))
-        {
-            continue;
+    let mut new_text = None;
+    for (&interaction, map) in interactions.iter() {
+        match interaction {
+            Interaction::Hovered => {
+                highlighted.0 = Some(map.path().into());
+                minimaps.send(ShowMinimapEvent::new(map.path().into()));
+                new_text = Some(tooltip_text(map.metadata()));
+                // Un-hovering of another entry must not override this.
+                break;
+            }
+            Interaction::None => new_text = Some(String::new()),
+            Interaction::Clicked => (),
         }
+    }
 
-        let metadata = match load_metadata(path.as_path()).await {
-            Ok(meta) => meta,
-            Err(err) => return Err(LoadingError::Map { source: err }),
-        };
-        map_entries.push(MapEntry::new(path.into(), metadata));
+    let Some(new_text) = new_text else { return };
+    for &child in children.get(tooltip.0).unwrap().iter() {
+        if let Ok(mut text) = texts.get_mut(child) {
+            text.sections[0].value = new_text;
+            break;
+        }
     }
+}
 
-    map_entries.sort_by(|a, b| a.metadata().name().cmp(b.metadata().name()));
-    Ok(map_entries)
+/// Returns a human readable description of a map.
+fn tooltip_text(metadata: &MapMetadata) -> String {
+    let size = metadata.bounds().size();
+    format!(
+        "Size: {:.0} x {:.0} m\nMax players: {}",
+        size.x,
+        size.y,
+        metadata.max_player().to_num()
+    )
 }
 
 fn map_button(commands: &mut GuiCommands, map: MapEntry) -> Entity {
@@ -235,6 +345,152 @@ fn map_button(commands: &mut GuiCommands, map: MapEntry) -> Entity {
         .id()
 }
 
+#[cfg(feature = "hot-reload")]
+fn watch_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut stopwatch: Local<Stopwatch>,
+    task: Option<ResMut<ScanTask>>,
+    mut watcher: ResMut<MapsWatcher>,
+) {
+    if let Some(mut task) = task {
+        let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+        commands.remove_resource::<ScanTask>();
+
+        match result {
+            Ok(scan) => watcher.update(time.elapsed(), scan),
+            Err(error) => warn!("Maps directory scan failed: {error}"),
+        }
+        return;
+    }
+
+    stopwatch.tick(time.delta());
+    if stopwatch.elapsed() >= WATCH_INTERVAL {
+        stopwatch.reset();
+        let task = IoTaskPool::get().spawn(async { scan_maps(&asset_path("maps")).await });
+        commands.insert_resource(ScanTask(task));
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+fn reload_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    task: Option<Res<ReloadTask>>,
+    mut watcher: ResMut<MapsWatcher>,
+) {
+    if task.is_some() {
+        return;
+    }
+    let changes = watcher.take_changes(time.elapsed());
+    if changes.is_empty() {
+        return;
+    }
+
+    let task = IoTaskPool::get().spawn(async move {
+        let mut reloaded = Vec::with_capacity(changes.len());
+        for change in changes {
+            let path = match change {
+                MapChange::Added(path) | MapChange::Modified(path) => path,
+                MapChange::Removed(path) => {
+                    reloaded.push(ReloadedMap::Removed(path));
+                    continue;
+                }
+            };
+
+            match MapIndex::validate(&path).await {
+                Ok(metadata) => reloaded.push(ReloadedMap::Updated(path, metadata)),
+                Err(error) => {
+                    // A map which cannot be loaded cannot be selected either.
+                    warn!("Map {} reloading failed: {error}", path.display());
+                    reloaded.push(ReloadedMap::Removed(path));
+                }
+            }
+        }
+        reloaded
+    });
+    commands.insert_resource(ReloadTask(task));
+}
+
+#[cfg(feature = "hot-reload")]
+fn apply_reload_system(
+    mut commands: GuiCommands,
+    column: Option<Res<MapsColumn>>,
+    index: Option<ResMut<MapIndex>>,
+    task: Option<ResMut<ReloadTask>>,
+    mut entries: Query<(Entity, &mut MapEntry)>,
+    mut buttons: ButtonOps,
+    mut minimaps: ResMut<Minimaps>,
+) {
+    // Wait until the map buttons are spawned.
+    let Some(column) = column else { return };
+    let Some(mut index) = index else { return };
+    let Some(mut task) = task else { return };
+    let Some(reloaded) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<ReloadTask>();
+
+    for map in reloaded {
+        match map {
+            ReloadedMap::Updated(path, metadata) => {
+                minimaps.invalidate(&path);
+                index.insert(path.clone(), metadata.clone());
+                let existing = entries
+                    .iter_mut()
+                    .find(|(_, entry)| entry.path() == path.as_path());
+                match existing {
+                    Some((entity, mut entry)) => {
+                        if let Err(error) = buttons.set_text(entity, metadata.name().to_owned()) {
+                            error!("Map button update failed: {error}");
+                        }
+                        entry.1 = metadata;
+                    }
+                    None => {
+                        let button = map_button(&mut commands, MapEntry::new(path, metadata));
+                        commands.entity(column.0).add_child(button);
+                    }
+                }
+            }
+            ReloadedMap::Removed(path) => {
+                minimaps.invalidate(&path);
+                index.remove(&path);
+                for (entity, entry) in entries.iter() {
+                    if entry.path() == path.as_path() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn select_map_system(mut next_state: ResMut<NextState<MapState>>) {
     next_state.set(MapState::On);
 }
+
+#[cfg(test)]
+mod tests {
+    use de_core::player::Player;
+    use de_map::size::MapBounds;
+
+    use super::*;
+
+    #[test]
+    fn test_tooltip_text() {
+        let metadata = MapMetadata::new(
+            "Test Map".into(),
+            MapBounds::new(Vec2::new(1000., 2000.)),
+            Player::Player4,
+        );
+        assert_eq!(
+            tooltip_text(&metadata),
+            "Size: 1000 x 2000 m\nMax players: 4"
+        );
+
+        let metadata = MapMetadata::new(
+            "Small Map".into(),
+            MapBounds::new(Vec2::new(250.4, 600.)),
+            Player::Player2,
+        );
+        assert_eq!(tooltip_text(&metadata), "Size: 250 x 600 m\nMax players: 2");
+    }
+}
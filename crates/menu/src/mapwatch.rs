@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use async_std::{fs, io, stream::StreamExt};
+use bevy::prelude::Resource;
+use de_map::io::MAP_FILE_SUFFIX;
+
+/// Changes of map files are reported only after the files were not changed
+/// for this long. This prevents reloading of half written maps.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Modification times of map files in a directory.
+pub(crate) type MapsScan = HashMap<PathBuf, SystemTime>;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum MapChange {
+    Added(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf),
+}
+
+/// Detects changes of map files from repeated scans of a maps directory.
+#[derive(Resource, Default)]
+pub(crate) struct MapsWatcher {
+    /// State of the maps directory corresponding to the last reported
+    /// changes. None before the first scan.
+    reported: Option<MapsScan>,
+    latest: MapsScan,
+    /// Time of the last detected (and not yet reported) change.
+    changed: Option<Duration>,
+}
+
+impl MapsWatcher {
+    /// Updates the watcher with a new scan of the maps directory. The first
+    /// scan is the base line against which later changes are detected.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - time of the scan.
+    ///
+    /// * `scan` - current state of the maps directory.
+    pub(crate) fn update(&mut self, time: Duration, scan: MapsScan) {
+        if self.reported.is_none() {
+            self.reported = Some(scan.clone());
+            self.latest = scan;
+        } else if self.latest != scan {
+            self.latest = scan;
+            self.changed = Some(time);
+        }
+    }
+
+    /// Returns all map changes since the last call of this method, sorted by
+    /// map path. No changes are returned until the maps directory has not
+    /// changed for a while.
+    pub(crate) fn take_changes(&mut self, time: Duration) -> Vec<MapChange> {
+        let Some(changed) = self.changed else { return Vec::new() };
+        if time.saturating_sub(changed) < DEBOUNCE {
+            return Vec::new();
+        }
+        self.changed = None;
+
+        let reported = self.reported.replace(self.latest.clone()).unwrap();
+        let mut changes: Vec<MapChange> = self
+            .latest
+            .iter()
+            .filter_map(|(path, modified)| match reported.get(path) {
+                None => Some(MapChange::Added(path.clone())),
+                Some(old) if old != modified => Some(MapChange::Modified(path.clone())),
+                Some(_) => None,
+            })
+            .chain(
+                reported
+                    .keys()
+                    .filter(|path| !self.latest.contains_key(*path))
+                    .map(|path| MapChange::Removed(path.clone())),
+            )
+            .collect();
+        changes.sort();
+        changes
+    }
+}
+
+/// Returns modification times of all map files in a directory.
+pub(crate) async fn scan_maps(dir: &Path) -> io::Result<MapsScan> {
+    let mut scan = MapsScan::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next().await {
+        let path = entry?.path();
+        if !path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map_or(false, |n| n.ends_with(MAP_FILE_SUFFIX))
+        {
+            continue;
+        }
+
+        let metadata = fs::metadata(&path).await?;
+        if metadata.is_file() {
+            scan.insert(path.into(), metadata.modified()?);
+        }
+    }
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher() {
+        let base = SystemTime::UNIX_EPOCH;
+        let ms = Duration::from_millis;
+        let scan = |maps: &[(&str, u64)]| -> MapsScan {
+            maps.iter()
+                .map(|&(name, modified)| (PathBuf::from(name), base + ms(modified)))
+                .collect()
+        };
+
+        let mut watcher = MapsWatcher::default();
+        watcher.update(ms(0), scan(&[("a.dem.tar", 0), ("b.dem.tar", 0)]));
+        assert!(watcher.take_changes(ms(1000)).is_empty());
+
+        // Rapid successive writes of a single map are reported once.
+        watcher.update(ms(1000), scan(&[("a.dem.tar", 10), ("b.dem.tar", 0)]));
+        watcher.update(ms(1200), scan(&[("a.dem.tar", 20), ("b.dem.tar", 0)]));
+        assert!(watcher.take_changes(ms(1600)).is_empty());
+        assert_eq!(
+            watcher.take_changes(ms(1700)),
+            vec![MapChange::Modified(PathBuf::from("a.dem.tar"))]
+        );
+        assert!(watcher.take_changes(ms(5000)).is_empty());
+
+        watcher.update(ms(6000), scan(&[("a.dem.tar", 20), ("c.dem.tar", 30)]));
+        assert_eq!(
+            watcher.take_changes(ms(7000)),
+            vec![
+                MapChange::Added(PathBuf::from("c.dem.tar")),
+                MapChange::Removed(PathBuf::from("b.dem.tar")),
+            ]
+        );
+
+        // A change reverted before it is reported is not reported at all.
+        watcher.update(ms(8000), scan(&[("a.dem.tar", 20)]));
+        watcher.update(ms(8100), scan(&[("a.dem.tar", 20), ("c.dem.tar", 30)]));
+        assert!(watcher.take_changes(ms(9000)).is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use de_conf::Configuration;
 use de_core::state::AppState;
 use de_gui::{ButtonCommands, GuiCommands, OuterStyle};
 
@@ -20,7 +21,8 @@ impl Plugin for MenuPlugin {
                     .run_if(resource_exists::<Menu>())
                     .run_if(resource_changed::<State<MenuState>>()),
             )
-            .add_system(button_system.run_if(in_state(AppState::InMenu)));
+            .add_system(button_system.run_if(in_state(AppState::InMenu)))
+            .add_system(back_system.run_if(in_state(AppState::InMenu)));
     }
 }
 
@@ -31,7 +33,7 @@ pub(crate) struct Menu {
 }
 
 impl Menu {
-    fn new(root_node: Entity, corner_node: Entity) -> Self {
+    pub(crate) fn new(root_node: Entity, corner_node: Entity) -> Self {
         Self {
             root_node,
             corner_node,
@@ -149,3 +151,65 @@ fn button_system(
         }
     }
 }
+
+/// Navigates back to the main menu after the configured back key is pressed.
+fn back_system(
+    conf: Res<Configuration>,
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<MenuState>>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    if state.0 != MenuState::MainMenu && keys.just_pressed(conf.menu().back_key()) {
+        next_state.set(MenuState::MainMenu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use async_std::{path::Path, task};
+
+    use super::*;
+
+    fn state_after_press(conf: Configuration, key: KeyCode) -> MenuState {
+        let mut app = App::new();
+        app.add_state::<MenuState>()
+            .insert_resource(conf)
+            .insert_resource(Input::<KeyCode>::default())
+            .add_system(back_system);
+
+        app.world
+            .resource_mut::<NextState<MenuState>>()
+            .set(MenuState::GameListing);
+        app.update();
+        assert_eq!(
+            app.world.resource::<State<MenuState>>().0,
+            MenuState::GameListing
+        );
+
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app.update();
+        app.update();
+        app.world.resource::<State<MenuState>>().0
+    }
+
+    #[test]
+    fn test_back_key() {
+        assert_eq!(
+            state_after_press(Configuration::default(), KeyCode::Escape),
+            MenuState::MainMenu
+        );
+
+        let path = env::temp_dir().join("de_menu_test_back_key.yaml");
+        fs::write(&path, "menu:\n  back_key: F2\n").unwrap();
+        let conf = task::block_on(Configuration::load(Path::new(path.as_os_str()))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            state_after_press(conf.clone(), KeyCode::Escape),
+            MenuState::GameListing
+        );
+        assert_eq!(state_after_press(conf, KeyCode::F2), MenuState::MainMenu);
+    }
+}
@@ -0,0 +1,448 @@
+#[cfg(feature = "hot-reload")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    tasks::{IoTaskPool, Task},
+    utils::HashMap,
+};
+use de_core::{
+    objects::{ActiveObjectType, BuildingType},
+    player::Player,
+};
+use de_gui::{Drawing, MINIMAP_ENEMY_COLOR, MINIMAP_PLAYER_COLOR, MINIMAP_TERRAIN_COLOR};
+use de_map::{
+    content::InnerObject,
+    io::{load_map, MapLoadingError},
+    map::Map,
+};
+use futures_lite::future;
+
+use crate::mapselection::MapState;
+
+/// Width and height of rendered minimaps in pixels.
+const RESOLUTION: u32 = 256;
+const BACKGROUND_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+const INACTIVE_COLOR: Color = Color::DARK_GREEN;
+const START_BORDER_COLOR: Color = Color::WHITE;
+/// Displayed for maps whose content cannot be loaded.
+const PLACEHOLDER_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+/// Minimum size of a marker relative to the minimap size.
+const MIN_MARKER_SIZE: f32 = 0.015;
+
+pub(crate) struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShowMinimapEvent>()
+            .init_resource::<Minimaps>()
+            .add_system(cleanup.in_schedule(OnExit(MapState::On)))
+            .add_system(
+                start_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(on_event::<ShowMinimapEvent>()),
+            )
+            .add_system(
+                render_system
+                    .run_if(in_state(MapState::On))
+                    .run_if(resource_exists::<MinimapTask>()),
+            );
+    }
+}
+
+/// Send this event to display a schematic minimap of a map in the
+/// [`MinimapNode`]. Rendered minimaps are cached.
+pub(crate) struct ShowMinimapEvent(PathBuf);
+
+impl ShowMinimapEvent {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+/// UI image node displaying minimaps. It is hidden until a minimap is
+/// rendered.
+#[derive(Component)]
+pub(crate) struct MinimapNode;
+
+impl MinimapNode {
+    pub(crate) fn bundle() -> (ImageBundle, Self) {
+        (
+            ImageBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Percent(65.),
+                        top: Val::Percent(55.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Auto, Val::Percent(35.)),
+                    aspect_ratio: Some(1.),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Self,
+        )
+    }
+}
+
+/// Cache of rendered minimaps of individual maps.
+#[derive(Resource, Default)]
+pub(crate) struct Minimaps(HashMap<PathBuf, Handle<Image>>);
+
+impl Minimaps {
+    /// Forgets the minimap of a (changed) map.
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn invalidate(&mut self, path: &Path) {
+        self.0.remove(path);
+    }
+}
+
+/// Pending loading of a map whose minimap is to be rendered.
+#[derive(Resource)]
+struct MinimapTask {
+    path: PathBuf,
+    task: Task<Result<Map, MapLoadingError>>,
+}
+
+/// Schematic top-down representation of a map. All coordinates are relative
+/// to the (square) minimap, between (0, 0) in the top-left corner and (1, 1)
+/// in the bottom-right corner.
+#[derive(Debug, PartialEq)]
+struct Geometry {
+    /// Area covered by the map. Non-square maps do not cover the whole
+    /// minimap.
+    terrain: Rect,
+    /// Markers in the order of drawing.
+    markers: Vec<Marker>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Marker {
+    center: Vec2,
+    size: Vec2,
+    kind: MarkerKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MarkerKind {
+    /// Inactive objects, e.g. trees.
+    Inactive,
+    Unit(Player),
+    Building(Player),
+    /// Player base, i.e. a player's start position.
+    Start(Player),
+}
+
+impl MarkerKind {
+    /// Markers of higher layers are drawn over markers of lower layers.
+    fn layer(self) -> u8 {
+        match self {
+            Self::Inactive => 0,
+            Self::Unit(_) => 1,
+            Self::Building(_) => 2,
+            Self::Start(_) => 3,
+        }
+    }
+
+    /// Real world size of the marked objects in meters.
+    fn size(self) -> f32 {
+        match self {
+            Self::Inactive => 5.,
+            Self::Unit(_) => 3.,
+            Self::Building(_) => 12.,
+            Self::Start(_) => 20.,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Inactive => INACTIVE_COLOR,
+            Self::Unit(player) | Self::Building(player) | Self::Start(player) => {
+                player_color(player)
+            }
+        }
+    }
+}
+
+/// Minimaps are displayed from the point of view of the local player of
+/// single player games.
+fn player_color(player: Player) -> Color {
+    match player {
+        Player::Player1 => MINIMAP_PLAYER_COLOR,
+        _ => MINIMAP_ENEMY_COLOR,
+    }
+}
+
+/// Transforms map content to its schematic minimap geometry.
+fn geometry(map: &Map) -> Geometry {
+    let bounds = map.metadata().bounds();
+    let size = bounds.size();
+    let scale = size.max_element().recip();
+    let offset = 0.5 * (Vec2::ONE - size * scale);
+    let min = bounds.min();
+    let max = bounds.max();
+    // The north (maximum y) is up.
+    let to_rel = |point: Vec2| offset + Vec2::new(point.x - min.x, max.y - point.y) * scale;
+
+    let mut markers: Vec<Marker> = map
+        .content()
+        .objects()
+        .iter()
+        .map(|object| {
+            let kind = match object.inner() {
+                InnerObject::Active(active) => match active.object_type() {
+                    ActiveObjectType::Building(BuildingType::Base) => {
+                        MarkerKind::Start(active.player())
+                    }
+                    ActiveObjectType::Building(_) => MarkerKind::Building(active.player()),
+                    ActiveObjectType::Unit(_) => MarkerKind::Unit(active.player()),
+                },
+                InnerObject::Inactive(_) => MarkerKind::Inactive,
+            };
+            Marker {
+                center: to_rel(object.placement().position()),
+                size: Vec2::splat((kind.size() * scale).max(MIN_MARKER_SIZE)),
+                kind,
+            }
+        })
+        .collect();
+    // Start positions are drawn last so that they are never hidden.
+    markers.sort_by_key(|marker| marker.kind.layer());
+
+    Geometry {
+        terrain: Rect::from_corners(
+            to_rel(Vec2::new(min.x, max.y)),
+            to_rel(Vec2::new(max.x, min.y)),
+        ),
+        markers,
+    }
+}
+
+/// Rasterizes minimap geometry to a square RGBA image.
+fn render(geometry: &Geometry, resolution: u32) -> Image {
+    let mut data = vec![0; 4 * (resolution * resolution) as usize];
+    let mut drawing = Drawing::new(UVec2::splat(resolution), &mut data);
+    drawing.fill(BACKGROUND_COLOR);
+    drawing.rect(
+        geometry.terrain.center(),
+        geometry.terrain.size(),
+        MINIMAP_TERRAIN_COLOR,
+    );
+    for marker in &geometry.markers {
+        if let MarkerKind::Start(_) = marker.kind {
+            drawing.rect(marker.center, marker.size * 1.4, START_BORDER_COLOR);
+        }
+        drawing.rect(marker.center, marker.size, marker.kind.color());
+    }
+    image(resolution, data)
+}
+
+fn placeholder() -> Image {
+    let mut data = vec![0; 4];
+    Drawing::new(UVec2::ONE, &mut data).fill(PLACEHOLDER_COLOR);
+    image(1, data)
+}
+
+fn image(resolution: u32, data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<MinimapTask>();
+}
+
+fn start_system(
+    mut commands: Commands,
+    minimaps: Res<Minimaps>,
+    task: Option<Res<MinimapTask>>,
+    mut events: EventReader<ShowMinimapEvent>,
+    mut nodes: Query<(&mut UiImage, &mut Visibility), With<MinimapNode>>,
+) {
+    let Some(event) = events.iter().last() else { return };
+
+    if let Some(handle) = minimaps.0.get(&event.0) {
+        commands.remove_resource::<MinimapTask>();
+        display(&mut nodes, handle.clone());
+        return;
+    }
+    if task.map_or(false, |task| task.path == event.0) {
+        return;
+    }
+
+    let path = event.0.clone();
+    let task = IoTaskPool::get().spawn(async move { load_map(&path).await });
+    commands.insert_resource(MinimapTask {
+        path: event.0.clone(),
+        task,
+    });
+}
+
+fn render_system(
+    mut commands: Commands,
+    mut task: ResMut<MinimapTask>,
+    mut minimaps: ResMut<Minimaps>,
+    mut images: ResMut<Assets<Image>>,
+    mut nodes: Query<(&mut UiImage, &mut Visibility), With<MinimapNode>>,
+) {
+    let Some(result) = future::block_on(future::poll_once(&mut task.task)) else { return };
+    commands.remove_resource::<MinimapTask>();
+
+    let image = match result {
+        Ok(map) => render(&geometry(&map), RESOLUTION),
+        Err(error) => {
+            warn!(
+                "Minimap of {} cannot be rendered: {error}",
+                task.path.display()
+            );
+            placeholder()
+        }
+    };
+    let handle = images.add(image);
+    minimaps.0.insert(task.path.clone(), handle.clone());
+    display(&mut nodes, handle);
+}
+
+fn display(
+    nodes: &mut Query<(&mut UiImage, &mut Visibility), With<MinimapNode>>,
+    handle: Handle<Image>,
+) {
+    for (mut image, mut visibility) in nodes.iter_mut() {
+        *image = UiImage::new(handle.clone());
+        *visibility = Visibility::Inherited;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use de_core::objects::{InactiveObjectType, UnitType};
+    use de_map::{
+        content::{ActiveObject, InactiveObject, Object},
+        meta::MapMetadata,
+        size::MapBounds,
+    };
+
+    use super::*;
+
+    fn insert(map: &mut Map, position: Vec2, inner: InnerObject) {
+        let placement = map.new_placement(position, 0.);
+        map.insert_object(Object::new(placement, inner));
+    }
+
+    fn active(object_type: ActiveObjectType, player: Player) -> InnerObject {
+        InnerObject::Active(ActiveObject::new(object_type, player))
+    }
+
+    fn test_map() -> Map {
+        let mut map = Map::empty(MapMetadata::new(
+            "Test".into(),
+            MapBounds::new(Vec2::new(1000., 500.)),
+            Player::Player2,
+        ));
+        insert(
+            &mut map,
+            Vec2::new(-400., 200.),
+            active(
+                ActiveObjectType::Building(BuildingType::Base),
+                Player::Player1,
+            ),
+        );
+        insert(
+            &mut map,
+            Vec2::new(0., 0.),
+            InnerObject::Inactive(InactiveObject::new(InactiveObjectType::Tree)),
+        );
+        insert(
+            &mut map,
+            Vec2::new(400., -200.),
+            active(ActiveObjectType::Unit(UnitType::Attacker), Player::Player2),
+        );
+        map
+    }
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-6),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn test_geometry() {
+        let geometry = geometry(&test_map());
+
+        // The map is twice as wide as it is high.
+        assert_close(geometry.terrain.min, Vec2::new(0., 0.25));
+        assert_close(geometry.terrain.max, Vec2::new(1., 0.75));
+
+        let kinds: Vec<MarkerKind> = geometry.markers.iter().map(|m| m.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MarkerKind::Inactive,
+                MarkerKind::Unit(Player::Player2),
+                MarkerKind::Start(Player::Player1),
+            ]
+        );
+
+        // The north-west base is in the top-left corner.
+        assert_close(geometry.markers[0].center, Vec2::new(0.5, 0.5));
+        assert_close(geometry.markers[1].center, Vec2::new(0.9, 0.7));
+        assert_close(geometry.markers[2].center, Vec2::new(0.1, 0.3));
+
+        // Small objects are enlarged to stay visible.
+        assert_close(geometry.markers[0].size, Vec2::splat(MIN_MARKER_SIZE));
+        assert_close(geometry.markers[2].size, Vec2::splat(0.02));
+    }
+
+    #[test]
+    fn test_geometry_empty() {
+        let map = Map::empty(MapMetadata::new(
+            "Empty".into(),
+            MapBounds::new(Vec2::new(300., 600.)),
+            Player::Player4,
+        ));
+        assert_eq!(
+            geometry(&map),
+            Geometry {
+                terrain: Rect::from_corners(Vec2::new(0.25, 0.), Vec2::new(0.75, 1.)),
+                markers: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let image = render(&geometry(&test_map()), 100);
+        assert_eq!(image.texture_descriptor.size.width, 100);
+        assert_eq!(image.texture_descriptor.size.height, 100);
+
+        let pixel = |x: usize, y: usize| {
+            let offset = 4 * (y * 100 + x);
+            <[u8; 4]>::try_from(&image.data[offset..offset + 4]).unwrap()
+        };
+        let bytes = |color: Color| color.as_rgba_u32().to_le_bytes();
+
+        assert_eq!(pixel(50, 5), bytes(BACKGROUND_COLOR));
+        assert_eq!(pixel(50, 40), bytes(MINIMAP_TERRAIN_COLOR));
+        assert_eq!(pixel(50, 50), bytes(INACTIVE_COLOR));
+        assert_eq!(pixel(10, 30), bytes(MINIMAP_PLAYER_COLOR));
+        assert_eq!(pixel(89, 69), bytes(MINIMAP_ENEMY_COLOR));
+
+        let placeholder = placeholder();
+        assert_eq!(placeholder.data, bytes(PLACEHOLDER_COLOR));
+    }
+}
@@ -0,0 +1,373 @@
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+    time::Stopwatch,
+};
+use de_core::assets::asset_path;
+use de_gui::{ButtonCommands, ButtonOps, GuiCommands, LabelCommands, OuterStyle, ToastEvent};
+use de_lobby_client::{ChangeMapRequest, GetGameRequest, StartGameRequest};
+use de_lobby_model::{Game, GameMap};
+use de_map::{
+    hash::MapHash,
+    io::{verify_map, MapLoadingError, MapVerification},
+};
+use futures_lite::future;
+
+use crate::{
+    mapselection::{MapSelectedEvent, SelectMapEvent},
+    menu::Menu,
+    requests::{Receiver, RequestsPlugin, Sender},
+    MenuState,
+};
+
+/// The game is re-requested this often so that map changes made by the
+/// author of the game are noticed by the other players.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub(crate) struct MultiPlayerPlugin;
+
+impl Plugin for MultiPlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RequestsPlugin::<GetGameRequest>::new())
+            .add_plugin(RequestsPlugin::<ChangeMapRequest>::new())
+            .add_plugin(RequestsPlugin::<StartGameRequest>::new())
+            .add_system(setup.in_schedule(OnEnter(MenuState::MultiPlayerGame)))
+            .add_system(cleanup.in_schedule(OnExit(MenuState::MultiPlayerGame)))
+            .add_system(refresh_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(game_response_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(verification_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(button_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(map_selected_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(change_map_response_system.run_if(in_state(MenuState::MultiPlayerGame)))
+            .add_system(start_response_system.run_if(in_state(MenuState::MultiPlayerGame)));
+    }
+}
+
+/// Lobby game created or joined by the local player.
+#[derive(Resource)]
+pub(crate) struct CurrentGame(String);
+
+impl CurrentGame {
+    pub(crate) fn new(name: String) -> Self {
+        Self(name)
+    }
+}
+
+#[derive(Resource)]
+struct MapButton(Entity);
+
+/// Map of the game and the result of its verification against the local
+/// copy. The verification is None while it is running.
+#[derive(Resource)]
+struct GameMapState {
+    map: GameMap,
+    verification: Option<MapVerification>,
+}
+
+#[derive(Resource)]
+struct MapVerificationTask(Task<Result<MapVerification, MapLoadingError>>);
+
+#[derive(Component, Clone, Copy)]
+enum ButtonAction {
+    ChangeMap,
+    StartGame,
+}
+
+fn setup(
+    mut commands: GuiCommands,
+    menu: Res<Menu>,
+    game: Res<CurrentGame>,
+    mut sender: Sender<GetGameRequest>,
+) {
+    let column_node = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                size: Size::new(Val::Percent(25.), Val::Percent(100.)),
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(menu.root_node()).add_child(column_node);
+
+    let caption = commands
+        .spawn_label(
+            OuterStyle {
+                size: Size::new(Val::Percent(100.), Val::Percent(8.)),
+                margin: UiRect::bottom(Val::Percent(2.)),
+            },
+            game.0.as_str(),
+        )
+        .id();
+    commands.entity(column_node).add_child(caption);
+
+    let map_button = button(&mut commands, column_node, ButtonAction::ChangeMap, "-");
+    commands.insert_resource(MapButton(map_button));
+    button(
+        &mut commands,
+        column_node,
+        ButtonAction::StartGame,
+        "Start Game",
+    );
+
+    sender.send(GetGameRequest::new(game.0.clone()));
+}
+
+fn button(
+    commands: &mut GuiCommands,
+    parent: Entity,
+    action: ButtonAction,
+    caption: &str,
+) -> Entity {
+    let button = commands
+        .spawn_button(
+            OuterStyle {
+                size: Size::new(Val::Percent(100.), Val::Percent(8.)),
+                margin: UiRect::new(
+                    Val::Percent(0.),
+                    Val::Percent(0.),
+                    Val::Percent(2.),
+                    Val::Percent(2.),
+                ),
+            },
+            caption,
+        )
+        .insert(action)
+        .id();
+    commands.entity(parent).add_child(button);
+    button
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<CurrentGame>();
+    commands.remove_resource::<MapButton>();
+    commands.remove_resource::<GameMapState>();
+    commands.remove_resource::<MapVerificationTask>();
+}
+
+fn refresh_system(
+    time: Res<Time>,
+    mut stopwatch: Local<Stopwatch>,
+    game: Res<CurrentGame>,
+    mut sender: Sender<GetGameRequest>,
+) {
+    stopwatch.tick(time.delta());
+    if stopwatch.elapsed() >= REFRESH_INTERVAL {
+        stopwatch.reset();
+        sender.send(GetGameRequest::new(game.0.clone()));
+    }
+}
+
+/// Starts verification of the local copy of the game map whenever the map
+/// changes.
+fn game_response_system(
+    mut commands: Commands,
+    mut receiver: Receiver<GetGameRequest>,
+    state: Option<Res<GameMapState>>,
+    map_button: Res<MapButton>,
+    mut buttons: ButtonOps,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(result) = receiver.receive() else { return };
+    let game: &Game = match result {
+        Ok(game) => game,
+        Err(error) => {
+            toasts.send(ToastEvent::new(error));
+            return;
+        }
+    };
+
+    let map = game.config().map();
+    if state.map_or(false, |state| state.map.hash() == map.hash()) {
+        return;
+    }
+
+    let hash = match MapHash::from_hex(map.hash()) {
+        Ok(hash) => hash,
+        Err(error) => {
+            toasts.send(ToastEvent::new(format!("Invalid map hash: {error}")));
+            return;
+        }
+    };
+
+    buttons
+        .set_text(map_button.0, map_caption(map.name(), None))
+        .unwrap();
+    commands.insert_resource(GameMapState {
+        map: map.clone(),
+        verification: None,
+    });
+    let task = IoTaskPool::get().spawn(async move { verify_map(asset_path("maps"), &hash).await });
+    commands.insert_resource(MapVerificationTask(task));
+}
+
+fn verification_system(
+    mut commands: Commands,
+    task: Option<ResMut<MapVerificationTask>>,
+    state: Option<ResMut<GameMapState>>,
+    map_button: Res<MapButton>,
+    mut buttons: ButtonOps,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let (Some(mut task), Some(mut state)) = (task, state) else { return };
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else { return };
+    commands.remove_resource::<MapVerificationTask>();
+
+    let verification = match result {
+        Ok(verification) => verification,
+        Err(error) => {
+            toasts.send(ToastEvent::new(format!("Map verification error: {error}")));
+            return;
+        }
+    };
+
+    if let Some(problem) = map_problem(state.map.name(), verification) {
+        toasts.send(ToastEvent::new(problem));
+    }
+    buttons
+        .set_text(
+            map_button.0,
+            map_caption(state.map.name(), Some(verification)),
+        )
+        .unwrap();
+    state.verification = Some(verification);
+}
+
+fn button_system(
+    interactions: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    game: Res<CurrentGame>,
+    state: Option<Res<GameMapState>>,
+    mut map_events: EventWriter<SelectMapEvent>,
+    mut sender: Sender<StartGameRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    for (&interaction, &action) in interactions.iter() {
+        if let Interaction::Clicked = interaction {
+            match action {
+                ButtonAction::ChangeMap => map_events.send(SelectMapEvent),
+                ButtonAction::StartGame => {
+                    let Some(state) = state.as_ref() else {
+                        toasts.send(ToastEvent::new("The game is not loaded yet."));
+                        continue;
+                    };
+                    match state.verification {
+                        Some(MapVerification::Matching) => {
+                            sender.send(StartGameRequest::new(game.0.clone()))
+                        }
+                        Some(verification) => toasts.send(ToastEvent::new(
+                            map_problem(state.map.name(), verification).unwrap(),
+                        )),
+                        None => toasts.send(ToastEvent::new("The map is being verified.")),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn map_selected_system(
+    mut events: EventReader<MapSelectedEvent>,
+    game: Res<CurrentGame>,
+    mut sender: Sender<ChangeMapRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    let Some(event) = events.iter().last() else { return };
+    let hash = match MapHash::try_from(event.path()) {
+        Ok(hash) => hash,
+        Err(error) => {
+            toasts.send(ToastEvent::new(format!("Map error: {error}")));
+            return;
+        }
+    };
+
+    sender.send(ChangeMapRequest::new(
+        game.0.clone(),
+        GameMap::new(hash.to_hex(), event.metadata().name().to_owned()),
+    ));
+}
+
+fn change_map_response_system(
+    mut receiver: Receiver<ChangeMapRequest>,
+    game: Res<CurrentGame>,
+    mut sender: Sender<GetGameRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    if let Some(result) = receiver.receive() {
+        match result {
+            // The new map is verified once the game is received.
+            Ok(_) => sender.send(GetGameRequest::new(game.0.clone())),
+            Err(error) => toasts.send(ToastEvent::new(error)),
+        }
+    }
+}
+
+fn start_response_system(
+    mut receiver: Receiver<StartGameRequest>,
+    mut toasts: EventWriter<ToastEvent>,
+) {
+    if let Some(result) = receiver.receive() {
+        match result {
+            Ok(_) => toasts.send(ToastEvent::new("Game started.")),
+            Err(error) => toasts.send(ToastEvent::new(error)),
+        }
+    }
+}
+
+/// Returns caption of the map button, `verification` is None while the map
+/// is being verified.
+fn map_caption(name: &str, verification: Option<MapVerification>) -> String {
+    match verification {
+        Some(MapVerification::Matching) => format!("Map: {name}"),
+        Some(MapVerification::Mismatching) => format!("Map: {name} (differs)"),
+        Some(MapVerification::Missing) => format!("Map: {name} (missing)"),
+        None => format!("Map: {name} (verifying...)"),
+    }
+}
+
+/// Returns a description of a problem with the local copy of the game map,
+/// or None if the local copy matches.
+fn map_problem(name: &str, verification: MapVerification) -> Option<String> {
+    match verification {
+        MapVerification::Matching => None,
+        MapVerification::Mismatching => Some(format!(
+            "Your copy of map \"{name}\" differs from the map used by the game."
+        )),
+        MapVerification::Missing => {
+            Some(format!("Map \"{name}\" used by the game is not available."))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_texts() {
+        assert_eq!(map_caption("Alpha", None), "Map: Alpha (verifying...)");
+        assert_eq!(
+            map_caption("Alpha", Some(MapVerification::Matching)),
+            "Map: Alpha"
+        );
+        assert_eq!(
+            map_caption("Alpha", Some(MapVerification::Missing)),
+            "Map: Alpha (missing)"
+        );
+
+        assert!(map_problem("Alpha", MapVerification::Matching).is_none());
+        assert_eq!(
+            map_problem("Alpha", MapVerification::Missing).unwrap(),
+            "Map \"Alpha\" used by the game is not available."
+        );
+        assert_eq!(
+            map_problem("Alpha", MapVerification::Mismatching).unwrap(),
+            "Your copy of map \"Alpha\" differs from the map used by the game."
+        );
+    }
+}
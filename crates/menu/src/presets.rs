@@ -0,0 +1,214 @@
+use async_std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use de_core::fs::{conf_dir, DirError};
+use de_lobby_model::{GameConfig, Validatable};
+use de_map::{
+    hash::{HexError, MapHash},
+    io::{verify_map, MapLoadingError, MapVerification},
+};
+use thiserror::Error;
+
+use crate::versioned::{decode, encode, Versioned, VersionedError};
+
+const PRESET_FILE_SUFFIX: &str = ".json";
+const MAX_PRESET_NAME_LEN: usize = 32;
+
+/// Returns the directory where game creation presets are stored.
+pub(crate) fn presets_dir() -> Result<PathBuf, DirError> {
+    conf_dir().map(|d| d.join("presets"))
+}
+
+/// Stores game creation settings as a named preset. A previously stored
+/// preset with the same name is overwritten.
+///
+/// # Arguments
+///
+/// * `dir` - directory with stored presets.
+///
+/// * `name` - name of the preset. It must be non-empty and consist solely of
+///   ASCII alphanumeric characters, `-` and `_`.
+///
+/// * `config` - settings to be stored.
+pub(crate) async fn store_preset(
+    dir: &Path,
+    name: &str,
+    config: &GameConfig,
+) -> Result<(), PresetError> {
+    let path = preset_path(dir, name)?;
+    fs::create_dir_all(dir).await?;
+    let json = encode(config)?;
+    fs::write(path, json).await?;
+    Ok(())
+}
+
+/// Loads a named preset of game creation settings and verifies that the map
+/// it refers to is available locally.
+///
+/// # Arguments
+///
+/// * `dir` - directory with stored presets.
+///
+/// * `maps_dir` - directory with locally available maps.
+///
+/// * `name` - name of the preset.
+pub(crate) async fn load_preset(
+    dir: &Path,
+    maps_dir: &Path,
+    name: &str,
+) -> Result<LoadedPreset, PresetError> {
+    let path = preset_path(dir, name)?;
+    let json = fs::read(path).await?;
+    let config: GameConfig = decode(&json)?;
+    config
+        .validate()
+        .map_err(|error| PresetError::Invalid(error.to_string()))?;
+
+    let hash = MapHash::from_hex(config.map().hash())?;
+    let map = verify_map(maps_dir.to_path_buf(), &hash).await?;
+    Ok(LoadedPreset { config, map })
+}
+
+fn preset_path(dir: &Path, name: &str) -> Result<PathBuf, PresetError> {
+    if name.is_empty() {
+        return Err(PresetError::Name("Preset name cannot be empty."));
+    }
+    if name.len() > MAX_PRESET_NAME_LEN {
+        return Err(PresetError::Name("Preset name is too long."));
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(PresetError::Name(
+            "Preset name may contain only letters, digits, `-` and `_`.",
+        ));
+    }
+
+    Ok(dir.join(format!("{name}{PRESET_FILE_SUFFIX}")))
+}
+
+impl Versioned for GameConfig {
+    const VERSION: u32 = 2;
+}
+
+pub(crate) struct LoadedPreset {
+    config: GameConfig,
+    map: MapVerification,
+}
+
+impl LoadedPreset {
+    /// Stored game creation settings.
+    pub(crate) fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    /// Result of verification of the local copy of the preset map.
+    pub(crate) fn map(&self) -> MapVerification {
+        self.map
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum PresetError {
+    #[error("{0}")]
+    Name(&'static str),
+    #[error(transparent)]
+    Dir(#[from] DirError),
+    #[error("preset I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid preset format: {0}")]
+    Format(#[from] VersionedError),
+    #[error("invalid preset: {0}")]
+    Invalid(String),
+    #[error("invalid preset map hash: {0}")]
+    Hash(#[from] HexError),
+    #[error(transparent)]
+    Map(#[from] MapLoadingError),
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+    use bevy::prelude::Vec2;
+    use de_core::player::Player;
+    use de_lobby_model::GameMap;
+    use de_map::{io::store_map, map::Map, meta::MapMetadata, size::MapBounds};
+    use tempfile::Builder;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let map = Map::empty(MapMetadata::new(
+            "Test Map".into(),
+            MapBounds::new(Vec2::new(1000., 2000.)),
+            Player::Player4,
+        ));
+        let hash = map.compute_hash();
+
+        let tmp_dir = Builder::new().prefix("de_menu_").tempdir().unwrap();
+        let maps_dir = PathBuf::from(tmp_dir.path()).join("maps");
+        let presets_dir = PathBuf::from(tmp_dir.path()).join("presets");
+
+        task::block_on(async {
+            fs::create_dir_all(&maps_dir).await.unwrap();
+            store_map(&map, hash.construct_path(maps_dir.clone()))
+                .await
+                .unwrap();
+
+            let config = GameConfig::new(
+                "Tournament".into(),
+                3,
+                GameMap::new(hash.to_hex(), "Test Map".into()),
+            );
+            store_preset(&presets_dir, "finals", &config).await.unwrap();
+
+            let loaded = load_preset(&presets_dir, &maps_dir, "finals")
+                .await
+                .unwrap();
+            assert_eq!(loaded.config().name(), "Tournament");
+            assert_eq!(loaded.config().max_players(), 3);
+            assert_eq!(loaded.config().map().hash(), hash.to_hex());
+            assert_eq!(loaded.config().map().name(), "Test Map");
+            assert_eq!(loaded.map(), MapVerification::Matching);
+
+            let missing = Map::empty(MapMetadata::new(
+                "Missing Map".into(),
+                MapBounds::new(Vec2::new(1000., 2000.)),
+                Player::Player2,
+            ));
+            let config = GameConfig::new(
+                "Tournament".into(),
+                2,
+                GameMap::new(missing.compute_hash().to_hex(), "Missing Map".into()),
+            );
+            store_preset(&presets_dir, "semi_finals", &config)
+                .await
+                .unwrap();
+            let loaded = load_preset(&presets_dir, &maps_dir, "semi_finals")
+                .await
+                .unwrap();
+            assert_eq!(loaded.map(), MapVerification::Missing);
+        });
+    }
+
+    #[test]
+    fn test_preset_name() {
+        let dir = Path::new("presets");
+        assert_eq!(
+            preset_path(dir, "Finals-2_a").unwrap(),
+            dir.join("Finals-2_a.json")
+        );
+        assert!(matches!(preset_path(dir, ""), Err(PresetError::Name(_))));
+        assert!(matches!(
+            preset_path(dir, "../conf"),
+            Err(PresetError::Name(_))
+        ));
+        assert!(matches!(
+            preset_path(dir, &"a".repeat(33)),
+            Err(PresetError::Name(_))
+        ));
+    }
+}
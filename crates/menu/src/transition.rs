@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, ui::FocusPolicy};
+use de_conf::Configuration;
+use de_core::state::AppState;
+
+use crate::MenuState;
+
+/// Color of the transition overlay. It matches the menu background so that
+/// the newly displayed screen fades in from an empty menu.
+const OVERLAY_COLOR: Color = Color::GRAY;
+
+pub(crate) struct TransitionPlugin;
+
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup.in_schedule(OnEnter(AppState::InMenu)))
+            .add_system(cleanup.in_schedule(OnExit(AppState::InMenu)))
+            .add_systems(
+                (
+                    start_system.run_if(resource_changed::<State<MenuState>>()),
+                    animate_system.run_if(resource_exists::<Transition>()),
+                )
+                    .chain()
+                    .distributive_run_if(resource_exists::<Overlay>())
+                    .distributive_run_if(in_state(AppState::InMenu)),
+            );
+    }
+}
+
+/// Full screen node covering the menu during transitions. It does not block
+/// any interactions, thus a transition never delays input handling.
+#[derive(Resource)]
+struct Overlay(Entity);
+
+/// A transition (fade-in) of the currently displayed menu screen.
+#[derive(Resource)]
+struct Transition {
+    start: Duration,
+    duration: Duration,
+}
+
+/// Returns opacity of the transition overlay at a given time since the
+/// start of the transition. The overlay is fully opaque at the start and
+/// fully transparent at the end of the transition.
+fn opacity(elapsed: Duration, duration: Duration) -> f32 {
+    let progress = if duration.is_zero() {
+        1.
+    } else {
+        (elapsed.as_secs_f32() / duration.as_secs_f32()).clamp(0., 1.)
+    };
+    // Smooth step easing.
+    let eased = progress * progress * (3. - 2. * progress);
+    1. - eased
+}
+
+fn setup(mut commands: Commands) {
+    let overlay = commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect::all(Val::Percent(0.)),
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..default()
+            },
+            background_color: OVERLAY_COLOR.into(),
+            focus_policy: FocusPolicy::Pass,
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(2),
+            ..default()
+        })
+        .id();
+    commands.insert_resource(Overlay(overlay));
+}
+
+fn cleanup(mut commands: Commands, overlay: Res<Overlay>) {
+    commands.entity(overlay.0).despawn_recursive();
+    commands.remove_resource::<Overlay>();
+    commands.remove_resource::<Transition>();
+}
+
+/// Starts a transition after each menu state change. A transition in
+/// progress is interrupted and replaced by the new one.
+fn start_system(
+    mut commands: Commands,
+    conf: Res<Configuration>,
+    time: Res<Time>,
+    overlay: Res<Overlay>,
+    mut visibility: Query<&mut Visibility>,
+) {
+    match conf.menu().transition() {
+        Some(duration) => commands.insert_resource(Transition {
+            start: time.elapsed(),
+            duration,
+        }),
+        None => {
+            commands.remove_resource::<Transition>();
+            *visibility.get_mut(overlay.0).unwrap() = Visibility::Hidden;
+        }
+    }
+}
+
+fn animate_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    transition: Res<Transition>,
+    overlay: Res<Overlay>,
+    mut nodes: Query<(&mut Visibility, &mut BackgroundColor)>,
+) {
+    let (mut visibility, mut color) = nodes.get_mut(overlay.0).unwrap();
+
+    let elapsed = time.elapsed().saturating_sub(transition.start);
+    if elapsed >= transition.duration {
+        commands.remove_resource::<Transition>();
+        *visibility = Visibility::Hidden;
+    } else {
+        *visibility = Visibility::Inherited;
+        color.0 = OVERLAY_COLOR.with_a(opacity(elapsed, transition.duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use async_std::{path::Path, task};
+
+    use super::*;
+
+    fn overlay_after_transition(conf: Configuration) -> (bool, Visibility) {
+        let mut app = App::new();
+        app.add_state::<AppState>()
+            .add_state::<MenuState>()
+            .insert_resource(conf)
+            .insert_resource(Time::default())
+            .add_plugin(TransitionPlugin);
+
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::InMenu);
+        app.update();
+        app.world
+            .resource_mut::<NextState<MenuState>>()
+            .set(MenuState::GameListing);
+        app.update();
+
+        let overlay = app.world.resource::<Overlay>().0;
+        (
+            app.world.contains_resource::<Transition>(),
+            *app.world.get::<Visibility>(overlay).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_opacity() {
+        let duration = Duration::from_millis(200);
+        assert_eq!(opacity(Duration::ZERO, duration), 1.);
+        assert_eq!(opacity(Duration::from_millis(100), duration), 0.5);
+        let opacity_early = opacity(Duration::from_millis(50), duration);
+        let opacity_late = opacity(Duration::from_millis(150), duration);
+        assert!(opacity_early > 0.5 && opacity_early < 1.);
+        assert!((opacity_early + opacity_late - 1.).abs() < 1e-6);
+        assert_eq!(opacity(duration, duration), 0.);
+        assert_eq!(opacity(Duration::from_secs(10), duration), 0.);
+        assert_eq!(opacity(Duration::ZERO, Duration::ZERO), 0.);
+    }
+
+    #[test]
+    fn test_reduced_motion() {
+        assert_eq!(
+            overlay_after_transition(Configuration::default()),
+            (true, Visibility::Inherited)
+        );
+
+        let path = env::temp_dir().join("de_menu_test_reduced_motion.yaml");
+        fs::write(&path, "menu:\n  reduced_motion: true\n").unwrap();
+        let conf = task::block_on(Configuration::load(Path::new(path.as_os_str()))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(overlay_after_transition(conf), (false, Visibility::Hidden));
+    }
+}
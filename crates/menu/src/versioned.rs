@@ -0,0 +1,157 @@
+//! Versioned format of files persisted by the menu.
+//!
+//! Each file holds an object with the format version and the data. Data
+//! stored by older versions of the game are upgraded step by step to the
+//! current format when loaded, thus updating the game does not wipe
+//! previously stored settings. Files stored before the versioning was
+//! introduced hold the bare data and are considered to be of version 1.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Persisted data with a versioned format.
+pub(crate) trait Versioned: Serialize + DeserializeOwned {
+    /// Current format version. Versions start at 1.
+    const VERSION: u32;
+
+    /// Upgrades data stored in format `version` to format `version + 1`.
+    ///
+    /// Fields which cannot be upgraded should be removed from the data so
+    /// that defaults are used for them (see `#[serde(default)]`).
+    fn upgrade(version: u32, data: Value) -> Value {
+        let _ = version;
+        data
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+    version: u32,
+    data: Value,
+}
+
+/// Serializes data to JSON in the current format.
+pub(crate) fn encode<T: Versioned>(data: &T) -> Result<Vec<u8>, VersionedError> {
+    let envelope = Envelope {
+        version: T::VERSION,
+        data,
+    };
+    Ok(serde_json::to_vec_pretty(&envelope)?)
+}
+
+/// Deserializes data stored in the current or any older format.
+pub(crate) fn decode<T: Versioned>(json: &[u8]) -> Result<T, VersionedError> {
+    let value: Value = serde_json::from_slice(json)?;
+    let is_envelope = value.as_object().map_or(false, |object| {
+        object.contains_key("version") && object.contains_key("data")
+    });
+    let (mut version, mut data) = if is_envelope {
+        let envelope: RawEnvelope = serde_json::from_value(value)?;
+        (envelope.version, envelope.data)
+    } else {
+        (1, value)
+    };
+
+    if version == 0 || version > T::VERSION {
+        return Err(VersionedError::Version(version));
+    }
+    while version < T::VERSION {
+        data = T::upgrade(version, data);
+        version += 1;
+    }
+
+    Ok(serde_json::from_value(data)?)
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum VersionedError {
+    #[error("unsupported format version {0}, the file might be from a newer version of the game")]
+    Version(u32),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Version 1 stored `{"name": String, "sound": bool}`, version 2 replaced
+    /// `sound` with `volume` and version 3 added `keys`.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Settings {
+        name: String,
+        #[serde(default = "default_volume")]
+        volume: f32,
+        #[serde(default)]
+        keys: Vec<String>,
+    }
+
+    fn default_volume() -> f32 {
+        0.8
+    }
+
+    impl Versioned for Settings {
+        const VERSION: u32 = 3;
+
+        fn upgrade(version: u32, mut data: Value) -> Value {
+            if version == 1 {
+                if let Some(object) = data.as_object_mut() {
+                    if let Some(Value::Bool(sound)) = object.remove("sound") {
+                        object.insert("volume".into(), json!(if sound { 1. } else { 0. }));
+                    }
+                }
+            }
+            data
+        }
+    }
+
+    #[test]
+    fn test_migration() {
+        let v1 = br#"{"name": "Player", "sound": false}"#;
+        assert_eq!(
+            decode::<Settings>(v1).unwrap(),
+            Settings {
+                name: "Player".into(),
+                volume: 0.,
+                keys: Vec::new(),
+            }
+        );
+
+        // A field which cannot be migrated falls back to its default.
+        let v1 = br#"{"name": "Player", "sound": "loud"}"#;
+        assert_eq!(decode::<Settings>(v1).unwrap().volume, 0.8);
+
+        let v2 = br#"{"version": 2, "data": {"name": "Player", "volume": 0.5}}"#;
+        assert_eq!(
+            decode::<Settings>(v2).unwrap(),
+            Settings {
+                name: "Player".into(),
+                volume: 0.5,
+                keys: Vec::new(),
+            }
+        );
+
+        let settings = Settings {
+            name: "Player".into(),
+            volume: 0.25,
+            keys: vec!["F1".into()],
+        };
+        let json = encode(&settings).unwrap();
+        assert_eq!(decode::<Settings>(&json).unwrap(), settings);
+
+        let v4 = br#"{"version": 4, "data": {"name": "Player"}}"#;
+        assert!(matches!(
+            decode::<Settings>(v4),
+            Err(VersionedError::Version(4))
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use async_std::task;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use de_net::{startup, Communicator, NetConf, Network, OutMessage, Peers, MAX_MESSAGE_SIZE};
+
+/// Sender and receiver communicating over the loopback.
+struct Pair {
+    sender: Communicator,
+    receiver: Communicator,
+    target: SocketAddr,
+}
+
+impl Pair {
+    fn new() -> Self {
+        task::block_on(async {
+            let sender = Network::bind(None).await.unwrap();
+            let receiver = Network::bind(None).await.unwrap();
+            let target = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), receiver.port().unwrap());
+
+            Self {
+                sender: startup(sender, NetConf::default()),
+                receiver: startup(receiver, NetConf::default()),
+                target,
+            }
+        })
+    }
+
+    /// Reliably sends `count` messages of `size` bytes and waits until all
+    /// of them are received.
+    fn transfer(&mut self, count: usize, size: usize) {
+        task::block_on(async {
+            for i in 0..count {
+                let message =
+                    OutMessage::new(vec![i as u8; size], true, Peers::Players, vec![self.target]);
+                self.sender.send(message).await.unwrap();
+            }
+
+            for _ in 0..count {
+                self.receiver.recv().await.unwrap();
+            }
+        });
+    }
+}
+
+fn small_reliable_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_reliable");
+    let mut pair = Pair::new();
+
+    for count in [10, 100, 1000] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| pair.transfer(count, 16));
+        });
+    }
+}
+
+fn bulk_reliable_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_reliable");
+    let mut pair = Pair::new();
+
+    for count in [10, 100] {
+        group.throughput(Throughput::Bytes((count * MAX_MESSAGE_SIZE) as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| pair.transfer(count, MAX_MESSAGE_SIZE));
+        });
+    }
+}
+
+criterion_group!(benches, small_reliable_benchmark, bulk_reliable_benchmark);
+criterion_main!(benches);
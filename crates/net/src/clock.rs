@@ -0,0 +1,45 @@
+use std::time::Instant;
+#[cfg(test)]
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Source of current time for time dependent networking logic.
+pub(crate) trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// Clock based on the system monotonic clock.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which is advanced manually. Clones of the clock share the current
+/// time.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct ManualClock(Arc<Mutex<Instant>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
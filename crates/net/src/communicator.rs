@@ -1,13 +1,33 @@
-use std::{marker::PhantomData, mem, net::SocketAddr};
+use std::{
+    marker::PhantomData,
+    mem,
+    net::SocketAddr,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
-use async_std::channel::{Receiver, RecvError, SendError, Sender, TryRecvError};
+use async_std::{
+    channel::{Receiver, RecvError, SendError, Sender, TryRecvError},
+    sync::Arc,
+    task,
+};
 use bincode::{
     config::{BigEndian, Configuration, Limit, Varint},
     decode_from_slice, encode_into_slice, encode_to_vec,
     error::{DecodeError, EncodeError},
 };
-
-use crate::{header::Peers, messages::MAX_MESSAGE_SIZE};
+use tracing::error;
+
+use crate::{
+    delivery::{DeliveryLog, DeliveryStats},
+    fault::{FaultLog, NetworkFaulted},
+    header::Peers,
+    memory::MemoryLog,
+    messages::MAX_MESSAGE_SIZE,
+    observers::{Direction, HeaderType, Observers},
+    peerlog::PeerEvent,
+    transitions::{Recorder, Transition},
+};
 
 const BINCODE_CONF: Configuration<BigEndian, Varint, Limit<MAX_MESSAGE_SIZE>> =
     bincode::config::standard()
@@ -15,22 +35,29 @@ const BINCODE_CONF: Configuration<BigEndian, Varint, Limit<MAX_MESSAGE_SIZE>> =
         .with_variable_int_encoding()
         .with_limit::<MAX_MESSAGE_SIZE>();
 
+/// Interval between checks of reliable delivery progress in
+/// [`Communicator::flush`].
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 /// It cumulatively builds output messages from encodable data items.
 pub struct OutMessageBuilder {
     reliable: bool,
     peers: Peers,
-    targets: Vec<SocketAddr>,
+    destination: Destination,
     buffer: Vec<u8>,
     used: usize,
     messages: Vec<OutMessage>,
 }
 
 impl OutMessageBuilder {
-    pub fn new(reliable: bool, peers: Peers, targets: Vec<SocketAddr>) -> Self {
+    pub fn new<D>(reliable: bool, peers: Peers, destination: D) -> Self
+    where
+        D: Into<Destination>,
+    {
         Self {
             reliable,
             peers,
-            targets,
+            destination: destination.into(),
             buffer: vec![0; MAX_MESSAGE_SIZE],
             used: 0,
             messages: Vec::new(),
@@ -44,8 +71,12 @@ impl OutMessageBuilder {
 
         if self.used > 0 {
             self.buffer.truncate(self.used);
-            let message =
-                OutMessage::new(self.buffer, self.reliable, self.peers, self.targets.clone());
+            let message = OutMessage::new(
+                self.buffer,
+                self.reliable,
+                self.peers,
+                self.destination.clone(),
+            );
             messages.push(message);
         }
 
@@ -66,7 +97,7 @@ impl OutMessageBuilder {
                 self.used = 0;
 
                 let message =
-                    OutMessage::new(data, self.reliable, self.peers, self.targets.clone());
+                    OutMessage::new(data, self.reliable, self.peers, self.destination.clone());
                 self.messages.push(message);
 
                 self.push_inner(payload)
@@ -86,56 +117,127 @@ impl OutMessageBuilder {
     }
 }
 
+/// Recipients of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    /// The message is delivered to the listed targets.
+    Targets(Vec<SocketAddr>),
+    /// The message is delivered to all live connections except the given
+    /// one. Live connections are those from which a datagram was recently
+    /// received and which have not failed.
+    ///
+    /// The recipients are resolved only once the message is being sent, thus
+    /// they are consistent with the actual set of connections. It is not an
+    /// error if the excluded connection is not live (for example it has just
+    /// disconnected).
+    AllExcept(SocketAddr),
+}
+
+impl From<Vec<SocketAddr>> for Destination {
+    fn from(targets: Vec<SocketAddr>) -> Self {
+        Self::Targets(targets)
+    }
+}
+
 /// A message / datagram to be delivered.
 pub struct OutMessage {
     pub(crate) data: Vec<u8>,
     reliable: bool,
+    acked: bool,
     peers: Peers,
-    pub(crate) targets: Vec<SocketAddr>,
+    pub(crate) destination: Destination,
+    /// Tick and content key of a de-duplicated message.
+    pub(crate) dedup: Option<(u32, u64)>,
 }
 
 impl OutMessage {
     /// Creates datagram message from a single encodable item.
     ///
     /// See also [`Self::new`].
-    pub fn encode_single<E>(
+    pub fn encode_single<E, D>(
         message: &E,
         reliable: bool,
         peers: Peers,
-        targets: Vec<SocketAddr>,
+        destination: D,
     ) -> Result<Self, EncodeError>
     where
         E: bincode::Encode,
+        D: Into<Destination>,
     {
         let data = encode_to_vec(message, BINCODE_CONF)?;
-        Ok(Self::new(data, reliable, peers, targets))
+        Ok(Self::new(data, reliable, peers, destination))
     }
 
     /// # Arguments
     ///
-    /// * `data` - data to be send.
+    /// * `data` - data to be send. It might be empty, for example when the
+    ///   message is a mere signal.
     ///
-    /// * `reliable` - whether to deliver the data reliably.
+    /// * `reliable` - whether to deliver the data reliably. Reliable messages
+    ///   are re-sent until confirmed and forgotten right afterwards. They are
+    ///   delivered at most once, but not necessarily in order.
     ///
-    /// * `targets` - list of message recipients.
+    /// * `destination` - message recipients, for example a list of target
+    ///   addresses.
     ///
     /// # Panics
     ///
     /// Panics if data is longer than [`MAX_MESSAGE_SIZE`].
-    pub fn new(data: Vec<u8>, reliable: bool, peers: Peers, targets: Vec<SocketAddr>) -> Self {
-        assert!(data.len() < MAX_MESSAGE_SIZE);
+    pub fn new<D>(data: Vec<u8>, reliable: bool, peers: Peers, destination: D) -> Self
+    where
+        D: Into<Destination>,
+    {
+        assert!(data.len() <= MAX_MESSAGE_SIZE);
         Self {
             data,
             reliable,
+            acked: false,
             peers,
-            targets,
+            destination: destination.into(),
+            dedup: None,
         }
     }
 
+    /// Requests confirmation of delivery of an unreliable message. The
+    /// confirmations are used solely to measure delivery (see
+    /// [`Communicator::delivery_stats`]), the message is never re-sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the message is reliable.
+    pub fn with_confirmation(mut self) -> Self {
+        assert!(!self.reliable);
+        self.acked = true;
+        self
+    }
+
+    /// Opts in to send-side de-duplication. The message is not sent to
+    /// targets to which a message with the same `key` was already sent
+    /// during the same `tick`, and it is dropped if there is no other target.
+    ///
+    /// This is useful when several independent systems might broadcast the
+    /// same content during a single game tick.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - the game tick (e.g. simulation tick) during which the
+    ///   message is sent. Keys of previous ticks are forgotten once a message
+    ///   of another tick is sent.
+    ///
+    /// * `key` - a caller provided key of the message content, e.g. its hash.
+    pub fn with_dedup(mut self, tick: u32, key: u64) -> Self {
+        self.dedup = Some((tick, key));
+        self
+    }
+
     pub(crate) fn reliable(&self) -> bool {
         self.reliable
     }
 
+    pub(crate) fn acked(&self) -> bool {
+        self.acked
+    }
+
     pub(crate) fn peers(&self) -> Peers {
         self.peers
     }
@@ -147,15 +249,23 @@ pub struct InMessage {
     reliable: bool,
     peers: Peers,
     source: SocketAddr,
+    received: Instant,
 }
 
 impl InMessage {
-    pub(crate) fn new(data: Vec<u8>, reliable: bool, peers: Peers, source: SocketAddr) -> Self {
+    pub(crate) fn new(
+        data: Vec<u8>,
+        reliable: bool,
+        peers: Peers,
+        source: SocketAddr,
+        received: Instant,
+    ) -> Self {
         Self {
             data,
             reliable,
             peers,
             source,
+            received,
         }
     }
 
@@ -187,6 +297,24 @@ impl InMessage {
     pub fn peers(&self) -> Peers {
         self.peers
     }
+
+    /// Time when the datagram carrying the message was received from the
+    /// socket. Note that reliable messages might have been sent
+    /// significantly earlier due to resends.
+    pub fn received(&self) -> Instant {
+        self.received
+    }
+
+    /// Returns the age of the message at `time`, i.e. the time elapsed since
+    /// it was received (see [`Self::received`]). Zero is returned if `time`
+    /// precedes the receiving.
+    ///
+    /// Passing the time at which the message is processed gives the delay
+    /// introduced by the networking stack and by the application, e.g. to
+    /// reject stale commands or to measure processing lag.
+    pub fn age(&self, time: Instant) -> Duration {
+        time.saturating_duration_since(self.received)
+    }
 }
 
 /// An iterator which decodes binary input data item by item.
@@ -240,18 +368,38 @@ pub struct Communicator {
     outputs: Sender<OutMessage>,
     inputs: Receiver<InMessage>,
     errors: Receiver<ConnectionError>,
+    observers: Arc<Observers>,
+    log: Recorder,
+    deliveries: DeliveryLog,
+    memory: MemoryLog,
+    faults: FaultLog,
+    /// Dedicated thread running the networking tasks (if any).
+    thread: Option<JoinHandle<()>>,
 }
 
 impl Communicator {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         outputs: Sender<OutMessage>,
         inputs: Receiver<InMessage>,
         errors: Receiver<ConnectionError>,
+        observers: Arc<Observers>,
+        log: Recorder,
+        deliveries: DeliveryLog,
+        memory: MemoryLog,
+        faults: FaultLog,
+        thread: Option<JoinHandle<()>>,
     ) -> Self {
         Self {
             outputs,
             inputs,
             errors,
+            observers,
+            log,
+            deliveries,
+            memory,
+            faults,
+            thread,
         }
     }
 
@@ -259,17 +407,166 @@ impl Communicator {
         self.inputs.recv().await
     }
 
+    /// Retrieves up to `max` received messages without waiting, leaving the
+    /// rest for subsequent calls. This suits applications polling the
+    /// messages once per frame or per fixed time step. The messages are
+    /// returned in the order of their delivery by the networking tasks, i.e.
+    /// in the same order as they would be returned by [`Self::recv`].
+    ///
+    /// Retrieving the messages makes room for more messages, see
+    /// [`crate::NetConf::with_message_capacity`].
+    pub fn drain_inbound(&mut self, max: usize) -> Vec<InMessage> {
+        let mut messages = Vec::with_capacity(max.min(self.inputs.len()));
+        while messages.len() < max {
+            match self.inputs.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(_) => break,
+            }
+        }
+        messages
+    }
+
+    /// Returns the number of received messages waiting to be retrieved with
+    /// [`Self::recv`].
+    pub fn inbound_len(&self) -> usize {
+        self.inputs.len()
+    }
+
     pub async fn send(&mut self, message: OutMessage) -> Result<(), SendError<OutMessage>> {
         self.outputs.send(message).await
     }
 
+    /// Waits until all reliable messages sent so far are either confirmed
+    /// by their recipients or failed (see [`Self::errors`]), but at most
+    /// `timeout`. This is useful before the communicator is dropped, for
+    /// example to give farewell messages a chance to be delivered.
+    ///
+    /// Delivery progress is accounted periodically, thus the call lasts at
+    /// least a fraction of a second.
+    ///
+    /// Returns false if the timeout elapsed before that.
+    pub async fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        // Accountings done after the networking tasks picked up all the sent
+        // messages reflect them.
+        let mut since = None;
+
+        loop {
+            if since.is_none() && self.outputs.is_empty() {
+                since = Some(self.memory.updates());
+            }
+            if let Some(since) = since {
+                if self.memory.updates() > since && self.memory.unconfirmed() == 0 {
+                    return true;
+                }
+            }
+
+            let time = Instant::now();
+            if time >= deadline {
+                return false;
+            }
+            task::sleep(FLUSH_POLL_INTERVAL.min(deadline - time)).await;
+        }
+    }
+
     pub fn errors(&mut self) -> Result<ConnectionError, TryRecvError> {
         self.errors.try_recv()
     }
+
+    /// Registers an observer called whenever a datagram with a header of
+    /// `header_type` is sent or received. The observer is called with the
+    /// direction of the datagram, the address of the remote peer and the
+    /// length of the datagram in bytes.
+    ///
+    /// Datagrams sent to multiple targets are observed once per target.
+    ///
+    /// The observer is called directly from the networking tasks, thus it
+    /// must be cheap and must not block. Any heavy work should be offloaded,
+    /// for example by sending the observation over a channel.
+    pub fn observe<F>(&self, header_type: HeaderType, observer: F)
+    where
+        F: Fn(Direction, SocketAddr, usize) + Send + Sync + 'static,
+    {
+        self.observers.register(header_type, Box::new(observer));
+    }
+
+    /// Returns recorded protocol state transitions in the order in which they
+    /// happened. The log is empty unless enabled with
+    /// [`crate::NetConf::with_transition_log`].
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.log.transitions().snapshot()
+    }
+
+    /// Returns significant lifecycle and health events of the connection to
+    /// a peer in the order in which they happened. Only a limited number of
+    /// the most recent events is kept for each peer.
+    pub fn peer_events(&self, addr: SocketAddr) -> Vec<PeerEvent> {
+        self.log.peers().snapshot(addr)
+    }
+
+    /// Returns delivery statistics of unreliable messages sent to a peer
+    /// with a confirmation request (see [`OutMessage::with_confirmation`]).
+    /// None is returned if no such message was sent to the peer recently.
+    pub fn delivery_stats(&self, addr: SocketAddr) -> Option<DeliveryStats> {
+        self.deliveries.get(addr)
+    }
+
+    /// Returns the approximate memory footprint (in bytes) of the state kept
+    /// for the connection to a peer or None if no state is kept. The value
+    /// is refreshed periodically.
+    pub fn memory_usage(&self, addr: SocketAddr) -> Option<usize> {
+        self.memory.get(addr)
+    }
+
+    /// Returns the number of connections closed due to exceeding the memory
+    /// limit, see [`crate::NetConf::with_memory_limit`].
+    pub fn memory_exceeded(&self) -> u64 {
+        self.memory.exceeded_count()
+    }
+
+    /// Returns the number of received confirmations of datagrams which were
+    /// not waiting for a confirmation, for example late confirmations of
+    /// datagrams already given up on or redundant confirmations. Such
+    /// confirmations are ignored.
+    pub fn stale_confirmations(&self) -> u64 {
+        self.log.counters().stale_confirmations()
+    }
+
+    /// Returns the number of received datagrams dropped because they did not
+    /// match the application protocol ID, see
+    /// [`crate::NetConf::with_protocol_id`].
+    pub fn foreign_datagrams(&self) -> u64 {
+        self.log.counters().foreign_datagrams()
+    }
+
+    /// Returns the fatal failure of the networking tasks, if any. After a
+    /// fault, no more messages are received and [`Self::recv`] eventually
+    /// fails.
+    pub fn fault(&self) -> Option<NetworkFaulted> {
+        self.faults.get()
+    }
+}
+
+impl Drop for Communicator {
+    /// Closes the communication channels, which terminates the networking
+    /// tasks. The networking thread is joined if the tasks run on a dedicated
+    /// thread.
+    fn drop(&mut self) {
+        self.outputs.close();
+        self.inputs.close();
+        self.errors.close();
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Networking thread panicked.");
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use async_std::{channel::bounded, task};
     use bincode::Decode;
 
     use super::*;
@@ -312,6 +609,92 @@ mod tests {
         assert!(messages[3].data.len() < 128 * 2);
     }
 
+    #[test]
+    fn test_inbound_len() {
+        let (outputs_sender, _outputs_receiver) = bounded(16);
+        let (inputs_sender, inputs_receiver) = bounded(16);
+        let (_errors_sender, errors_receiver) = bounded(16);
+        let mut communicator = Communicator::new(
+            outputs_sender,
+            inputs_receiver,
+            errors_receiver,
+            Arc::new(Observers::default()),
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+            FaultLog::default(),
+            None,
+        );
+        assert_eq!(communicator.inbound_len(), 0);
+
+        for i in 1..=3 {
+            inputs_sender
+                .try_send(InMessage::new(
+                    vec![i],
+                    false,
+                    Peers::Players,
+                    "127.0.0.1:1111".parse().unwrap(),
+                    Instant::now(),
+                ))
+                .unwrap();
+            assert_eq!(communicator.inbound_len(), i as usize);
+        }
+
+        task::block_on(communicator.recv()).unwrap();
+        assert_eq!(communicator.inbound_len(), 2);
+    }
+
+    #[test]
+    fn test_drain_inbound() {
+        let (outputs_sender, _outputs_receiver) = bounded(16);
+        let (inputs_sender, inputs_receiver) = bounded(8);
+        let (_errors_sender, errors_receiver) = bounded(16);
+        let mut communicator = Communicator::new(
+            outputs_sender,
+            inputs_receiver,
+            errors_receiver,
+            Arc::new(Observers::default()),
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+            FaultLog::default(),
+            None,
+        );
+        assert!(communicator.drain_inbound(4).is_empty());
+
+        let mut next = 0u8;
+        let mut drained = Vec::new();
+        for batch in [3, 0, 5, 1, 8] {
+            // Keep the channel full as the networking tasks would.
+            while inputs_sender
+                .try_send(InMessage::new(
+                    vec![next],
+                    true,
+                    Peers::Players,
+                    "127.0.0.1:1111".parse().unwrap(),
+                    Instant::now(),
+                ))
+                .is_ok()
+            {
+                next += 1;
+            }
+
+            let messages = communicator.drain_inbound(batch);
+            assert_eq!(messages.len(), batch);
+            assert_eq!(communicator.inbound_len(), 8 - batch);
+            drained.extend(messages.into_iter().map(|message| message.data()[0]));
+        }
+        drained.extend(
+            communicator
+                .drain_inbound(usize::MAX)
+                .into_iter()
+                .map(|message| message.data()[0]),
+        );
+
+        assert_eq!(drained, (0..next).collect::<Vec<u8>>());
+        assert_eq!(communicator.inbound_len(), 0);
+    }
+
     #[test]
     fn test_decoding() {
         #[derive(Decode, Debug, Eq, PartialEq)]
@@ -326,6 +709,7 @@ mod tests {
             reliable: false,
             peers: Peers::Players,
             source: "127.0.0.1:1111".parse().unwrap(),
+            received: Instant::now(),
         };
 
         let mut items: MessageDecoder<Message> = message.decode();
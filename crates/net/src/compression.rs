@@ -0,0 +1,304 @@
+//! Compression of message payloads with Zstandard.
+//!
+//! Small game messages compress poorly on their own because there is little
+//! repetition within a single message. A dictionary trained on typical
+//! payloads (see [`Dictionary::train`]) captures the structure shared by the
+//! messages (field names, enum tags, common values) and greatly improves the
+//! ratio.
+//!
+//! Both peers must use the same dictionary. Each side advertises
+//! [`Codec::dictionary_id`] during its handshake (for example in the join
+//! message) and passes the identifier received from the other side to
+//! [`Codec::negotiate`]. Payloads sent to a peer with a different or no
+//! dictionary are compressed without the dictionary.
+//!
+//! Each compressed payload starts with a single byte denoting the
+//! compression mode, thus payloads compressed with and without the
+//! dictionary (or not compressed at all) might be freely mixed.
+
+use std::io;
+
+use thiserror::Error;
+use zstd::{
+    bulk::{Compressor, Decompressor},
+    zstd_safe::{get_dict_id_from_dict, CParameter},
+    DEFAULT_COMPRESSION_LEVEL,
+};
+
+/// Maximum size of a decompressed payload.
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+
+/// The payload is stored as is because compression would not make it
+/// smaller.
+const MODE_RAW: u8 = 0;
+const MODE_PLAIN: u8 = 1;
+const MODE_DICTIONARY: u8 = 2;
+
+/// Zstandard compression dictionary.
+pub struct Dictionary {
+    id: u32,
+    data: Vec<u8>,
+}
+
+impl Dictionary {
+    /// Creates a dictionary from its serialized form, for example from a
+    /// dictionary shipped with the game or from [`Self::data`].
+    pub fn new(data: Vec<u8>) -> Result<Self, CompressionError> {
+        let id = get_dict_id_from_dict(&data).ok_or(CompressionError::InvalidDictionary)?;
+        Ok(Self { id: id.get(), data })
+    }
+
+    /// Trains a dictionary on representative payloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - typical payloads. Hundreds or more samples are needed
+    ///   to train a useful dictionary.
+    ///
+    /// * `max_size` - maximum size of the dictionary in bytes.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self, CompressionError> {
+        let data =
+            zstd::dict::from_samples(samples, max_size).map_err(CompressionError::Training)?;
+        Self::new(data)
+    }
+
+    /// Identifier of the dictionary, it is (almost surely) different for
+    /// different dictionaries.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Serialized form of the dictionary.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Compressor and decompressor of message payloads. See the [module level
+/// documentation](self).
+pub struct Codec {
+    compressor: Compressor<'static>,
+    decompressor: Decompressor<'static>,
+    dictionary: Option<DictionaryCodec>,
+}
+
+struct DictionaryCodec {
+    id: u32,
+    compressor: Compressor<'static>,
+    decompressor: Decompressor<'static>,
+}
+
+impl Codec {
+    /// # Arguments
+    ///
+    /// * `dictionary` - dictionary used for compression of payloads sent to
+    ///   peers with the same dictionary. Without a dictionary, all payloads
+    ///   are compressed without it and payloads compressed with a dictionary
+    ///   cannot be decompressed.
+    pub fn new(dictionary: Option<&Dictionary>) -> Result<Self, CompressionError> {
+        let dictionary = match dictionary {
+            Some(dictionary) => Some(DictionaryCodec {
+                id: dictionary.id,
+                compressor: compressor(Compressor::with_dictionary(
+                    DEFAULT_COMPRESSION_LEVEL,
+                    &dictionary.data,
+                )?)?,
+                decompressor: Decompressor::with_dictionary(&dictionary.data)?,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            compressor: compressor(Compressor::new(DEFAULT_COMPRESSION_LEVEL)?)?,
+            decompressor: Decompressor::new()?,
+            dictionary,
+        })
+    }
+
+    /// Returns ID of the dictionary to be advertised to the other side or
+    /// None if the codec has no dictionary.
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary.as_ref().map(|dictionary| dictionary.id)
+    }
+
+    /// Returns true if payloads to a peer which advertised dictionary ID
+    /// `remote` might be compressed with the dictionary.
+    pub fn negotiate(&self, remote: Option<u32>) -> bool {
+        remote.is_some() && self.dictionary_id() == remote
+    }
+
+    /// Compresses a payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - the payload.
+    ///
+    /// * `with_dictionary` - whether the dictionary is to be used, see
+    ///   [`Self::negotiate`]. The payload is compressed without the
+    ///   dictionary if the codec has none.
+    pub fn compress(
+        &mut self,
+        data: &[u8],
+        with_dictionary: bool,
+    ) -> Result<Vec<u8>, CompressionError> {
+        let (mode, compressed) = match self.dictionary.as_mut() {
+            Some(dictionary) if with_dictionary => {
+                (MODE_DICTIONARY, dictionary.compressor.compress(data)?)
+            }
+            _ => (MODE_PLAIN, self.compressor.compress(data)?),
+        };
+
+        let (mode, body) = if compressed.len() < data.len() {
+            (mode, compressed.as_slice())
+        } else {
+            (MODE_RAW, data)
+        };
+
+        let mut payload = Vec::with_capacity(1 + body.len());
+        payload.push(mode);
+        payload.extend_from_slice(body);
+        Ok(payload)
+    }
+
+    /// Decompresses a payload created with [`Self::compress`].
+    pub fn decompress(&mut self, payload: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let Some((&mode, body)) = payload.split_first() else {
+            return Err(CompressionError::Empty);
+        };
+
+        match mode {
+            MODE_RAW => Ok(body.to_vec()),
+            MODE_PLAIN => Ok(self.decompressor.decompress(body, MAX_PAYLOAD_SIZE)?),
+            MODE_DICTIONARY => match self.dictionary.as_mut() {
+                Some(dictionary) => {
+                    Ok(dictionary.decompressor.decompress(body, MAX_PAYLOAD_SIZE)?)
+                }
+                None => Err(CompressionError::MissingDictionary),
+            },
+            mode => Err(CompressionError::UnknownMode(mode)),
+        }
+    }
+}
+
+/// Configures a compressor for small payloads: the checksum, content size
+/// and dictionary ID are omitted, the latter is negotiated out of band.
+fn compressor(mut compressor: Compressor<'static>) -> io::Result<Compressor<'static>> {
+    compressor.set_parameter(CParameter::ChecksumFlag(false))?;
+    compressor.set_parameter(CParameter::ContentSizeFlag(false))?;
+    compressor.set_parameter(CParameter::DictIdFlag(false))?;
+    Ok(compressor)
+}
+
+#[derive(Error, Debug)]
+pub enum CompressionError {
+    #[error("invalid compression dictionary")]
+    InvalidDictionary,
+    #[error("compression dictionary training failed")]
+    Training(#[source] io::Error),
+    #[error("the payload is compressed with an unavailable dictionary")]
+    MissingDictionary,
+    #[error("unknown compression mode {0}")]
+    UnknownMode(u8),
+    #[error("the payload is empty")]
+    Empty,
+    #[error("(de)compression failed")]
+    Zstd(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns payloads resembling entity state updates.
+    fn payloads(rng: &fastrand::Rng, count: usize) -> Vec<Vec<u8>> {
+        const KINDS: [&str; 4] = ["base", "powerhub", "attacker", "excavator"];
+
+        (0..count)
+            .map(|_| {
+                format!(
+                    "{{\"entity\":{},\"kind\":\"{}\",\"player\":\"Player{}\",\
+                     \"position\":[{:.1},{:.1}],\"health\":{},\"moving\":{}}}",
+                    rng.u32(0..5000),
+                    KINDS[rng.usize(0..KINDS.len())],
+                    rng.u8(1..5),
+                    rng.f32() * 1000.,
+                    rng.f32() * 1000.,
+                    rng.u16(0..1000),
+                    rng.bool(),
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let rng = fastrand::Rng::with_seed(3);
+        let dictionary = Dictionary::train(&payloads(&rng, 2000), 4096).unwrap();
+        let dictionary = Dictionary::new(dictionary.data().to_vec()).unwrap();
+        let mut codec = Codec::new(Some(&dictionary)).unwrap();
+        assert_eq!(codec.dictionary_id(), Some(dictionary.id()));
+
+        let mut plain_size = 0;
+        let mut dictionary_size = 0;
+        for payload in payloads(&rng, 200) {
+            let plain = codec.compress(&payload, false).unwrap();
+            assert_eq!(codec.decompress(&plain).unwrap(), payload);
+            plain_size += plain.len();
+
+            let compressed = codec.compress(&payload, true).unwrap();
+            assert_eq!(compressed[0], MODE_DICTIONARY);
+            assert!(compressed.len() < payload.len());
+            assert_eq!(codec.decompress(&compressed).unwrap(), payload);
+            dictionary_size += compressed.len();
+        }
+        assert!(
+            2 * dictionary_size < plain_size,
+            "{dictionary_size} >= {plain_size} / 2"
+        );
+    }
+
+    #[test]
+    fn test_fallback() {
+        let rng = fastrand::Rng::with_seed(5);
+        let dictionary = Dictionary::train(&payloads(&rng, 2000), 4096).unwrap();
+        let mut with_dictionary = Codec::new(Some(&dictionary)).unwrap();
+        let mut without_dictionary = Codec::new(None).unwrap();
+
+        assert!(with_dictionary.negotiate(Some(dictionary.id())));
+        assert!(!with_dictionary.negotiate(Some(dictionary.id().wrapping_add(1))));
+        assert!(!with_dictionary.negotiate(None));
+        assert!(!without_dictionary.negotiate(without_dictionary.dictionary_id()));
+
+        let payload = payloads(&rng, 1).pop().unwrap();
+        let compressed = without_dictionary.compress(&payload, true).unwrap();
+        assert_ne!(compressed[0], MODE_DICTIONARY);
+        assert_eq!(with_dictionary.decompress(&compressed).unwrap(), payload);
+
+        let compressed = with_dictionary.compress(&payload, true).unwrap();
+        assert!(matches!(
+            without_dictionary.decompress(&compressed),
+            Err(CompressionError::MissingDictionary)
+        ));
+
+        // Incompressible data are stored as they are.
+        let noise: Vec<u8> = (0..64).map(|_| rng.u8(..)).collect();
+        let stored = with_dictionary.compress(&noise, false).unwrap();
+        assert_eq!(stored[0], MODE_RAW);
+        assert_eq!(stored.len(), noise.len() + 1);
+        assert_eq!(without_dictionary.decompress(&stored).unwrap(), noise);
+
+        assert!(matches!(
+            without_dictionary.decompress(&[]),
+            Err(CompressionError::Empty)
+        ));
+        assert!(matches!(
+            without_dictionary.decompress(&[9, 1]),
+            Err(CompressionError::UnknownMode(9))
+        ));
+        assert!(matches!(
+            Dictionary::new(vec![1, 2, 3]),
+            Err(CompressionError::InvalidDictionary)
+        ));
+    }
+}
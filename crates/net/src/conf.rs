@@ -0,0 +1,386 @@
+use std::time::Duration;
+
+/// Maximum number of times a single batch of datagram confirmations might be
+/// sent.
+pub const MAX_CONFIRM_REDUNDANCY: u8 = 4;
+
+/// Default capacity of channels between the socket tasks and the processing
+/// loop.
+const DEFAULT_DATAGRAM_CAPACITY: usize = 16;
+/// Default capacity of channels between the processing loop and the
+/// application.
+const DEFAULT_MESSAGE_CAPACITY: usize = 1024;
+/// Default maximum amount of work done during a single iteration of the
+/// processing loop.
+const DEFAULT_WORK_BUDGET: usize = 64;
+
+/// Default time after which a peer from which nothing was received is
+/// considered disconnected.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Default number of malformed datagrams received from a peer within
+/// [`DEFAULT_MALFORMED_WINDOW`] at which the peer is disconnected.
+const DEFAULT_MALFORMED_LIMIT: usize = 32;
+const DEFAULT_MALFORMED_WINDOW: Duration = Duration::from_secs(10);
+/// Default maximum memory footprint (in bytes) of a single connection.
+const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+/// Default maximum time the processing loop sleeps while there is nothing
+/// to do.
+const DEFAULT_IDLE_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Configuration of the communication stack started with
+/// [`crate::startup`].
+#[derive(Clone, Copy, Debug)]
+pub struct NetConf {
+    confirm_redundancy: u8,
+    dedicated_thread: bool,
+    inbound_watermark: Option<usize>,
+    datagram_capacity: usize,
+    message_capacity: usize,
+    work_budget: usize,
+    protocol_id: Option<u16>,
+    transition_log: Option<usize>,
+    confirm_priority: bool,
+    receive_window: Option<usize>,
+    idle_timeout: Duration,
+    malformed_limit: Option<(usize, Duration)>,
+    memory_limit: Option<usize>,
+    idle_interval: Option<Duration>,
+}
+
+impl NetConf {
+    /// Sets the number of times each batch of datagram confirmations is sent.
+    ///
+    /// A lost confirmation leads to an unnecessary retransmission of the
+    /// confirmed data. Sending the confirmations redundantly reduces the
+    /// chance of this happening on lossy links at the expense of some
+    /// bandwidth.
+    ///
+    /// Confirmations are sent only once by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `redundancy` is 0 or larger than [`MAX_CONFIRM_REDUNDANCY`].
+    pub fn with_confirm_redundancy(mut self, redundancy: u8) -> Self {
+        assert!(redundancy > 0);
+        assert!(redundancy <= MAX_CONFIRM_REDUNDANCY);
+        self.confirm_redundancy = redundancy;
+        self
+    }
+
+    /// Sets whether the networking tasks run on a dedicated thread with its
+    /// own executor instead of the shared async-std executor.
+    ///
+    /// This decouples the network I/O from other tasks scheduled on the shared
+    /// executor, for example on busy servers. The thread is joined when the
+    /// [`crate::Communicator`] is dropped.
+    ///
+    /// The shared executor is used by default.
+    pub fn with_dedicated_thread(mut self, dedicated: bool) -> Self {
+        self.dedicated_thread = dedicated;
+        self
+    }
+
+    /// Sets a number of received messages waiting for processing by the
+    /// application (see [`crate::Communicator::inbound_len`]) above which a
+    /// warning is logged. This indicates that the application does not keep up
+    /// with incoming messages.
+    ///
+    /// No warning is logged by default.
+    pub fn with_inbound_watermark(mut self, watermark: Option<usize>) -> Self {
+        self.inbound_watermark = watermark;
+        self
+    }
+
+    /// Sets capacity of the channels between UDP socket handling tasks and
+    /// the processing loop, i.e. the number of datagrams waiting to be sent
+    /// or processed.
+    ///
+    /// The socket handling tasks pause once a channel is full. Incoming
+    /// datagrams are then buffered only by the OS socket buffer.
+    ///
+    /// Default is 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_datagram_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        self.datagram_capacity = capacity;
+        self
+    }
+
+    /// Sets capacity of the channels between the processing loop and the
+    /// application (see [`crate::Communicator`]), i.e. the number of
+    /// messages and errors waiting to be sent or retrieved.
+    ///
+    /// Sending via the communicator waits once the outgoing channel is full.
+    /// Processing of incoming datagrams pauses once the incoming channel is
+    /// full.
+    ///
+    /// Default is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_message_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        self.message_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum amount of work done during a single iteration of the
+    /// processing loop. A unit of work is processing of a single received
+    /// datagram, sending of a single confirmation datagram or re-sending of a
+    /// single datagram. Remaining work is deferred to subsequent iterations
+    /// so that a large backlog (e.g. a burst of incoming datagrams) does not
+    /// block the loop, and thus other tasks sharing its thread, for too long.
+    ///
+    /// The budget is checked between connections when sending confirmations,
+    /// thus it might be slightly exceeded.
+    ///
+    /// Default is 64.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is 0.
+    pub fn with_work_budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0);
+        self.work_budget = budget;
+        self
+    }
+
+    /// Sets an application protocol ID. If set, the ID is prepended to each
+    /// sent datagram and received datagrams not starting with the same ID
+    /// are dropped. This prevents datagrams of unrelated applications (or of
+    /// incompatible versions of the game) from being misinterpreted.
+    ///
+    /// Both communicating sides must use the same ID. The ID costs
+    /// 2 bytes per datagram, these are reserved in [`crate::MAX_MESSAGE_SIZE`]
+    /// regardless of this setting.
+    ///
+    /// No ID is used by default.
+    pub fn with_protocol_id(mut self, protocol_id: Option<u16>) -> Self {
+        self.protocol_id = protocol_id;
+        self
+    }
+
+    /// Sets the capacity of a log of protocol state transitions (sent,
+    /// received, confirmed and re-sent datagrams, throttling changes and
+    /// connection failures). Once the capacity is reached, the oldest records
+    /// are dropped. The log is retrieved with
+    /// [`crate::Communicator::transitions`].
+    ///
+    /// The log is meant for debugging of the protocol logic. The recording
+    /// has a non-negligible cost, thus it is disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_transition_log(mut self, capacity: Option<usize>) -> Self {
+        assert!(capacity != Some(0));
+        self.transition_log = capacity;
+        self
+    }
+
+    /// Sets whether datagram confirmations are sent before any data
+    /// datagrams waiting to be sent. Otherwise, all datagrams are sent in the
+    /// order in which they were produced.
+    ///
+    /// Confirmations delayed behind bulk data lead to unnecessary
+    /// retransmissions by the peer.
+    ///
+    /// Confirmations are prioritized by default.
+    pub fn with_confirm_priority(mut self, prioritize: bool) -> Self {
+        self.confirm_priority = prioritize;
+        self
+    }
+
+    /// Sets the number of bytes of received messages the application is
+    /// willing to buffer (i.e. messages not yet retrieved with
+    /// [`crate::Communicator::recv`]). The remaining capacity is advertised
+    /// to peers together with datagram confirmations. Peers keep the amount
+    /// of unconfirmed reliable data sent to this side within the advertised
+    /// window, so a slow application is not overrun.
+    ///
+    /// Sending of a reliable message to a target with a full window is
+    /// postponed, as is sending of subsequent reliable messages to the same
+    /// target. Other targets and unreliable messages are not held back. A
+    /// single message is always allowed while nothing is waiting for a
+    /// confirmation, thus a zero window does not stall the connection.
+    ///
+    /// Windows advertised by peers are honored only if a window is
+    /// configured on this side as well. No window is advertised by default,
+    /// peers then send without any limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    pub fn with_receive_window(mut self, window: Option<usize>) -> Self {
+        assert!(window != Some(0));
+        self.receive_window = window;
+        self
+    }
+
+    /// Sets the time after which a peer from which nothing was received, and
+    /// to which no reliable data are waiting for a confirmation, is
+    /// considered disconnected. The peer is then no longer targeted by
+    /// messages sent to all peers and its
+    /// [`crate::PeerEventKind::Disconnected`] event is recorded.
+    ///
+    /// The timeout is 10 minutes by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout` is zero.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero());
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the number of malformed datagrams (e.g. datagrams with an
+    /// invalid header) received from a single live peer within a time window
+    /// at which the peer is disconnected. The peer is then no longer targeted
+    /// by messages sent to all peers and its
+    /// [`crate::PeerEventKind::Disconnected`] event with
+    /// [`crate::DisconnectReason::ProtocolError`] is recorded. Malformed
+    /// datagrams from unknown peers are only dropped.
+    ///
+    /// Occasional malformed datagrams, for example due to transient
+    /// corruption, do not lead to a disconnection as long as they stay below
+    /// the limit.
+    ///
+    /// Peers are disconnected after 32 malformed datagrams within 10 seconds
+    /// by default. None disables the disconnection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is 0 or the window is zero.
+    pub fn with_malformed_limit(mut self, limit: Option<(usize, Duration)>) -> Self {
+        if let Some((count, window)) = limit {
+            assert!(count > 0);
+            assert!(!window.is_zero());
+        }
+        self.malformed_limit = limit;
+        self
+    }
+
+    /// Sets the maximum approximate memory footprint (in bytes) of the state
+    /// kept for a single connection, for example of messages waiting for
+    /// confirmations or of buffered confirmations. A peer whose connection
+    /// exceeds the limit is disconnected: its state is dropped, an error is
+    /// reported via [`crate::Communicator::errors`] and its
+    /// [`crate::PeerEventKind::Disconnected`] event with
+    /// [`crate::DisconnectReason::MemoryLimit`] is recorded.
+    ///
+    /// The footprint is reported by [`crate::Communicator::memory_usage`].
+    ///
+    /// The limit is 16 MiB by default. None disables the limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is 0.
+    pub fn with_memory_limit(mut self, limit: Option<usize>) -> Self {
+        assert!(limit != Some(0));
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Sets the maximum time the processing loop sleeps after an iteration
+    /// in which there was nothing to do (nothing received, sent, confirmed
+    /// or re-sent). The loop wakes up immediately once a datagram is
+    /// received or a message is sent by the application, thus only
+    /// time-driven work (batched confirmations, re-sends) is delayed, by at
+    /// most the interval.
+    ///
+    /// Longer intervals reduce CPU usage of idle connections.
+    ///
+    /// The interval is 5 milliseconds by default. None disables sleeping,
+    /// the loop then only yields to other tasks between iterations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the interval is zero.
+    pub fn with_idle_interval(mut self, interval: Option<Duration>) -> Self {
+        assert!(interval.map_or(true, |interval| !interval.is_zero()));
+        self.idle_interval = interval;
+        self
+    }
+
+    pub(crate) fn confirm_redundancy(&self) -> u8 {
+        self.confirm_redundancy
+    }
+
+    pub(crate) fn dedicated_thread(&self) -> bool {
+        self.dedicated_thread
+    }
+
+    pub(crate) fn inbound_watermark(&self) -> Option<usize> {
+        self.inbound_watermark
+    }
+
+    pub(crate) fn datagram_capacity(&self) -> usize {
+        self.datagram_capacity
+    }
+
+    pub(crate) fn message_capacity(&self) -> usize {
+        self.message_capacity
+    }
+
+    pub(crate) fn work_budget(&self) -> usize {
+        self.work_budget
+    }
+
+    pub(crate) fn protocol_id(&self) -> Option<u16> {
+        self.protocol_id
+    }
+
+    pub(crate) fn transition_log(&self) -> Option<usize> {
+        self.transition_log
+    }
+
+    pub(crate) fn confirm_priority(&self) -> bool {
+        self.confirm_priority
+    }
+
+    pub(crate) fn receive_window(&self) -> Option<usize> {
+        self.receive_window
+    }
+
+    pub(crate) fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    pub(crate) fn malformed_limit(&self) -> Option<(usize, Duration)> {
+        self.malformed_limit
+    }
+
+    pub(crate) fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    pub(crate) fn idle_interval(&self) -> Option<Duration> {
+        self.idle_interval
+    }
+}
+
+impl Default for NetConf {
+    fn default() -> Self {
+        Self {
+            confirm_redundancy: 1,
+            dedicated_thread: false,
+            inbound_watermark: None,
+            datagram_capacity: DEFAULT_DATAGRAM_CAPACITY,
+            message_capacity: DEFAULT_MESSAGE_CAPACITY,
+            work_budget: DEFAULT_WORK_BUDGET,
+            protocol_id: None,
+            transition_log: None,
+            confirm_priority: true,
+            receive_window: None,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            malformed_limit: Some((DEFAULT_MALFORMED_LIMIT, DEFAULT_MALFORMED_WINDOW)),
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
+            idle_interval: Some(DEFAULT_IDLE_INTERVAL),
+        }
+    }
+}
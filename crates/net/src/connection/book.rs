@@ -1,31 +1,52 @@
 use std::{
+    mem,
     net::SocketAddr,
     time::{Duration, Instant},
 };
 
 use ahash::AHashMap;
 
-/// Connection info should be tossed away after this time.
+/// Connection info should be tossed away after this time by default.
 const MAX_CONN_AGE: Duration = Duration::from_secs(600);
 
 pub(super) trait Connection {
     /// Returns true if the value holds any pending actions on the connection.
     fn pending(&self) -> bool;
+
+    /// Returns approximate number of heap allocated bytes held by the value.
+    fn memory(&self) -> usize {
+        0
+    }
 }
 
 /// Bookkeeping of per connection data.
 ///
 /// It behaves like a connection storage and a custom cyclic connection
 /// "iterator".
+///
+/// Connections are always traversed in the order of their insertion (the
+/// first [`Self::update`] of the connection), regardless of removals of
+/// other connections. The order does not depend on hashing, thus the same
+/// sequence of operations always leads to the same traversal and the
+/// round-robin processing of connections is predictable.
 pub(super) struct ConnectionBook<T: Connection> {
+    max_age: Duration,
     next_index: usize,
+    /// Addresses of all connections in the order of insertion.
     addrs: Vec<SocketAddr>,
     records: AHashMap<SocketAddr, ConnectionRecord<T>>,
 }
 
 impl<T: Connection> ConnectionBook<T> {
     pub(super) fn new() -> Self {
+        Self::with_max_age(MAX_CONN_AGE)
+    }
+
+    /// Creates a book forgetting connections without any pending activity
+    /// which were not updated for longer than `max_age`.
+    pub(super) fn with_max_age(max_age: Duration) -> Self {
         Self {
+            max_age,
             next_index: 0,
             addrs: Vec::new(),
             records: AHashMap::new(),
@@ -55,15 +76,54 @@ impl<T: Connection> ConnectionBook<T> {
 
     /// Forget all connections which:
     ///
-    /// - has not been actively used for longer than [`MAX_CONN_AGE`],
+    /// - has not been actively used for longer than the maximum age (see
+    ///   [`Self::with_max_age`]),
     /// - have no pending activity.
-    pub(super) fn clean(&mut self, time: Instant) {
-        self.next_index = 0;
-        while let Some((_addr, record)) = self.next_inner() {
-            if record.is_inactive(time) {
-                self.remove_current();
+    ///
+    /// The order of the remaining connections is preserved, thus the cyclic
+    /// "iterator" neither skips nor repeats any of them.
+    ///
+    /// Returns addresses of the forgotten connections.
+    pub(super) fn clean(&mut self, time: Instant) -> Vec<SocketAddr> {
+        let max_age = self.max_age;
+        let next_index = self.next_index;
+        let records = &mut self.records;
+        let mut removed = Vec::new();
+        let mut removed_before_next = 0;
+
+        let mut index = 0;
+        self.addrs.retain(|addr| {
+            let inactive = records[addr].is_inactive(time, max_age);
+            if inactive {
+                records.remove(addr);
+                removed.push(*addr);
+                if index < next_index {
+                    removed_before_next += 1;
+                }
             }
-        }
+            index += 1;
+            !inactive
+        });
+        self.next_index = next_index - removed_before_next;
+
+        removed
+    }
+
+    /// Returns the connection value object or None if there is no record of
+    /// the connection in the book.
+    pub(super) fn get(&self, addr: SocketAddr) -> Option<&T> {
+        self.records.get(&addr).map(|record| &record.value)
+    }
+
+    /// Returns the connection value object or None if there is no record of
+    /// the connection in the book.
+    pub(super) fn get_mut(&mut self, addr: SocketAddr) -> Option<&mut T> {
+        self.records.get_mut(&addr).map(|record| &mut record.value)
+    }
+
+    /// Returns true if there is a record of the connection in the book.
+    pub(super) fn contains(&self, addr: SocketAddr) -> bool {
+        self.records.contains_key(&addr)
     }
 
     /// Yields an element (one by one) from the book. Once all elements are
@@ -85,6 +145,39 @@ impl<T: Connection> ConnectionBook<T> {
         Some((addr, record))
     }
 
+    /// Removes a connection from the book. Nothing happens if there is no
+    /// such connection.
+    pub(super) fn remove(&mut self, addr: SocketAddr) {
+        if self.records.remove(&addr).is_none() {
+            return;
+        }
+
+        let index = self.addrs.iter().position(|&a| a == addr).unwrap();
+        // Order preserving removal so that no connection is skipped or
+        // yielded twice by the cyclic "iterator".
+        self.addrs.remove(index);
+        if index < self.next_index {
+            self.next_index -= 1;
+        }
+    }
+
+    /// Returns approximate memory footprint (in bytes) of each connection in
+    /// the book.
+    pub(super) fn memory(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.addrs.iter().map(|&addr| {
+            (
+                addr,
+                mem::size_of::<(SocketAddr, ConnectionRecord<T>)>()
+                    + self.records[&addr].value.memory(),
+            )
+        })
+    }
+
+    /// Returns addresses of all connections in the book.
+    pub(super) fn addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.addrs.iter().copied()
+    }
+
     /// Remove last yielded item by [`Self::next`] from the book.
     ///
     /// # Panics
@@ -96,7 +189,7 @@ impl<T: Connection> ConnectionBook<T> {
     pub(super) fn remove_current(&mut self) {
         assert!(self.next_index > 0);
         self.next_index -= 1;
-        let addr = self.addrs.swap_remove(self.next_index);
+        let addr = self.addrs.remove(self.next_index);
         self.records.remove(&addr).unwrap();
     }
 }
@@ -109,13 +202,15 @@ struct ConnectionRecord<T: Connection> {
 impl<T: Connection> ConnectionRecord<T> {
     /// Returns `true` if the connection holds no pending data and was last
     /// updated more than `max_age` in the past.
-    fn is_inactive(&self, time: Instant) -> bool {
-        !self.value.pending() && time - self.last_update > MAX_CONN_AGE
+    fn is_inactive(&self, time: Instant, max_age: Duration) -> bool {
+        !self.value.pending() && time.saturating_duration_since(self.last_update) > max_age
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use ahash::{AHashMap, AHashSet};
+
     use super::*;
 
     #[test]
@@ -150,10 +245,175 @@ mod tests {
         assert_eq!(book.next().unwrap().1 .0, 4);
         assert!(book.next().is_none());
 
-        book.clean(start + MAX_CONN_AGE + Duration::from_millis(200));
+        assert_eq!(book.next().unwrap().1 .0, 1);
+        book.clean(start + Duration::from_millis(300));
+        assert_eq!(book.next().unwrap().1 .0, 2);
+        assert_eq!(book.next().unwrap().1 .0, 3);
+        assert_eq!(book.next().unwrap().1 .0, 4);
+        assert!(book.next().is_none());
+
+        let mut removed = book.clean(start + MAX_CONN_AGE + Duration::from_millis(200));
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![
+                "1.2.3.4:1111".parse::<SocketAddr>().unwrap(),
+                "1.2.3.4:1113".parse().unwrap()
+            ]
+        );
+        assert!(!book.contains("1.2.3.4:1111".parse().unwrap()));
+        assert!(book.contains("1.2.3.4:1112".parse().unwrap()));
         let mut numbers = vec![book.next().unwrap().1 .0, book.next().unwrap().1 .0];
         numbers.sort();
         assert_eq!(numbers, vec![2, 4]);
         assert!(book.next().is_none());
+
+        book.remove("1.2.3.4:1112".parse().unwrap());
+        book.remove("1.2.3.4:1112".parse().unwrap());
+        let addrs: Vec<SocketAddr> = book.addrs().collect();
+        assert_eq!(addrs, vec!["1.2.3.4:1114".parse().unwrap()]);
+        assert_eq!(book.next().unwrap().1 .0, 4);
+        assert!(book.next().is_none());
+    }
+
+    #[test]
+    fn test_order() {
+        struct Item;
+
+        impl Connection for Item {
+            fn pending(&self) -> bool {
+                false
+            }
+        }
+
+        let addrs: Vec<SocketAddr> = [9, 3, 7, 1, 5, 2, 8]
+            .iter()
+            .map(|&i| SocketAddr::from(([10, 0, 0, i], 1000)))
+            .collect();
+
+        let traverse = || {
+            let start = Instant::now();
+            let mut book: ConnectionBook<Item> = ConnectionBook::new();
+            for &addr in &addrs {
+                book.update(start, addr, || Item);
+            }
+
+            let mut order = Vec::new();
+            while let Some((addr, _)) = book.next() {
+                order.push(addr);
+                if addr == addrs[1] {
+                    book.remove_current();
+                }
+            }
+            book.remove(addrs[4]);
+            // Re-inserted connections go last.
+            book.update(start, addrs[1], || Item);
+            while let Some((addr, _)) = book.next() {
+                order.push(addr);
+            }
+            order
+        };
+
+        let expected: Vec<SocketAddr> = addrs
+            .iter()
+            .copied()
+            .chain([0, 2, 3, 5, 6, 1].iter().map(|&i| addrs[i]))
+            .collect();
+        // Each book uses differently seeded hash maps.
+        for _ in 0..8 {
+            assert_eq!(traverse(), expected);
+        }
+    }
+
+    #[test]
+    fn test_churn() {
+        struct Item {
+            pending: bool,
+        }
+
+        impl Connection for Item {
+            fn pending(&self) -> bool {
+                self.pending
+            }
+        }
+
+        const MAX_AGE: Duration = Duration::from_secs(30);
+
+        let rng = fastrand::Rng::with_seed(7);
+        let mut book: ConnectionBook<Item> = ConnectionBook::with_max_age(MAX_AGE);
+        // Expected last update time and pending state of each connection.
+        let mut model: AHashMap<SocketAddr, (Instant, bool)> = AHashMap::new();
+        let mut created = 0;
+
+        let start = Instant::now();
+        for step in 0..2000 {
+            let time = start + Duration::from_secs(step);
+
+            for _ in 0..rng.usize(0..3) {
+                let addr = SocketAddr::from(([10, 0, 0, 1], 1000 + created));
+                created += 1;
+                let pending = rng.u8(0..5) == 0;
+                book.update(time, addr, || Item { pending });
+                model.insert(addr, (time, pending));
+            }
+
+            let mut addrs: Vec<SocketAddr> = model.keys().copied().collect();
+            addrs.sort();
+            for _ in 0..rng.usize(0..4) {
+                if addrs.is_empty() {
+                    break;
+                }
+                let addr = addrs[rng.usize(0..addrs.len())];
+                let pending = rng.u8(0..5) == 0;
+                book.update(time, addr, || unreachable!()).pending = pending;
+                model.insert(addr, (time, pending));
+            }
+
+            if step % 10 != 0 {
+                continue;
+            }
+
+            // Start a new cycle of the "iterator" and interrupt it by the
+            // cleaning at a random position.
+            while book.next().is_some() {}
+            let mut yielded = AHashSet::new();
+            for _ in 0..rng.usize(0..=addrs.len()) {
+                yielded.insert(book.next().unwrap().0);
+            }
+
+            let mut removed = book.clean(time);
+            removed.sort();
+            let mut expected: Vec<SocketAddr> = model
+                .iter()
+                .filter(|(_, &(last, pending))| !pending && time - last > MAX_AGE)
+                .map(|(&addr, _)| addr)
+                .collect();
+            expected.sort();
+            assert_eq!(removed, expected);
+            for addr in &removed {
+                model.remove(addr);
+            }
+
+            assert_eq!(book.records.len(), model.len());
+            assert_eq!(book.addrs.len(), model.len());
+            assert!(model.keys().all(|&addr| book.contains(addr)));
+
+            // The rest of the interrupted cycle yields exactly the remaining
+            // connections.
+            while let Some((addr, _)) = book.next() {
+                assert!(yielded.insert(addr), "{addr} yielded twice");
+            }
+            assert!(model.keys().all(|addr| yielded.contains(addr)));
+        }
+        assert!(created > 1000);
+
+        for addr in model.keys() {
+            book.update(start, *addr, || unreachable!()).pending = false;
+        }
+        let removed = book.clean(start + Duration::from_secs(3000));
+        assert_eq!(removed.len(), model.len());
+        assert!(book.records.is_empty());
+        assert!(book.addrs.is_empty());
+        assert!(book.next().is_none());
     }
 }
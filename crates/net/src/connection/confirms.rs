@@ -5,8 +5,12 @@ use std::{
 
 use async_std::channel::{SendError, Sender};
 
-use super::book::{Connection, ConnectionBook};
+use super::{
+    book::{Connection, ConnectionBook},
+    window::IdWindow,
+};
 use crate::{
+    clock::{Clock, RealClock},
     header::{DatagramHeader, DatagramId},
     messages::MAX_MESSAGE_SIZE,
     tasks::dsender::OutDatagram,
@@ -15,68 +19,152 @@ use crate::{
 /// The buffer is flushed after it grows beyond this number of bytes.
 // Each ID is 3 bytes, thus this must be a multiple of 3.
 const MAX_BUFF_SIZE: usize = 96;
+/// The buffer is flushed without regard to the work budget once it grows to
+/// this number of bytes, so that no confirmation is ever dropped.
+// Each ID is 3 bytes, thus this must be a multiple of 3.
+const MAX_BUFF_CAPACITY: usize = 16 * MAX_BUFF_SIZE;
 /// The buffer is flushed after the oldest part is older than this.
 const MAX_BUFF_AGE: Duration = Duration::from_millis(100);
 
-pub(crate) struct Confirmations {
+pub(crate) struct Confirmations<C: Clock = RealClock> {
+    clock: C,
+    redundancy: u8,
     book: ConnectionBook<Buffer>,
+    /// Connections whose buffers are full and must be flushed during the
+    /// next [`Self::send_confirms`] call.
+    full: Vec<SocketAddr>,
 }
 
-impl Confirmations {
-    pub(crate) fn new() -> Self {
+impl<C: Clock> Confirmations<C> {
+    /// # Arguments
+    ///
+    /// * `clock` - source of current time.
+    ///
+    /// * `redundancy` - number of times each batch of confirmations is sent.
+    pub(crate) fn new(clock: C, redundancy: u8) -> Self {
         Self {
+            clock,
+            redundancy,
             book: ConnectionBook::new(),
+            full: Vec::new(),
         }
     }
 
-    /// This method marks a message with `id` from `addr` as received.
+    /// This method marks a message with `id` from `addr` as received and
+    /// schedules its confirmation.
     ///
-    /// This method should be called exactly once after each reliable message
-    /// is delivered.
-    pub(crate) fn received(&mut self, time: Instant, addr: SocketAddr, id: DatagramId) {
-        self.book.update(time, addr, Buffer::new).push(time, id);
+    /// This method should be called after each reliable message is received,
+    /// including duplicates, because the duplicate might have been
+    /// retransmitted due to a lost confirmation.
+    ///
+    /// Returns false if the message is a duplicate of an already received
+    /// message and therefore should not be delivered again.
+    pub(crate) fn received(&mut self, addr: SocketAddr, id: DatagramId) -> bool {
+        let time = self.clock.now();
+        let buffer = self.book.update(time, addr, || Buffer::new(time));
+        buffer.push(time, id);
+        if buffer.full() && !self.full.contains(&addr) {
+            self.full.push(addr);
+        }
+        buffer.received.insert(id)
     }
 
     /// Send message confirmation packets which are ready to be send.
     ///
-    /// # Arguments
+    /// Returns the number of sent datagrams.
     ///
-    /// * `time` - current time.
+    /// # Arguments
     ///
-    /// * `buf` - buffer for message construction. Must be at least
-    ///   [`crate::MAX_DATAGRAM_SIZE`] long.
+    /// * `datagrams` - output datagrams channel to be used for delivery of
+    ///   the confirmations.
     ///
-    /// * `messages` - message connection to be used for delivery of the
-    ///   confirmations.
+    /// * `budget` - no more connections are processed once this number of
+    ///   datagrams is sent. The remaining connections are processed during
+    ///   subsequent calls. Full buffers are always flushed, even if the
+    ///   budget is exhausted.
     ///
-    /// # Panics
-    ///
-    /// May panic if `buf` is not large enough.
+    /// * `window` - receive window advertised after the confirmations sent
+    ///   to each connection (if any).
     pub(crate) async fn send_confirms(
         &mut self,
-        time: Instant,
         datagrams: &mut Sender<OutDatagram>,
-    ) -> Result<(), SendError<OutDatagram>> {
-        while let Some((addr, buffer)) = self.book.next() {
+        budget: usize,
+        window: Option<u32>,
+    ) -> Result<usize, SendError<OutDatagram>> {
+        let time = self.clock.now();
+        let mut sent = 0;
+
+        for addr in std::mem::take(&mut self.full) {
+            // The connection might have been removed in the meantime.
+            if let Some(buffer) = self.book.get_mut(addr) {
+                sent += send_buffer(datagrams, addr, buffer, self.redundancy, window).await?;
+            }
+        }
+
+        while sent < budget {
+            let Some((addr, buffer)) = self.book.next() else { break };
             if buffer.ready(time) {
-                while let Some(data) = buffer.flush(MAX_MESSAGE_SIZE) {
-                    datagrams
-                        .send(OutDatagram::new(
-                            DatagramHeader::Confirmation,
-                            data.to_vec(),
-                            addr,
-                        ))
-                        .await?;
-                }
+                sent += send_buffer(datagrams, addr, buffer, self.redundancy, window).await?;
             }
         }
 
-        Ok(())
+        Ok(sent)
+    }
+
+    /// Forgets all confirmations and received datagram IDs of a connection.
+    pub(crate) fn remove(&mut self, addr: SocketAddr) {
+        self.book.remove(addr);
+        self.full.retain(|&full| full != addr);
     }
 
-    pub(crate) fn clean(&mut self, time: Instant) {
-        self.book.clean(time);
+    /// Returns approximate memory footprint (in bytes) of each connection.
+    pub(crate) fn memory(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.book.memory()
     }
+
+    pub(crate) fn clean(&mut self) {
+        self.book.clean(self.clock.now());
+    }
+}
+
+/// Flushes all confirmations from the buffer, sends each of them `redundancy`
+/// times and advertises the receive window (if any).
+///
+/// Returns the number of sent datagrams.
+async fn send_buffer(
+    datagrams: &mut Sender<OutDatagram>,
+    addr: SocketAddr,
+    buffer: &mut Buffer,
+    redundancy: u8,
+    window: Option<u32>,
+) -> Result<usize, SendError<OutDatagram>> {
+    let mut sent = 0;
+
+    while let Some(data) = buffer.flush(MAX_MESSAGE_SIZE) {
+        for _ in 0..redundancy {
+            datagrams
+                .send(OutDatagram::new(
+                    DatagramHeader::Confirmation,
+                    data.to_vec(),
+                    addr,
+                ))
+                .await?;
+            sent += 1;
+        }
+    }
+
+    if let Some(window) = window {
+        datagrams
+            .send(OutDatagram::new(
+                DatagramHeader::Window,
+                window.to_be_bytes().to_vec(),
+                addr,
+            ))
+            .await?;
+        sent += 1;
+    }
+
+    Ok(sent)
 }
 
 /// Buffer with datagram confirmations.
@@ -84,14 +172,22 @@ struct Buffer {
     oldest: Instant,
     buffer: Vec<u8>,
     flushed: usize,
+    /// True until the buffer is flushed for the first time. Confirmation of
+    /// the very first datagram of a connection is sent without any delay so
+    /// that connection setup is as fast as possible.
+    first: bool,
+    /// Recently received datagram IDs.
+    received: IdWindow,
 }
 
 impl Buffer {
-    fn new() -> Self {
+    fn new(time: Instant) -> Self {
         Self {
-            oldest: Instant::now(),
+            oldest: time,
             buffer: Vec::with_capacity(MAX_BUFF_SIZE),
             flushed: 0,
+            first: true,
+            received: IdWindow::new(),
         }
     }
 
@@ -104,28 +200,41 @@ impl Buffer {
         self.flushed = self.buffer.len();
     }
 
-    /// Returns true if the buffer is ready to be flushed (too old or too
-    /// large).
+    /// Returns true if the buffer must be flushed as soon as possible (see
+    /// [`MAX_BUFF_CAPACITY`]).
+    fn full(&self) -> bool {
+        self.buffer.len() >= MAX_BUFF_CAPACITY
+    }
+
+    /// Returns true if the buffer is ready to be flushed (too old, too large
+    /// or never flushed before).
     fn ready(&self, time: Instant) -> bool {
         if self.buffer.is_empty() {
             return false;
         }
 
-        (self.oldest + MAX_BUFF_AGE) <= time || self.buffer.len() >= MAX_BUFF_SIZE
+        self.first || (self.oldest + MAX_BUFF_AGE) <= time || self.buffer.len() >= MAX_BUFF_SIZE
     }
 
     /// Return accumulated bytes from the buffer if it is not empty. The number
-    /// of returned bytes is always smaller than `max_size`. This method should
-    /// be called repeatedly until it returns None.
+    /// of returned bytes is at most `max_size` and it is always a multiple of
+    /// 3 so that no ID is split between two flushes. This method should be
+    /// called repeatedly until it returns None.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_size` is smaller than size of a single ID.
     fn flush(&mut self, max_size: usize) -> Option<&[u8]> {
+        assert!(max_size >= 3);
         self.buffer.truncate(self.flushed);
 
         if self.buffer.is_empty() {
+            self.first = false;
             None
         } else {
-            // Make sure it is multiple of 4 (i.e. larges multiple of 4 smaller
-            // or equal than the original).
-            let size = self.buffer.len().min(max_size & (usize::MAX - 3));
+            // Each ID is 3 bytes, the buffer length is always a multiple of 3
+            // too.
+            let size = self.buffer.len().min(max_size - max_size % 3);
             self.flushed = self.buffer.len() - size;
             Some(&self.buffer[self.flushed..])
         }
@@ -136,16 +245,114 @@ impl Connection for Buffer {
     fn pending(&self) -> bool {
         !self.buffer.is_empty()
     }
+
+    fn memory(&self) -> usize {
+        self.buffer.capacity() + self.received.memory()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use async_std::{channel::bounded, task};
+
     use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_ready_after_max_age() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut confirms = Confirmations::new(clock.clone(), 1);
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        confirms.received(addr, 7.try_into().unwrap());
+        task::block_on(confirms.send_confirms(&mut sender, usize::MAX, None)).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().data, &[0, 0, 7]);
+
+        confirms.received(addr, 1042.try_into().unwrap());
+        task::block_on(confirms.send_confirms(&mut sender, usize::MAX, None)).unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(MAX_BUFF_AGE - Duration::from_millis(1));
+        task::block_on(confirms.send_confirms(&mut sender, usize::MAX, None)).unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(Duration::from_millis(1));
+        task::block_on(confirms.send_confirms(&mut sender, usize::MAX, None)).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().data, &[0, 4, 18]);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_redundancy() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut confirms = Confirmations::new(clock.clone(), 2);
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        confirms.received(addr, 1042.try_into().unwrap());
+        confirms.received(addr, 43.try_into().unwrap());
+        clock.advance(MAX_BUFF_AGE);
+        task::block_on(confirms.send_confirms(&mut sender, usize::MAX, None)).unwrap();
+
+        for _ in 0..2 {
+            let datagram = receiver.try_recv().unwrap();
+            assert_eq!(datagram.header, DatagramHeader::Confirmation);
+            assert_eq!(datagram.data, &[0, 4, 18, 0, 0, 43]);
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_full_buffer() {
+        let (mut sender, receiver) = bounded(1024);
+        let mut confirms = Confirmations::new(ManualClock::new(), 1);
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        let num_ids = MAX_BUFF_CAPACITY as u32 / 3;
+        for i in 0..num_ids - 1 {
+            confirms.received(addr, i.try_into().unwrap());
+        }
+        // The work budget is exhausted.
+        assert_eq!(
+            task::block_on(confirms.send_confirms(&mut sender, 0, None)).unwrap(),
+            0
+        );
+        assert!(receiver.try_recv().is_err());
+
+        for i in num_ids - 1..num_ids + 10 {
+            confirms.received(addr, i.try_into().unwrap());
+        }
+        let sent = task::block_on(confirms.send_confirms(&mut sender, 0, Some(7))).unwrap();
+
+        let mut ids = Vec::new();
+        for _ in 0..sent - 1 {
+            let datagram = receiver.try_recv().unwrap();
+            assert_eq!(datagram.header, DatagramHeader::Confirmation);
+            for chunk in datagram.data.chunks(3) {
+                ids.push(DatagramId::from_bytes(chunk).to_u32());
+            }
+        }
+        assert_eq!(receiver.try_recv().unwrap().header, DatagramHeader::Window);
+        assert!(receiver.try_recv().is_err());
+
+        // No confirmation is dropped.
+        ids.sort_unstable();
+        let expected: Vec<u32> = (0..num_ids + 10).collect();
+        assert_eq!(ids, expected);
+
+        // The buffer is not flushed again unless it is full.
+        confirms.received(addr, 1042.try_into().unwrap());
+        assert_eq!(
+            task::block_on(confirms.send_confirms(&mut sender, 0, None)).unwrap(),
+            0
+        );
+    }
 
     #[test]
     fn test_buffer() {
         let now = Instant::now();
-        let mut buf = Buffer::new();
+        let mut buf = Buffer::new(now);
 
         assert!(buf.flush(13).is_none());
         assert!(!buf.ready(now));
@@ -173,26 +380,45 @@ mod tests {
             }
         }
 
-        for i in 0..8 {
+        // The newest IDs are flushed first, 3 IDs at a time.
+        for i in 0..10 {
             assert_eq!(
-                buf.flush(12 + (i as usize) % 3).unwrap(),
-                &[
-                    0,
-                    0,
-                    128 - i * 4,
-                    0,
-                    0,
-                    129 - i * 4,
-                    0,
-                    0,
-                    130 - i * 4,
-                    0,
-                    0,
-                    131 - i * 4
-                ]
+                buf.flush(9 + (i as usize) % 3).unwrap(),
+                &[0, 0, 129 - i * 3, 0, 0, 130 - i * 3, 0, 0, 131 - i * 3]
             );
         }
+        assert_eq!(buf.flush(11).unwrap(), &[0, 0, 100, 0, 0, 101]);
 
         assert!(buf.flush(8).is_none());
     }
+
+    #[test]
+    fn test_flush_whole_ids() {
+        let now = Instant::now();
+
+        for max_size in 3..64 {
+            let mut buf = Buffer::new(now);
+            for i in 0..50u32 {
+                buf.push(now, (0x010203 * i).try_into().unwrap());
+            }
+
+            let mut ids = Vec::new();
+            let mut flushes = 0;
+            while let Some(data) = buf.flush(max_size) {
+                flushes += 1;
+                assert!(data.len() <= max_size);
+                assert_eq!(data.len() % 3, 0);
+                for chunk in data.chunks_exact(3) {
+                    ids.push(DatagramId::from_bytes(chunk).to_u32());
+                }
+            }
+
+            // Only the last flush may be shorter.
+            assert_eq!(flushes, (50 + max_size / 3 - 1) / (max_size / 3));
+
+            ids.sort_unstable();
+            let expected: Vec<u32> = (0..50).map(|i| 0x010203 * i).collect();
+            assert_eq!(ids, expected);
+        }
+    }
 }
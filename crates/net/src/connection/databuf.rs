@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, mem};
 
 use ahash::AHashMap;
 
@@ -79,6 +79,7 @@ impl DataBuf {
     pub(super) fn remove(&mut self, id: DatagramId) {
         let Some(slot_index) = self.slot_index(id) else { return };
         self.slots.get_mut(slot_index).unwrap().used = false;
+        self.ordinals.remove(&id);
 
         while let Some(front) = self.slots.front() {
             if front.used {
@@ -92,6 +93,13 @@ impl DataBuf {
         }
     }
 
+    /// Returns approximate number of heap allocated bytes.
+    pub(super) fn memory(&self) -> usize {
+        self.data.capacity()
+            + self.slots.capacity() * mem::size_of::<Slot>()
+            + self.ordinals.capacity() * mem::size_of::<(DatagramId, usize)>()
+    }
+
     /// Get index (withing slots deque) of the slot with ID `id`.
     fn slot_index(&self, id: DatagramId) -> Option<usize> {
         let Some(&ordinal) = self.ordinals.get(&id) else { return None };
@@ -179,7 +187,23 @@ mod tests {
                 );
                 assert_eq!(&buf[..3], &[i, j, 23]);
                 data.remove(DatagramId::try_from(id).unwrap());
+                assert!(data
+                    .get(DatagramId::try_from(id).unwrap(), &mut buf)
+                    .is_none());
             }
         }
+
+        data.push(DatagramId::try_from(3).unwrap(), &[]);
+        assert_eq!(
+            data.get(DatagramId::try_from(3).unwrap(), &mut buf)
+                .unwrap(),
+            0
+        );
+
+        data.remove(DatagramId::try_from(12).unwrap());
+        data.remove(DatagramId::try_from(8).unwrap());
+        data.remove(DatagramId::try_from(3).unwrap());
+        assert!(data.slots.is_empty());
+        assert!(data.ordinals.is_empty());
     }
 }
@@ -0,0 +1,160 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use super::book::{Connection, ConnectionBook};
+use crate::{
+    clock::{Clock, RealClock},
+    delivery::DeliveryLog,
+    header::DatagramId,
+};
+
+/// Unconfirmed datagrams are considered lost after this time.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximum number of unconfirmed datagrams tracked per connection. The
+/// oldest datagram is considered lost once the limit is reached.
+const MAX_PENDING: usize = 1024;
+
+/// Tracking of confirmations of unreliable datagrams sent with confirmation
+/// request. Such datagrams are only measured, never re-sent.
+pub(crate) struct Deliveries<C: Clock = RealClock> {
+    clock: C,
+    book: ConnectionBook<Pending>,
+    log: DeliveryLog,
+}
+
+impl<C: Clock> Deliveries<C> {
+    pub(crate) fn new(clock: C, log: DeliveryLog) -> Self {
+        Self {
+            clock,
+            book: ConnectionBook::new(),
+            log,
+        }
+    }
+
+    /// Registers a datagram sent to `addr`.
+    pub(crate) fn sent(&mut self, addr: SocketAddr, id: DatagramId) {
+        let time = self.clock.now();
+        let pending = self.book.update(time, addr, Pending::default);
+        if pending.0.len() >= MAX_PENDING {
+            pending.0.pop_front();
+            self.log.lost(addr);
+        }
+        pending.0.push_back((id, time));
+        self.log.sent(addr);
+    }
+
+    /// Processes data of a confirmation datagram received from `addr`.
+    ///
+    /// Returns confirmation data (encoded in the same way as `data`) with
+    /// IDs of datagrams not tracked here, e.g. of reliable datagrams.
+    pub(crate) fn confirmed(&mut self, addr: SocketAddr, data: &[u8]) -> Vec<u8> {
+        if !self.book.contains(addr) {
+            return data.to_vec();
+        }
+
+        let time = self.clock.now();
+        let pending = self.book.update(time, addr, Pending::default);
+        let mut unknown = Vec::new();
+        for bytes in data.chunks_exact(3) {
+            let id = DatagramId::from_bytes(bytes);
+            let Some(index) = pending.0.iter().position(|&(p, _)| p == id) else {
+                unknown.extend_from_slice(bytes);
+                continue;
+            };
+            let (_, sent) = pending.0.remove(index).unwrap();
+            self.log.delivered(addr, time - sent);
+        }
+        unknown
+    }
+
+    /// Stops tracking of datagrams sent to a connection.
+    pub(crate) fn remove(&mut self, addr: SocketAddr) {
+        self.book.remove(addr);
+        self.log.remove(addr);
+    }
+
+    /// Returns approximate memory footprint (in bytes) of each connection.
+    pub(crate) fn memory(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.book.memory()
+    }
+
+    /// Resolves datagrams unconfirmed for too long as lost and forgets
+    /// inactive connections.
+    pub(crate) fn clean(&mut self) {
+        let time = self.clock.now();
+        while let Some((addr, pending)) = self.book.next() {
+            while let Some(&(_, sent)) = pending.0.front() {
+                if time - sent < DELIVERY_TIMEOUT {
+                    break;
+                }
+                pending.0.pop_front();
+                self.log.lost(addr);
+            }
+        }
+
+        for addr in self.book.clean(time) {
+            self.log.remove(addr);
+        }
+    }
+}
+
+/// Unconfirmed datagrams of a connection in the order of sending.
+#[derive(Default)]
+struct Pending(VecDeque<(DatagramId, Instant)>);
+
+impl Connection for Pending {
+    fn pending(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    fn memory(&self) -> usize {
+        self.0.capacity() * mem::size_of::<(DatagramId, Instant)>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_deliveries() {
+        let clock = ManualClock::new();
+        let log = DeliveryLog::default();
+        let mut deliveries = Deliveries::new(clock.clone(), log.clone());
+        let addr = "1.2.3.4:1111".parse().unwrap();
+        let id = |id: u32| DatagramId::try_from(id).unwrap();
+
+        for i in 0..3 {
+            deliveries.sent(addr, id(i));
+        }
+        clock.advance(Duration::from_millis(100));
+        // Unknown IDs (of reliable datagrams) are returned.
+        assert_eq!(
+            deliveries.confirmed(addr, &[0, 0, 2, 0, 0, 100]),
+            vec![0, 0, 100]
+        );
+        clock.advance(Duration::from_millis(300));
+        assert!(deliveries.confirmed(addr, &[0, 0, 0]).is_empty());
+        deliveries.clean();
+
+        let stats = log.get(addr).unwrap();
+        assert_eq!(stats.sent(), 3);
+        assert_eq!(stats.delivered(), 2);
+        assert_eq!(stats.lost(), 0);
+        assert_eq!(stats.rtt(), Some(Duration::from_micros(137_500)));
+
+        clock.advance(DELIVERY_TIMEOUT);
+        deliveries.clean();
+        // A late confirmation of a datagram already considered lost.
+        assert_eq!(deliveries.confirmed(addr, &[0, 0, 1]), vec![0, 0, 1]);
+        let stats = log.get(addr).unwrap();
+        assert_eq!(stats.delivered(), 2);
+        assert_eq!(stats.lost(), 1);
+        assert_eq!(stats.loss(), Some(1. / 3.));
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use super::book::{Connection, ConnectionBook};
+use crate::clock::{Clock, RealClock};
+
+/// Counting of malformed datagrams received from individual peers.
+pub(crate) struct Malformed<C: Clock = RealClock> {
+    clock: C,
+    book: ConnectionBook<Reports>,
+    limit: usize,
+    window: Duration,
+}
+
+impl<C: Clock> Malformed<C> {
+    /// # Arguments
+    ///
+    /// * `limit` - number of malformed datagrams received from a single peer
+    ///   within `window` at which the peer is considered faulty.
+    ///
+    /// * `window` - the time window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
+    pub(crate) fn new(clock: C, limit: usize, window: Duration) -> Self {
+        assert!(limit > 0);
+        Self {
+            clock,
+            book: ConnectionBook::with_max_age(window),
+            limit,
+            window,
+        }
+    }
+
+    /// Registers a malformed datagram received from `addr`.
+    ///
+    /// Returns true if the limit of malformed datagrams from the peer was
+    /// reached. The count of the peer is reset in such a case.
+    pub(crate) fn received(&mut self, addr: SocketAddr) -> bool {
+        let time = self.clock.now();
+        let reports = self.book.update(time, addr, Reports::default);
+        while let Some(&oldest) = reports.0.front() {
+            if time.saturating_duration_since(oldest) < self.window {
+                break;
+            }
+            reports.0.pop_front();
+        }
+        reports.0.push_back(time);
+
+        let reached = reports.0.len() >= self.limit;
+        if reached {
+            self.book.remove(addr);
+        }
+        reached
+    }
+
+    /// Forgets peers from which no malformed datagram was received recently.
+    pub(crate) fn clean(&mut self) {
+        self.book.clean(self.clock.now());
+    }
+}
+
+/// Times of recently received malformed datagrams from a peer.
+#[derive(Default)]
+struct Reports(VecDeque<Instant>);
+
+impl Connection for Reports {
+    fn pending(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_malformed() {
+        let clock = ManualClock::new();
+        let mut malformed = Malformed::new(clock.clone(), 4, Duration::from_secs(1));
+        let a = "1.2.3.4:1111".parse().unwrap();
+        let b = "1.2.3.4:1112".parse().unwrap();
+
+        // Sparse malformed datagrams (e.g. transient corruption).
+        for _ in 0..20 {
+            assert!(!malformed.received(a));
+            clock.advance(Duration::from_millis(400));
+            malformed.clean();
+        }
+        clock.advance(Duration::from_secs(1));
+
+        // Sustained malformed input.
+        for _ in 0..3 {
+            assert!(!malformed.received(a));
+            assert!(!malformed.received(b));
+            clock.advance(Duration::from_millis(10));
+        }
+        assert!(malformed.received(a));
+        // Peers are counted independently and the count is reset.
+        assert!(!malformed.received(a));
+        assert!(malformed.received(b));
+    }
+}
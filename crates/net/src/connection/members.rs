@@ -0,0 +1,168 @@
+use std::{net::SocketAddr, time::Duration};
+
+use super::book::{Connection, ConnectionBook};
+use crate::clock::{Clock, RealClock};
+
+/// Connections closed by this side are remembered for this long unless the
+/// peer is told about the closure earlier.
+const CLOSED_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set of live connections, i.e. peers from which a datagram was recently
+/// received and which did not fail.
+pub(crate) struct Members<C: Clock = RealClock> {
+    clock: C,
+    book: ConnectionBook<Member>,
+    /// Connections recently closed by this side. The peers might not know
+    /// about the closure (half-open connections).
+    closed: ConnectionBook<Member>,
+}
+
+impl<C: Clock> Members<C> {
+    pub(crate) fn new(clock: C) -> Self {
+        Self {
+            clock,
+            book: ConnectionBook::new(),
+            closed: ConnectionBook::with_max_age(CLOSED_TIMEOUT),
+        }
+    }
+
+    /// Sets the time after which connections from which nothing was received
+    /// are forgotten.
+    pub(crate) fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.book = ConnectionBook::with_max_age(timeout);
+        self
+    }
+
+    /// Marks the connection as live. This should be called after each
+    /// received datagram.
+    ///
+    /// Returns true if the connection was not live before.
+    pub(crate) fn received(&mut self, addr: SocketAddr) -> bool {
+        let new = !self.book.contains(addr);
+        self.book.update(self.clock.now(), addr, || Member);
+        new
+    }
+
+    /// Returns true if the connection is live.
+    pub(crate) fn contains(&self, addr: SocketAddr) -> bool {
+        self.book.contains(addr)
+    }
+
+    /// Returns true if the connection was recently closed by this side (see
+    /// [`Self::remove`]) and the closure was not forgotten yet.
+    pub(crate) fn closed(&self, addr: SocketAddr) -> bool {
+        self.closed.contains(addr)
+    }
+
+    /// Forgets closure of a connection. This should be called once the peer
+    /// is told about the closure, afterwards it may connect again.
+    pub(crate) fn forget_closed(&mut self, addr: SocketAddr) {
+        self.closed.remove(addr);
+    }
+
+    /// Removes a (failed) connection and remembers it as closed. Nothing
+    /// happens if the connection is not a member.
+    pub(crate) fn remove(&mut self, addr: SocketAddr) {
+        if self.book.contains(addr) {
+            self.book.remove(addr);
+            self.closed.update(self.clock.now(), addr, || Member);
+        }
+    }
+
+    /// Removes a connection closed by the peer. Unlike [`Self::remove`], the
+    /// connection is not remembered as closed.
+    pub(crate) fn reset(&mut self, addr: SocketAddr) {
+        self.book.remove(addr);
+    }
+
+    /// Returns addresses of all live connections except `excluded`.
+    pub(crate) fn all_except(&self, excluded: SocketAddr) -> Vec<SocketAddr> {
+        self.book.addrs().filter(|&addr| addr != excluded).collect()
+    }
+
+    /// Forgets connections from which nothing was received for a long time.
+    ///
+    /// Returns addresses of the forgotten connections.
+    pub(crate) fn clean(&mut self) -> Vec<SocketAddr> {
+        let time = self.clock.now();
+        self.closed.clean(time);
+        self.book.clean(time)
+    }
+}
+
+struct Member;
+
+impl Connection for Member {
+    fn pending(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_members() {
+        let clock = ManualClock::new();
+        let mut members = Members::new(clock.clone());
+
+        let a = "1.2.3.4:1111".parse().unwrap();
+        let b = "1.2.3.4:1112".parse().unwrap();
+        let c = "1.2.3.4:1113".parse().unwrap();
+
+        assert!(members.received(a));
+        assert!(members.received(b));
+        assert!(members.received(c));
+        assert!(!members.received(a));
+        assert_eq!(members.all_except(a), vec![b, c]);
+        assert_eq!(members.all_except(b), vec![a, c]);
+
+        members.remove(b);
+        members.remove(b);
+        assert_eq!(members.all_except(b), vec![a, c]);
+
+        clock.advance(Duration::from_secs(500));
+        assert!(!members.received(c));
+        clock.advance(Duration::from_secs(500));
+        assert_eq!(members.clean(), vec![a]);
+        assert_eq!(members.all_except(a), vec![c]);
+    }
+
+    #[test]
+    fn test_closed() {
+        let clock = ManualClock::new();
+        let mut members = Members::new(clock.clone());
+
+        let a = "1.2.3.4:1111".parse().unwrap();
+        let b = "1.2.3.4:1112".parse().unwrap();
+        let c = "1.2.3.4:1113".parse().unwrap();
+
+        members.received(a);
+        members.received(b);
+        members.remove(a);
+        members.reset(b);
+        members.remove(c);
+        assert!(members.closed(a));
+        assert!(!members.closed(b));
+        assert!(!members.closed(c));
+
+        // The peer is told about the closure and reconnects.
+        members.forget_closed(a);
+        assert!(!members.closed(a));
+        assert!(members.received(a));
+        assert!(members.contains(a));
+
+        // An unreported closure is forgotten after a while.
+        members.remove(a);
+        clock.advance(Duration::from_secs(8));
+        members.clean();
+        assert!(members.closed(a));
+        clock.advance(Duration::from_secs(3));
+        members.clean();
+        assert!(!members.closed(a));
+    }
+}
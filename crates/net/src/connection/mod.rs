@@ -1,7 +1,15 @@
 pub(crate) use confirms::Confirmations;
+pub(crate) use deliveries::Deliveries;
+pub(crate) use malformed::Malformed;
+pub(crate) use members::Members;
+pub use resend::IdRanges;
 pub(crate) use resend::Resends;
 
 mod book;
 mod confirms;
 mod databuf;
+mod deliveries;
+mod malformed;
+mod members;
 mod resend;
+mod window;
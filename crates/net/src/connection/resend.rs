@@ -1,5 +1,7 @@
 use std::{
     cmp::Ordering,
+    collections::VecDeque,
+    fmt, mem,
     net::SocketAddr,
     time::{Duration, Instant},
 };
@@ -8,96 +10,253 @@ use ahash::AHashMap;
 use async_std::channel::{SendError, Sender};
 use priority_queue::PriorityQueue;
 use thiserror::Error;
+use tracing::{debug, info, trace, warn};
 
 use super::{
     book::{Connection, ConnectionBook},
     databuf::DataBuf,
 };
 use crate::{
+    clock::{Clock, RealClock},
     header::{DatagramHeader, DatagramId, Peers},
+    messages::MAX_MESSAGE_SIZE,
     tasks::dsender::OutDatagram,
+    transitions::{Recorder, TransitionKind},
+    MAX_DATAGRAM_SIZE,
 };
 
 const START_BACKOFF_MS: u64 = 220;
 const MAX_TRIES: u8 = 6;
+/// Length of the window in which re-sends are counted for the purpose of
+/// re-send storm detection.
+const THROTTLE_WINDOW: Duration = Duration::from_secs(1);
+/// Maximum number of re-sends to a single connection per
+/// [`THROTTLE_WINDOW`]. Reaching the limit is considered a re-send storm
+/// (e.g. confirmations are not getting through) and further re-sends to the
+/// connection are postponed to next windows.
+const MAX_WINDOW_RESENDS: usize = 128;
+/// The re-send limit is halved with each window in which it was reached, down
+/// to this value.
+const MIN_WINDOW_RESENDS: usize = 16;
+/// This number of re-sends (i.e. confirmation timeouts) to a single connection
+/// within [`CONGESTION_SPAN`] is considered an acute congestion event.
+const CONGESTION_RESENDS: usize = 32;
+const CONGESTION_SPAN: Duration = Duration::from_millis(100);
+/// Re-sending to a connection is paused for this long after a congestion
+/// event.
+const CONGESTION_PAUSE: Duration = Duration::from_millis(200);
+/// Maximum number of ranges kept in [`IdRanges`].
+const MAX_ID_RANGES: usize = 8;
 
-pub(crate) struct Resends {
+pub(crate) struct Resends<C: Clock = RealClock> {
+    clock: C,
     book: ConnectionBook<Queue>,
+    log: Recorder,
 }
 
-impl Resends {
-    pub(crate) fn new() -> Self {
+impl<C: Clock> Resends<C> {
+    pub(crate) fn new(clock: C) -> Self {
         Self {
+            clock,
             book: ConnectionBook::new(),
+            log: Recorder::default(),
         }
     }
 
-    pub(crate) fn sent(
-        &mut self,
-        time: Instant,
-        addr: SocketAddr,
-        id: DatagramId,
-        peers: Peers,
-        data: &[u8],
-    ) {
+    /// Sets a recorder of confirmations, re-sends, throttling changes and
+    /// failures.
+    pub(crate) fn with_log(mut self, log: Recorder) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Registers a sent reliable message for re-sending until it is
+    /// confirmed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` are longer than [`MAX_MESSAGE_SIZE`].
+    pub(crate) fn sent(&mut self, addr: SocketAddr, id: DatagramId, peers: Peers, data: &[u8]) {
+        assert!(data.len() <= MAX_MESSAGE_SIZE);
+        let time = self.clock.now();
         let queue = self.book.update(time, addr, Queue::new);
         queue.push(id, peers, data, time);
     }
 
+    /// Processes a receive window advertisement of a connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - address of the advertising connection.
+    ///
+    /// * `window` - number of bytes of reliable data the connection is
+    ///   willing to accept.
+    pub(crate) fn window(&mut self, addr: SocketAddr, window: usize) {
+        let time = self.clock.now();
+        self.book.update(time, addr, Queue::new).window = Some(window);
+    }
+
+    /// Returns true if a reliable message with `len` bytes of data can be
+    /// sent to `addr` without exceeding receive window advertised by the
+    /// connection. A message is always allowed if no data to the connection
+    /// are waiting for a confirmation.
+    pub(crate) fn allows(&self, addr: SocketAddr, len: usize) -> bool {
+        let Some(queue) = self.book.get(addr) else { return true };
+        match queue.window {
+            Some(window) => queue.in_flight == 0 || queue.in_flight + len <= window,
+            None => true,
+        }
+    }
+
     /// Processes message with datagram confirmations.
     ///
     /// The data encode IDs of delivered (and confirmed) messages so that they
     /// can be forgotten.
-    pub(crate) fn confirmed(&mut self, time: Instant, addr: SocketAddr, data: &[u8]) {
-        let queue = self.book.update(time, addr, Queue::new);
+    ///
+    /// Confirmations of messages not waiting for a confirmation (e.g. late
+    /// confirmations of failed messages, duplicate confirmations or
+    /// confirmations of never sent messages) are counted and otherwise
+    /// ignored. In particular, they never create a connection record.
+    pub(crate) fn confirmed(&mut self, addr: SocketAddr, data: &[u8]) {
+        let time = self.clock.now();
+        let mut queue = if self.book.contains(addr) {
+            Some(self.book.update(time, addr, Queue::new))
+        } else {
+            None
+        };
 
-        for i in 0..data.len() / 3 {
-            let offset = i * 4;
-            let id = DatagramId::from_bytes(&data[offset..offset + 3]);
-            queue.resolve(id);
+        for bytes in data.chunks_exact(3) {
+            let id = DatagramId::from_bytes(bytes);
+            let resolved = queue.as_mut().map_or(false, |queue| queue.resolve(id));
+            let id = id.to_u32();
+            if resolved {
+                self.log
+                    .record(time, TransitionKind::Confirmed { source: addr, id });
+            } else {
+                let stale = self.log.counters().stale_confirmation();
+                trace!(
+                    "Ignoring stale confirmation of {id} from {addr} ({stale} ignored in total)."
+                );
+                self.log
+                    .record(time, TransitionKind::StaleConfirmation { source: addr, id });
+            }
         }
     }
 
-    /// Re-send all messages already due for re-sending.
+    /// Re-send messages already due for re-sending.
+    ///
+    /// At most one message is re-sent to each connection per call so that a
+    /// connection with many messages due does not delay re-sending to other
+    /// connections. Remaining due messages are re-sent during subsequent
+    /// calls.
+    ///
+    /// Returns the number of re-sent datagrams and addresses of failed
+    /// connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - buffer used for datagram data.
+    ///
+    /// * `datagrams` - output datagrams channel.
+    ///
+    /// * `budget` - maximum number of datagrams re-sent during this call.
+    ///   Connections not processed due to the budget are processed during
+    ///   subsequent calls.
     pub(crate) async fn resend(
         &mut self,
-        time: Instant,
-        buf: &mut [u8],
+        buf: &mut [u8; MAX_DATAGRAM_SIZE],
         datagrams: &mut Sender<OutDatagram>,
-    ) -> Result<Vec<SocketAddr>, SendError<OutDatagram>> {
+        budget: usize,
+    ) -> Result<(usize, Vec<(SocketAddr, IdRanges)>), SendError<OutDatagram>> {
+        let time = self.clock.now();
+        let mut resent = 0;
         let mut failures = Vec::new();
 
-        while let Some((addr, queue)) = self.book.next() {
-            let failure = loop {
-                match queue.reschedule(buf, time) {
-                    Ok(Some((len, id, peers))) => {
-                        datagrams
-                            .send(OutDatagram::new(
-                                DatagramHeader::new_data(true, peers, id),
-                                buf[..len].to_vec(),
-                                addr,
-                            ))
-                            .await?;
-                    }
-                    Ok(None) => break false,
-                    Err(_) => {
-                        failures.push(addr);
-                        break true;
-                    }
+        while resent < budget {
+            let Some((addr, queue)) = self.book.next() else { break };
+            let storm = queue.throttle.storm();
+            let paused = queue.throttle.paused(time);
+            let result = queue.reschedule(buf, time);
+            if !paused && queue.throttle.paused(time) {
+                debug!("Congestion detected on connection to {addr}, pausing re-sends.");
+                self.log
+                    .record(time, TransitionKind::Paused { target: addr });
+            }
+            match (storm, queue.throttle.storm()) {
+                (false, true) => {
+                    warn!("Re-send storm detected on connection to {addr}, throttling re-sends.");
+                    self.log
+                        .record(time, TransitionKind::ThrottleStarted { target: addr });
                 }
-            };
+                (true, false) => {
+                    info!("Re-send storm on connection to {addr} ended.");
+                    self.log
+                        .record(time, TransitionKind::ThrottleEnded { target: addr });
+                }
+                _ => (),
+            }
 
-            if failure {
-                self.book.remove_current();
-                failures.push(addr);
+            match result {
+                Ok(Some((len, id, peers))) => {
+                    datagrams
+                        .send(OutDatagram::new(
+                            DatagramHeader::new_data(true, peers, id),
+                            buf[..len].to_vec(),
+                            addr,
+                        ))
+                        .await?;
+                    resent += 1;
+                    let id = id.to_u32();
+                    self.log
+                        .record(time, TransitionKind::Resent { target: addr, id });
+                }
+                Ok(None) => (),
+                Err(_) => {
+                    let unconfirmed = queue.unconfirmed();
+                    warn!("Connection to {addr} failed, unconfirmed datagrams: {unconfirmed}");
+                    self.book.remove_current();
+                    self.log
+                        .record(time, TransitionKind::Failed { target: addr });
+                    failures.push((addr, unconfirmed));
+                }
             }
         }
 
-        Ok(failures)
+        Ok((resent, failures))
+    }
+
+    /// Forgets all messages waiting for re-sending to a (disconnected)
+    /// connection. Returns IDs of the forgotten datagrams, i.e. datagrams
+    /// never confirmed by the connection.
+    pub(crate) fn remove(&mut self, addr: SocketAddr) -> IdRanges {
+        let unconfirmed = match self.book.get(addr) {
+            Some(queue) => queue.unconfirmed(),
+            None => IdRanges::default(),
+        };
+        if !unconfirmed.is_empty() {
+            warn!("Dropping datagrams to {addr}, unconfirmed datagrams: {unconfirmed}");
+        }
+        self.book.remove(addr);
+        unconfirmed
+    }
+
+    /// Returns approximate memory footprint (in bytes) of each connection.
+    pub(crate) fn memory(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.book.memory()
     }
 
-    pub(crate) fn clean(&mut self, time: Instant) {
-        self.book.clean(time);
+    /// Returns the number of datagrams waiting for a confirmation across
+    /// all connections.
+    pub(crate) fn unconfirmed(&self) -> usize {
+        self.book
+            .addrs()
+            .filter_map(|addr| self.book.get(addr))
+            .map(|queue| queue.queue.len())
+            .sum()
+    }
+
+    pub(crate) fn clean(&mut self) {
+        self.book.clean(self.clock.now());
     }
 }
 
@@ -105,8 +264,13 @@ impl Resends {
 /// confirmed).
 struct Queue {
     queue: PriorityQueue<DatagramId, Timing>,
-    meta: AHashMap<DatagramId, Peers>,
+    meta: AHashMap<DatagramId, Meta>,
     data: DataBuf,
+    throttle: Throttle,
+    /// Total number of bytes of not yet confirmed data.
+    in_flight: usize,
+    /// Last receive window advertised by the connection.
+    window: Option<usize>,
 }
 
 impl Queue {
@@ -115,36 +279,51 @@ impl Queue {
             queue: PriorityQueue::new(),
             meta: AHashMap::new(),
             data: DataBuf::new(),
+            throttle: Throttle::new(),
+            in_flight: 0,
+            window: None,
         }
     }
 
     /// Registers new message for re-sending until it is resolved.
     fn push(&mut self, id: DatagramId, peers: Peers, data: &[u8], now: Instant) {
         self.queue.push(id, Timing::new(now));
-        self.meta.insert(id, peers);
+        self.meta.insert(
+            id,
+            Meta {
+                peers,
+                len: data.len(),
+            },
+        );
         self.data.push(id, data);
+        self.in_flight += data.len();
     }
 
     /// Marks a message as delivered. No more re-sends will be scheduled and
     /// message data will be dropped.
-    fn resolve(&mut self, id: DatagramId) {
+    ///
+    /// Returns false if the message was not waiting for a confirmation.
+    fn resolve(&mut self, id: DatagramId) -> bool {
         let result = self.queue.remove(&id);
         if result.is_some() {
-            self.meta.remove(&id);
+            let meta = self.meta.remove(&id).unwrap();
+            self.in_flight -= meta.len;
             self.data.remove(id);
         }
+        result.is_some()
     }
 
     /// Retrieves next message to be resend or None if there is not (yet) such
     /// a message.
     ///
     /// Each message is resent multiple times with randomized exponential
-    /// backoff.
+    /// backoff. Due messages are postponed while re-sending is throttled, see
+    /// [`Throttle`].
     ///
     /// # Arguments
     ///
-    /// * `buf` - the message data is written to this buffer. The buffer length
-    ///   must be greater or equal to the length of the message.
+    /// * `buf` - the message data is written to this buffer. Messages are at
+    ///   most [`MAX_MESSAGE_SIZE`] long, thus they always fit.
     ///
     /// * `now` - current time, used for the retry scheduling.
     ///
@@ -152,23 +331,23 @@ impl Queue {
     ///
     /// Returns a tuple with number of bytes of retrieved data and header of
     /// the retrieved message.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `buf` is smaller than the retrieved message.
     fn reschedule(
         &mut self,
-        buf: &mut [u8],
+        buf: &mut [u8; MAX_DATAGRAM_SIZE],
         now: Instant,
     ) -> Result<Option<(usize, DatagramId, Peers)>, RescheduleError> {
         match self.queue.peek() {
             Some((&id, timing)) => {
                 if timing.expired(now) {
+                    if !self.throttle.allow(now) {
+                        return Ok(None);
+                    }
+
                     match timing.another(now) {
                         Some(backoff) => {
                             self.queue.change_priority(&id, backoff);
                             let len = self.data.get(id, buf).unwrap();
-                            let peers = *self.meta.get(&id).unwrap();
+                            let peers = self.meta.get(&id).unwrap().peers;
                             Ok(Some((len, id, peers)))
                         }
                         None => Err(RescheduleError::DatagramFailed(id)),
@@ -180,12 +359,215 @@ impl Queue {
             None => Ok(None),
         }
     }
+
+    /// Returns a summary of IDs of all not yet confirmed messages.
+    fn unconfirmed(&self) -> IdRanges {
+        IdRanges::from_ids(self.queue.iter().map(|(&id, _)| id))
+    }
+}
+
+struct Meta {
+    peers: Peers,
+    /// Length of the message data.
+    len: usize,
 }
 
 impl Connection for Queue {
     fn pending(&self) -> bool {
         !self.queue.is_empty()
     }
+
+    fn memory(&self) -> usize {
+        self.queue.capacity() * mem::size_of::<(DatagramId, Timing)>()
+            + self.meta.capacity() * mem::size_of::<(DatagramId, Meta)>()
+            + self.data.memory()
+    }
+}
+
+/// Per connection re-send rate limiter. It detects and breaks re-send
+/// storms, i.e. situations where a peer keeps re-sending because
+/// confirmations are not getting through.
+///
+/// At most a limited number of messages is re-sent in each
+/// [`THROTTLE_WINDOW`]. The limit is halved after each window in which it was
+/// reached and restored once a window passes without reaching it.
+///
+/// Additionally, re-sending is briefly paused after an acute congestion
+/// event, i.e. a burst of confirmation timeouts, see [`CONGESTION_RESENDS`].
+/// Only re-sends are throttled, confirmations are sent regardless of this.
+struct Throttle {
+    /// Start of the current window, None before the first re-send.
+    window_start: Option<Instant>,
+    resent: usize,
+    limit: usize,
+    /// Whether some re-send was postponed in the current window.
+    postponed: bool,
+    storm: bool,
+    /// Start of the current congestion detection span.
+    span_start: Option<Instant>,
+    span_resends: usize,
+    paused_until: Option<Instant>,
+}
+
+impl Throttle {
+    fn new() -> Self {
+        Self {
+            window_start: None,
+            resent: 0,
+            limit: MAX_WINDOW_RESENDS,
+            postponed: false,
+            storm: false,
+            span_start: None,
+            span_resends: 0,
+            paused_until: None,
+        }
+    }
+
+    /// Returns true if the connection is in a re-send storm.
+    fn storm(&self) -> bool {
+        self.storm
+    }
+
+    /// Returns true if re-sending is paused due to a congestion event.
+    fn paused(&self, now: Instant) -> bool {
+        self.paused_until.map_or(false, |until| now < until)
+    }
+
+    /// Returns true and counts a re-send if another message may be re-sent
+    /// at `now`.
+    fn allow(&mut self, now: Instant) -> bool {
+        match self.window_start {
+            Some(start) if now < start + THROTTLE_WINDOW => (),
+            _ => self.next_window(now),
+        }
+
+        if self.paused(now) {
+            return false;
+        }
+
+        if self.resent >= self.limit {
+            self.postponed = true;
+            self.storm = true;
+            return false;
+        }
+        self.resent += 1;
+
+        match self.span_start {
+            Some(start) if now < start + CONGESTION_SPAN => (),
+            _ => {
+                self.span_start = Some(now);
+                self.span_resends = 0;
+            }
+        }
+        self.span_resends += 1;
+        if self.span_resends >= CONGESTION_RESENDS {
+            self.paused_until = Some(now + CONGESTION_PAUSE);
+            self.span_start = None;
+        }
+
+        true
+    }
+
+    fn next_window(&mut self, now: Instant) {
+        if self.postponed {
+            self.limit = (self.limit / 2).max(MIN_WINDOW_RESENDS);
+        } else {
+            self.limit = MAX_WINDOW_RESENDS;
+            self.storm = false;
+        }
+
+        self.window_start = Some(now);
+        self.resent = 0;
+        self.postponed = false;
+    }
+}
+
+/// Bounded size summary of a set of datagram IDs (e.g. IDs of datagrams
+/// never confirmed by a disconnected peer).
+///
+/// Consecutive IDs are merged into inclusive ranges, IDs wrap around so a
+/// range might start near the maximum ID and end near zero. Only the 8
+/// oldest ranges are kept, IDs of the remaining ranges are only counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IdRanges {
+    ranges: [(u32, u32); MAX_ID_RANGES],
+    len: usize,
+    omitted: u32,
+}
+
+impl IdRanges {
+    pub(crate) fn from_ids<I: Iterator<Item = DatagramId>>(ids: I) -> Self {
+        let mut ids: Vec<u32> = ids.map(|id| id.to_u32()).collect();
+        ids.sort_unstable();
+
+        let mut ranges: VecDeque<(u32, u32)> = VecDeque::new();
+        for id in ids {
+            match ranges.back_mut() {
+                Some((_, end)) if *end + 1 == id => *end = id,
+                _ => ranges.push_back((id, id)),
+            }
+        }
+
+        if ranges.len() > 1 && ranges[0].0 == 0 && ranges[ranges.len() - 1].1 == DatagramId::MAX {
+            // The IDs wrapped around, the wrapping range is the oldest one.
+            let (_, end) = ranges.pop_front().unwrap();
+            ranges.back_mut().unwrap().1 = end;
+            ranges.rotate_right(1);
+        }
+
+        let mut summary = Self::default();
+        for (start, end) in ranges {
+            if summary.len < MAX_ID_RANGES {
+                summary.ranges[summary.len] = (start, end);
+                summary.len += 1;
+            } else {
+                summary.omitted += end.wrapping_sub(start) & DatagramId::MAX;
+                summary.omitted += 1;
+            }
+        }
+        summary
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inclusive ranges of IDs from the oldest. A range with start larger
+    /// than its end wraps around the maximum ID.
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges[..self.len]
+    }
+
+    /// Number of IDs not covered by [`Self::ranges`].
+    pub fn omitted(&self) -> u32 {
+        self.omitted
+    }
+}
+
+impl fmt::Display for IdRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+
+        for (i, &(start, end)) in self.ranges().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+
+        if self.omitted > 0 {
+            write!(f, " and {} more", self.omitted)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -239,9 +621,12 @@ impl Timing {
 }
 
 impl Ord for Timing {
+    /// Timings expiring sooner are greater so that they are at the top of the
+    /// (max-)priority queue.
     fn cmp(&self, other: &Self) -> Ordering {
-        self.expiration
-            .cmp(&other.expiration)
+        other
+            .expiration
+            .cmp(&self.expiration)
             .then_with(|| self.attempt.cmp(&other.attempt))
     }
 }
@@ -257,3 +642,395 @@ impl PartialEq for Timing {
         self.expiration == other.expiration && self.attempt == other.attempt
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_std::{
+        channel::{bounded, Receiver},
+        task,
+    };
+
+    use super::*;
+    use crate::{clock::ManualClock, messages::Targets};
+
+    #[test]
+    fn test_resend_round_robin() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+        let addrs: Vec<SocketAddr> = vec![
+            "1.2.3.4:1111".parse().unwrap(),
+            "1.2.3.5:1111".parse().unwrap(),
+            "1.2.3.6:1111".parse().unwrap(),
+        ];
+        let mut id = 0;
+        for &addr in &addrs {
+            for _ in 0..3 {
+                resends.sent(addr, id.try_into().unwrap(), Peers::Players, &[1, 2]);
+                id += 1;
+            }
+        }
+
+        clock.advance(Duration::from_secs(10));
+        for _ in 0..3 {
+            let (resent, failures) =
+                task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+            assert_eq!(resent, 3);
+            assert!(failures.is_empty());
+
+            let mut targets = Vec::new();
+            while let Ok(datagram) = receiver.try_recv() {
+                let Targets::Single(target) = datagram.targets else { panic!() };
+                targets.push(target);
+            }
+            targets.sort();
+            assert_eq!(targets, addrs);
+        }
+
+        task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_resend_budget() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+        for (i, addr) in ["1.2.3.4:1111", "1.2.3.5:1111", "1.2.3.6:1111"]
+            .iter()
+            .enumerate()
+        {
+            let addr = addr.parse().unwrap();
+            resends.sent(addr, (i as u32).try_into().unwrap(), Peers::Players, &[1]);
+        }
+
+        clock.advance(Duration::from_secs(10));
+        let (resent, _) = task::block_on(resends.resend(&mut buf, &mut sender, 2)).unwrap();
+        assert_eq!(resent, 2);
+        // The remaining connection is processed first.
+        let (resent, _) = task::block_on(resends.resend(&mut buf, &mut sender, 2)).unwrap();
+        assert_eq!(resent, 1);
+        assert_eq!(receiver.len(), 3);
+    }
+
+    #[test]
+    fn test_resend_max_size() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        let data = vec![7; MAX_MESSAGE_SIZE];
+        resends.sent(addr, 1.try_into().unwrap(), Peers::Players, &data);
+        clock.advance(Duration::from_secs(10));
+        task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().data, data);
+    }
+
+    #[test]
+    fn test_resend_storm() {
+        fn resend_all(
+            resends: &mut Resends<ManualClock>,
+            sender: &mut Sender<OutDatagram>,
+            receiver: &Receiver<OutDatagram>,
+        ) -> usize {
+            let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+            loop {
+                let (resent, failures) =
+                    task::block_on(resends.resend(&mut buf, sender, usize::MAX)).unwrap();
+                assert!(failures.is_empty());
+                if resent == 0 {
+                    break;
+                }
+            }
+
+            let mut count = 0;
+            while receiver.try_recv().is_ok() {
+                count += 1;
+            }
+            count
+        }
+
+        /// Returns throttling state of the only connection.
+        fn throttle(resends: &mut Resends<ManualClock>) -> (bool, usize) {
+            let (_, queue) = resends.book.next().unwrap();
+            let state = (queue.throttle.storm(), queue.throttle.limit);
+            assert!(resends.book.next().is_none());
+            state
+        }
+
+        let (mut sender, receiver) = bounded(1024);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        // Nothing gets confirmed, e.g. due to heavy loss.
+        for id in 0..500u32 {
+            resends.sent(addr, id.try_into().unwrap(), Peers::Players, &[1]);
+        }
+        clock.advance(Duration::from_secs(10));
+
+        for expected in [128, 64, 32, 16, 16] {
+            // Re-sending is paused after each burst of re-sends.
+            let mut resent = 0;
+            for _ in 0..5 {
+                resent += resend_all(&mut resends, &mut sender, &receiver);
+                clock.advance(CONGESTION_PAUSE);
+            }
+            assert_eq!(resent, expected);
+        }
+
+        for id in 0..500u32 {
+            let id: DatagramId = id.try_into().unwrap();
+            resends.confirmed(addr, &id.to_bytes());
+        }
+        resends.sent(addr, 500.try_into().unwrap(), Peers::Players, &[1]);
+        clock.advance(THROTTLE_WINDOW);
+        assert_eq!(resend_all(&mut resends, &mut sender, &receiver), 1);
+        assert_eq!(throttle(&mut resends), (true, MIN_WINDOW_RESENDS));
+
+        // The limit was not reached in the previous window.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(resend_all(&mut resends, &mut sender, &receiver), 1);
+        assert_eq!(throttle(&mut resends), (false, MAX_WINDOW_RESENDS));
+    }
+
+    #[test]
+    fn test_congestion_pause() {
+        let (mut sender, receiver) = bounded(64);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        for id in 0..100u32 {
+            resends.sent(addr, id.try_into().unwrap(), Peers::Players, &[1]);
+        }
+        clock.advance(Duration::from_secs(10));
+
+        let mut resend = || {
+            let mut total = 0;
+            loop {
+                let (resent, _) =
+                    task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+                if resent == 0 {
+                    break;
+                }
+                total += resent;
+            }
+            while receiver.try_recv().is_ok() {}
+            total
+        };
+
+        // A burst of losses.
+        assert_eq!(resend(), CONGESTION_RESENDS);
+        clock.advance(CONGESTION_PAUSE - Duration::from_millis(1));
+        assert_eq!(resend(), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(resend(), CONGESTION_RESENDS);
+    }
+
+    #[test]
+    fn test_confirmed() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let addr = "1.2.3.4:1111".parse().unwrap();
+
+        for id in 0..5u32 {
+            resends.sent(addr, id.try_into().unwrap(), Peers::Players, &[1]);
+        }
+
+        let mut data = Vec::new();
+        for id in [0u32, 1, 3, 4] {
+            let id: DatagramId = id.try_into().unwrap();
+            data.extend_from_slice(&id.to_bytes());
+        }
+        resends.confirmed(addr, &data);
+
+        clock.advance(Duration::from_secs(10));
+        task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+        let datagram = receiver.try_recv().unwrap();
+        let DatagramHeader::Data(header) = datagram.header else { panic!() };
+        assert_eq!(header.id(), 2.try_into().unwrap());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_stale_confirmations() {
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock);
+        let addr = "1.2.3.4:1111".parse().unwrap();
+        let other = "1.2.3.4:1112".parse().unwrap();
+        let id = |id: u32| DatagramId::try_from(id).unwrap().to_bytes();
+
+        resends.sent(addr, 1.try_into().unwrap(), Peers::Players, &[1, 2]);
+        resends.sent(addr, 2.try_into().unwrap(), Peers::Players, &[3]);
+        resends.confirmed(addr, &id(1));
+        assert_eq!(resends.log.counters().stale_confirmations(), 0);
+
+        // Never sent, already confirmed and unknown connection.
+        resends.confirmed(addr, &id(9));
+        resends.confirmed(addr, &id(1));
+        resends.confirmed(other, &id(2));
+        assert_eq!(resends.log.counters().stale_confirmations(), 3);
+
+        assert!(!resends.book.contains(other));
+        let queue = resends.book.get(addr).unwrap();
+        assert_eq!(queue.in_flight, 1);
+        assert_eq!(queue.meta.len(), 1);
+        assert!(queue.pending());
+    }
+
+    #[test]
+    fn test_resend_until_confirmed() {
+        let (mut sender, receiver) = bounded(16);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let addr = "1.2.3.4:1111".parse().unwrap();
+        let id: DatagramId = 7.try_into().unwrap();
+
+        resends.sent(addr, id, Peers::Players, &[1, 2]);
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(10));
+            task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+            assert_eq!(receiver.try_recv().unwrap().data, vec![1, 2]);
+        }
+
+        resends.confirmed(addr, &id.to_bytes());
+        let (_, queue) = resends.book.next().unwrap();
+        assert!(!queue.pending());
+        assert!(queue.meta.is_empty());
+        assert!(queue.data.get(id, &mut buf).is_none());
+        assert!(resends.book.next().is_none());
+
+        clock.advance(Duration::from_secs(10));
+        task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        // Nothing is kept once the connection is inactive.
+        clock.advance(Duration::from_secs(3600));
+        resends.clean();
+        assert!(resends.book.next().is_none());
+        assert!(!resends.book.contains(addr));
+    }
+
+    #[test]
+    fn test_receive_window() {
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock);
+        let addr = "1.2.3.4:1111".parse().unwrap();
+        let id = |id: u32| DatagramId::try_from(id).unwrap();
+
+        // Unlimited until a window is advertised.
+        assert!(resends.allows(addr, 1000));
+        resends.sent(addr, id(0), Peers::Players, &[0; 100]);
+        resends.sent(addr, id(1), Peers::Players, &[0; 100]);
+        assert!(resends.allows(addr, 1000));
+
+        resends.window(addr, 250);
+        assert!(resends.allows(addr, 50));
+        assert!(!resends.allows(addr, 51));
+        resends.sent(addr, id(2), Peers::Players, &[0; 50]);
+        assert!(!resends.allows(addr, 1));
+
+        resends.confirmed(addr, &[0, 0, 0, 0, 0, 2]);
+        assert!(resends.allows(addr, 150));
+        assert!(!resends.allows(addr, 151));
+
+        // Growing window lets more data in flight.
+        resends.window(addr, 1000);
+        assert!(resends.allows(addr, 900));
+        assert!(!resends.allows(addr, 901));
+
+        // A single message is allowed even with a zero window so that the
+        // window is eventually re-advertised.
+        resends.window(addr, 0);
+        assert!(!resends.allows(addr, 1));
+        resends.confirmed(addr, &[0, 0, 1]);
+        assert!(resends.allows(addr, 500));
+    }
+
+    #[test]
+    fn test_unconfirmed() {
+        let now = Instant::now();
+        let mut queue = Queue::new();
+        assert_eq!(queue.unconfirmed().to_string(), "none");
+
+        for id in [7, 1, 2, 3, 5, 8, 11, 9] {
+            queue.push(id.try_into().unwrap(), Peers::Players, &[1, 2], now);
+        }
+        queue.resolve(2.try_into().unwrap());
+
+        let unconfirmed = queue.unconfirmed();
+        assert_eq!(
+            unconfirmed.ranges(),
+            &[(1, 1), (3, 3), (5, 5), (7, 9), (11, 11)]
+        );
+        assert_eq!(unconfirmed.to_string(), "1, 3, 5, 7-9, 11");
+    }
+
+    #[test]
+    fn test_id_ranges() {
+        let ranges = |ids: &[u32]| IdRanges::from_ids(ids.iter().map(|&id| id.try_into().unwrap()));
+
+        let wrapped = ranges(&[2, 0, DatagramId::MAX - 1, DatagramId::MAX, 1, 7]);
+        assert_eq!(wrapped.ranges(), &[(DatagramId::MAX - 1, 2), (7, 7)]);
+        assert_eq!(wrapped.omitted(), 0);
+        assert_eq!(wrapped.to_string(), "16777214-2, 7");
+
+        // Every other ID is unconfirmed.
+        let ids: Vec<u32> = (0..100).map(|i| 10 + 2 * i).collect();
+        let sparse = ranges(&ids);
+        assert_eq!(sparse.ranges().len(), MAX_ID_RANGES);
+        assert_eq!(sparse.ranges()[0], (10, 10));
+        assert_eq!(sparse.ranges()[MAX_ID_RANGES - 1], (24, 24));
+        assert_eq!(sparse.omitted(), 92);
+        assert_eq!(
+            sparse.to_string(),
+            "10, 12, 14, 16, 18, 20, 22, 24 and 92 more"
+        );
+
+        let full = ranges(&(0..=DatagramId::MAX).step_by(4096).collect::<Vec<u32>>());
+        assert_eq!(full.omitted(), 4096 - MAX_ID_RANGES as u32);
+    }
+
+    #[test]
+    fn test_teardown() {
+        let (mut sender, _receiver) = bounded(64);
+        let clock = ManualClock::new();
+        let mut resends = Resends::new(clock.clone());
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+        let removed = "1.2.3.4:1111".parse().unwrap();
+        let failed = "1.2.3.5:1111".parse().unwrap();
+        for id in [1, 2, 3, 5] {
+            resends.sent(removed, id.try_into().unwrap(), Peers::Players, &[1]);
+        }
+        for id in [7, 8, 10] {
+            resends.sent(failed, id.try_into().unwrap(), Peers::Players, &[1]);
+        }
+
+        assert_eq!(resends.remove(removed).ranges(), &[(1, 3), (5, 5)]);
+        assert_eq!(resends.remove(removed), IdRanges::default());
+
+        let mut failures = Vec::new();
+        for _ in 0..100 {
+            clock.advance(Duration::from_secs(100));
+            let (_, new_failures) =
+                task::block_on(resends.resend(&mut buf, &mut sender, usize::MAX)).unwrap();
+            failures.extend(new_failures);
+        }
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, failed);
+        assert_eq!(failures[0].1.ranges(), &[(7, 8), (10, 10)]);
+        assert_eq!(resends.unconfirmed(), 0);
+    }
+}
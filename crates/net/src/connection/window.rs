@@ -0,0 +1,135 @@
+use std::mem;
+
+use crate::header::DatagramId;
+
+/// Number of most recent datagram IDs tracked by [`IdWindow`]. Must be a
+/// multiple of 64.
+const WINDOW_SIZE: u32 = 4096;
+/// Datagram IDs are 24 bit numbers wrapping around to zero.
+const ID_MODULO: u32 = 1 << 24;
+
+/// Bounded sliding window of recently received datagram IDs of a single
+/// connection used for duplicate detection (see [`Self::insert`]).
+///
+/// The window spans [`WINDOW_SIZE`] IDs ending with the most recent (highest)
+/// received ID. IDs wrap around after 2^24 - 1, an ID is considered more
+/// recent than the highest ID if it is less than 2^23 IDs ahead of it
+/// (modulo 2^24).
+pub(super) struct IdWindow {
+    /// Most recently received ID or None if no ID was received yet.
+    highest: Option<u32>,
+    /// Bit set of received IDs. ID `i` is stored at position `i % WINDOW_SIZE`.
+    bits: Box<[u64; (WINDOW_SIZE / 64) as usize]>,
+}
+
+impl IdWindow {
+    pub(super) fn new() -> Self {
+        Self {
+            highest: None,
+            bits: Box::new([0; (WINDOW_SIZE / 64) as usize]),
+        }
+    }
+
+    /// Returns number of heap allocated bytes.
+    pub(super) fn memory(&self) -> usize {
+        mem::size_of_val(self.bits.as_ref())
+    }
+
+    /// Marks an ID as received.
+    ///
+    /// Returns false if the ID has already been received (it is a
+    /// duplicate). IDs older than the window cannot be detected as
+    /// duplicates and true is returned for them.
+    pub(super) fn insert(&mut self, id: DatagramId) -> bool {
+        let id = id.to_u32();
+
+        let Some(highest) = self.highest else {
+            self.highest = Some(id);
+            self.set(id);
+            return true;
+        };
+
+        let ahead = id.wrapping_sub(highest) % ID_MODULO;
+        if ahead == 0 {
+            false
+        } else if ahead < ID_MODULO / 2 {
+            // Forget IDs which are leaving the window.
+            for i in 1..=ahead.min(WINDOW_SIZE) {
+                self.clear((highest + i) % ID_MODULO);
+            }
+            self.set(id);
+            self.highest = Some(id);
+            true
+        } else {
+            let behind = ID_MODULO - ahead;
+            if behind >= WINDOW_SIZE {
+                return true;
+            }
+
+            let new = !self.get(id);
+            self.set(id);
+            new
+        }
+    }
+
+    fn get(&self, id: u32) -> bool {
+        let (word, bit) = Self::position(id);
+        self.bits[word] & (1 << bit) != 0
+    }
+
+    fn set(&mut self, id: u32) {
+        let (word, bit) = Self::position(id);
+        self.bits[word] |= 1 << bit;
+    }
+
+    fn clear(&mut self, id: u32) {
+        let (word, bit) = Self::position(id);
+        self.bits[word] &= !(1 << bit);
+    }
+
+    fn position(id: u32) -> (usize, u32) {
+        let index = id % WINDOW_SIZE;
+        ((index / 64) as usize, index % 64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(value: u32) -> DatagramId {
+        value.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut window = IdWindow::new();
+
+        assert!(window.insert(id(10)));
+        assert!(!window.insert(id(10)));
+        assert!(window.insert(id(12)));
+        assert!(window.insert(id(8)));
+        assert!(!window.insert(id(8)));
+        assert!(!window.insert(id(12)));
+
+        assert!(window.insert(id(10 + WINDOW_SIZE)));
+        assert!(!window.insert(id(10 + WINDOW_SIZE)));
+        // 12 is still within the window, 10 is not.
+        assert!(!window.insert(id(12)));
+        assert!(window.insert(id(10)));
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let mut window = IdWindow::new();
+
+        assert!(window.insert(id(0xfffffe)));
+        assert!(window.insert(id(0xffffff)));
+        assert!(window.insert(id(1)));
+
+        assert!(!window.insert(id(0xffffff)));
+        assert!(window.insert(id(0)));
+        assert!(!window.insert(id(0)));
+        assert!(!window.insert(id(1)));
+    }
+}
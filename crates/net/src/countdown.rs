@@ -0,0 +1,298 @@
+use std::time::{Duration, Instant};
+
+/// Countdown timer, for example of a ready check timeout, of a match start or
+/// of a reconnection grace period.
+///
+/// The timer is driven by the time passed to its methods, thus it might be
+/// used with any clock. Expiry is reported by [`Countdown::poll`] exactly
+/// once.
+pub struct Countdown {
+    duration: Duration,
+    state: State,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Running {
+        deadline: Instant,
+    },
+    Paused {
+        remaining: Duration,
+    },
+    /// The timer expired and the expiry was reported.
+    Fired,
+}
+
+impl Countdown {
+    /// Creates and starts a new countdown.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - time from start to expiry of the countdown.
+    ///
+    /// * `time` - current time.
+    pub fn start(duration: Duration, time: Instant) -> Self {
+        Self {
+            duration,
+            state: State::Running {
+                deadline: time + duration,
+            },
+        }
+    }
+
+    /// Returns true if the countdown expired since the last call of this
+    /// method. Expiry is reported only once, unless the countdown is reset.
+    pub fn poll(&mut self, time: Instant) -> bool {
+        match self.state {
+            State::Running { deadline } if time >= deadline => {
+                self.state = State::Fired;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the time remaining to expiry of the countdown.
+    pub fn remaining(&self, time: Instant) -> Duration {
+        match self.state {
+            State::Running { deadline } => deadline.saturating_duration_since(time),
+            State::Paused { remaining } => remaining,
+            State::Fired => Duration::ZERO,
+        }
+    }
+
+    /// Returns true if the countdown is paused.
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, State::Paused { .. })
+    }
+
+    /// Pauses the countdown. Nothing happens if it is already paused or
+    /// expired.
+    pub fn pause(&mut self, time: Instant) {
+        if let State::Running { deadline } = self.state {
+            // An expired but not yet polled countdown fires after resuming.
+            self.state = State::Paused {
+                remaining: deadline.saturating_duration_since(time),
+            };
+        }
+    }
+
+    /// Resumes a paused countdown. Nothing happens if it is not paused.
+    pub fn resume(&mut self, time: Instant) {
+        if let State::Paused { remaining } = self.state {
+            self.state = State::Running {
+                deadline: time + remaining,
+            };
+        }
+    }
+
+    /// Restarts the countdown with its full duration. The countdown is
+    /// running afterwards, even if it was paused or has already expired.
+    pub fn reset(&mut self, time: Instant) {
+        self.state = State::Running {
+            deadline: time + self.duration,
+        };
+    }
+}
+
+/// Start of a match at a tick agreed by all peers.
+///
+/// The host picks a start tick lying a short countdown in the future (see
+/// [`SyncedStart::pick`]) and broadcasts it reliably. Ticks are counted from
+/// a synchronized epoch, i.e. from a point in time whose local time is known
+/// to each peer from its clock offset to the host. Every peer thus begins the
+/// match at the same moment, regardless of when it received the start
+/// message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncedStart {
+    tick: u32,
+    tick_duration: Duration,
+    lead: Duration,
+}
+
+impl SyncedStart {
+    /// Picks the start tick: the first tick at least `lead` after `now`.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - current time since the synchronized epoch.
+    ///
+    /// * `tick_duration` - duration of a single game tick.
+    ///
+    /// * `lead` - minimum duration of the countdown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick_duration` is zero or if the start tick does not fit
+    /// to u32.
+    pub fn pick(now: Duration, tick_duration: Duration, lead: Duration) -> Self {
+        assert!(!tick_duration.is_zero(), "Tick duration must be positive.");
+        let start = (now + lead).as_nanos();
+        let tick_nanos = tick_duration.as_nanos();
+        let tick = (start + tick_nanos - 1) / tick_nanos;
+        Self::new(
+            tick.try_into().expect("Start tick does not fit to u32."),
+            tick_duration,
+            lead,
+        )
+    }
+
+    /// Creates the start from a received start tick.
+    ///
+    /// See [`SyncedStart::pick`] for the arguments.
+    pub fn new(tick: u32, tick_duration: Duration, lead: Duration) -> Self {
+        Self {
+            tick,
+            tick_duration,
+            lead,
+        }
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    pub fn lead(&self) -> Duration {
+        self.lead
+    }
+
+    /// Returns the local time at which the match begins.
+    ///
+    /// If the clock offset of the peer is not yet established, the match
+    /// conservatively begins the full countdown duration after reception of
+    /// the start message. Such a peer begins late by the message delivery
+    /// latency but never before the other peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - local time of the synchronized epoch, or None if the clock
+    ///   offset is not known.
+    ///
+    /// * `received` - local time of reception of the start message.
+    pub fn local_start(&self, epoch: Option<Instant>, received: Instant) -> Instant {
+        match epoch {
+            Some(epoch) => epoch + self.tick_duration * self.tick,
+            None => received + self.lead,
+        }
+    }
+
+    /// Returns a countdown expiring at the local start of the match (see
+    /// [`SyncedStart::local_start`]), e.g. to be displayed to the player.
+    pub fn countdown(&self, epoch: Option<Instant>, received: Instant, time: Instant) -> Countdown {
+        let start = self.local_start(epoch, received);
+        Countdown::start(start.saturating_duration_since(time), time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+
+    #[test]
+    fn test_expiry() {
+        let clock = ManualClock::new();
+        let mut countdown = Countdown::start(Duration::from_secs(10), clock.now());
+
+        clock.advance(Duration::from_secs(4));
+        assert!(!countdown.poll(clock.now()));
+        assert_eq!(countdown.remaining(clock.now()), Duration::from_secs(6));
+
+        clock.advance(Duration::from_secs(6));
+        assert!(countdown.poll(clock.now()));
+        assert!(!countdown.poll(clock.now()));
+        clock.advance(Duration::from_secs(60));
+        assert!(!countdown.poll(clock.now()));
+        assert_eq!(countdown.remaining(clock.now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_pause() {
+        let clock = ManualClock::new();
+        let mut countdown = Countdown::start(Duration::from_secs(10), clock.now());
+
+        clock.advance(Duration::from_secs(3));
+        countdown.pause(clock.now());
+        assert!(countdown.is_paused());
+        clock.advance(Duration::from_secs(60));
+        assert!(!countdown.poll(clock.now()));
+        assert_eq!(countdown.remaining(clock.now()), Duration::from_secs(7));
+
+        countdown.resume(clock.now());
+        assert!(!countdown.is_paused());
+        clock.advance(Duration::from_secs(6));
+        assert!(!countdown.poll(clock.now()));
+        clock.advance(Duration::from_secs(1));
+        assert!(countdown.poll(clock.now()));
+    }
+
+    #[test]
+    fn test_reset() {
+        let clock = ManualClock::new();
+        let mut countdown = Countdown::start(Duration::from_secs(10), clock.now());
+
+        clock.advance(Duration::from_secs(8));
+        countdown.reset(clock.now());
+        clock.advance(Duration::from_secs(8));
+        assert!(!countdown.poll(clock.now()));
+        clock.advance(Duration::from_secs(2));
+        assert!(countdown.poll(clock.now()));
+
+        // An expired countdown can be started again.
+        countdown.reset(clock.now());
+        assert_eq!(countdown.remaining(clock.now()), Duration::from_secs(10));
+        clock.advance(Duration::from_secs(10));
+        assert!(countdown.poll(clock.now()));
+        assert!(!countdown.poll(clock.now()));
+    }
+
+    #[test]
+    fn test_synced_start() {
+        let tick = Duration::from_millis(50);
+        let lead = Duration::from_secs(3);
+        let start = SyncedStart::pick(Duration::from_millis(1230), tick, lead);
+        assert_eq!(start.tick(), 85);
+        assert_eq!(
+            SyncedStart::pick(Duration::from_secs(2), tick, lead).tick(),
+            100
+        );
+
+        // The host and the clients would receive the start tick over the
+        // network.
+        let received = SyncedStart::new(start.tick(), tick, lead);
+        assert_eq!(received, start);
+
+        let clock = ManualClock::new();
+        let base = clock.now();
+        clock.advance(Duration::from_secs(2));
+
+        // Synchronized epoch of the first client is at `base`, of the second
+        // client 700ms later on its local clock.
+        assert_eq!(
+            start.local_start(Some(base), clock.now()),
+            base + Duration::from_millis(4250)
+        );
+        let late_epoch = base + Duration::from_millis(700);
+        assert_eq!(
+            start.local_start(Some(late_epoch), clock.now()),
+            base + Duration::from_millis(4950)
+        );
+        // Unknown clock offset.
+        assert_eq!(
+            start.local_start(None, clock.now()),
+            base + Duration::from_secs(5)
+        );
+
+        let mut countdown = start.countdown(Some(late_epoch), clock.now(), clock.now());
+        assert_eq!(
+            countdown.remaining(clock.now()),
+            Duration::from_millis(2950)
+        );
+        clock.advance(Duration::from_millis(2950));
+        assert!(countdown.poll(clock.now()));
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Counters of anomalies which the networking tasks handle by ignoring them,
+/// shared between the networking tasks and the application. Clones of the
+/// counters share the values.
+#[derive(Clone, Default)]
+pub(crate) struct Counters(Arc<Values>);
+
+#[derive(Default)]
+struct Values {
+    stale_confirmations: AtomicU64,
+    foreign_datagrams: AtomicU64,
+}
+
+impl Counters {
+    /// Counts an ignored confirmation and returns the total count.
+    pub(crate) fn stale_confirmation(&self) -> u64 {
+        self.0.stale_confirmations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub(crate) fn stale_confirmations(&self) -> u64 {
+        self.0.stale_confirmations.load(Ordering::Relaxed)
+    }
+
+    /// Counts a dropped datagram of a foreign protocol and returns the total
+    /// count.
+    pub(crate) fn foreign_datagram(&self) -> u64 {
+        self.0.foreign_datagrams.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub(crate) fn foreign_datagrams(&self) -> u64 {
+        self.0.foreign_datagrams.load(Ordering::Relaxed)
+    }
+}
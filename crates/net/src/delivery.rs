@@ -0,0 +1,94 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ahash::AHashMap;
+
+/// Delivery statistics of unreliable messages sent with a confirmation
+/// request to a peer (see [`crate::OutMessage::with_confirmation`] and
+/// [`crate::Communicator::delivery_stats`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryStats {
+    sent: u64,
+    delivered: u64,
+    lost: u64,
+    rtt: Option<Duration>,
+}
+
+impl DeliveryStats {
+    /// Number of sent datagrams.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Number of datagrams confirmed by the peer.
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Number of datagrams not confirmed in time. These are never re-sent.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Smoothed round trip time, including the delay of confirmation
+    /// batching. None until the first confirmation is received.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Fraction of resolved (i.e. delivered or lost) datagrams which were
+    /// lost. None if no datagram is resolved yet.
+    pub fn loss(&self) -> Option<f64> {
+        let resolved = self.delivered + self.lost;
+        if resolved == 0 {
+            None
+        } else {
+            Some(self.lost as f64 / resolved as f64)
+        }
+    }
+}
+
+/// Per peer delivery statistics shared between the networking tasks and the
+/// application. Clones of the log share the statistics.
+#[derive(Clone, Default)]
+pub(crate) struct DeliveryLog(Arc<Mutex<AHashMap<SocketAddr, DeliveryStats>>>);
+
+impl DeliveryLog {
+    pub(crate) fn sent(&self, addr: SocketAddr) {
+        self.update(addr, |stats| stats.sent += 1);
+    }
+
+    pub(crate) fn delivered(&self, addr: SocketAddr, rtt: Duration) {
+        self.update(addr, |stats| {
+            stats.delivered += 1;
+            // Exponentially weighted moving average as in RFC 6298.
+            stats.rtt = Some(match stats.rtt {
+                Some(smoothed) => (smoothed * 7 + rtt) / 8,
+                None => rtt,
+            });
+        });
+    }
+
+    pub(crate) fn lost(&self, addr: SocketAddr) {
+        self.update(addr, |stats| stats.lost += 1);
+    }
+
+    pub(crate) fn remove(&self, addr: SocketAddr) {
+        self.lock().remove(&addr);
+    }
+
+    pub(crate) fn get(&self, addr: SocketAddr) -> Option<DeliveryStats> {
+        self.lock().get(&addr).copied()
+    }
+
+    fn update<F: FnOnce(&mut DeliveryStats)>(&self, addr: SocketAddr, update: F) {
+        update(self.lock().entry(addr).or_default());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<AHashMap<SocketAddr, DeliveryStats>> {
+        self.0.lock().expect("Delivery log lock is poisoned")
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(feature = "portmap")]
+use std::net::SocketAddrV4;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use async_std::future::timeout;
+use thiserror::Error;
+
+use crate::{BindError, Network, RecvError, SendError, MAX_DATAGRAM_SIZE};
+#[cfg(feature = "portmap")]
+use crate::{PortMapError, PortMapping, SocketOptions};
+
+/// Maximum time a single network check might take.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+const LOOPBACK_PAYLOAD: [u8; 8] = [0xde, 0xde, 1, 2, 3, 4, 5, 6];
+
+/// Checks that a UDP socket can be bound to `port`, or to a system assigned
+/// port if `port` is None. Returns the bound port.
+///
+/// The check might be canceled by dropping the returned future.
+pub async fn check_bind(port: Option<u16>) -> Result<u16, CheckError> {
+    timeout(CHECK_TIMEOUT, async {
+        let network = Network::bind(port).await?;
+        Ok(network.port().map_err(BindError::Io)?)
+    })
+    .await
+    .map_err(|_| CheckError::Timeout)?
+}
+
+/// Checks that a datagram can make a round trip between two local UDP
+/// sockets. Returns the round trip time.
+///
+/// The check might be canceled by dropping the returned future.
+pub async fn check_loopback() -> Result<Duration, CheckError> {
+    timeout(CHECK_TIMEOUT, async {
+        let first = Network::bind(None).await?;
+        let second = Network::bind(None).await?;
+        let first_addr = local_addr(&first)?;
+        let second_addr = local_addr(&second)?;
+
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let start = Instant::now();
+
+        first.send(second_addr, &LOOPBACK_PAYLOAD).await?;
+        let (len, source) = second.recv(&mut buf).await?;
+        if source != first_addr || buf[..len] != LOOPBACK_PAYLOAD {
+            return Err(CheckError::UnexpectedDatagram);
+        }
+
+        second.send(first_addr, &buf[..len]).await?;
+        let (len, source) = first.recv(&mut buf).await?;
+        if source != second_addr || buf[..len] != LOOPBACK_PAYLOAD {
+            return Err(CheckError::UnexpectedDatagram);
+        }
+
+        Ok(start.elapsed())
+    })
+    .await
+    .map_err(|_| CheckError::Timeout)?
+}
+
+/// Checks that the gateway (home router) of the local network opens UDP
+/// ports on request, see [`crate::PortMapping`]. A temporary mapping of a
+/// system assigned port, bound to all interfaces, is opened and closed.
+/// Returns the external address of the mapping.
+///
+/// The check might take longer than [`CHECK_TIMEOUT`] when the gateway does
+/// not respond. It might be canceled by dropping the returned future.
+#[cfg(feature = "portmap")]
+pub async fn check_port_mapping() -> Result<SocketAddrV4, CheckError> {
+    let port = {
+        let options = SocketOptions::default().with_address(Ipv4Addr::UNSPECIFIED);
+        let network = Network::bind_with(None, options).await?;
+        network.port().map_err(BindError::Io)?
+    };
+
+    let mapping = PortMapping::open(port, Duration::from_secs(60)).await?;
+    let addr = SocketAddrV4::new(mapping.external_ip(), mapping.external_port());
+    mapping.close().await?;
+    Ok(addr)
+}
+
+fn local_addr(network: &Network) -> Result<SocketAddr, CheckError> {
+    let port = network.port().map_err(BindError::Io)?;
+    Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+}
+
+#[derive(Error, Debug)]
+pub enum CheckError {
+    #[error(transparent)]
+    Bind(#[from] BindError),
+    #[error(transparent)]
+    Send(#[from] SendError),
+    #[error(transparent)]
+    Recv(#[from] RecvError),
+    #[cfg(feature = "portmap")]
+    #[error(transparent)]
+    PortMap(#[from] PortMapError),
+    #[error("an unexpected datagram was received")]
+    UnexpectedDatagram,
+    #[error("the check did not finish in time")]
+    Timeout,
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+
+    use super::*;
+
+    #[test]
+    fn test_check_bind() {
+        task::block_on(async {
+            let port = check_bind(None).await.unwrap();
+            assert!(port > 0);
+
+            let network = Network::bind(None).await.unwrap();
+            let port = network.port().unwrap();
+            let result = check_bind(Some(port)).await;
+            assert!(matches!(
+                result,
+                Err(CheckError::Bind(BindError::AddrInUse(conflict))) if conflict == port
+            ));
+        });
+    }
+
+    #[test]
+    fn test_check_loopback() {
+        let rtt = task::block_on(check_loopback()).unwrap();
+        assert!(rtt < CHECK_TIMEOUT);
+    }
+}
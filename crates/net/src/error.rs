@@ -0,0 +1,109 @@
+use std::io;
+
+use async_std::channel;
+use bincode::error::{DecodeError, EncodeError};
+use thiserror::Error;
+
+use crate::{
+    BindError, CheckError, FecError, InputDelayError, LockstepError, OutMessage, RecvError,
+    SendError,
+};
+
+/// Error type composing all errors of this crate so that they can be
+/// propagated with `?` by callers which do not need to distinguish them.
+#[derive(Error, Debug)]
+pub enum NetError {
+    #[error("failed to receive a datagram: {0}")]
+    Recv(#[from] RecvError),
+    #[error("failed to send a datagram: {0}")]
+    Send(#[from] SendError),
+    #[error("failed to bind the network: {0}")]
+    Bind(#[from] BindError),
+    #[error("network I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("network check failed: {0}")]
+    Check(#[from] CheckError),
+    #[error("failed to encode a message: {0}")]
+    Encode(#[from] EncodeError),
+    #[error("failed to decode a message: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("forward error correction failed: {0}")]
+    Fec(#[from] FecError),
+    #[error("lockstep error: {0}")]
+    Lockstep(#[from] LockstepError),
+    #[error("input delay error: {0}")]
+    InputDelay(#[from] InputDelayError),
+    #[error("the communication stack has been shut down")]
+    Disconnected,
+}
+
+impl From<channel::RecvError> for NetError {
+    fn from(_: channel::RecvError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<channel::SendError<OutMessage>> for NetError {
+    fn from(_: channel::SendError<OutMessage>) -> Self {
+        Self::Disconnected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+
+    use super::*;
+    use crate::{FecDecoder, Peers};
+
+    #[test]
+    fn test_conversions() {
+        fn fec() -> Result<(), NetError> {
+            FecDecoder::new().push(&[1])?;
+            Ok(())
+        }
+
+        fn send() -> Result<(), NetError> {
+            Err(SendError::PartialSend(3, 7))?
+        }
+
+        fn disconnected() -> Result<(), NetError> {
+            let (sender, receiver) = channel::bounded(1);
+            drop(receiver);
+            let message = OutMessage::new(vec![], false, Peers::Players, vec![]);
+            task::block_on(sender.send(message))?;
+            Ok(())
+        }
+
+        let err = fec().unwrap_err();
+        assert!(matches!(err, NetError::Fec(FecError::Truncated)));
+        assert_eq!(
+            err.to_string(),
+            "forward error correction failed: the message is shorter than the FEC header"
+        );
+
+        let err = send().unwrap_err();
+        assert!(matches!(err, NetError::Send(SendError::PartialSend(3, 7))));
+        assert_eq!(
+            err.to_string(),
+            "failed to send a datagram: only 3 of 7 bytes sent"
+        );
+
+        let err = NetError::from(LockstepError::Duplicate(4));
+        assert_eq!(
+            err.to_string(),
+            "lockstep error: turn 4 has already been reported by the peer"
+        );
+
+        let err = disconnected().unwrap_err();
+        assert!(matches!(err, NetError::Disconnected));
+        assert_eq!(
+            err.to_string(),
+            "the communication stack has been shut down"
+        );
+        assert!(matches!(
+            NetError::from(channel::RecvError),
+            NetError::Disconnected
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// A fatal failure of the networking tasks. No more datagrams are received
+/// after the fault (see [`crate::Communicator::fault`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetworkFaulted {
+    time: Instant,
+    reason: String,
+}
+
+impl NetworkFaulted {
+    /// Time of the fault.
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    /// Human readable description of the failure.
+    pub fn reason(&self) -> &str {
+        self.reason.as_str()
+    }
+}
+
+/// Storage of the network fault shared between the networking tasks and the
+/// application. Clones of the log share the fault.
+#[derive(Clone, Default)]
+pub(crate) struct FaultLog(Arc<Mutex<Option<NetworkFaulted>>>);
+
+impl FaultLog {
+    /// Records a fault. Only the first fault is kept, subsequent faults are
+    /// mere consequences of it.
+    pub(crate) fn fault(&self, time: Instant, reason: String) {
+        self.lock().get_or_insert(NetworkFaulted { time, reason });
+    }
+
+    pub(crate) fn get(&self) -> Option<NetworkFaulted> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<Option<NetworkFaulted>> {
+        self.0.lock().expect("Fault log lock is poisoned")
+    }
+}
@@ -0,0 +1,459 @@
+//! XOR based forward error correction (FEC) of unreliable messages.
+//!
+//! Messages are grouped into groups of K data messages and M parity messages
+//! are sent after each group. Parity message `j` is a XOR of all data messages
+//! `i` of the group with `i % M == j`, thus a single lost message of each such
+//! subset can be reconstructed by the receiver without any round trip. If
+//! more messages of a subset are lost, only the received messages are
+//! delivered.
+//!
+//! The bandwidth overhead is M / K additional messages per data message, each
+//! message carries additional [`FEC_HEADER_SIZE`] bytes and each parity
+//! message is as long as the longest message of its subset plus 2 bytes.
+//!
+//! The network stack does not apply FEC on its own. An application encodes
+//! payloads of a single stream of unreliable messages to a single target
+//! (for example snapshots sent to a player) with a [`FecEncoder`], sends all
+//! returned messages and passes received payloads of the stream to a
+//! [`FecDecoder`].
+
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use crate::MAX_MESSAGE_SIZE;
+
+/// Number of bytes prepended to each message.
+pub const FEC_HEADER_SIZE: usize = 5;
+/// Maximum size of a single data message passed to [`FecEncoder::push`].
+pub const MAX_FEC_DATA_SIZE: usize = MAX_MESSAGE_SIZE - FEC_HEADER_SIZE - 2;
+/// Number of most recent groups kept by the decoder.
+const MAX_GROUPS: usize = 16;
+
+/// Encoder of outgoing messages. See the [module level](self) documentation.
+pub struct FecEncoder {
+    k: u8,
+    m: u8,
+    group: u16,
+    buffered: Vec<Vec<u8>>,
+}
+
+impl FecEncoder {
+    /// # Arguments
+    ///
+    /// * `k` - number of data messages in a group.
+    ///
+    /// * `m` - number of parity messages sent after each group.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` or `m` is 0, if `m` is larger than `k` or if `k + m` is
+    /// larger than 256 (messages of a group are indexed by a single byte).
+    pub fn new(k: u8, m: u8) -> Self {
+        assert!(k > 0);
+        assert!(m > 0);
+        assert!(m <= k);
+        assert!(u16::from(k) + u16::from(m) <= 256);
+
+        Self {
+            k,
+            m,
+            group: 0,
+            buffered: Vec::with_capacity(k.into()),
+        }
+    }
+
+    /// Encodes a data message. Returns messages to be sent: the encoded data
+    /// message, followed by parity messages if the group is complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` are longer than [`MAX_FEC_DATA_SIZE`].
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        assert!(data.len() <= MAX_FEC_DATA_SIZE);
+
+        let index = self.buffered.len() as u8;
+        let mut messages = vec![encode(self.group, index, self.k, self.m, data)];
+        self.buffered.push(data.to_vec());
+
+        if self.buffered.len() == usize::from(self.k) {
+            messages.extend(self.flush());
+        }
+        messages
+    }
+
+    /// Finishes the current group even if it does not contain K data
+    /// messages yet. Returns parity messages to be sent. This is useful when
+    /// there is no more data to be sent for some time.
+    pub fn flush(&mut self) -> Vec<Vec<u8>> {
+        if self.buffered.is_empty() {
+            return Vec::new();
+        }
+
+        // Number of data messages in the group.
+        let count = self.buffered.len() as u8;
+        let mut messages = Vec::with_capacity(self.m.into());
+        for j in 0..self.m.min(count) {
+            let parity = parity(
+                self.buffered
+                    .iter()
+                    .skip(j.into())
+                    .step_by(self.m.into())
+                    .map(Vec::as_slice),
+            );
+            messages.push(encode(self.group, count + j, count, self.m, &parity));
+        }
+
+        self.buffered.clear();
+        self.group = self.group.wrapping_add(1);
+        messages
+    }
+}
+
+/// Decoder of incoming messages. See the [module level](self) documentation.
+pub struct FecDecoder {
+    groups: VecDeque<Group>,
+}
+
+impl FecDecoder {
+    pub fn new() -> Self {
+        Self {
+            groups: VecDeque::with_capacity(MAX_GROUPS),
+        }
+    }
+
+    /// Processes a single received (encoded) message. Returns data messages
+    /// which became available, i.e. the received data message itself or data
+    /// messages reconstructed with the help of the received message.
+    ///
+    /// Each data message is returned at most once.
+    pub fn push(&mut self, message: &[u8]) -> Result<Vec<Vec<u8>>, FecError> {
+        if message.len() < FEC_HEADER_SIZE {
+            return Err(FecError::Truncated);
+        }
+
+        let group_id = u16::from_be_bytes([message[0], message[1]]);
+        let index = message[2];
+        let count = message[3];
+        let m = message[4];
+        let payload = &message[FEC_HEADER_SIZE..];
+        if count == 0 || m == 0 {
+            return Err(FecError::InvalidHeader);
+        }
+
+        let group = self.group(group_id, m);
+        if group.m != m {
+            return Err(FecError::InvalidHeader);
+        }
+
+        let mut available = Vec::new();
+        if index < count {
+            // Data message, its count is the configured group size which
+            // might be larger than the final number of data messages if the
+            // group was flushed early.
+            let index = usize::from(index);
+            if group.data.len() <= index {
+                group.data.resize(index + 1, None);
+            }
+            if group.data[index].is_some() {
+                return Ok(available);
+            }
+            group.data[index] = Some(payload.to_vec());
+            available.push(payload.to_vec());
+        } else {
+            // Parity message, its count is the final number of data messages.
+            if payload.len() < 2 || index - count >= m {
+                return Err(FecError::InvalidHeader);
+            }
+            if group.count.map_or(false, |known| known != count) {
+                // All parity messages of a group carry the same count.
+                return Err(FecError::InvalidHeader);
+            }
+            group.count = Some(count);
+            if !group.parities.iter().any(|p| p.index == index) {
+                group.parities.push(Parity {
+                    index,
+                    data: payload.to_vec(),
+                });
+            }
+        }
+
+        available.extend(group.reconstruct());
+        Ok(available)
+    }
+
+    fn group(&mut self, id: u16, m: u8) -> &mut Group {
+        match self.groups.iter().position(|g| g.id == id) {
+            Some(position) => &mut self.groups[position],
+            None => {
+                if self.groups.len() >= MAX_GROUPS {
+                    self.groups.pop_front();
+                }
+                self.groups.push_back(Group::new(id, m));
+                self.groups.back_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl Default for FecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Group {
+    id: u16,
+    m: u8,
+    /// Final number of data messages, known once a parity message arrives.
+    count: Option<u8>,
+    /// Received or reconstructed data messages.
+    data: Vec<Option<Vec<u8>>>,
+    parities: Vec<Parity>,
+}
+
+impl Group {
+    fn new(id: u16, m: u8) -> Self {
+        Self {
+            id,
+            m,
+            count: None,
+            data: Vec::new(),
+            parities: Vec::new(),
+        }
+    }
+
+    /// Reconstructs all data messages which are missing but can be
+    /// reconstructed and returns them.
+    fn reconstruct(&mut self) -> Vec<Vec<u8>> {
+        let Some(count) = self.count else { return Vec::new() };
+        let count = usize::from(count);
+        if self.data.len() < count {
+            self.data.resize(count, None);
+        }
+
+        let m = usize::from(self.m);
+        let mut reconstructed = Vec::new();
+        for parity in &self.parities {
+            let Some(j) = usize::from(parity.index).checked_sub(count) else { continue };
+
+            let mut missing = None;
+            let mut num_missing = 0;
+            for i in (j..count).step_by(m) {
+                if self.data[i].is_none() {
+                    missing = Some(i);
+                    num_missing += 1;
+                }
+            }
+            if num_missing != 1 {
+                continue;
+            }
+            let missing = missing.unwrap();
+
+            let mut xor = parity.data.clone();
+            for i in (j..count).step_by(m) {
+                if let Some(data) = self.data[i].as_ref() {
+                    xor_into(&mut xor[..2], &(data.len() as u16).to_be_bytes());
+                    xor_into(&mut xor[2..], data);
+                }
+            }
+
+            let len = usize::from(u16::from_be_bytes([xor[0], xor[1]]));
+            if len > xor.len() - 2 {
+                // Corrupted parity, the missing message cannot be recovered.
+                continue;
+            }
+            let data = xor[2..2 + len].to_vec();
+            self.data[missing] = Some(data.clone());
+            reconstructed.push(data);
+        }
+
+        reconstructed
+    }
+}
+
+struct Parity {
+    index: u8,
+    data: Vec<u8>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FecError {
+    #[error("the message is shorter than the FEC header")]
+    Truncated,
+    #[error("the message has an invalid FEC header")]
+    InvalidHeader,
+}
+
+fn encode(group: u16, index: u8, count: u8, m: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(FEC_HEADER_SIZE + payload.len());
+    message.extend_from_slice(&group.to_be_bytes());
+    message.push(index);
+    message.push(count);
+    message.push(m);
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Returns XOR of data lengths (2 bytes) followed by XOR of the data padded
+/// by zeros to the same length.
+fn parity<'a, I>(data: I) -> Vec<u8>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    let mut parity = vec![0, 0];
+    for data in data {
+        xor_into(&mut parity[..2], &(data.len() as u16).to_be_bytes());
+        if parity.len() < data.len() + 2 {
+            parity.resize(data.len() + 2, 0);
+        }
+        xor_into(&mut parity[2..], data);
+    }
+    parity
+}
+
+/// XORs `source` into the beginning of `target`.
+fn xor_into(target: &mut [u8], source: &[u8]) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshots(n: u8) -> Vec<Vec<u8>> {
+        (0..n).map(|i| vec![i; 3 + usize::from(i)]).collect()
+    }
+
+    fn sorted(mut messages: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn test_single_loss() {
+        let mut encoder = FecEncoder::new(4, 1);
+        let mut decoder = FecDecoder::new();
+
+        let mut encoded = Vec::new();
+        for snapshot in snapshots(4) {
+            encoded.extend(encoder.push(&snapshot));
+        }
+        assert_eq!(encoded.len(), 5);
+
+        let mut received = Vec::new();
+        for (i, message) in encoded.iter().enumerate() {
+            if i != 2 {
+                received.extend(decoder.push(message).unwrap());
+            }
+        }
+        assert_eq!(sorted(received), snapshots(4));
+
+        // Late arrival of the lost message is not delivered twice.
+        assert!(decoder.push(&encoded[2]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_too_many_losses() {
+        let mut encoder = FecEncoder::new(4, 1);
+        let mut decoder = FecDecoder::new();
+
+        let mut received = Vec::new();
+        for (i, snapshot) in snapshots(4).iter().enumerate() {
+            for message in encoder.push(snapshot) {
+                if i != 1 && i != 3 || message[2] == 4 {
+                    received.extend(decoder.push(&message).unwrap());
+                }
+            }
+        }
+
+        let data = snapshots(4);
+        assert_eq!(sorted(received), vec![data[0].clone(), data[2].clone()]);
+    }
+
+    #[test]
+    fn test_multiple_parities() {
+        let mut encoder = FecEncoder::new(5, 2);
+        let mut decoder = FecDecoder::new();
+
+        let mut encoded = Vec::new();
+        for snapshot in snapshots(5) {
+            encoded.extend(encoder.push(&snapshot));
+        }
+        assert_eq!(encoded.len(), 7);
+
+        // Parity messages are received first and one message of each subset
+        // is lost.
+        let mut received = Vec::new();
+        for &i in &[6, 5, 0, 2, 3, 4] {
+            received.extend(decoder.push(&encoded[i]).unwrap());
+        }
+        assert_eq!(sorted(received), snapshots(5));
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut encoder = FecEncoder::new(4, 2);
+        let mut decoder = FecDecoder::new();
+
+        let first = encoder.push(&[1, 2, 3]);
+        let second = encoder.push(&[]);
+        let parities = encoder.flush();
+        assert_eq!(parities.len(), 2);
+        assert!(encoder.flush().is_empty());
+
+        let mut received = decoder.push(&first[0]).unwrap();
+        for parity in &parities {
+            received.extend(decoder.push(parity).unwrap());
+        }
+        assert_eq!(sorted(received), vec![vec![], vec![1, 2, 3]]);
+        assert!(decoder.push(&second[0]).unwrap().is_empty());
+
+        // Next group.
+        let next = encoder.push(&[7]);
+        assert_eq!(&next[0][..2], &[0, 1]);
+        assert_eq!(decoder.push(&next[0]).unwrap(), vec![vec![7]]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut decoder = FecDecoder::new();
+        assert_eq!(decoder.push(&[0, 0, 1]), Err(FecError::Truncated));
+        assert_eq!(decoder.push(&[0, 0, 1, 0, 1]), Err(FecError::InvalidHeader));
+
+        // Parity messages of a single group with conflicting counts.
+        assert!(decoder.push(&[0, 0, 5, 4, 2, 0, 0]).unwrap().is_empty());
+        assert_eq!(
+            decoder.push(&[0, 0, 7, 6, 2, 0, 0]),
+            Err(FecError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_largest_group() {
+        let mut encoder = FecEncoder::new(254, 2);
+        let mut decoder = FecDecoder::new();
+
+        let mut encoded = Vec::new();
+        for i in 0..254u8 {
+            encoded.extend(encoder.push(&[i]));
+        }
+        assert_eq!(encoded.len(), 256);
+        assert_eq!(encoded[255][2], 255);
+
+        let mut received = Vec::new();
+        for message in encoded.iter().skip(1) {
+            received.extend(decoder.push(message).unwrap());
+        }
+        assert_eq!(received.len(), 254);
+        assert!(received.contains(&vec![0]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_too_large_group() {
+        FecEncoder::new(255, 2);
+    }
+}
@@ -7,15 +7,29 @@ pub(crate) const HEADER_SIZE: usize = 4;
 
 /// This bit is set in protocol control datagrams.
 const CONTROL_BIT: u8 = 0b1000_0000;
+/// This bit is set (together with [`CONTROL_BIT`]) in receive window
+/// advertisements.
+const WINDOW_BIT: u8 = 0b0000_0001;
+/// This bit is set (together with [`CONTROL_BIT`]) in connection resets.
+const RESET_BIT: u8 = 0b0000_0010;
 /// This bit is set on datagrams which must be delivered reliably.
 const RELIABLE_BIT: u8 = 0b0100_0000;
 /// This bit is set on datagrams which are sent to the server instead of other
 /// players.
 const SERVER_PEER_BIT: u8 = 0b0010_0000;
+/// This bit is set on unreliable datagrams whose delivery is to be confirmed
+/// (but which are never re-sent).
+const ACKED_BIT: u8 = 0b0001_0000;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum DatagramHeader {
     Confirmation,
+    /// Advertisement of the number of bytes of reliable data the sender of
+    /// the datagram is willing to accept.
+    Window,
+    /// Notification that the sender of the datagram considers the connection
+    /// closed and the recipient should tear down its side of the connection.
+    Reset,
     Data(DataHeader),
 }
 
@@ -23,6 +37,18 @@ impl DatagramHeader {
     pub(crate) fn new_data(reliable: bool, peers: Peers, id: DatagramId) -> Self {
         Self::Data(DataHeader {
             reliable,
+            acked: false,
+            peers,
+            id,
+        })
+    }
+
+    /// Creates a header of an unreliable data datagram whose delivery is to
+    /// be confirmed by the recipient.
+    pub(crate) fn new_acked_data(peers: Peers, id: DatagramId) -> Self {
+        Self::Data(DataHeader {
+            reliable: false,
+            acked: true,
             peers,
             id,
         })
@@ -37,11 +63,16 @@ impl DatagramHeader {
         assert!(buf.len() >= HEADER_SIZE);
         let (mask, id) = match self {
             Self::Confirmation => (CONTROL_BIT, [0, 0, 0]),
+            Self::Window => (CONTROL_BIT | WINDOW_BIT, [0, 0, 0]),
+            Self::Reset => (CONTROL_BIT | RESET_BIT, [0, 0, 0]),
             Self::Data(data_header) => {
                 let mut mask = 0;
                 if data_header.reliable {
                     mask |= RELIABLE_BIT;
                 }
+                if data_header.acked {
+                    mask |= ACKED_BIT;
+                }
                 if matches!(data_header.peers, Peers::Server) {
                     mask |= SERVER_PEER_BIT;
                 }
@@ -53,13 +84,12 @@ impl DatagramHeader {
         buf[1..HEADER_SIZE].copy_from_slice(&id);
     }
 
-    /// Reads the header from the beginning of a bytes buffer.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the buffer is smaller than header.
+    /// Reads the header from the beginning of a bytes buffer. An error is
+    /// returned if the buffer is smaller than header.
     pub(crate) fn read(data: &[u8]) -> Result<Self, HeaderError> {
-        assert!(data.len() >= 4);
+        if data.len() < HEADER_SIZE {
+            return Err(HeaderError::Truncated);
+        }
         debug_assert!(u32::BITS == (HEADER_SIZE as u32) * 8);
 
         let mask = data[0];
@@ -67,11 +97,19 @@ impl DatagramHeader {
         if mask & CONTROL_BIT > 0 {
             if mask == CONTROL_BIT {
                 Ok(Self::Confirmation)
+            } else if mask == CONTROL_BIT | WINDOW_BIT {
+                Ok(Self::Window)
+            } else if mask == CONTROL_BIT | RESET_BIT {
+                Ok(Self::Reset)
             } else {
                 Err(HeaderError::Invalid)
             }
         } else {
             let reliable = mask & RELIABLE_BIT > 0;
+            let acked = mask & ACKED_BIT > 0;
+            if reliable && acked {
+                return Err(HeaderError::Invalid);
+            }
             let peers = if mask & SERVER_PEER_BIT > 0 {
                 Peers::Server
             } else {
@@ -79,6 +117,7 @@ impl DatagramHeader {
             };
             Ok(Self::Data(DataHeader {
                 reliable,
+                acked,
                 peers,
                 id: DatagramId::from_bytes(&data[1..HEADER_SIZE]),
             }))
@@ -90,11 +129,13 @@ impl fmt::Display for DatagramHeader {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Confirmation => write!(f, "Confirmation"),
+            Self::Window => write!(f, "Window"),
+            Self::Reset => write!(f, "Reset"),
             Self::Data(header) => {
                 write!(
                     f,
-                    "Data {{ reliable: {}, peers: {}, id: {} }}",
-                    header.reliable, header.peers, header.id
+                    "Data {{ reliable: {}, acked: {}, peers: {}, id: {} }}",
+                    header.reliable, header.acked, header.peers, header.id
                 )
             }
         }
@@ -105,6 +146,8 @@ impl fmt::Display for DatagramHeader {
 pub(crate) struct DataHeader {
     /// True if the datagram is delivered reliably.
     reliable: bool,
+    /// True if delivery of an unreliable datagram is to be confirmed.
+    acked: bool,
     peers: Peers,
     /// ID of the datagram.
     id: DatagramId,
@@ -115,6 +158,12 @@ impl DataHeader {
         self.reliable
     }
 
+    /// Returns true if the datagram is unreliable, but its delivery is to
+    /// be confirmed.
+    pub(crate) fn acked(&self) -> bool {
+        self.acked
+    }
+
     pub(crate) fn peers(&self) -> Peers {
         self.peers
     }
@@ -145,12 +194,17 @@ impl fmt::Display for Peers {
 pub(crate) enum HeaderError {
     #[error("The header is invalid")]
     Invalid,
+    #[error("The datagram is shorter than the header")]
+    Truncated,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct DatagramId(u32);
 
 impl DatagramId {
+    /// Maximum value of the (24 bit) ID.
+    pub(crate) const MAX: u32 = 0xffffff;
+
     pub(crate) const fn zero() -> Self {
         Self(0)
     }
@@ -158,7 +212,7 @@ impl DatagramId {
     /// Increments the counter by one. It wraps around to zero after reaching
     /// maximum value.
     pub(crate) fn incremented(self) -> Self {
-        if self.0 >= 0xffffff {
+        if self.0 >= Self::MAX {
             Self(0)
         } else {
             Self(self.0 + 1)
@@ -176,6 +230,10 @@ impl DatagramId {
         Self(a + b + c)
     }
 
+    pub(crate) fn to_u32(self) -> u32 {
+        self.0
+    }
+
     pub(crate) fn to_bytes(self) -> [u8; 3] {
         [
             ((self.0 >> 16) & 0xff) as u8,
@@ -195,7 +253,7 @@ impl TryFrom<u32> for DatagramId {
     type Error = &'static str;
 
     fn try_from(value: u32) -> Result<Self, Self::Error> {
-        if value > 0xffffff {
+        if value > Self::MAX {
             Err("ID is too large")
         } else {
             Ok(Self(value))
@@ -221,6 +279,14 @@ mod tests {
         DatagramHeader::new_data(true, Peers::Players, 1033.try_into().unwrap()).write(&mut buf);
         assert_eq![&buf[0..4], &[0b0100_0000, 0, 4, 9]];
         assert_eq![&buf[4..], &[0; 252]];
+
+        DatagramHeader::new_acked_data(Peers::Players, 7.try_into().unwrap()).write(&mut buf);
+        assert_eq![&buf[0..4], &[0b0001_0000, 0, 0, 7]];
+        assert_eq![&buf[4..], &[0; 252]];
+
+        DatagramHeader::Reset.write(&mut buf);
+        assert_eq![&buf[0..4], &[0b1000_0010, 0, 0, 0]];
+        assert_eq![&buf[4..], &[0; 252]];
     }
 
     #[test]
@@ -244,6 +310,27 @@ mod tests {
             DatagramHeader::read(&buf).unwrap(),
             DatagramHeader::new_data(false, Peers::Server, 2.try_into().unwrap())
         );
+
+        buf[0..4].copy_from_slice(&[48, 0, 0, 2]);
+        assert_eq!(
+            DatagramHeader::read(&buf).unwrap(),
+            DatagramHeader::new_acked_data(Peers::Server, 2.try_into().unwrap())
+        );
+
+        buf[0..4].copy_from_slice(&[80, 0, 0, 2]);
+        assert!(DatagramHeader::read(&buf).is_err());
+
+        buf[0..4].copy_from_slice(&[129, 0, 0, 0]);
+        assert_eq!(DatagramHeader::read(&buf).unwrap(), DatagramHeader::Window);
+        buf[0..4].copy_from_slice(&[130, 0, 0, 0]);
+        assert_eq!(DatagramHeader::read(&buf).unwrap(), DatagramHeader::Reset);
+        buf[0..4].copy_from_slice(&[131, 0, 0, 0]);
+        assert!(DatagramHeader::read(&buf).is_err());
+
+        assert!(matches!(
+            DatagramHeader::read(&buf[..3]),
+            Err(HeaderError::Truncated)
+        ));
     }
 
     #[test]
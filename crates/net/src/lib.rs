@@ -1,15 +1,58 @@
-pub use communicator::{Communicator, InMessage, OutMessage, OutMessageBuilder};
+pub use communicator::{Communicator, Destination, InMessage, OutMessage, OutMessageBuilder};
+#[cfg(feature = "compression")]
+pub use compression::{Codec, CompressionError, Dictionary, MAX_PAYLOAD_SIZE};
+pub use conf::{NetConf, MAX_CONFIRM_REDUNDANCY};
+pub use connection::IdRanges;
+pub use countdown::{Countdown, SyncedStart};
+pub use delivery::DeliveryStats;
+#[cfg(feature = "portmap")]
+pub use diagnostics::check_port_mapping;
+pub use diagnostics::{check_bind, check_loopback, CheckError, CHECK_TIMEOUT};
+pub use error::NetError;
+pub use fault::NetworkFaulted;
+pub use fec::{FecDecoder, FecEncoder, FecError, FEC_HEADER_SIZE, MAX_FEC_DATA_SIZE};
 pub use header::Peers;
+pub use lockstep::{InputDelay, InputDelayError, Lockstep, LockstepError, Turn, TurnStatus};
 pub use messages::MAX_MESSAGE_SIZE;
-pub use net::{Network, RecvError, SendError, MAX_DATAGRAM_SIZE};
+pub use net::{BindError, Network, RecvError, SendError, SocketOptions, MAX_DATAGRAM_SIZE};
+pub use observers::{Direction, HeaderType};
+pub use peerlog::{DisconnectReason, PeerEvent, PeerEventKind};
+#[cfg(feature = "portmap")]
+pub use portmap::{PortMapError, PortMapping, ResultCode};
+pub use presence::{Presence, PresenceStatus};
 pub use processor::startup;
 pub use protocol::{FromGame, FromServer, ToGame, ToServer};
+pub use snapshot::{
+    produce_snapshot, read_tick, stamp_tick, Bracket, InterpolationDelay, SnapshotDetail,
+    SnapshotError, SnapshotHistory, SnapshotSource, TickFilter, TICK_HEADER_SIZE,
+};
+pub use transitions::{Transition, TransitionKind};
 
+mod clock;
 mod communicator;
+#[cfg(feature = "compression")]
+mod compression;
+mod conf;
 mod connection;
+mod countdown;
+mod counters;
+mod delivery;
+mod diagnostics;
+mod error;
+mod fault;
+mod fec;
 mod header;
+mod lockstep;
+mod memory;
 mod messages;
 mod net;
+mod observers;
+mod peerlog;
+#[cfg(feature = "portmap")]
+mod portmap;
+mod presence;
 mod processor;
 mod protocol;
+mod snapshot;
 mod tasks;
+mod transitions;
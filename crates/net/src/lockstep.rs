@@ -0,0 +1,492 @@
+use std::{
+    collections::BTreeMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use thiserror::Error;
+
+/// Maximum number of turns by which a reported turn may be ahead of the next
+/// executed turn. This limits memory used by reports of misbehaving peers.
+const MAX_TURNS_AHEAD: u32 = 64;
+/// Maximum number of ticks by which a scheduled target tick may be ahead of
+/// the next applied tick. This limits memory used by commands of misbehaving
+/// peers.
+const MAX_TICKS_AHEAD: u32 = 256;
+
+/// Lockstep turn scheduler.
+///
+/// Commands of each turn are collected from all peers (for example received
+/// over the reliable channel). A turn is executed only after all peers
+/// reported their commands for it, thus all peers execute exactly the same
+/// commands at each turn.
+///
+/// Commands of an executed turn are ordered by the order of peers given to
+/// [`Lockstep::new`], and by the order of reporting for each peer. The order
+/// is therefore identical on all peers.
+pub struct Lockstep<P, C> {
+    peers: Vec<P>,
+    indices: AHashMap<P, usize>,
+    timeout: Duration,
+    /// Next turn to be executed.
+    turn: u32,
+    /// Time since which the next turn is waited for.
+    since: Instant,
+    /// Commands of not yet executed turns, a None means that the peer (on
+    /// the corresponding index) did not report yet.
+    reports: BTreeMap<u32, Vec<Option<Vec<C>>>>,
+}
+
+impl<P, C> Lockstep<P, C>
+where
+    P: Copy + Eq + Hash,
+{
+    /// # Arguments
+    ///
+    /// * `peers` - all peers participating in the game, including the local
+    ///   one.
+    ///
+    /// * `timeout` - a turn is reported as stalled after it could not be
+    ///   executed for this long.
+    ///
+    /// * `time` - current time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `peers` are empty or not unique.
+    pub fn new<I>(peers: I, timeout: Duration, time: Instant) -> Self
+    where
+        I: IntoIterator<Item = P>,
+    {
+        let peers: Vec<P> = peers.into_iter().collect();
+        assert!(!peers.is_empty(), "Lockstep needs at least one peer.");
+
+        let indices: AHashMap<P, usize> = peers.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+        assert_eq!(indices.len(), peers.len(), "Lockstep peers are not unique.");
+
+        Self {
+            peers,
+            indices,
+            timeout,
+            turn: 0,
+            since: time,
+            reports: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the next turn to be executed.
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    /// Records commands of a peer for a turn. A peer must report each turn
+    /// exactly once, possibly with no commands. Turns might be reported in
+    /// any order, but at most 64 turns ahead of the next executed turn.
+    pub fn report(&mut self, peer: P, turn: u32, commands: Vec<C>) -> Result<(), LockstepError> {
+        let Some(&index) = self.indices.get(&peer) else { return Err(LockstepError::UnknownPeer) };
+        if turn < self.turn {
+            return Err(LockstepError::Executed(turn));
+        }
+        if turn - self.turn > MAX_TURNS_AHEAD {
+            return Err(LockstepError::Ahead(turn));
+        }
+
+        let num_peers = self.peers.len();
+        let reports = self
+            .reports
+            .entry(turn)
+            .or_insert_with(|| (0..num_peers).map(|_| None).collect());
+
+        if reports[index].is_some() {
+            return Err(LockstepError::Duplicate(turn));
+        }
+        reports[index] = Some(commands);
+        Ok(())
+    }
+
+    /// Tries to advance to the next turn.
+    ///
+    /// This should be called repeatedly (e.g. each frame) until it returns
+    /// something else than [`TurnStatus::Ready`].
+    pub fn advance(&mut self, time: Instant) -> TurnStatus<P, C> {
+        let complete = self
+            .reports
+            .get(&self.turn)
+            .map_or(false, |reports| reports.iter().all(Option::is_some));
+
+        if complete {
+            let reports = self.reports.remove(&self.turn).unwrap();
+            let commands = self
+                .peers
+                .iter()
+                .zip(reports)
+                .flat_map(|(&peer, commands)| commands.unwrap().into_iter().map(move |c| (peer, c)))
+                .collect();
+
+            let turn = Turn {
+                turn: self.turn,
+                commands,
+            };
+            self.turn += 1;
+            self.since = time;
+            TurnStatus::Ready(turn)
+        } else if time.saturating_duration_since(self.since) >= self.timeout {
+            TurnStatus::Stalled(self.missing())
+        } else {
+            TurnStatus::Waiting
+        }
+    }
+
+    /// Returns peers which have not yet reported the next turn.
+    fn missing(&self) -> Vec<P> {
+        match self.reports.get(&self.turn) {
+            Some(reports) => self
+                .peers
+                .iter()
+                .zip(reports)
+                .filter(|(_, commands)| commands.is_none())
+                .map(|(&peer, _)| peer)
+                .collect(),
+            None => self.peers.clone(),
+        }
+    }
+}
+
+/// Scheduler of commands with a fixed input delay.
+///
+/// Commands issued by a peer are stamped with a target tick lying a fixed
+/// number of ticks in the future (see [`InputDelay::target`]) and sent to
+/// all other peers together with the target tick. Each peer, including the
+/// issuing one, applies the command at the target tick. Thus commands of all
+/// players take effect simultaneously regardless of latency of individual
+/// peers, as long as they are delivered within the delay.
+///
+/// Ticks of all peers must be aligned, i.e. derived from synchronized
+/// clocks, for example counted from a [`crate::SyncedStart`].
+pub struct InputDelay<C> {
+    delay: u32,
+    /// Next tick to be applied.
+    tick: u32,
+    scheduled: BTreeMap<u32, Vec<C>>,
+    late: u64,
+}
+
+impl<C> InputDelay<C> {
+    /// # Arguments
+    ///
+    /// * `delay` - number of ticks between issuing and application of a
+    ///   command.
+    ///
+    /// * `tick` - the next tick to be applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `delay` is larger than 256 ticks.
+    pub fn new(delay: u32, tick: u32) -> Self {
+        assert!(
+            delay <= MAX_TICKS_AHEAD,
+            "Input delay {delay} is larger than {MAX_TICKS_AHEAD} ticks."
+        );
+        Self {
+            delay,
+            tick,
+            scheduled: BTreeMap::new(),
+            late: 0,
+        }
+    }
+
+    /// Returns the next tick to be applied.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Returns the tick at which a command issued now, i.e. before the next
+    /// tick is applied, is to be applied. None is returned if the target
+    /// tick is not representable.
+    pub fn target(&self) -> Option<u32> {
+        self.tick.checked_add(self.delay)
+    }
+
+    /// Schedules a local or a received command to be applied at its target
+    /// tick. A command whose target tick has already been applied is
+    /// scheduled to the next tick and counted as late. A command whose target
+    /// tick is more than 256 ticks ahead of the next applied tick is
+    /// rejected.
+    pub fn schedule(&mut self, target: u32, command: C) -> Result<(), InputDelayError> {
+        let target = if target < self.tick {
+            self.late += 1;
+            self.tick
+        } else if target - self.tick > MAX_TICKS_AHEAD {
+            return Err(InputDelayError::Ahead(target));
+        } else {
+            target
+        };
+        self.scheduled.entry(target).or_default().push(command);
+        Ok(())
+    }
+
+    /// Applies the next tick. The tick is returned together with commands
+    /// scheduled to it, ordered by the order of scheduling.
+    pub fn advance(&mut self) -> (u32, Vec<C>) {
+        let tick = self.tick;
+        self.tick += 1;
+        (tick, self.scheduled.remove(&tick).unwrap_or_default())
+    }
+
+    /// Returns the total number of commands received too late for their
+    /// target tick.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+}
+
+pub enum TurnStatus<P, C> {
+    /// All peers reported the turn, its commands are to be executed.
+    Ready(Turn<P, C>),
+    /// Some peers have not reported the next turn yet.
+    Waiting,
+    /// The next turn has been waited for longer than the timeout. The game
+    /// should be paused until the listed peers report the turn (or are
+    /// removed from the game).
+    Stalled(Vec<P>),
+}
+
+/// Agreed set of commands of a single turn.
+pub struct Turn<P, C> {
+    turn: u32,
+    commands: Vec<(P, C)>,
+}
+
+impl<P, C> Turn<P, C> {
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    /// Commands of the turn together with the peers who issued them.
+    pub fn commands(&self) -> &[(P, C)] {
+        self.commands.as_slice()
+    }
+
+    pub fn into_commands(self) -> Vec<(P, C)> {
+        self.commands
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LockstepError {
+    #[error("commands reported by an unknown peer")]
+    UnknownPeer,
+    #[error("turn {0} has already been executed")]
+    Executed(u32),
+    #[error("turn {0} has already been reported by the peer")]
+    Duplicate(u32),
+    #[error("turn {0} is too far ahead of the next executed turn")]
+    Ahead(u32),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum InputDelayError {
+    #[error("tick {0} is too far ahead of the next applied tick")]
+    Ahead(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Advances all peers up to (excluding) `until`, returns ticks with
+    /// non-empty commands as seen by each peer.
+    fn advance_until(
+        peers: &mut [InputDelay<&'static str>],
+        until: u32,
+    ) -> Vec<Vec<(u32, Vec<&'static str>)>> {
+        peers
+            .iter_mut()
+            .map(|peer| {
+                let mut applied = Vec::new();
+                while peer.tick() < until {
+                    let (tick, commands) = peer.advance();
+                    if !commands.is_empty() {
+                        applied.push((tick, commands));
+                    }
+                }
+                applied
+            })
+            .collect()
+    }
+
+    fn turn_commands(status: TurnStatus<u8, &'static str>) -> (u32, Vec<(u8, &'static str)>) {
+        match status {
+            TurnStatus::Ready(turn) => (turn.turn(), turn.into_commands()),
+            _ => panic!("Turn is not ready."),
+        }
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let now = Instant::now();
+        let mut lockstep = Lockstep::new([1, 2, 3], Duration::from_secs(1), now);
+
+        lockstep.report(3, 1, vec!["c1"]).unwrap();
+        lockstep.report(2, 0, vec!["b0"]).unwrap();
+        lockstep.report(3, 0, vec!["c0", "c0'"]).unwrap();
+        assert!(matches!(lockstep.advance(now), TurnStatus::Waiting));
+
+        lockstep.report(1, 1, vec![]).unwrap();
+        lockstep.report(2, 1, vec!["b1"]).unwrap();
+        assert!(matches!(lockstep.advance(now), TurnStatus::Waiting));
+        assert_eq!(lockstep.turn(), 0);
+
+        lockstep.report(1, 0, vec!["a0"]).unwrap();
+        assert_eq!(
+            turn_commands(lockstep.advance(now)),
+            (0, vec![(1, "a0"), (2, "b0"), (3, "c0"), (3, "c0'")])
+        );
+        assert_eq!(
+            turn_commands(lockstep.advance(now)),
+            (1, vec![(2, "b1"), (3, "c1")])
+        );
+        assert!(matches!(lockstep.advance(now), TurnStatus::Waiting));
+        assert_eq!(lockstep.turn(), 2);
+
+        assert_eq!(
+            lockstep.report(1, 1, vec![]),
+            Err(LockstepError::Executed(1))
+        );
+        lockstep.report(1, 2, vec![]).unwrap();
+        assert_eq!(
+            lockstep.report(1, 2, vec![]),
+            Err(LockstepError::Duplicate(2))
+        );
+        assert_eq!(
+            lockstep.report(4, 2, vec![]),
+            Err(LockstepError::UnknownPeer)
+        );
+    }
+
+    #[test]
+    fn test_horizon() {
+        let now = Instant::now();
+        let mut lockstep = Lockstep::new([1, 2], Duration::from_secs(1), now);
+
+        lockstep.report(1, MAX_TURNS_AHEAD, vec!["a64"]).unwrap();
+        assert_eq!(
+            lockstep.report(1, MAX_TURNS_AHEAD + 1, vec![]),
+            Err(LockstepError::Ahead(MAX_TURNS_AHEAD + 1))
+        );
+        assert_eq!(
+            lockstep.report(2, u32::MAX, vec![]),
+            Err(LockstepError::Ahead(u32::MAX))
+        );
+
+        // The horizon moves with executed turns.
+        lockstep.report(1, 0, vec![]).unwrap();
+        lockstep.report(2, 0, vec![]).unwrap();
+        assert!(matches!(lockstep.advance(now), TurnStatus::Ready(_)));
+        lockstep.report(1, MAX_TURNS_AHEAD + 1, vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_stall() {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(500);
+        let mut lockstep = Lockstep::new([1, 2, 3], timeout, start);
+
+        let almost = start + timeout - Duration::from_millis(1);
+        assert!(matches!(lockstep.advance(almost), TurnStatus::Waiting));
+        match lockstep.advance(start + timeout) {
+            TurnStatus::Stalled(missing) => assert_eq!(missing, vec![1, 2, 3]),
+            _ => panic!("Turn is not stalled."),
+        }
+
+        lockstep.report(1, 0, vec![]).unwrap();
+        lockstep.report(3, 0, vec![]).unwrap();
+        match lockstep.advance(start + timeout) {
+            TurnStatus::Stalled(missing) => assert_eq!(missing, vec![2]),
+            _ => panic!("Turn is not stalled."),
+        }
+
+        let resumed = start + 2 * timeout;
+        lockstep.report(2, 0, vec!["b0"]).unwrap();
+        assert_eq!(
+            turn_commands(lockstep.advance(resumed)),
+            (0, vec![(2, "b0")])
+        );
+        // The timeout is measured since the last executed turn.
+        assert!(matches!(
+            lockstep.advance(resumed + timeout - Duration::from_millis(1)),
+            TurnStatus::Waiting
+        ));
+        assert!(matches!(
+            lockstep.advance(resumed + timeout),
+            TurnStatus::Stalled(_)
+        ));
+    }
+
+    #[test]
+    fn test_input_delay() {
+        let mut peers: Vec<InputDelay<&'static str>> =
+            (0..3).map(|_| InputDelay::new(3, 0)).collect();
+        assert!(advance_until(&mut peers, 5).iter().all(Vec::is_empty));
+
+        let target = peers[0].target().unwrap();
+        assert_eq!(target, 8);
+        for peer in peers.iter_mut() {
+            peer.schedule(target, "a5").unwrap();
+        }
+        let applied = advance_until(&mut peers, 10);
+        for peer_applied in applied {
+            assert_eq!(peer_applied, vec![(8, vec!["a5"])]);
+        }
+
+        let target = peers[1].target().unwrap();
+        assert_eq!(target, 13);
+        peers[0].schedule(target, "b10").unwrap();
+        peers[1].schedule(target, "b10'").unwrap();
+        peers[1].schedule(target, "b10").unwrap();
+        let mut applied = advance_until(&mut peers, 14);
+        assert_eq!(applied[0], vec![(13, vec!["b10"])]);
+        assert_eq!(applied[1], vec![(13, vec!["b10'", "b10"])]);
+        assert!(applied[2].is_empty());
+
+        // The command arrives after its target tick was applied.
+        peers[2].schedule(target, "b10").unwrap();
+        applied = advance_until(&mut peers, 16);
+        assert_eq!(applied[2], vec![(14, vec!["b10"])]);
+        assert_eq!(peers[0].late(), 0);
+        assert_eq!(peers[2].late(), 1);
+    }
+
+    #[test]
+    fn test_input_delay_horizon() {
+        let mut peers = vec![InputDelay::new(3, 10)];
+        peers[0].schedule(10 + MAX_TICKS_AHEAD, "a").unwrap();
+        assert_eq!(
+            peers[0].schedule(11 + MAX_TICKS_AHEAD, "b"),
+            Err(InputDelayError::Ahead(11 + MAX_TICKS_AHEAD))
+        );
+        assert_eq!(
+            peers[0].schedule(u32::MAX, "c"),
+            Err(InputDelayError::Ahead(u32::MAX))
+        );
+
+        let applied = advance_until(&mut peers, 11 + MAX_TICKS_AHEAD);
+        assert_eq!(applied[0], vec![(10 + MAX_TICKS_AHEAD, vec!["a"])]);
+        assert_eq!(peers[0].late(), 0);
+    }
+
+    #[test]
+    fn test_input_delay_target_overflow() {
+        let delay: InputDelay<()> = InputDelay::new(3, u32::MAX - 3);
+        assert_eq!(delay.target(), Some(u32::MAX));
+        let delay: InputDelay<()> = InputDelay::new(3, u32::MAX - 2);
+        assert_eq!(delay.target(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "larger than 256 ticks")]
+    fn test_input_delay_too_long() {
+        InputDelay::<()>::new(MAX_TICKS_AHEAD + 1, 0);
+    }
+}
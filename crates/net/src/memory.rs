@@ -0,0 +1,69 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use ahash::AHashMap;
+
+/// Approximate memory footprint of per connection state of the networking
+/// tasks (re-send queues, confirmation buffers, duplicate detection windows
+/// and so on) shared between the networking tasks and the application.
+/// Clones of the log share the data.
+///
+/// The number of reliable datagrams waiting for a confirmation is accounted
+/// together with the footprint.
+#[derive(Clone, Default)]
+pub(crate) struct MemoryLog(Arc<Mutex<Usage>>);
+
+#[derive(Default)]
+struct Usage {
+    connections: AHashMap<SocketAddr, usize>,
+    unconfirmed: usize,
+    exceeded: u64,
+    /// Number of accountings done so far.
+    updates: u64,
+}
+
+impl MemoryLog {
+    /// Replaces the footprint of all connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `connections` - footprint of individual connections.
+    ///
+    /// * `unconfirmed` - number of reliable datagrams (sent or about to be
+    ///   sent) waiting for a confirmation.
+    pub(crate) fn update(&self, connections: AHashMap<SocketAddr, usize>, unconfirmed: usize) {
+        let mut usage = self.lock();
+        usage.connections = connections;
+        usage.unconfirmed = unconfirmed;
+        usage.updates += 1;
+    }
+
+    /// Counts a connection closed due to exceeding the memory limit.
+    pub(crate) fn exceeded(&self) {
+        self.lock().exceeded += 1;
+    }
+
+    pub(crate) fn get(&self, addr: SocketAddr) -> Option<usize> {
+        self.lock().connections.get(&addr).copied()
+    }
+
+    pub(crate) fn exceeded_count(&self) -> u64 {
+        self.lock().exceeded
+    }
+
+    pub(crate) fn unconfirmed(&self) -> usize {
+        self.lock().unconfirmed
+    }
+
+    /// Returns the number of accountings done so far. It might be used to
+    /// wait for an accounting which reflects a particular change.
+    pub(crate) fn updates(&self) -> u64 {
+        self.lock().updates
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<Usage> {
+        self.0.lock().expect("Memory log lock is poisoned")
+    }
+}
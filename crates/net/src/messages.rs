@@ -1,32 +1,54 @@
 use std::{borrow::Cow, io, net::SocketAddr};
 
 use async_std::sync::Arc;
-use futures::future::try_join_all;
+use futures::future::join_all;
 use thiserror::Error;
 use tracing::{error, trace};
 
 use crate::{
     header::{DatagramHeader, HeaderError, HEADER_SIZE},
-    net, Network, SendError, MAX_DATAGRAM_SIZE,
+    net,
+    observers::{Direction, Observers},
+    Network, SendError, MAX_DATAGRAM_SIZE,
 };
 
+/// Number of bytes of the application protocol ID prepended to each datagram
+/// (if configured, see [`crate::NetConf::with_protocol_id`]).
+pub(crate) const PROTOCOL_ID_SIZE: usize = 2;
 /// Maximum number of bytes of a single message.
-pub const MAX_MESSAGE_SIZE: usize = MAX_DATAGRAM_SIZE - HEADER_SIZE;
+pub const MAX_MESSAGE_SIZE: usize = MAX_DATAGRAM_SIZE - PROTOCOL_ID_SIZE - HEADER_SIZE;
 
 /// A thin layer over UDP datagram based network translating UDP datagrams to
 /// messages with headers.
 #[derive(Clone)]
 pub(crate) struct Messages {
     network: Arc<Network>,
+    protocol_id: Option<u16>,
+    observers: Arc<Observers>,
 }
 
 impl Messages {
-    pub(crate) fn new(network: Network) -> Self {
+    /// # Arguments
+    ///
+    /// * `network` - network used for datagram sending and receiving.
+    ///
+    /// * `protocol_id` - application protocol ID. If not None, it is
+    ///   prepended to each sent datagram and datagrams received without it
+    ///   are rejected.
+    pub(crate) fn new(network: Network, protocol_id: Option<u16>) -> Self {
         Self {
             network: Arc::new(network),
+            protocol_id,
+            observers: Arc::new(Observers::default()),
         }
     }
 
+    /// Returns traffic observers notified about each sent and received
+    /// datagram.
+    pub(crate) fn observers(&self) -> Arc<Observers> {
+        Arc::clone(&self.observers)
+    }
+
     pub(crate) fn port(&self) -> io::Result<u16> {
         self.network.port()
     }
@@ -37,12 +59,13 @@ impl Messages {
     ///
     /// # Arguments
     ///
-    /// * `data` - to be modified data slice containing the data of the message
-    ///   to be send. The data must start at position [`HEADER_SIZE`] and span
-    ///   across the rest of the slice.
+    /// * `buf` - buffer used for datagram construction. It must be at least
+    ///   [`MAX_DATAGRAM_SIZE`] long.
     ///
     /// * `header` - header of the message.
     ///
+    /// * `data` - data of the message.
+    ///
     /// * `targets` - recipients of the message.
     pub(crate) async fn send<'a, T>(
         &'a self,
@@ -54,20 +77,36 @@ impl Messages {
     where
         T: Into<Targets<'a>>,
     {
-        let len = HEADER_SIZE + data.len();
+        let prefix = self.prefix_len();
+        let len = prefix + HEADER_SIZE + data.len();
         assert!(buf.len() >= len);
         let buf = &mut buf[..len];
-        buf[HEADER_SIZE..len].copy_from_slice(data);
+        buf[prefix + HEADER_SIZE..len].copy_from_slice(data);
 
         trace!("Going to send datagram {}", header);
-        header.write(buf);
+        if let Some(protocol_id) = self.protocol_id {
+            buf[..PROTOCOL_ID_SIZE].copy_from_slice(&protocol_id.to_be_bytes());
+        }
+        header.write(&mut buf[prefix..]);
 
         match targets.into() {
             Targets::Single(target) => {
                 self.network.send(target, buf).await?;
+                self.observers
+                    .notify(Direction::Sent, &header, target, buf.len());
             }
             Targets::Many(targets) => {
-                try_join_all(targets.iter().map(|&target| self.network.send(target, buf))).await?;
+                // A failure to send the datagram to one of the targets must
+                // not prevent sending it to the others.
+                let results =
+                    join_all(targets.iter().map(|&target| self.network.send(target, buf))).await;
+                for (&target, result) in targets.iter().zip(&results) {
+                    if result.is_ok() {
+                        self.observers
+                            .notify(Direction::Sent, &header, target, buf.len());
+                    }
+                }
+                results.into_iter().collect::<Result<(), SendError>>()?;
             }
         }
 
@@ -95,10 +134,29 @@ impl Messages {
     ) -> Result<(SocketAddr, DatagramHeader, &'a [u8]), MsgRecvError> {
         let (stop, source) = self.network.recv(buf).await.map_err(MsgRecvError::from)?;
 
-        let header = DatagramHeader::read(&buf[0..stop]).map_err(MsgRecvError::from)?;
+        let prefix = self.prefix_len();
+        if let Some(protocol_id) = self.protocol_id {
+            if stop < PROTOCOL_ID_SIZE || buf[..PROTOCOL_ID_SIZE] != protocol_id.to_be_bytes() {
+                return Err(MsgRecvError::ForeignProtocol(source));
+            }
+        }
+
+        let header = DatagramHeader::read(&buf[prefix..stop])
+            .map_err(|err| MsgRecvError::InvalidHeader(source, err))?;
         trace!("Received datagram with ID {header}");
+        self.observers
+            .notify(Direction::Received, &header, source, stop);
+
+        Ok((source, header, &buf[prefix + HEADER_SIZE..stop]))
+    }
 
-        Ok((source, header, &buf[HEADER_SIZE..stop]))
+    /// Number of bytes preceding datagram header.
+    fn prefix_len(&self) -> usize {
+        if self.protocol_id.is_some() {
+            PROTOCOL_ID_SIZE
+        } else {
+            0
+        }
     }
 }
 
@@ -127,8 +185,51 @@ impl<'a> From<Vec<SocketAddr>> for Targets<'a> {
 
 #[derive(Error, Debug)]
 pub(crate) enum MsgRecvError {
-    #[error(transparent)]
-    InvalidHeader(#[from] HeaderError),
+    #[error("datagram from {0} has an invalid header: {1}")]
+    InvalidHeader(SocketAddr, #[source] HeaderError),
+    #[error("datagram from {0} does not match the application protocol ID")]
+    ForeignProtocol(SocketAddr),
     #[error("error while receiving data from the socket")]
     RecvError(#[from] net::RecvError),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use async_std::{future::timeout, task};
+
+    use super::*;
+    use crate::header::{DatagramId, Peers};
+
+    #[test]
+    fn test_protocol_id() {
+        task::block_on(async {
+            let receiver = Messages::new(Network::bind(None).await.unwrap(), Some(0xde01));
+            let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), receiver.port().unwrap());
+
+            let matching = Messages::new(Network::bind(None).await.unwrap(), Some(0xde01));
+            let foreign = Messages::new(Network::bind(None).await.unwrap(), Some(0xde02));
+            let plain = Messages::new(Network::bind(None).await.unwrap(), None);
+
+            let header = DatagramHeader::new_data(false, Peers::Players, DatagramId::zero());
+            let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+            for (sender, data) in [(&foreign, 1), (&plain, 2), (&matching, 3)] {
+                sender.send(&mut buf, header, &[data], addr).await.unwrap();
+
+                let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+                let result = timeout(Duration::from_secs(1), receiver.recv(&mut buf))
+                    .await
+                    .unwrap();
+                if data == 3 {
+                    let (_, received_header, received) = result.unwrap();
+                    assert_eq!(received_header, header);
+                    assert_eq!(received, &[3]);
+                } else {
+                    assert!(matches!(result, Err(MsgRecvError::ForeignProtocol(_))));
+                }
+            }
+        });
+    }
+}
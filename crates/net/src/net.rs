@@ -1,10 +1,18 @@
 use std::{
     io,
     net::{IpAddr, Ipv4Addr},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
-use async_std::net::{SocketAddr, UdpSocket};
+use async_std::{
+    channel::{bounded, Receiver, Sender},
+    net::{SocketAddr, UdpSocket},
+    task,
+};
+use futures::{future, pin_mut};
 use thiserror::Error;
+use tracing::warn;
 
 /// Maximum size of a UDP datagram which might be sent by this crate.
 ///
@@ -15,24 +23,140 @@ pub const MAX_DATAGRAM_SIZE: usize = 512;
 /// This struct represents a low level network connection. The connection is
 /// based on UDP and is unreliable and unordered.
 pub struct Network {
-    socket: UdpSocket,
+    /// None after the network was closed.
+    socket: RwLock<Option<Arc<UdpSocket>>>,
+    /// No message is ever sent via this channel. It is closed once the
+    /// network is closed so that pending receives are interrupted.
+    closing: (Sender<()>, Receiver<()>),
 }
 
 impl Network {
-    /// Creates / binds a new IPv4 based connection (socket).
+    /// Creates / binds a new IPv4 based connection (socket). The socket is
+    /// bound to the loopback interface, see [`SocketOptions::with_address`].
     ///
     /// # Arguments
     ///
     /// * `port` - if None, system assigned port is used.
-    pub async fn bind(port: Option<u16>) -> io::Result<Self> {
-        let port = port.unwrap_or(0);
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
-        let socket = UdpSocket::bind(addr).await?;
-        Ok(Self { socket })
+    pub async fn bind(port: Option<u16>) -> Result<Self, BindError> {
+        Self::bind_with(port, SocketOptions::default()).await
+    }
+
+    /// Same as [`Network::bind`] but the socket is configured with `options`.
+    /// Failure to set DSCP is not fatal, see [`Network::set_dscp`].
+    pub async fn bind_with(port: Option<u16>, options: SocketOptions) -> Result<Self, BindError> {
+        let ip = options.address.unwrap_or(Ipv4Addr::LOCALHOST);
+        let addr = SocketAddr::new(IpAddr::V4(ip), port.unwrap_or(0));
+        let socket = UdpSocket::bind(addr).await.map_err(|err| match port {
+            Some(port) if err.kind() == io::ErrorKind::AddrInUse => BindError::AddrInUse(port),
+            _ => BindError::Io(err),
+        })?;
+        if let Some(ttl) = options.ttl {
+            socket.set_ttl(ttl.into()).map_err(BindError::Io)?;
+        }
+
+        let network = Self {
+            socket: RwLock::new(Some(Arc::new(socket))),
+            closing: bounded(1),
+        };
+        if let Some(dscp) = options.dscp {
+            network.set_dscp(dscp);
+        }
+        Ok(network)
     }
 
     pub fn port(&self) -> io::Result<u16> {
-        self.socket.local_addr().map(|addr| addr.port())
+        self.open_socket()?.local_addr().map(|addr| addr.port())
+    }
+
+    /// Returns IP TTL (time to live) of sent datagrams.
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.open_socket()?.ttl()
+    }
+
+    /// Gracefully closes the network.
+    ///
+    /// All subsequent sends and receives fail with a `Closed` error and
+    /// pending receives are interrupted. Datagrams whose sending is already in
+    /// progress are handed over to the OS, this method waits for them at most
+    /// `timeout`. The socket is released once none of them is in progress,
+    /// i.e. the port might be bound again afterwards.
+    ///
+    /// Returns true if all in progress operations finished before the
+    /// timeout.
+    pub async fn close(&self, timeout: Duration) -> bool {
+        let Some(socket) = self.socket.write().unwrap().take() else { return true };
+        self.closing.0.close();
+
+        let deadline = Instant::now() + timeout;
+        // Each in progress operation holds a reference to the socket.
+        while Arc::strong_count(&socket) > 1 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            task::sleep(Duration::from_millis(1)).await;
+        }
+
+        true
+    }
+
+    fn socket(&self) -> Option<Arc<UdpSocket>> {
+        self.socket.read().unwrap().clone()
+    }
+
+    fn open_socket(&self) -> io::Result<Arc<UdpSocket>> {
+        self.socket().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "the network is closed")
+        })
+    }
+
+    /// Sets DSCP (Differentiated Services Code Point) of all subsequently
+    /// sent datagrams. This allows QoS-capable networks to prioritize the
+    /// traffic, for example 46 (Expedited Forwarding) marks low-latency
+    /// traffic.
+    ///
+    /// Failure to set the value, for example on platforms which do not
+    /// support it, is not fatal. A warning is logged and the datagrams are
+    /// sent unmarked.
+    ///
+    /// Returns true if the value was successfully set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dscp` is larger than 63 (DSCP is a 6 bit value).
+    pub fn set_dscp(&self, dscp: u8) -> bool {
+        assert!(dscp < 64, "DSCP must be smaller than 64, got {dscp}.");
+        let Some(socket) = self.socket() else {
+            warn!("Cannot set DSCP of a closed network.");
+            return false;
+        };
+
+        #[cfg(not(any(
+            target_os = "fuchsia",
+            target_os = "redox",
+            target_os = "solaris",
+            target_os = "illumos",
+        )))]
+        {
+            // DSCP occupies the upper 6 bits of the ToS byte.
+            match socket2::SockRef::from(socket.as_ref()).set_tos(u32::from(dscp) << 2) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Failed to set DSCP to {dscp}: {err:?}");
+                    false
+                }
+            }
+        }
+
+        #[cfg(any(
+            target_os = "fuchsia",
+            target_os = "redox",
+            target_os = "solaris",
+            target_os = "illumos",
+        ))]
+        {
+            warn!("Setting DSCP is not supported on this platform.");
+            false
+        }
     }
 
     /// Receive a single datagram.
@@ -45,12 +169,18 @@ impl Network {
     /// Panics if len of `buf` is smaller than [`MAX_DATAGRAM_SIZE`].
     pub async fn recv(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), RecvError> {
         assert!(buf.len() >= MAX_DATAGRAM_SIZE);
+        let Some(socket) = self.socket() else { return Err(RecvError::Closed) };
 
-        self.socket
-            .recv_from(buf)
-            .await
-            .map(|(len, source)| (len.min(MAX_DATAGRAM_SIZE), source))
-            .map_err(RecvError::from)
+        let received = socket.recv_from(buf);
+        let closing = self.closing.1.recv();
+        pin_mut!(received, closing);
+
+        match future::select(received, closing).await {
+            future::Either::Left((result, _)) => result
+                .map(|(len, source)| (len.min(MAX_DATAGRAM_SIZE), source))
+                .map_err(RecvError::from),
+            future::Either::Right(_) => Err(RecvError::Closed),
+        }
     }
 
     /// Send data to a single target.
@@ -68,24 +198,85 @@ impl Network {
             );
         }
 
-        let n = self
-            .socket
+        let Some(socket) = self.socket() else { return Err(SendError::Closed) };
+        let n = socket
             .send_to(data, target)
             .await
             .map_err(SendError::from)?;
+        check_sent(n, data.len())
+    }
+}
 
-        if n < data.len() {
-            Err(SendError::PartialSend(n, data.len()))
-        } else {
-            Ok(())
-        }
+/// Options of the UDP socket of a [`Network`], see [`Network::bind_with`].
+/// Unset options keep the system defaults.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SocketOptions {
+    address: Option<Ipv4Addr>,
+    ttl: Option<u8>,
+    dscp: Option<u8>,
+}
+
+impl SocketOptions {
+    /// Sets the local address the socket is bound to. The socket is bound to
+    /// [`Ipv4Addr::LOCALHOST`] by default, thus it can communicate only with
+    /// other local sockets. [`Ipv4Addr::UNSPECIFIED`] binds it to all
+    /// interfaces.
+    pub fn with_address(mut self, address: Ipv4Addr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sets IP TTL (time to live) of sent datagrams, i.e. the maximum number
+    /// of hops. For example TTL 1 keeps the traffic within the local network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is 0.
+    pub fn with_ttl(mut self, ttl: u8) -> Self {
+        assert!(ttl > 0, "TTL must be positive.");
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets DSCP of sent datagrams, see [`Network::set_dscp`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dscp` is larger than 63.
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        assert!(dscp < 64, "DSCP must be smaller than 64, got {dscp}.");
+        self.dscp = Some(dscp);
+        self
+    }
+}
+
+/// Checks that a whole datagram of `len` bytes was sent given that the OS
+/// reported `sent` bytes as sent. A partially sent datagram is corrupted on
+/// the wire.
+fn check_sent(sent: usize, len: usize) -> Result<(), SendError> {
+    if sent < len {
+        Err(SendError::PartialSend(sent, len))
+    } else {
+        Ok(())
     }
 }
 
+#[derive(Error, Debug)]
+pub enum BindError {
+    /// The port is already in use, most likely by another instance of the
+    /// game.
+    #[error("port {0} is already in use")]
+    AddrInUse(u16),
+    #[error("failed to bind a UDP socket")]
+    Io(#[source] io::Error),
+}
+
 #[derive(Error, Debug)]
 pub enum RecvError {
     #[error("an IO error occurred")]
     Io(#[from] io::Error),
+    #[error("the network is closed")]
+    Closed,
 }
 
 #[derive(Error, Debug)]
@@ -94,4 +285,123 @@ pub enum SendError {
     Io(#[from] io::Error),
     #[error("only {0} of {1} bytes sent")]
     PartialSend(usize, usize),
+    #[error("the network is closed")]
+    Closed,
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::task;
+
+    use super::*;
+
+    #[test]
+    fn test_set_dscp() {
+        let network = task::block_on(Network::bind(None)).unwrap();
+
+        let applied = network.set_dscp(46);
+        #[cfg(target_os = "linux")]
+        assert!(applied);
+
+        if applied {
+            let socket = network.socket().unwrap();
+            let tos = socket2::SockRef::from(socket.as_ref()).tos().unwrap();
+            assert_eq!(tos, 46 << 2);
+        }
+    }
+
+    #[test]
+    fn test_socket_options() {
+        task::block_on(async {
+            let options = SocketOptions::default().with_ttl(7).with_dscp(46);
+            let network = Network::bind_with(None, options).await.unwrap();
+            assert_eq!(network.ttl().unwrap(), 7);
+
+            #[cfg(target_os = "linux")]
+            {
+                let socket = network.socket().unwrap();
+                let tos = socket2::SockRef::from(socket.as_ref()).tos().unwrap();
+                assert_eq!(tos, 46 << 2);
+            }
+
+            network.close(Duration::from_secs(1)).await;
+            assert!(network.ttl().is_err());
+        });
+    }
+
+    #[test]
+    fn test_address() {
+        task::block_on(async {
+            let local = Network::bind(None).await.unwrap();
+            let any = Network::bind_with(
+                None,
+                SocketOptions::default().with_address(Ipv4Addr::UNSPECIFIED),
+            )
+            .await
+            .unwrap();
+
+            let addr = |network: &Network| network.socket().unwrap().local_addr().unwrap();
+            assert_eq!(addr(&local).ip(), Ipv4Addr::LOCALHOST);
+            assert_eq!(addr(&any).ip(), Ipv4Addr::UNSPECIFIED);
+
+            // Datagrams to a loopback address are delivered either way.
+            let target = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), any.port().unwrap());
+            local.send(target, &[1, 2]).await.unwrap();
+            let mut buf = [0; MAX_DATAGRAM_SIZE];
+            let (len, source) = any.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &[1, 2]);
+            assert_eq!(source, addr(&local));
+        });
+    }
+
+    #[test]
+    fn test_bind_conflict() {
+        task::block_on(async {
+            let network = Network::bind(None).await.unwrap();
+            let port = network.port().unwrap();
+            assert!(matches!(
+                Network::bind(Some(port)).await,
+                Err(BindError::AddrInUse(conflict)) if conflict == port
+            ));
+        });
+    }
+
+    #[test]
+    fn test_check_sent() {
+        assert!(check_sent(12, 12).is_ok());
+        assert!(matches!(
+            check_sent(10, 12),
+            Err(SendError::PartialSend(10, 12))
+        ));
+    }
+
+    #[test]
+    fn test_close() {
+        task::block_on(async {
+            let network = Arc::new(Network::bind(None).await.unwrap());
+            let port = network.port().unwrap();
+            let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+
+            let receiver = {
+                let network = Arc::clone(&network);
+                task::spawn(async move {
+                    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+                    network.recv(&mut buf).await
+                })
+            };
+
+            assert!(network.close(Duration::from_secs(1)).await);
+            assert!(matches!(receiver.await, Err(RecvError::Closed)));
+            assert!(matches!(
+                network.send(target, &[1, 2, 3]).await,
+                Err(SendError::Closed)
+            ));
+            assert!(network.port().is_err());
+            // Closing again is a no-op.
+            assert!(network.close(Duration::from_secs(1)).await);
+
+            let rebound = Network::bind(Some(port)).await.unwrap();
+            assert_eq!(rebound.port().unwrap(), port);
+        });
+    }
 }
@@ -0,0 +1,118 @@
+use std::{net::SocketAddr, sync::RwLock};
+
+use crate::header::DatagramHeader;
+
+/// Type of datagram headers, used to select the traffic observed by an
+/// observer (see [`crate::Communicator::observe`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderType {
+    /// Protocol control datagrams confirming delivery of reliable datagrams.
+    Confirmation,
+    /// Protocol control datagrams advertising receive window (see
+    /// [`crate::NetConf::with_receive_window`]).
+    Window,
+    /// Protocol control datagrams notifying the recipient that the sender
+    /// considers the connection closed.
+    Reset,
+    /// Data datagrams delivered reliably.
+    Reliable,
+    /// Data datagrams delivered unreliably.
+    Unreliable,
+}
+
+impl From<&DatagramHeader> for HeaderType {
+    fn from(header: &DatagramHeader) -> Self {
+        match header {
+            DatagramHeader::Confirmation => Self::Confirmation,
+            DatagramHeader::Window => Self::Window,
+            DatagramHeader::Reset => Self::Reset,
+            DatagramHeader::Data(data_header) => {
+                if data_header.reliable() {
+                    Self::Reliable
+                } else {
+                    Self::Unreliable
+                }
+            }
+        }
+    }
+}
+
+/// Direction of an observed datagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Observer callback. It is called with the direction of the datagram, the
+/// address of the remote peer and the length of the datagram in bytes.
+type Observer = Box<dyn Fn(Direction, SocketAddr, usize) + Send + Sync>;
+
+/// Registry of traffic observers shared between the application and the
+/// networking tasks.
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: RwLock<Vec<(HeaderType, Observer)>>,
+}
+
+impl Observers {
+    pub(crate) fn register(&self, header_type: HeaderType, observer: Observer) {
+        self.observers
+            .write()
+            .expect("Observers lock is poisoned")
+            .push((header_type, observer));
+    }
+
+    /// Calls all observers registered for the type of `header`.
+    pub(crate) fn notify(
+        &self,
+        direction: Direction,
+        header: &DatagramHeader,
+        addr: SocketAddr,
+        len: usize,
+    ) {
+        let header_type = HeaderType::from(header);
+        let observers = self.observers.read().expect("Observers lock is poisoned");
+        for (observed, observer) in observers.iter() {
+            if *observed == header_type {
+                observer(direction, addr, len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::header::{DatagramId, Peers};
+
+    #[test]
+    fn test_observers() {
+        let observers = Observers::default();
+        let addr = "127.0.0.1:1111".parse().unwrap();
+
+        let confirmed = Arc::new(AtomicUsize::new(0));
+        let confirmed_clone = Arc::clone(&confirmed);
+        observers.register(
+            HeaderType::Confirmation,
+            Box::new(move |direction, observed_addr, len| {
+                assert_eq!(direction, Direction::Received);
+                assert_eq!(observed_addr, addr);
+                confirmed_clone.fetch_add(len, Ordering::Relaxed);
+            }),
+        );
+
+        let reliable = DatagramHeader::new_data(true, Peers::Players, DatagramId::zero());
+        observers.notify(Direction::Received, &reliable, addr, 10);
+        assert_eq!(confirmed.load(Ordering::Relaxed), 0);
+
+        observers.notify(Direction::Received, &DatagramHeader::Confirmation, addr, 7);
+        observers.notify(Direction::Received, &DatagramHeader::Confirmation, addr, 4);
+        assert_eq!(confirmed.load(Ordering::Relaxed), 11);
+    }
+}
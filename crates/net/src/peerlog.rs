@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use ahash::AHashMap;
+
+use crate::{connection::IdRanges, transitions::TransitionKind};
+
+/// Maximum number of events kept per peer. The oldest events are dropped
+/// first.
+const MAX_PEER_EVENTS: usize = 32;
+/// Maximum number of peers with a kept log. Log of the peer with the oldest
+/// last event is dropped first.
+const MAX_LOGGED_PEERS: usize = 1024;
+
+/// A significant lifecycle or health event of a connection to a peer (see
+/// [`crate::Communicator::peer_events`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerEvent {
+    time: Instant,
+    kind: PeerEventKind,
+}
+
+impl PeerEvent {
+    /// Time of the event. Time of the last occurrence for aggregated
+    /// events.
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn kind(&self) -> PeerEventKind {
+        self.kind
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerEventKind {
+    /// First datagram was received from the peer (again after a
+    /// disconnection).
+    Connected,
+    /// Reliable datagrams were re-sent to the peer. Consecutive re-sends are
+    /// aggregated into a single event.
+    Retransmits(u32),
+    /// Re-sending to the peer is throttled due to a re-send storm.
+    Throttled,
+    /// Re-sending to the peer is paused due to congestion.
+    Stalled,
+    /// Reliable datagrams sent to the peer were never confirmed. This is
+    /// recorded right before [`PeerEventKind::Disconnected`].
+    Unconfirmed(IdRanges),
+    Disconnected(DisconnectReason),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// A reliable datagram could not be delivered to the peer.
+    Undelivered,
+    /// Nothing was received from the peer for a long time.
+    TimedOut,
+    /// The peer sent too many malformed datagrams (see
+    /// [`crate::NetConf::with_malformed_limit`]).
+    ProtocolError,
+    /// State kept for the connection to the peer grew over the memory limit
+    /// (see [`crate::NetConf::with_memory_limit`]).
+    MemoryLimit,
+    /// The peer considers the connection closed and asked for its tear down.
+    Reset,
+}
+
+/// Bounded per peer event log shared between the networking tasks and the
+/// application. Clones of the log share the events.
+#[derive(Clone, Default)]
+pub(crate) struct PeerLog(Arc<Mutex<AHashMap<SocketAddr, VecDeque<PeerEvent>>>>);
+
+impl PeerLog {
+    pub(crate) fn record(&self, time: Instant, addr: SocketAddr, kind: PeerEventKind) {
+        let mut peers = self.0.lock().expect("Peer log lock is poisoned");
+
+        if !peers.contains_key(&addr) && peers.len() >= MAX_LOGGED_PEERS {
+            let oldest = peers
+                .iter()
+                .min_by_key(|(_, events)| events.back().map(|event| event.time))
+                .map(|(&addr, _)| addr);
+            if let Some(oldest) = oldest {
+                peers.remove(&oldest);
+            }
+        }
+
+        let events = peers.entry(addr).or_default();
+        if let PeerEventKind::Retransmits(count) = kind {
+            if let Some(last) = events.back_mut() {
+                if let PeerEventKind::Retransmits(last_count) = last.kind {
+                    last.time = time;
+                    last.kind = PeerEventKind::Retransmits(last_count.saturating_add(count));
+                    return;
+                }
+            }
+        }
+
+        if events.len() >= MAX_PEER_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(PeerEvent { time, kind });
+    }
+
+    /// Records the peer event corresponding to a protocol state transition
+    /// (if there is any). Disconnections are recorded directly, together
+    /// with their reason.
+    pub(crate) fn record_transition(&self, time: Instant, transition: TransitionKind) {
+        let (addr, kind) = match transition {
+            TransitionKind::Resent { target, .. } => (target, PeerEventKind::Retransmits(1)),
+            TransitionKind::ThrottleStarted { target } => (target, PeerEventKind::Throttled),
+            TransitionKind::Paused { target } => (target, PeerEventKind::Stalled),
+            _ => return,
+        };
+        self.record(time, addr, kind);
+    }
+
+    /// Returns all kept events of a peer in the order in which they
+    /// happened.
+    pub(crate) fn snapshot(&self, addr: SocketAddr) -> Vec<PeerEvent> {
+        let peers = self.0.lock().expect("Peer log lock is poisoned");
+        peers
+            .get(&addr)
+            .map_or_else(Vec::new, |events| events.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_peer_log() {
+        let log = PeerLog::default();
+        let a = "1.2.3.4:1111".parse().unwrap();
+        let b = "1.2.3.4:1112".parse().unwrap();
+        let start = Instant::now();
+        let time = |ms| start + Duration::from_millis(ms);
+
+        log.record(time(0), a, PeerEventKind::Connected);
+        log.record(time(1), b, PeerEventKind::Connected);
+        log.record_transition(time(2), TransitionKind::Confirmed { source: a, id: 1 });
+        for (ms, id) in [(3, 1), (4, 2), (5, 3)] {
+            log.record_transition(time(ms), TransitionKind::Resent { target: a, id });
+        }
+        log.record_transition(time(6), TransitionKind::ThrottleStarted { target: a });
+        log.record_transition(time(7), TransitionKind::Resent { target: a, id: 4 });
+        log.record_transition(time(8), TransitionKind::Paused { target: a });
+        log.record_transition(time(9), TransitionKind::Failed { target: a });
+        log.record(
+            time(9),
+            a,
+            PeerEventKind::Disconnected(DisconnectReason::Undelivered),
+        );
+        log.record(
+            time(10),
+            b,
+            PeerEventKind::Disconnected(DisconnectReason::TimedOut),
+        );
+
+        let events: Vec<(Instant, PeerEventKind)> = log
+            .snapshot(a)
+            .iter()
+            .map(|event| (event.time(), event.kind()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (time(0), PeerEventKind::Connected),
+                (time(5), PeerEventKind::Retransmits(3)),
+                (time(6), PeerEventKind::Throttled),
+                (time(7), PeerEventKind::Retransmits(1)),
+                (time(8), PeerEventKind::Stalled),
+                (
+                    time(9),
+                    PeerEventKind::Disconnected(DisconnectReason::Undelivered)
+                ),
+            ]
+        );
+
+        let kinds: Vec<PeerEventKind> = log.snapshot(b).iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                PeerEventKind::Connected,
+                PeerEventKind::Disconnected(DisconnectReason::TimedOut)
+            ]
+        );
+
+        for i in 0..(MAX_PEER_EVENTS + 5) {
+            let kind = if i % 2 == 0 {
+                PeerEventKind::Stalled
+            } else {
+                PeerEventKind::Throttled
+            };
+            log.record(time(20), b, kind);
+        }
+        assert_eq!(log.snapshot(b).len(), MAX_PEER_EVENTS);
+        assert!(log.snapshot("1.2.3.4:1113".parse().unwrap()).is_empty());
+    }
+}
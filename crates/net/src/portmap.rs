@@ -0,0 +1,442 @@
+//! Opening of UDP ports on home routers via NAT-PMP (RFC 6886).
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use async_std::future::timeout;
+use thiserror::Error;
+
+use crate::{BindError, Network, RecvError, SendError, SocketOptions, MAX_DATAGRAM_SIZE};
+
+/// Port on which gateways listen to NAT-PMP requests.
+const NAT_PMP_PORT: u16 = 5351;
+const VERSION: u8 = 0;
+const OP_ADDRESS: u8 = 0;
+const OP_MAP_UDP: u8 = 1;
+/// Added to request opcodes in responses.
+const OP_RESPONSE: u8 = 128;
+/// Timeout of the first request attempt, it is doubled with each retry.
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const ATTEMPTS: u32 = 4;
+
+/// UDP port mapping opened on the gateway (home router) of the local
+/// network.
+///
+/// The mapping expires after its lifetime unless it is renewed. It should be
+/// closed with [`PortMapping::close`] once no longer needed.
+pub struct PortMapping {
+    gateway: Ipv4Addr,
+    external_ip: Ipv4Addr,
+    internal_port: u16,
+    external_port: u16,
+    lifetime: Duration,
+}
+
+impl PortMapping {
+    /// Asks the default gateway to forward a UDP port to a local port.
+    ///
+    /// Failures due to a gateway which does not support port mapping are
+    /// reported with errors for which [`PortMapError::is_unavailable`]
+    /// returns true.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - local UDP port. The same external port is requested but
+    ///   the gateway might assign a different one.
+    ///
+    /// * `lifetime` - requested lifetime of the mapping. The gateway might
+    ///   grant a different one.
+    pub async fn open(port: u16, lifetime: Duration) -> Result<Self, PortMapError> {
+        let gateway = default_gateway().ok_or(PortMapError::NoGateway)?;
+        Self::open_via(gateway, port, lifetime).await
+    }
+
+    /// Same as [`PortMapping::open`] but with an explicitly given gateway.
+    pub async fn open_via(
+        gateway: Ipv4Addr,
+        port: u16,
+        lifetime: Duration,
+    ) -> Result<Self, PortMapError> {
+        let Response::Address { ip: external_ip } = request(gateway, &address_request()).await?
+        else {
+            return Err(PortMapError::InvalidResponse);
+        };
+
+        let mut mapping = Self {
+            gateway,
+            external_ip,
+            internal_port: port,
+            external_port: port,
+            lifetime,
+        };
+        mapping.renew().await?;
+        Ok(mapping)
+    }
+
+    /// Public IP address of the gateway.
+    pub fn external_ip(&self) -> Ipv4Addr {
+        self.external_ip
+    }
+
+    /// External UDP port forwarded to the local port.
+    pub fn external_port(&self) -> u16 {
+        self.external_port
+    }
+
+    /// Lifetime of the mapping as granted by the gateway.
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    /// Renews the mapping for another lifetime. It is recommended to do so
+    /// after half of the lifetime.
+    pub async fn renew(&mut self) -> Result<(), PortMapError> {
+        let data = mapping_request(self.internal_port, self.external_port, self.lifetime);
+        match request(self.gateway, &data).await? {
+            Response::Mapping {
+                internal_port,
+                external_port,
+                lifetime,
+                ..
+            } if internal_port == self.internal_port => {
+                self.external_port = external_port;
+                self.lifetime = lifetime;
+                Ok(())
+            }
+            _ => Err(PortMapError::InvalidResponse),
+        }
+    }
+
+    /// Asks the gateway to remove the mapping.
+    pub async fn close(self) -> Result<(), PortMapError> {
+        request(
+            self.gateway,
+            &mapping_request(self.internal_port, 0, Duration::ZERO),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for PortMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} -> local port {}",
+            self.external_ip, self.external_port, self.internal_port
+        )
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PortMapError {
+    #[error("no default gateway found")]
+    NoGateway,
+    #[error("the gateway does not respond to port mapping requests")]
+    NoResponse,
+    #[error("the gateway rejected the request: {0}")]
+    Rejected(ResultCode),
+    #[error("the gateway sent an invalid response")]
+    InvalidResponse,
+    #[error(transparent)]
+    Bind(#[from] BindError),
+    #[error(transparent)]
+    Send(#[from] SendError),
+    #[error(transparent)]
+    Recv(#[from] RecvError),
+}
+
+impl PortMapError {
+    /// Returns true if the error indicates that port mapping is not
+    /// supported or not enabled on the local network, as opposed to a
+    /// failure of a supported mapping.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            Self::NoGateway
+                | Self::NoResponse
+                | Self::Rejected(
+                    ResultCode::UnsupportedVersion
+                        | ResultCode::NotAuthorized
+                        | ResultCode::UnsupportedOpcode
+                )
+        )
+    }
+}
+
+/// Non-success result code of a NAT-PMP response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultCode {
+    UnsupportedVersion,
+    /// For example the gateway supports port mapping but it is disabled.
+    NotAuthorized,
+    NetworkFailure,
+    OutOfResources,
+    UnsupportedOpcode,
+    Other(u16),
+}
+
+impl ResultCode {
+    /// Returns None for the success code.
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            0 => None,
+            1 => Some(Self::UnsupportedVersion),
+            2 => Some(Self::NotAuthorized),
+            3 => Some(Self::NetworkFailure),
+            4 => Some(Self::OutOfResources),
+            5 => Some(Self::UnsupportedOpcode),
+            code => Some(Self::Other(code)),
+        }
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion => write!(f, "unsupported protocol version"),
+            Self::NotAuthorized => write!(f, "port mapping is disabled"),
+            Self::NetworkFailure => write!(f, "the gateway has no external address"),
+            Self::OutOfResources => write!(f, "the gateway is out of resources"),
+            Self::UnsupportedOpcode => write!(f, "unsupported request"),
+            Self::Other(code) => write!(f, "result code {code}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Address {
+        ip: Ipv4Addr,
+    },
+    Mapping {
+        internal_port: u16,
+        external_port: u16,
+        lifetime: Duration,
+    },
+}
+
+impl Response {
+    /// Returns opcode of the request this is response to.
+    fn opcode(&self) -> u8 {
+        match self {
+            Self::Address { .. } => OP_ADDRESS,
+            Self::Mapping { .. } => OP_MAP_UDP,
+        }
+    }
+}
+
+fn address_request() -> [u8; 2] {
+    [VERSION, OP_ADDRESS]
+}
+
+fn mapping_request(internal_port: u16, external_port: u16, lifetime: Duration) -> [u8; 12] {
+    let lifetime = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX);
+
+    let mut data = [0; 12];
+    data[0] = VERSION;
+    data[1] = OP_MAP_UDP;
+    // Bytes 2..4 are reserved.
+    data[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    data[6..8].copy_from_slice(&external_port.to_be_bytes());
+    data[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    data
+}
+
+fn parse_response(data: &[u8]) -> Result<Response, PortMapError> {
+    if data.len() < 4 || data[0] != VERSION || data[1] < OP_RESPONSE {
+        return Err(PortMapError::InvalidResponse);
+    }
+    if let Some(code) = ResultCode::from_code(u16::from_be_bytes([data[2], data[3]])) {
+        return Err(PortMapError::Rejected(code));
+    }
+
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .ok_or(PortMapError::InvalidResponse)
+    };
+    let u16_at = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+            .ok_or(PortMapError::InvalidResponse)
+    };
+
+    // Seconds since the gateway's port mapping table was initialized.
+    let _epoch = u32_at(4)?;
+    match data[1] - OP_RESPONSE {
+        OP_ADDRESS => Ok(Response::Address {
+            ip: Ipv4Addr::from(u32_at(8)?),
+        }),
+        OP_MAP_UDP => Ok(Response::Mapping {
+            internal_port: u16_at(8)?,
+            external_port: u16_at(10)?,
+            lifetime: Duration::from_secs(u32_at(12)?.into()),
+        }),
+        _ => Err(PortMapError::InvalidResponse),
+    }
+}
+
+/// Sends a request to the gateway, retrying with exponentially increasing
+/// timeouts, and returns the response.
+async fn request(gateway: Ipv4Addr, data: &[u8]) -> Result<Response, PortMapError> {
+    // A socket bound to the loopback interface cannot reach the gateway.
+    let options = SocketOptions::default().with_address(Ipv4Addr::UNSPECIFIED);
+    let network = Network::bind_with(None, options).await?;
+    let target = SocketAddr::V4(SocketAddrV4::new(gateway, NAT_PMP_PORT));
+    let mut buf = [0; MAX_DATAGRAM_SIZE];
+
+    let mut attempt_timeout = INITIAL_TIMEOUT;
+    for _ in 0..ATTEMPTS {
+        network.send(target, data).await?;
+
+        let attempt = timeout(attempt_timeout, async {
+            loop {
+                let (len, source) = network.recv(&mut buf).await?;
+                // Datagrams from other sources or responses to other requests
+                // (e.g. delayed ones) are ignored.
+                if source != target {
+                    continue;
+                }
+                match parse_response(&buf[..len]) {
+                    Ok(response) if response.opcode() != data[1] => continue,
+                    result => return result,
+                }
+            }
+        });
+        if let Ok(result) = attempt.await {
+            return result;
+        }
+
+        attempt_timeout *= 2;
+    }
+
+    Err(PortMapError::NoResponse)
+}
+
+/// Returns IP address of the default gateway or None if it cannot be
+/// determined.
+fn default_gateway() -> Option<Ipv4Addr> {
+    #[cfg(target_os = "linux")]
+    {
+        let table = std::fs::read_to_string("/proc/net/route").ok()?;
+        parse_route_table(&table)
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Parses the default gateway from the content of `/proc/net/route`.
+#[cfg(any(target_os = "linux", test))]
+fn parse_route_table(table: &str) -> Option<Ipv4Addr> {
+    // Flags marking usable routes via a gateway.
+    const RTF_UP_GATEWAY: u16 = 0x0003;
+
+    table.lines().skip(1).find_map(|line| {
+        let mut columns = line.split_whitespace().skip(1);
+        let destination = u32::from_str_radix(columns.next()?, 16).ok()?;
+        let gateway = u32::from_str_radix(columns.next()?, 16).ok()?;
+        let flags = u16::from_str_radix(columns.next()?, 16).ok()?;
+
+        if destination != 0 || flags & RTF_UP_GATEWAY != RTF_UP_GATEWAY {
+            return None;
+        }
+        // Addresses are in the native byte order.
+        Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(address_request(), [0, 0]);
+        assert_eq!(
+            mapping_request(8082, 8082, Duration::from_secs(7200)),
+            [0, 1, 0, 0, 0x1f, 0x92, 0x1f, 0x92, 0, 0, 0x1c, 0x20]
+        );
+        assert_eq!(
+            mapping_request(8082, 0, Duration::ZERO),
+            [0, 1, 0, 0, 0x1f, 0x92, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        // Responses recorded from a home router.
+        assert_eq!(
+            parse_response(&[
+                0x00, 0x80, 0x00, 0x00, 0x00, 0x01, 0x51, 0x80, 0x4e, 0x1c, 0x2a, 0x07
+            ])
+            .unwrap(),
+            Response::Address {
+                ip: Ipv4Addr::new(78, 28, 42, 7),
+            }
+        );
+        assert_eq!(
+            parse_response(&[
+                0x00, 0x81, 0x00, 0x00, 0x00, 0x01, 0x51, 0x83, 0x1f, 0x92, 0x1f, 0x93, 0x00, 0x00,
+                0x0e, 0x10
+            ])
+            .unwrap(),
+            Response::Mapping {
+                internal_port: 8082,
+                external_port: 8083,
+                lifetime: Duration::from_secs(3600),
+            }
+        );
+
+        // Port mapping disabled in router settings.
+        assert!(matches!(
+            parse_response(&[
+                0x00, 0x81, 0x00, 0x02, 0x00, 0x00, 0x10, 0x00, 0x1f, 0x92, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00
+            ]),
+            Err(PortMapError::Rejected(ResultCode::NotAuthorized))
+        ));
+        // A gateway supporting only a newer protocol (PCP) sends a truncated
+        // response.
+        let err = parse_response(&[0x00, 0x80, 0x00, 0x01]).unwrap_err();
+        assert!(matches!(
+            err,
+            PortMapError::Rejected(ResultCode::UnsupportedVersion)
+        ));
+        assert!(err.is_unavailable());
+
+        assert!(matches!(
+            parse_response(&[0x00, 0x80, 0x00, 0x00, 0x00, 0x01, 0x51]),
+            Err(PortMapError::InvalidResponse)
+        ));
+        // A request, not a response.
+        assert!(matches!(
+            parse_response(&mapping_request(1, 1, Duration::ZERO)),
+            Err(PortMapError::InvalidResponse)
+        ));
+    }
+
+    #[test]
+    fn test_parse_route_table() {
+        let table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlp2s0\t0000A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+wlp2s0\t00000000\t0100A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+";
+        let expected = if cfg!(target_endian = "little") {
+            Ipv4Addr::new(192, 168, 0, 1)
+        } else {
+            Ipv4Addr::new(1, 0, 168, 192)
+        };
+        assert_eq!(parse_route_table(table), Some(expected));
+
+        let no_default = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlp2s0\t0000A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+";
+        assert_eq!(parse_route_table(no_default), None);
+    }
+}
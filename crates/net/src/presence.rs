@@ -0,0 +1,133 @@
+use std::{
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+
+/// In-game presence of peers based on heartbeats.
+///
+/// Each peer broadcasts a small heartbeat message (for example unreliably) at
+/// a fixed interval, see [`Presence::heartbeat_due`]. A peer from which no
+/// heartbeat was received for a configured number of intervals is reported
+/// as having connection trouble. This is distinct from a disconnection of the
+/// peer which is detected by the communication stack (see
+/// [`crate::PeerEventKind::Disconnected`]): a lagging peer is still connected
+/// and becomes active again once its heartbeats resume.
+pub struct Presence<P> {
+    interval: Duration,
+    missed: u32,
+    /// Time at which the next local heartbeat is due.
+    next_heartbeat: Instant,
+    /// Time of the last heartbeat received from each peer. Time of the
+    /// start of tracking for peers with no heartbeat yet.
+    last: AHashMap<P, Instant>,
+}
+
+impl<P> Presence<P>
+where
+    P: Copy + Eq + Hash,
+{
+    /// # Arguments
+    ///
+    /// * `peers` - remote peers whose presence is tracked.
+    ///
+    /// * `interval` - interval between heartbeats.
+    ///
+    /// * `missed` - number of consecutive missed heartbeats after which a
+    ///   peer is considered to have connection trouble.
+    ///
+    /// * `time` - current time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero or `missed` is 0.
+    pub fn new<I>(peers: I, interval: Duration, missed: u32, time: Instant) -> Self
+    where
+        I: IntoIterator<Item = P>,
+    {
+        assert!(!interval.is_zero(), "Heartbeat interval must be positive.");
+        assert!(missed > 0, "Number of missed heartbeats must be positive.");
+
+        Self {
+            interval,
+            missed,
+            next_heartbeat: time,
+            last: peers.into_iter().map(|peer| (peer, time)).collect(),
+        }
+    }
+
+    /// Returns true if a local heartbeat is to be broadcasted now. Each call
+    /// returning true schedules the next heartbeat.
+    pub fn heartbeat_due(&mut self, time: Instant) -> bool {
+        if time < self.next_heartbeat {
+            return false;
+        }
+        self.next_heartbeat = time + self.interval;
+        true
+    }
+
+    /// Records a heartbeat received from a peer. Heartbeats of untracked
+    /// peers are ignored.
+    pub fn received(&mut self, peer: P, time: Instant) {
+        if let Some(last) = self.last.get_mut(&peer) {
+            *last = (*last).max(time);
+        }
+    }
+
+    /// Returns presence status of a peer or None if the peer is not tracked.
+    pub fn status(&self, peer: P, time: Instant) -> Option<PresenceStatus> {
+        let last = self.last.get(&peer)?;
+        let silence = time.saturating_duration_since(*last);
+        Some(if silence > self.interval * self.missed {
+            PresenceStatus::Trouble
+        } else {
+            PresenceStatus::Active
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresenceStatus {
+    /// Heartbeats of the peer are received regularly.
+    Active,
+    /// Heartbeats of the peer are missing, it is lagging or its connection
+    /// is failing.
+    Trouble,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PresenceStatus::*, *};
+
+    #[test]
+    fn test_presence() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut presence = Presence::new([1, 2], ms(100), 3, start);
+
+        assert!(presence.heartbeat_due(start));
+        assert!(!presence.heartbeat_due(start + ms(50)));
+        assert!(presence.heartbeat_due(start + ms(100)));
+
+        for i in 1..=5 {
+            presence.received(1, start + ms(i * 100));
+        }
+        assert_eq!(presence.status(1, start + ms(500)), Some(Active));
+        // Peer 2 missed all heartbeats.
+        assert_eq!(presence.status(2, start + ms(300)), Some(Active));
+        assert_eq!(presence.status(2, start + ms(500)), Some(Trouble));
+        assert_eq!(presence.status(3, start), None);
+
+        // Peer 1 stops sending heartbeats.
+        assert_eq!(presence.status(1, start + ms(800)), Some(Active));
+        assert_eq!(presence.status(1, start + ms(900)), Some(Trouble));
+
+        // Heartbeats resume.
+        presence.received(2, start + ms(1000));
+        assert_eq!(presence.status(2, start + ms(1000)), Some(Active));
+        // A delayed old heartbeat does not rewind the last heartbeat.
+        presence.received(2, start + ms(600));
+        assert_eq!(presence.status(2, start + ms(1200)), Some(Active));
+    }
+}
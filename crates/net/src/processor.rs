@@ -1,59 +1,162 @@
-use std::time::Instant;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
 
+use ahash::{AHashMap, AHashSet};
 use async_std::{
     channel::{bounded, Receiver, SendError, Sender, TryRecvError},
     task,
 };
-use futures::FutureExt;
+use futures::{join, select_biased, FutureExt};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{error, info, trace, warn};
 
 use crate::{
-    communicator::{Communicator, ConnectionError, InMessage, OutMessage},
-    connection::{Confirmations, Resends},
+    clock::{Clock, RealClock},
+    communicator::{Communicator, ConnectionError, Destination, InMessage, OutMessage},
+    conf::NetConf,
+    connection::{Confirmations, Deliveries, IdRanges, Malformed, Members, Resends},
+    delivery::DeliveryLog,
+    fault::FaultLog,
     header::{DatagramHeader, DatagramId},
+    memory::MemoryLog,
     messages::{Messages, MsgRecvError},
+    peerlog::{DisconnectReason, PeerEventKind, PeerLog},
     tasks::{
         dreceiver::{self, InDatagram},
         dsender::{self, OutDatagram},
     },
+    transitions::{Recorder, TransitionKind, TransitionLog},
     Network, MAX_DATAGRAM_SIZE,
 };
 
-const CHANNEL_CAPACITY: usize = 1024;
+/// Interval between accountings of memory used by individual connections.
+const MEMORY_INTERVAL: Duration = Duration::from_millis(100);
 
 /// This struct implements an async loop which handles the network
 /// communication.
-struct Processor {
+struct Processor<C: Clock = RealClock> {
     buf: [u8; MAX_DATAGRAM_SIZE],
     counter: DatagramId,
     out_datagrams: Sender<OutDatagram>,
+    /// Output of confirmation datagrams. It might be the same channel as
+    /// `out_datagrams`.
+    out_confirms: Sender<OutDatagram>,
     in_datagrams: Receiver<InDatagram>,
-    confirms: Confirmations,
-    resends: Resends,
+    confirms: Confirmations<C>,
+    resends: Resends<C>,
+    deliveries: Deliveries<C>,
+    members: Members<C>,
+    /// None if peers sending malformed datagrams are never disconnected.
+    malformed: Option<Malformed<C>>,
     outputs: Receiver<OutMessage>,
     inputs: Sender<InMessage>,
+    inbound_watermark: Option<usize>,
+    above_watermark: bool,
     errors: Sender<ConnectionError>,
+    work_budget: usize,
+    log: Recorder,
+    receive_window: Option<usize>,
+    inbound: InboundBytes,
+    sent_keys: SentKeys,
+    memory: MemoryLog,
+    memory_limit: Option<usize>,
+    next_memory_check: Instant,
+    /// Reliable messages postponed until they fit to receive windows of
+    /// their targets.
+    stalled: StalledMessages,
+    /// Message taken from the outputs channel while waiting for activity, it
+    /// is sent first.
+    pending: Option<OutMessage>,
+    /// Datagram received while waiting for activity, it is processed first.
+    woken: Option<InDatagram>,
+    /// None if the loop never sleeps, see [`NetConf::with_idle_interval`].
+    idle_interval: Option<Duration>,
+    /// True if any work was done during the last iteration of the loop.
+    busy: bool,
 }
 
 impl Processor {
+    #[allow(clippy::too_many_arguments)]
     fn new(
+        conf: NetConf,
+        out_datagrams: Sender<OutDatagram>,
+        out_confirms: Sender<OutDatagram>,
+        in_datagrams: Receiver<InDatagram>,
+        outputs: Receiver<OutMessage>,
+        inputs: Sender<InMessage>,
+        errors: Sender<ConnectionError>,
+        log: Recorder,
+        deliveries: DeliveryLog,
+        memory: MemoryLog,
+    ) -> Self {
+        Self::with_clock(
+            RealClock,
+            conf,
+            out_datagrams,
+            out_confirms,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            log,
+            deliveries,
+            memory,
+        )
+    }
+}
+
+impl<C: Clock + Clone> Processor<C> {
+    /// Creates the processor whose connection state (confirmations,
+    /// re-sends, deliveries and members) is driven by `clock`.
+    #[allow(clippy::too_many_arguments)]
+    fn with_clock(
+        clock: C,
+        conf: NetConf,
         out_datagrams: Sender<OutDatagram>,
+        out_confirms: Sender<OutDatagram>,
         in_datagrams: Receiver<InDatagram>,
         outputs: Receiver<OutMessage>,
         inputs: Sender<InMessage>,
         errors: Sender<ConnectionError>,
+        log: Recorder,
+        deliveries: DeliveryLog,
+        memory: MemoryLog,
     ) -> Self {
         Self {
             buf: [0; MAX_DATAGRAM_SIZE],
             out_datagrams,
+            out_confirms,
             in_datagrams,
             counter: DatagramId::zero(),
-            confirms: Confirmations::new(),
-            resends: Resends::new(),
+            confirms: Confirmations::new(clock.clone(), conf.confirm_redundancy()),
+            resends: Resends::new(clock.clone()).with_log(log.clone()),
+            deliveries: Deliveries::new(clock.clone(), deliveries),
+            members: Members::new(clock.clone()).with_idle_timeout(conf.idle_timeout()),
+            malformed: conf
+                .malformed_limit()
+                .map(|(limit, window)| Malformed::new(clock, limit, window)),
             outputs,
             inputs,
+            inbound_watermark: conf.inbound_watermark(),
+            above_watermark: false,
             errors,
+            work_budget: conf.work_budget(),
+            log,
+            receive_window: conf.receive_window(),
+            inbound: InboundBytes::default(),
+            sent_keys: SentKeys::default(),
+            memory,
+            memory_limit: conf.memory_limit(),
+            next_memory_check: Instant::now(),
+            stalled: StalledMessages::default(),
+            pending: None,
+            woken: None,
+            idle_interval: conf.idle_interval(),
+            busy: false,
         }
     }
 
@@ -69,137 +172,641 @@ impl Processor {
         info!("Starting network loop...");
 
         loop {
-            if self.handle_output().await {
-                info!("Output finished...");
+            if self.step().await {
                 break;
             }
+        }
+    }
 
-            if self.handle_input().await {
-                info!("Input finished...");
-                break;
+    /// Runs a single iteration of the loop and waits before the next one.
+    ///
+    /// Returns true if the loop should terminate.
+    async fn step(&mut self) -> bool {
+        if self.tick().await {
+            return true;
+        }
+
+        match self.idle_interval {
+            Some(interval) if !self.busy => self.wait(interval).await,
+            // The loop might share a thread with other networking tasks (see
+            // NetConf::with_dedicated_thread).
+            _ => task::yield_now().await,
+        }
+        false
+    }
+
+    /// Waits until a datagram is received, a message is sent by the
+    /// application or `interval` elapses, whichever comes first.
+    async fn wait(&mut self, interval: Duration) {
+        select_biased! {
+            datagram = self.in_datagrams.recv().fuse() => self.woken = datagram.ok(),
+            message = self.outputs.recv().fuse() => self.pending = message.ok(),
+            _ = task::sleep(interval).fuse() => (),
+        }
+    }
+
+    /// Runs a single iteration of the loop. The amount of work done is
+    /// limited by the work budget (see [`NetConf::with_work_budget`]), the
+    /// rest is deferred to subsequent iterations.
+    ///
+    /// Returns true if the loop should terminate.
+    async fn tick(&mut self) -> bool {
+        self.busy = false;
+        if self.handle_output().await {
+            info!("Output finished...");
+            return true;
+        }
+
+        let mut budget = self.work_budget;
+        while budget > 0 {
+            match self.handle_input().await {
+                InputResult::Processed => {
+                    self.busy = true;
+                    budget -= 1;
+                }
+                InputResult::Empty => break,
+                InputResult::Closed => {
+                    info!("Input finished...");
+                    return true;
+                }
             }
+        }
 
-            if let Err(err) = self
-                .confirms
-                .send_confirms(Instant::now(), &mut self.out_datagrams)
-                .await
-            {
+        let window = self.receive_window();
+        match self
+            .confirms
+            .send_confirms(&mut self.out_confirms, budget, window)
+            .await
+        {
+            Ok(sent) => {
+                self.busy |= sent > 0;
+                budget = budget.saturating_sub(sent);
+            }
+            Err(err) => {
                 error!("Message confirmation error: {err:?}");
-                break;
+                return true;
             }
+        }
 
-            if self.handle_resends().await {
-                info!("Errors finished...");
-                break;
-            }
+        if self.handle_resends(budget).await {
+            info!("Errors finished...");
+            return true;
+        }
+        if self.handle_memory().await {
+            info!("Errors finished...");
+            return true;
+        }
 
-            let time = Instant::now();
-            self.resends.clean(time);
-            self.confirms.clean(time);
+        self.resends.clean();
+        self.deliveries.clean();
+        self.confirms.clean();
+        if let Some(malformed) = self.malformed.as_mut() {
+            malformed.clean();
+        }
+        let time = Instant::now();
+        for addr in self.members.clean() {
+            self.stalled.remove(addr);
+            self.log.peers().record(
+                time,
+                addr,
+                PeerEventKind::Disconnected(DisconnectReason::TimedOut),
+            );
         }
+        false
     }
 
     async fn handle_output(&mut self) -> bool {
-        match self.outputs.try_recv() {
-            Ok(message) => {
-                let header =
-                    DatagramHeader::new_data(message.reliable(), message.peers(), self.counter);
-                self.counter = self.counter.incremented();
-
-                if let DatagramHeader::Data(data_header) = header {
-                    if data_header.reliable() {
-                        let time = Instant::now();
-                        for &target in &message.targets {
-                            self.resends.sent(
-                                time,
-                                target,
-                                data_header.id(),
-                                data_header.peers(),
-                                &message.data,
-                            );
-                        }
-                    }
-                }
+        // Stalled messages are sent first so that they are not overtaken by
+        // newer messages to the same targets.
+        while let Some((target, message)) = self.stalled.pop_allowed(&self.resends) {
+            if self.send(message, vec![target]).await {
+                return true;
+            }
+        }
 
-                let closed = self
-                    .out_datagrams
-                    .send(OutDatagram::new(header, message.data, message.targets))
-                    .await
-                    .is_err();
+        let message = match self.pending.take() {
+            Some(message) => message,
+            None => match self.outputs.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Closed) => return true,
+            },
+        };
 
-                if closed {
-                    error!("Datagram output channel is unexpectedly closed.");
-                }
+        let targets = match message.destination {
+            Destination::Targets(ref targets) => targets.clone(),
+            Destination::AllExcept(excluded) => self.members.all_except(excluded),
+        };
+        let targets = match message.dedup {
+            Some((tick, key)) => self.sent_keys.filter(tick, key, targets),
+            None => targets,
+        };
 
-                closed
+        // Receive windows are honored only if flow control is enabled on this
+        // side as well. A target with a full window does not hold back
+        // messages to other targets.
+        let targets = if message.reliable() && self.receive_window.is_some() {
+            let len = message.data.len();
+            let (allowed, blocked): (Vec<SocketAddr>, Vec<SocketAddr>) =
+                targets.into_iter().partition(|&target| {
+                    !self.stalled.contains(target) && self.resends.allows(target, len)
+                });
+            for target in blocked {
+                self.stalled.push(target, &message);
             }
-            Err(err) => match err {
-                TryRecvError::Empty => false,
-                TryRecvError::Closed => true,
-            },
+            allowed
+        } else {
+            targets
+        };
+
+        if targets.is_empty() {
+            return false;
         }
+        self.send(message, targets).await
     }
 
-    async fn handle_input(&mut self) -> bool {
-        let Some(recv_result) = self.in_datagrams.recv().now_or_never() else {
-            return false;
+    /// Sends a message to `targets` and returns true if the datagram output
+    /// channel is closed.
+    async fn send(&mut self, message: OutMessage, targets: Vec<SocketAddr>) -> bool {
+        let header = if message.acked() {
+            DatagramHeader::new_acked_data(message.peers(), self.counter)
+        } else {
+            DatagramHeader::new_data(message.reliable(), message.peers(), self.counter)
+        };
+        self.counter = self.counter.incremented();
+        self.busy = true;
+
+        if let DatagramHeader::Data(data_header) = header {
+            let time = Instant::now();
+            for &target in &targets {
+                self.log.record(
+                    time,
+                    TransitionKind::Sent {
+                        target,
+                        id: data_header.id().to_u32(),
+                        reliable: data_header.reliable(),
+                    },
+                );
+            }
+
+            if data_header.reliable() {
+                for &target in &targets {
+                    self.resends
+                        .sent(target, data_header.id(), data_header.peers(), &message.data);
+                }
+            } else if data_header.acked() {
+                for &target in &targets {
+                    self.deliveries.sent(target, data_header.id());
+                }
+            }
+        }
+
+        let closed = self
+            .out_datagrams
+            .send(OutDatagram::new(header, message.data, targets))
+            .await
+            .is_err();
+
+        if closed {
+            error!("Datagram output channel is unexpectedly closed.");
+        }
+
+        closed
+    }
+
+    /// Returns the receive window to be advertised to peers or None if no
+    /// window is configured.
+    fn receive_window(&mut self) -> Option<u32> {
+        let window = self.receive_window?;
+        let waiting = self.inbound.sync(self.inputs.len());
+        Some(
+            window
+                .saturating_sub(waiting)
+                .try_into()
+                .unwrap_or(u32::MAX),
+        )
+    }
+
+    async fn handle_input(&mut self) -> InputResult {
+        let recv_result = match self.woken.take() {
+            Some(datagram) => Ok(datagram),
+            None => match self.in_datagrams.recv().now_or_never() {
+                Some(recv_result) => recv_result,
+                None => return InputResult::Empty,
+            },
         };
 
         let Ok(datagram) = recv_result else {
             error!("Datagram input channel is unexpectedly closed.");
-            return true;
+            return InputResult::Closed;
+        };
+        let Some(header) = datagram.header else {
+            return self.handle_malformed(datagram.time, datagram.source).await;
         };
+        if !self.members.contains(datagram.source) && self.members.closed(datagram.source) {
+            // The peer does not know that the connection is closed. It is
+            // told so once, further data re-open the connection.
+            if matches!(header, DatagramHeader::Data(_)) {
+                self.members.forget_closed(datagram.source);
+                return self.send_reset(datagram.source).await;
+            }
+            return InputResult::Processed;
+        }
+        if let DatagramHeader::Reset = header {
+            return self.handle_reset(datagram.time, datagram.source).await;
+        }
+        if self.members.received(datagram.source) {
+            self.log
+                .peers()
+                .record(datagram.time, datagram.source, PeerEventKind::Connected);
+        }
 
-        let data_header = match datagram.header {
+        let data_header = match header {
             DatagramHeader::Confirmation => {
-                self.resends
-                    .confirmed(Instant::now(), datagram.source, &datagram.data);
-                return false;
+                let reliable = self.deliveries.confirmed(datagram.source, &datagram.data);
+                self.resends.confirmed(datagram.source, &reliable);
+                return InputResult::Processed;
+            }
+            DatagramHeader::Window => {
+                match <[u8; 4]>::try_from(datagram.data.as_slice()) {
+                    Ok(window) => self
+                        .resends
+                        .window(datagram.source, u32::from_be_bytes(window) as usize),
+                    Err(_) => {
+                        warn!(
+                            "Invalid receive window advertisement from {}.",
+                            datagram.source
+                        );
+                        return self.handle_malformed(datagram.time, datagram.source).await;
+                    }
+                }
+                return InputResult::Processed;
             }
+            DatagramHeader::Reset => unreachable!("Resets are handled above."),
             DatagramHeader::Data(data_header) => data_header,
         };
 
         let reliable = if data_header.reliable() {
-            self.confirms
-                .received(Instant::now(), datagram.source, data_header.id());
+            if !self.confirms.received(datagram.source, data_header.id()) {
+                trace!(
+                    "Dropping duplicate datagram {} from {}.",
+                    data_header.id(),
+                    datagram.source
+                );
+                return InputResult::Processed;
+            }
             true
         } else {
+            if data_header.acked() {
+                // Unreliable datagrams are not deduplicated, only their
+                // confirmation is scheduled.
+                self.confirms.received(datagram.source, data_header.id());
+            }
             false
         };
 
-        self.inputs
+        self.log.record(
+            datagram.time,
+            TransitionKind::Received {
+                source: datagram.source,
+                id: data_header.id().to_u32(),
+                reliable,
+            },
+        );
+
+        let len = datagram.data.len();
+        let closed = self
+            .inputs
             .send(InMessage::new(
                 datagram.data,
                 reliable,
                 data_header.peers(),
                 datagram.source,
+                datagram.time,
             ))
             .await
-            .is_err()
+            .is_err();
+        if self.receive_window.is_some() {
+            self.inbound.pushed(len);
+        }
+
+        self.check_inbound_watermark();
+        if closed {
+            InputResult::Closed
+        } else {
+            InputResult::Processed
+        }
+    }
+
+    /// Asks a peer to tear down a connection closed by this side.
+    async fn send_reset(&mut self, target: SocketAddr) -> InputResult {
+        trace!("Sending reset to {target}.");
+        let closed = self
+            .out_confirms
+            .send(OutDatagram::new(DatagramHeader::Reset, Vec::new(), target))
+            .await
+            .is_err();
+
+        if closed {
+            error!("Datagram output channel is unexpectedly closed.");
+            InputResult::Closed
+        } else {
+            InputResult::Processed
+        }
+    }
+
+    /// Tears down a connection which the peer considers closed, for example
+    /// because it disconnected this side or because it was restarted.
+    async fn handle_reset(&mut self, time: Instant, source: SocketAddr) -> InputResult {
+        if !self.members.contains(source) {
+            return InputResult::Processed;
+        }
+
+        warn!("Connection reset by {source}.");
+        if self
+            .disconnect(time, source, DisconnectReason::Reset, None)
+            .await
+        {
+            InputResult::Closed
+        } else {
+            InputResult::Processed
+        }
+    }
+
+    /// Handles a malformed datagram received from `source`. A live peer is
+    /// disconnected once it sends too many malformed datagrams.
+    async fn handle_malformed(&mut self, time: Instant, source: SocketAddr) -> InputResult {
+        let Some(malformed) = self.malformed.as_mut() else { return InputResult::Processed };
+        if !self.members.contains(source) || !malformed.received(source) {
+            return InputResult::Processed;
+        }
+
+        warn!("Disconnecting {source} due to excessive malformed datagrams.");
+        if self
+            .disconnect(time, source, DisconnectReason::ProtocolError, None)
+            .await
+        {
+            InputResult::Closed
+        } else {
+            InputResult::Processed
+        }
+    }
+
+    /// Periodically accounts memory used by individual connections and
+    /// disconnects peers whose connections exceed the memory limit.
+    ///
+    /// Returns true if the errors channel is closed.
+    async fn handle_memory(&mut self) -> bool {
+        let time = Instant::now();
+        if time < self.next_memory_check {
+            return false;
+        }
+        self.next_memory_check = time + MEMORY_INTERVAL;
+
+        let mut usage: AHashMap<SocketAddr, usize> = AHashMap::new();
+        for (addr, bytes) in self
+            .resends
+            .memory()
+            .chain(self.confirms.memory())
+            .chain(self.deliveries.memory())
+            .chain(self.stalled.memory())
+        {
+            *usage.entry(addr).or_default() += bytes;
+        }
+
+        let exceeded: Vec<(SocketAddr, usize)> = match self.memory_limit {
+            Some(limit) => usage
+                .iter()
+                .filter(|(_, &bytes)| bytes > limit)
+                .map(|(&addr, &bytes)| (addr, bytes))
+                .collect(),
+            None => Vec::new(),
+        };
+        for (addr, bytes) in exceeded {
+            warn!("Disconnecting {addr} due to excessive memory usage ({bytes} bytes).");
+            usage.remove(&addr);
+            self.memory.exceeded();
+            if self
+                .disconnect(time, addr, DisconnectReason::MemoryLimit, None)
+                .await
+            {
+                return true;
+            }
+        }
+
+        let unconfirmed = self.resends.unconfirmed() + self.stalled.len();
+        self.memory.update(usage, unconfirmed);
+        false
     }
 
-    async fn handle_resends(&mut self) -> bool {
+    /// Logs a warning once the number of messages waiting for the application
+    /// rises above the configured watermark.
+    fn check_inbound_watermark(&mut self) {
+        let Some(watermark) = self.inbound_watermark else { return };
+
+        let len = self.inputs.len();
+        let above = len > watermark;
+        if above && !self.above_watermark {
+            warn!("{len} received messages are waiting for processing, the application is falling behind.");
+        }
+        self.above_watermark = above;
+    }
+
+    async fn handle_resends(&mut self, budget: usize) -> bool {
         let failures = match self
             .resends
-            .resend(Instant::now(), &mut self.buf, &mut self.out_datagrams)
+            .resend(&mut self.buf, &mut self.out_datagrams, budget)
             .await
         {
-            Ok(failures) => failures,
+            Ok((resent, failures)) => {
+                self.busy |= resent > 0 || !failures.is_empty();
+                failures
+            }
             Err(err) => {
                 error!("Resend error: {err:?}");
                 return true;
             }
         };
 
-        for target in failures {
-            let result = self.errors.send(ConnectionError::new(target)).await;
-            if result.is_err() {
+        let time = Instant::now();
+        for (target, unconfirmed) in failures {
+            if self
+                .disconnect(
+                    time,
+                    target,
+                    DisconnectReason::Undelivered,
+                    Some(unconfirmed),
+                )
+                .await
+            {
                 return true;
             }
         }
 
         false
     }
+
+    /// Tears down all state of a connection to a peer, records the
+    /// disconnection (and datagrams never confirmed by the peer) to the peer
+    /// log and informs the application.
+    ///
+    /// Returns true if the errors channel is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `unconfirmed` - datagrams never confirmed by the peer if re-sending
+    ///   to the peer has already been given up (see [`Resends::resend`]).
+    ///   Otherwise, they are taken from the re-sends.
+    async fn disconnect(
+        &mut self,
+        time: Instant,
+        addr: SocketAddr,
+        reason: DisconnectReason,
+        unconfirmed: Option<IdRanges>,
+    ) -> bool {
+        if reason == DisconnectReason::Reset {
+            // The peer already considers the connection closed.
+            self.members.reset(addr);
+        } else {
+            self.members.remove(addr);
+        }
+        let removed = self.resends.remove(addr);
+        self.confirms.remove(addr);
+        self.deliveries.remove(addr);
+        self.stalled.remove(addr);
+
+        let unconfirmed = unconfirmed.unwrap_or(removed);
+        if !unconfirmed.is_empty() {
+            self.log
+                .peers()
+                .record(time, addr, PeerEventKind::Unconfirmed(unconfirmed));
+        }
+        self.log
+            .peers()
+            .record(time, addr, PeerEventKind::Disconnected(reason));
+
+        self.errors.send(ConnectionError::new(addr)).await.is_err()
+    }
+}
+
+/// Number of bytes of received messages waiting for the application.
+#[derive(Default)]
+struct InboundBytes {
+    /// Lengths of the waiting messages in the order of receiving.
+    lengths: VecDeque<usize>,
+    total: usize,
+}
+
+impl InboundBytes {
+    fn pushed(&mut self, len: usize) {
+        self.lengths.push_back(len);
+        self.total += len;
+    }
+
+    /// Forgets messages already retrieved by the application and returns the
+    /// number of bytes of the waiting messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `waiting` - number of messages waiting in the (FIFO) channel to the
+    ///   application.
+    fn sync(&mut self, waiting: usize) -> usize {
+        while self.lengths.len() > waiting {
+            self.total -= self.lengths.pop_front().unwrap();
+        }
+        self.total
+    }
+}
+
+/// Targets of de-duplicated messages sent during the current tick (see
+/// [`OutMessage::with_dedup`]).
+#[derive(Default)]
+struct SentKeys {
+    tick: u32,
+    targets: AHashMap<u64, AHashSet<SocketAddr>>,
+}
+
+impl SentKeys {
+    /// Returns the targets to which a message with `key` was not yet sent
+    /// during `tick` and marks them as sent.
+    fn filter(&mut self, tick: u32, key: u64, targets: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        if tick != self.tick {
+            self.tick = tick;
+            self.targets.clear();
+        }
+
+        let sent = self.targets.entry(key).or_default();
+        targets
+            .into_iter()
+            .filter(|&target| sent.insert(target))
+            .collect()
+    }
+}
+
+/// Reliable messages postponed until they fit to receive windows of their
+/// targets. Messages are queued per target so that a target with a full
+/// window does not hold back messages to other targets.
+#[derive(Default)]
+struct StalledMessages(AHashMap<SocketAddr, VecDeque<OutMessage>>);
+
+impl StalledMessages {
+    /// Returns true if any message to `target` is postponed.
+    fn contains(&self, target: SocketAddr) -> bool {
+        self.0.contains_key(&target)
+    }
+
+    /// Postpones sending of a message to a single target.
+    fn push(&mut self, target: SocketAddr, message: &OutMessage) {
+        let message = OutMessage::new(
+            message.data.clone(),
+            message.reliable(),
+            message.peers(),
+            vec![target],
+        );
+        self.0.entry(target).or_default().push_back(message);
+    }
+
+    /// Removes and returns the oldest postponed message which fits to the
+    /// receive window of its target.
+    fn pop_allowed<C: Clock>(&mut self, resends: &Resends<C>) -> Option<(SocketAddr, OutMessage)> {
+        let target = self.0.iter().find_map(|(&target, queue)| {
+            let len = queue.front().unwrap().data.len();
+            resends.allows(target, len).then_some(target)
+        })?;
+
+        let queue = self.0.get_mut(&target).unwrap();
+        let message = queue.pop_front().unwrap();
+        if queue.is_empty() {
+            self.0.remove(&target);
+        }
+        Some((target, message))
+    }
+
+    /// Forgets all messages postponed for `target`.
+    fn remove(&mut self, target: SocketAddr) {
+        self.0.remove(&target);
+    }
+
+    /// Returns the number of postponed messages.
+    fn len(&self) -> usize {
+        self.0.values().map(|queue| queue.len()).sum()
+    }
+
+    /// Returns the number of bytes of postponed data per target.
+    fn memory(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.0
+            .iter()
+            .map(|(&target, queue)| (target, queue.iter().map(|message| message.data.len()).sum()))
+    }
+}
+
+enum InputResult {
+    /// No datagram is waiting for processing.
+    Empty,
+    /// A single datagram was processed.
+    Processed,
+    /// A communication channel is closed.
+    Closed,
 }
 
 #[derive(Error, Debug)]
@@ -211,29 +818,1278 @@ enum InputHandlingError {
 }
 
 /// Setups and starts communication stack tasks.
-pub fn startup(network: Network) -> Communicator {
-    let messages = Messages::new(network);
+///
+/// # Panics
+///
+/// Panics if a dedicated networking thread is configured and the thread
+/// cannot be spawned.
+pub fn startup(network: Network, conf: NetConf) -> Communicator {
+    let messages = Messages::new(network, conf.protocol_id());
+    let observers = messages.observers();
+    let log = Recorder::new(
+        TransitionLog::new(conf.transition_log()),
+        PeerLog::default(),
+    );
+    let deliveries = DeliveryLog::default();
+    let memory = MemoryLog::default();
+    let faults = FaultLog::default();
 
-    let (out_datagrams_sender, out_datagrams_receiver) = bounded(16);
-    task::spawn(dsender::run(out_datagrams_receiver, messages.clone()));
+    let (out_datagrams_sender, out_datagrams_receiver) = bounded(conf.datagram_capacity());
+    let (out_confirms_sender, out_confirms_receiver) = if conf.confirm_priority() {
+        bounded(conf.datagram_capacity())
+    } else {
+        (out_datagrams_sender.clone(), out_datagrams_receiver.clone())
+    };
+    let dsender = dsender::run(
+        out_confirms_receiver,
+        out_datagrams_receiver,
+        messages.clone(),
+    );
 
-    let (in_datagrams_sender, in_datagrams_receiver) = bounded(16);
-    task::spawn(dreceiver::run(in_datagrams_sender, messages));
+    let (in_datagrams_sender, in_datagrams_receiver) = bounded(conf.datagram_capacity());
+    let dreceiver = dreceiver::run(
+        in_datagrams_sender,
+        messages,
+        faults.clone(),
+        log.counters().clone(),
+    );
 
-    let (outputs_sender, outputs_receiver) = bounded(CHANNEL_CAPACITY);
-    let (inputs_sender, inputs_receiver) = bounded(CHANNEL_CAPACITY);
-    let (errors_sender, errors_receiver) = bounded(CHANNEL_CAPACITY);
+    let (outputs_sender, outputs_receiver) = bounded(conf.message_capacity());
+    let (inputs_sender, inputs_receiver) = bounded(conf.message_capacity());
+    let (errors_sender, errors_receiver) = bounded(conf.message_capacity());
 
-    let communicator = Communicator::new(outputs_sender, inputs_receiver, errors_receiver);
     let processor = Processor::new(
+        conf,
         out_datagrams_sender,
+        out_confirms_sender,
         in_datagrams_receiver,
         outputs_receiver,
         inputs_sender,
         errors_sender,
+        log.clone(),
+        deliveries.clone(),
+        memory.clone(),
     );
 
-    task::spawn(processor.run());
+    let thread = if conf.dedicated_thread() {
+        let thread = thread::Builder::new()
+            .name("de-net".to_owned())
+            .spawn(move || {
+                task::block_on(async {
+                    join!(dsender, dreceiver, processor.run());
+                })
+            })
+            .expect("Failed to spawn networking thread");
+        Some(thread)
+    } else {
+        task::spawn(dsender);
+        task::spawn(dreceiver);
+        task::spawn(processor.run());
+        None
+    };
+
+    Communicator::new(
+        outputs_sender,
+        inputs_receiver,
+        errors_receiver,
+        observers,
+        log,
+        deliveries,
+        memory,
+        faults,
+        thread,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use async_std::future::timeout;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock, header::HEADER_SIZE, messages::Targets, Direction, HeaderType, Peers,
+        TransitionKind,
+    };
+
+    #[test]
+    fn test_inbound_backpressure() {
+        task::block_on(async {
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let mut communicator_a = startup(network_a, NetConf::default());
+            let mut communicator_b = startup(
+                network_b,
+                NetConf::default()
+                    .with_datagram_capacity(1)
+                    .with_message_capacity(2),
+            );
+
+            for i in 0..8 {
+                communicator_a
+                    .send(OutMessage::new(
+                        vec![i],
+                        false,
+                        Peers::Players,
+                        vec![addr_b],
+                    ))
+                    .await
+                    .unwrap();
+            }
+
+            task::sleep(Duration::from_millis(300)).await;
+            assert_eq!(communicator_b.inbound_len(), 2);
+
+            // Receiving resumes once the application catches up.
+            for i in 0..8 {
+                let message = timeout(Duration::from_secs(1), communicator_b.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(message.data(), vec![i]);
+            }
+        });
+    }
+
+    #[test]
+    fn test_flush() {
+        task::block_on(async {
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+            // Nobody is listening on the port of a dropped network.
+            let addr_c = {
+                let network_c = Network::bind(None).await.unwrap();
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_c.port().unwrap())
+            };
+
+            let mut communicator_a = startup(network_a, NetConf::default());
+            let mut communicator_b = startup(network_b, NetConf::default());
+
+            assert!(communicator_a.flush(Duration::from_secs(1)).await);
+            for i in 0..4 {
+                communicator_a
+                    .send(OutMessage::new(vec![i], true, Peers::Players, vec![addr_b]))
+                    .await
+                    .unwrap();
+            }
+            assert!(communicator_a.flush(Duration::from_secs(2)).await);
+            assert_eq!(communicator_b.drain_inbound(8).len(), 4);
 
-    communicator
+            communicator_a
+                .send(OutMessage::new(vec![1], true, Peers::Players, vec![addr_c]))
+                .await
+                .unwrap();
+            let start = Instant::now();
+            assert!(!communicator_a.flush(Duration::from_millis(300)).await);
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+
+    #[test]
+    fn test_stale_confirmation_count() {
+        task::block_on(async {
+            let network = Network::bind(None).await.unwrap();
+            let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network.port().unwrap());
+            let communicator = startup(network, NetConf::default());
+
+            let raw = Messages::new(Network::bind(None).await.unwrap(), None);
+            let mut buf = [0; MAX_DATAGRAM_SIZE];
+            // Confirmations of never sent datagrams.
+            raw.send(
+                &mut buf,
+                DatagramHeader::Confirmation,
+                &[0, 0, 7, 0, 0, 9],
+                addr,
+            )
+            .await
+            .unwrap();
+
+            let start = Instant::now();
+            while communicator.stale_confirmations() < 2 {
+                assert!(start.elapsed() < Duration::from_secs(2));
+                task::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(communicator.stale_confirmations(), 2);
+        });
+    }
+
+    #[test]
+    fn test_work_budget() {
+        let (out_datagrams, _out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (_outputs_sender, outputs) = bounded(16);
+        let (inputs, inputs_receiver) = bounded(16);
+        let (errors, _errors_receiver) = bounded(16);
+
+        let mut processor = Processor::new(
+            NetConf::default().with_work_budget(4),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let source = "1.2.3.4:1111".parse().unwrap();
+        for i in 0..10 {
+            in_datagrams_sender
+                .try_send(InDatagram {
+                    source,
+                    header: Some(DatagramHeader::new_data(
+                        false,
+                        Peers::Players,
+                        DatagramId::zero(),
+                    )),
+                    data: vec![i],
+                    time: Instant::now(),
+                })
+                .unwrap();
+        }
+
+        task::block_on(async {
+            for expected in [4, 8, 10, 10] {
+                assert!(!processor.tick().await);
+                assert_eq!(inputs_receiver.len(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_idle_interval() {
+        let processor = |interval| {
+            let (out_datagrams, out_datagrams_receiver) = bounded(16);
+            let (in_datagrams_sender, in_datagrams) = bounded(16);
+            let (outputs_sender, outputs) = bounded(16);
+            let (inputs, inputs_receiver) = bounded(16);
+            let (errors, errors_receiver) = bounded(16);
+
+            let processor = Processor::new(
+                NetConf::default().with_idle_interval(interval),
+                out_datagrams.clone(),
+                out_datagrams,
+                in_datagrams,
+                outputs,
+                inputs,
+                errors,
+                Recorder::default(),
+                DeliveryLog::default(),
+                MemoryLog::default(),
+            );
+            let channels = (
+                out_datagrams_receiver,
+                in_datagrams_sender,
+                outputs_sender,
+                inputs_receiver,
+                errors_receiver,
+            );
+            (processor, channels)
+        };
+
+        /// Returns the number of loop iterations done within `duration`.
+        async fn steps(processor: &mut Processor, duration: Duration) -> usize {
+            let start = Instant::now();
+            let mut steps = 0;
+            while start.elapsed() < duration {
+                assert!(!processor.step().await);
+                steps += 1;
+            }
+            steps
+        }
+
+        task::block_on(async {
+            let (mut spinning, _channels) = processor(None);
+            assert!(steps(&mut spinning, Duration::from_millis(100)).await > 100);
+
+            let interval = Duration::from_millis(50);
+            let (mut processor, channels) = processor(Some(interval));
+            let (_, in_datagrams_sender, _, inputs_receiver, _) = channels;
+
+            // Without any activity, the loop wakes up once per interval.
+            let count = steps(&mut processor, 10 * interval).await;
+            assert!((5..=11).contains(&count), "{count}");
+
+            // New activity wakes the loop up immediately.
+            let sender = in_datagrams_sender.clone();
+            task::spawn(async move {
+                task::sleep(Duration::from_millis(10)).await;
+                sender
+                    .send(InDatagram {
+                        source: "1.2.3.4:1111".parse().unwrap(),
+                        header: Some(DatagramHeader::new_data(
+                            false,
+                            Peers::Players,
+                            DatagramId::zero(),
+                        )),
+                        data: vec![1],
+                        time: Instant::now(),
+                    })
+                    .await
+                    .unwrap();
+            });
+            let start = Instant::now();
+            assert!(!processor.step().await);
+            assert!(start.elapsed() < interval / 2);
+            assert!(!processor.step().await);
+            assert_eq!(inputs_receiver.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_message_age() {
+        let (out_datagrams, _out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (_outputs_sender, outputs) = bounded(16);
+        let (inputs, inputs_receiver) = bounded(16);
+        let (errors, _errors_receiver) = bounded(16);
+
+        let mut processor = Processor::new(
+            NetConf::default(),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let delay = Duration::from_millis(50);
+        let received = Instant::now();
+        in_datagrams_sender
+            .try_send(InDatagram {
+                source: "1.2.3.4:1111".parse().unwrap(),
+                header: Some(DatagramHeader::new_data(
+                    true,
+                    Peers::Players,
+                    DatagramId::zero(),
+                )),
+                data: vec![1],
+                time: received,
+            })
+            .unwrap();
+
+        // The message waits for processing by the application.
+        task::block_on(async {
+            assert!(!processor.tick().await);
+            task::sleep(delay).await;
+        });
+        let message = inputs_receiver.try_recv().unwrap();
+        let processed = Instant::now();
+
+        assert_eq!(message.received(), received);
+        assert!(message.age(processed) >= delay);
+        assert_eq!(message.age(processed), processed - received);
+        assert_eq!(message.age(received - delay), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_malformed_disconnect() {
+        let (out_datagrams, _out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(16);
+        let (inputs, _inputs_receiver) = bounded(16);
+        let (errors, errors_receiver) = bounded(16);
+        let log = Recorder::default();
+
+        let mut processor = Processor::new(
+            NetConf::default().with_malformed_limit(Some((4, Duration::from_secs(10)))),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            log.clone(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let faulty: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let sparse: SocketAddr = "1.2.3.4:1112".parse().unwrap();
+        let datagram = |source, header| InDatagram {
+            source,
+            header,
+            data: vec![1],
+            time: Instant::now(),
+        };
+        let valid = Some(DatagramHeader::new_data(
+            false,
+            Peers::Players,
+            DatagramId::zero(),
+        ));
+
+        // Reliable data to the faulty peer are waiting for a confirmation.
+        outputs_sender
+            .try_send(OutMessage::new(vec![2], true, Peers::Players, vec![faulty]))
+            .unwrap();
+        for source in [faulty, sparse] {
+            in_datagrams_sender
+                .try_send(datagram(source, valid))
+                .unwrap();
+        }
+        for _ in 0..4 {
+            in_datagrams_sender
+                .try_send(datagram(faulty, None))
+                .unwrap();
+        }
+        for _ in 0..3 {
+            in_datagrams_sender
+                .try_send(datagram(sparse, None))
+                .unwrap();
+        }
+
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+
+        let disconnected = |addr| {
+            log.peers().snapshot(addr).iter().any(|event| {
+                event.kind() == PeerEventKind::Disconnected(DisconnectReason::ProtocolError)
+            })
+        };
+        assert!(disconnected(faulty));
+        assert!(!disconnected(sparse));
+        assert_eq!(processor.members.all_except(faulty), vec![sparse]);
+        // The application is notified and nothing is re-sent to the
+        // disconnected peer.
+        assert_eq!(errors_receiver.try_recv().unwrap().target(), faulty);
+        assert!(errors_receiver.is_empty());
+        assert_eq!(processor.resends.unconfirmed(), 0);
+    }
+
+    #[test]
+    fn test_undelivered_disconnect() {
+        let (out_datagrams, _out_datagrams_receiver) = bounded(64);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(16);
+        let (inputs, _inputs_receiver) = bounded(16);
+        let (errors, errors_receiver) = bounded(16);
+        let log = Recorder::default();
+        let clock = ManualClock::new();
+
+        let mut processor = Processor::with_clock(
+            clock.clone(),
+            NetConf::default().with_idle_timeout(Duration::from_secs(3600)),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            log.clone(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let peer: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        in_datagrams_sender
+            .try_send(InDatagram {
+                source: peer,
+                header: Some(DatagramHeader::new_data(
+                    true,
+                    Peers::Players,
+                    DatagramId::zero(),
+                )),
+                data: vec![1],
+                time: Instant::now(),
+            })
+            .unwrap();
+        outputs_sender
+            .try_send(OutMessage::new(vec![2], true, Peers::Players, vec![peer]))
+            .unwrap();
+
+        task::block_on(async {
+            assert!(!processor.tick().await);
+            assert!(processor.members.contains(peer));
+
+            // The peer never confirms the message.
+            for _ in 0..32 {
+                if !errors_receiver.is_empty() {
+                    break;
+                }
+                clock.advance(Duration::from_secs(20));
+                assert!(!processor.tick().await);
+            }
+        });
+
+        assert_eq!(errors_receiver.try_recv().unwrap().target(), peer);
+        let kinds: Vec<PeerEventKind> = log
+            .peers()
+            .snapshot(peer)
+            .iter()
+            .map(|event| event.kind())
+            .filter(|kind| !matches!(kind, PeerEventKind::Retransmits(_)))
+            .collect();
+        assert_eq!(kinds.len(), 3);
+        assert_eq!(kinds[0], PeerEventKind::Connected);
+        match kinds[1] {
+            PeerEventKind::Unconfirmed(ranges) => assert_eq!(ranges.to_string(), "0"),
+            kind => panic!("Unexpected event: {kind:?}"),
+        }
+        assert_eq!(
+            kinds[2],
+            PeerEventKind::Disconnected(DisconnectReason::Undelivered)
+        );
+
+        // All state of the connection is torn down.
+        assert!(!processor.members.contains(peer));
+        assert_eq!(processor.resends.unconfirmed(), 0);
+        assert!(processor.confirms.memory().all(|(addr, _)| addr != peer));
+        assert!(processor.deliveries.memory().all(|(addr, _)| addr != peer));
+    }
+
+    #[test]
+    fn test_send_reset() {
+        let (out_datagrams, out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (_outputs_sender, outputs) = bounded(16);
+        let (inputs, inputs_receiver) = bounded(16);
+        let (errors, _errors_receiver) = bounded(16);
+
+        let mut processor = Processor::new(
+            NetConf::default().with_malformed_limit(Some((2, Duration::from_secs(10)))),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let source: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let datagram = |header| InDatagram {
+            source,
+            header,
+            data: vec![1],
+            time: Instant::now(),
+        };
+        let data = Some(DatagramHeader::new_data(
+            true,
+            Peers::Players,
+            DatagramId::zero(),
+        ));
+
+        // The connection is closed by this side after the peer sends
+        // malformed datagrams.
+        in_datagrams_sender.try_send(datagram(data)).unwrap();
+        in_datagrams_sender.try_send(datagram(None)).unwrap();
+        in_datagrams_sender.try_send(datagram(None)).unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+        assert_eq!(inputs_receiver.len(), 1);
+        assert!(!processor.members.contains(source));
+        while out_datagrams_receiver.try_recv().is_ok() {}
+
+        in_datagrams_sender
+            .try_send(datagram(Some(DatagramHeader::Confirmation)))
+            .unwrap();
+        in_datagrams_sender.try_send(datagram(data)).unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+
+        // Data are answered with a reset, control datagrams are ignored.
+        let reset = out_datagrams_receiver.try_recv().unwrap();
+        assert_eq!(reset.header, DatagramHeader::Reset);
+        assert!(reset.data.is_empty());
+        assert!(out_datagrams_receiver.is_empty());
+        assert_eq!(inputs_receiver.len(), 1);
+        assert!(!processor.members.contains(source));
+
+        // The reset peer reconnects.
+        in_datagrams_sender.try_send(datagram(data)).unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+        assert_eq!(inputs_receiver.len(), 2);
+        assert!(processor.members.contains(source));
+    }
+
+    #[test]
+    fn test_receive_reset() {
+        let (out_datagrams, _out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(16);
+        let (inputs, inputs_receiver) = bounded(16);
+        let (errors, errors_receiver) = bounded(16);
+        let log = Recorder::default();
+
+        let mut processor = Processor::new(
+            NetConf::default(),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            log.clone(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let peer: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let stranger: SocketAddr = "1.2.3.4:1112".parse().unwrap();
+        let datagram = |source, header| InDatagram {
+            source,
+            header: Some(header),
+            data: vec![1],
+            time: Instant::now(),
+        };
+        let data = DatagramHeader::new_data(false, Peers::Players, DatagramId::zero());
+
+        in_datagrams_sender.try_send(datagram(peer, data)).unwrap();
+        outputs_sender
+            .try_send(OutMessage::new(vec![2], true, Peers::Players, vec![peer]))
+            .unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+        assert!(processor.members.contains(peer));
+        assert!(processor.resends.memory().any(|(addr, _)| addr == peer));
+
+        in_datagrams_sender
+            .try_send(datagram(peer, DatagramHeader::Reset))
+            .unwrap();
+        in_datagrams_sender
+            .try_send(datagram(stranger, DatagramHeader::Reset))
+            .unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+
+        assert_eq!(errors_receiver.try_recv().unwrap().target(), peer);
+        assert!(errors_receiver.is_empty());
+        assert!(!processor.members.contains(peer));
+        assert!(!processor.members.contains(stranger));
+        assert!(processor.resends.memory().all(|(addr, _)| addr != peer));
+        let reset = PeerEventKind::Disconnected(DisconnectReason::Reset);
+        assert!(log
+            .peers()
+            .snapshot(peer)
+            .iter()
+            .any(|event| event.kind() == reset));
+
+        // The peer might connect again.
+        in_datagrams_sender.try_send(datagram(peer, data)).unwrap();
+        task::block_on(async {
+            assert!(!processor.tick().await);
+        });
+        assert!(processor.members.contains(peer));
+        assert_eq!(inputs_receiver.len(), 2);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let (out_datagrams, out_datagrams_receiver) = bounded(64);
+        let (_in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(64);
+        let (inputs, _inputs_receiver) = bounded(16);
+        let (errors, errors_receiver) = bounded(16);
+        let log = Recorder::default();
+        let memory = MemoryLog::default();
+
+        let mut processor = Processor::new(
+            NetConf::default().with_memory_limit(Some(16 * 1024)),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            log.clone(),
+            DeliveryLog::default(),
+            memory.clone(),
+        );
+
+        // The peer never confirms anything, thus all sent reliable messages
+        // are kept for re-sending.
+        let target: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let mut send = |count: usize| {
+            task::block_on(async {
+                for _ in 0..count {
+                    outputs_sender
+                        .send(OutMessage::new(
+                            vec![7; 400],
+                            true,
+                            Peers::Players,
+                            vec![target],
+                        ))
+                        .await
+                        .unwrap();
+                    assert!(!processor.tick().await);
+                    out_datagrams_receiver.recv().await.unwrap();
+                }
+                task::sleep(MEMORY_INTERVAL).await;
+                assert!(!processor.tick().await);
+            });
+        };
+
+        send(8);
+        let first = memory.get(target).unwrap();
+        assert!(first > 8 * 400);
+        assert!(errors_receiver.is_empty());
+
+        send(8);
+        let second = memory.get(target).unwrap();
+        assert!(second > first);
+        assert!(errors_receiver.is_empty());
+        assert_eq!(memory.exceeded_count(), 0);
+
+        send(32);
+        assert_eq!(errors_receiver.try_recv().unwrap().target(), target);
+        assert_eq!(memory.get(target), None);
+        assert_eq!(memory.exceeded_count(), 1);
+        assert!(log.peers().snapshot(target).iter().any(|event| {
+            event.kind() == PeerEventKind::Disconnected(DisconnectReason::MemoryLimit)
+        }));
+    }
+
+    #[test]
+    fn test_send_dedup() {
+        let (out_datagrams, out_datagrams_receiver) = bounded(16);
+        let (_in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(16);
+        let (inputs, _inputs_receiver) = bounded(16);
+        let (errors, _errors_receiver) = bounded(16);
+
+        let mut processor = Processor::new(
+            NetConf::default(),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let a: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let b: SocketAddr = "1.2.3.4:1112".parse().unwrap();
+        let message =
+            |tick| OutMessage::new(vec![42], false, Peers::Players, vec![a, b]).with_dedup(tick, 7);
+        for tick in [1, 1, 2] {
+            outputs_sender.try_send(message(tick)).unwrap();
+        }
+
+        task::block_on(async {
+            // A single message is sent per processing loop iteration.
+            for _ in 0..3 {
+                assert!(!processor.tick().await);
+            }
+        });
+
+        let sent: Vec<Vec<SocketAddr>> =
+            std::iter::from_fn(|| out_datagrams_receiver.try_recv().ok())
+                .map(|datagram| match datagram.targets {
+                    Targets::Single(target) => vec![target],
+                    Targets::Many(targets) => targets.to_vec(),
+                })
+                .collect();
+        // The duplicate broadcast of the first tick is not sent at all.
+        assert_eq!(sent, vec![vec![a, b], vec![a, b]]);
+    }
+
+    #[test]
+    fn test_zero_window_peer() {
+        let (out_datagrams, out_datagrams_receiver) = bounded(16);
+        let (in_datagrams_sender, in_datagrams) = bounded(16);
+        let (outputs_sender, outputs) = bounded(16);
+        let (inputs, _inputs_receiver) = bounded(16);
+        let (errors, _errors_receiver) = bounded(16);
+
+        let mut processor = Processor::new(
+            NetConf::default().with_receive_window(Some(1024)),
+            out_datagrams.clone(),
+            out_datagrams,
+            in_datagrams,
+            outputs,
+            inputs,
+            errors,
+            Recorder::default(),
+            DeliveryLog::default(),
+            MemoryLog::default(),
+        );
+
+        let slow: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let fast: SocketAddr = "1.2.3.4:1112".parse().unwrap();
+        in_datagrams_sender
+            .try_send(InDatagram {
+                source: slow,
+                header: Some(DatagramHeader::Window),
+                data: 0u32.to_be_bytes().to_vec(),
+                time: Instant::now(),
+            })
+            .unwrap();
+
+        let messages = [
+            OutMessage::new(vec![1], true, Peers::Players, vec![slow, fast]),
+            OutMessage::new(vec![2], true, Peers::Players, vec![slow, fast]),
+            OutMessage::new(vec![3], false, Peers::Players, vec![slow]),
+            OutMessage::new(vec![4], true, Peers::Players, vec![fast]),
+        ];
+        for message in messages {
+            outputs_sender.try_send(message).unwrap();
+        }
+
+        task::block_on(async {
+            for _ in 0..6 {
+                assert!(!processor.tick().await);
+            }
+        });
+
+        let sent: Vec<(u8, Vec<SocketAddr>)> =
+            std::iter::from_fn(|| out_datagrams_receiver.try_recv().ok())
+                .filter(|datagram| matches!(datagram.header, DatagramHeader::Data(_)))
+                .map(|datagram| {
+                    let targets = match datagram.targets {
+                        Targets::Single(target) => vec![target],
+                        Targets::Many(targets) => targets.to_vec(),
+                    };
+                    (datagram.data[0], targets)
+                })
+                .collect();
+        // The single message allowed while nothing is in flight fills the
+        // window of the slow peer. Neither the other peer nor unreliable
+        // messages are held back by the slow peer.
+        assert_eq!(
+            sent,
+            vec![
+                (1, vec![slow, fast]),
+                (2, vec![fast]),
+                (3, vec![slow]),
+                (4, vec![fast]),
+            ]
+        );
+        assert_eq!(processor.stalled.len(), 1);
+    }
+
+    #[test]
+    fn test_all_except() {
+        task::block_on(async {
+            let network_relay = Network::bind(None).await.unwrap();
+            let addr_relay =
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_relay.port().unwrap());
+            let mut relay = startup(network_relay, NetConf::default());
+
+            let mut peers = Vec::new();
+            for i in 0..3 {
+                let mut peer = startup(Network::bind(None).await.unwrap(), NetConf::default());
+                peer.send(OutMessage::new(
+                    vec![i],
+                    false,
+                    Peers::Players,
+                    vec![addr_relay],
+                ))
+                .await
+                .unwrap();
+                peers.push(peer);
+            }
+
+            let mut sources = [None; 3];
+            for _ in 0..3 {
+                let message = timeout(Duration::from_secs(1), relay.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let source = message.source();
+                sources[usize::from(message.data()[0])] = Some(source);
+            }
+            let excluded = sources[1].unwrap();
+
+            relay
+                .send(OutMessage::new(
+                    vec![42],
+                    true,
+                    Peers::Players,
+                    Destination::AllExcept(excluded),
+                ))
+                .await
+                .unwrap();
+
+            for i in [0, 2] {
+                let message = timeout(Duration::from_secs(1), peers[i].recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(message.data(), vec![42]);
+            }
+            assert!(timeout(Duration::from_millis(300), peers[1].recv())
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_observers() {
+        task::block_on(async {
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let mut communicator_a = startup(network_a, NetConf::default());
+            let mut communicator_b = startup(network_b, NetConf::default());
+
+            let received = Arc::new(AtomicUsize::new(0));
+            let received_clone = Arc::clone(&received);
+            communicator_a.observe(HeaderType::Confirmation, move |direction, addr, _| {
+                assert_eq!(direction, Direction::Received);
+                assert_eq!(addr.port(), addr_b.port());
+                received_clone.fetch_add(1, Ordering::Relaxed);
+            });
+            let sent = Arc::new(AtomicUsize::new(0));
+            let sent_clone = Arc::clone(&sent);
+            communicator_b.observe(HeaderType::Confirmation, move |direction, _, len| {
+                assert_eq!(direction, Direction::Sent);
+                // A single confirmed datagram ID.
+                assert_eq!(len, HEADER_SIZE + 3);
+                sent_clone.fetch_add(1, Ordering::Relaxed);
+            });
+
+            for i in 0..2 {
+                communicator_a
+                    .send(OutMessage::new(vec![i], true, Peers::Players, vec![addr_b]))
+                    .await
+                    .unwrap();
+                let message = timeout(Duration::from_secs(1), communicator_b.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(message.data(), vec![i]);
+
+                // Long enough for the confirmation to be sent and delivered
+                // but shorter than the re-send backoff.
+                task::sleep(Duration::from_millis(180)).await;
+                assert_eq!(sent.load(Ordering::Relaxed), i as usize + 1);
+                assert_eq!(received.load(Ordering::Relaxed), i as usize + 1);
+            }
+        });
+    }
+
+    #[test]
+    fn test_transition_log() {
+        task::block_on(async {
+            let conf = NetConf::default().with_transition_log(Some(16));
+
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_a = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_a.port().unwrap());
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let mut communicator_a = startup(network_a, conf);
+            let mut communicator_b = startup(network_b, conf);
+
+            for (reliable, data) in [(true, 1), (false, 2)] {
+                communicator_a
+                    .send(OutMessage::new(
+                        vec![data],
+                        reliable,
+                        Peers::Players,
+                        vec![addr_b],
+                    ))
+                    .await
+                    .unwrap();
+                timeout(Duration::from_secs(1), communicator_b.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                // Long enough for the confirmation to be delivered.
+                task::sleep(Duration::from_millis(100)).await;
+            }
+
+            let kinds = |communicator: &Communicator| -> Vec<TransitionKind> {
+                communicator
+                    .transitions()
+                    .iter()
+                    .map(|transition| transition.kind())
+                    .collect()
+            };
+
+            assert_eq!(
+                kinds(&communicator_a),
+                vec![
+                    TransitionKind::Sent {
+                        target: addr_b,
+                        id: 0,
+                        reliable: true
+                    },
+                    TransitionKind::Confirmed {
+                        source: addr_b,
+                        id: 0
+                    },
+                    TransitionKind::Sent {
+                        target: addr_b,
+                        id: 1,
+                        reliable: false
+                    },
+                ]
+            );
+            assert_eq!(
+                kinds(&communicator_b),
+                vec![
+                    TransitionKind::Received {
+                        source: addr_a,
+                        id: 0,
+                        reliable: true
+                    },
+                    TransitionKind::Received {
+                        source: addr_a,
+                        id: 1,
+                        reliable: false
+                    },
+                ]
+            );
+
+            let events = communicator_b.peer_events(addr_a);
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].kind(), PeerEventKind::Connected);
+
+            let transitions = communicator_a.transitions();
+            assert!(transitions[0].time() <= transitions[1].time());
+            assert!(transitions[1].time() <= transitions[2].time());
+        });
+    }
+
+    #[test]
+    fn test_empty_reliable() {
+        task::block_on(async {
+            let conf = NetConf::default().with_transition_log(Some(16));
+
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let mut communicator_a = startup(network_a, conf);
+            let mut communicator_b = startup(network_b, conf);
+
+            let confirmations = Arc::new(AtomicUsize::new(0));
+            let confirmations_clone = Arc::clone(&confirmations);
+            communicator_b.observe(HeaderType::Confirmation, move |_, _, _| {
+                confirmations_clone.fetch_add(1, Ordering::Relaxed);
+            });
+
+            communicator_a
+                .send(OutMessage::new(
+                    Vec::new(),
+                    true,
+                    Peers::Players,
+                    vec![addr_b],
+                ))
+                .await
+                .unwrap();
+
+            let message = timeout(Duration::from_secs(1), communicator_b.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(message.reliable());
+            assert!(message.decode::<u8>().next().is_none());
+            assert!(message.data().is_empty());
+
+            // Longer than the re-send backoff, nothing is re-sent once
+            // confirmed.
+            task::sleep(Duration::from_millis(500)).await;
+            assert_eq!(confirmations.load(Ordering::Relaxed), 1);
+            let kinds: Vec<TransitionKind> = communicator_a
+                .transitions()
+                .iter()
+                .map(|transition| transition.kind())
+                .collect();
+            assert_eq!(
+                kinds,
+                vec![
+                    TransitionKind::Sent {
+                        target: addr_b,
+                        id: 0,
+                        reliable: true
+                    },
+                    TransitionKind::Confirmed {
+                        source: addr_b,
+                        id: 0
+                    },
+                ]
+            );
+            assert!(timeout(Duration::from_millis(100), communicator_b.recv())
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_acked_unreliable() {
+        task::block_on(async {
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            // Nothing is ever confirmed by this one.
+            let network_c = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+            let addr_c = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_c.port().unwrap());
+
+            let mut communicator_a = startup(network_a, NetConf::default());
+            let mut communicator_b = startup(network_b, NetConf::default());
+
+            let sent = Arc::new(AtomicUsize::new(0));
+            let sent_clone = Arc::clone(&sent);
+            communicator_a.observe(HeaderType::Unreliable, move |direction, _, _| {
+                assert_eq!(direction, Direction::Sent);
+                sent_clone.fetch_add(1, Ordering::Relaxed);
+            });
+            let confirmations = Arc::new(AtomicUsize::new(0));
+            let confirmations_clone = Arc::clone(&confirmations);
+            communicator_a.observe(HeaderType::Confirmation, move |_, _, _| {
+                confirmations_clone.fetch_add(1, Ordering::Relaxed);
+            });
+
+            communicator_a
+                .send(
+                    OutMessage::new(vec![7], false, Peers::Players, vec![addr_b, addr_c])
+                        .with_confirmation(),
+                )
+                .await
+                .unwrap();
+
+            let message = timeout(Duration::from_secs(1), communicator_b.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(!message.reliable());
+            assert_eq!(message.data(), vec![7]);
+
+            // Longer than the delivery timeout.
+            task::sleep(Duration::from_millis(1500)).await;
+            assert_eq!(confirmations.load(Ordering::Relaxed), 1);
+            // Never re-sent to any of the targets.
+            assert_eq!(sent.load(Ordering::Relaxed), 2);
+
+            let stats_b = communicator_a.delivery_stats(addr_b).unwrap();
+            assert_eq!(stats_b.sent(), 1);
+            assert_eq!(stats_b.delivered(), 1);
+            assert_eq!(stats_b.lost(), 0);
+            assert!(stats_b.rtt().is_some());
+
+            let stats_c = communicator_a.delivery_stats(addr_c).unwrap();
+            assert_eq!(stats_c.sent(), 1);
+            assert_eq!(stats_c.delivered(), 0);
+            assert_eq!(stats_c.lost(), 1);
+            assert_eq!(stats_c.loss(), Some(1.));
+            assert!(stats_c.rtt().is_none());
+        });
+    }
+
+    #[test]
+    fn test_receive_window() {
+        task::block_on(async {
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let conf = NetConf::default().with_receive_window(Some(16));
+            let mut communicator_a = startup(network_a, conf);
+            let mut communicator_b = startup(network_b, conf);
+
+            let windows = Arc::new(AtomicUsize::new(0));
+            let windows_clone = Arc::clone(&windows);
+            communicator_a.observe(HeaderType::Window, move |direction, _, _| {
+                assert_eq!(direction, Direction::Received);
+                windows_clone.fetch_add(1, Ordering::Relaxed);
+            });
+
+            for i in 0..9 {
+                if i == 1 {
+                    // The window is advertised with the confirmation.
+                    let message = timeout(Duration::from_secs(1), communicator_b.recv())
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(message.data(), vec![0; 8]);
+                    task::sleep(Duration::from_millis(300)).await;
+                    assert_eq!(windows.load(Ordering::Relaxed), 1);
+                }
+
+                communicator_a
+                    .send(OutMessage::new(
+                        vec![i; 8],
+                        true,
+                        Peers::Players,
+                        vec![addr_b],
+                    ))
+                    .await
+                    .unwrap();
+            }
+
+            // Data does not pile up at the slow receiver, only a single
+            // message per confirmation round trip is let in once the window
+            // is full.
+            task::sleep(Duration::from_millis(250)).await;
+            assert!(communicator_b.inbound_len() < 8);
+
+            // Sending ramps up as the window grows. Nothing is lost nor
+            // re-ordered.
+            for i in 1..9 {
+                let message = timeout(Duration::from_secs(2), communicator_b.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(message.data(), vec![i; 8]);
+            }
+            assert!(windows.load(Ordering::Relaxed) > 0);
+        });
+    }
+
+    #[test]
+    fn test_dedicated_thread() {
+        task::block_on(async {
+            let conf = NetConf::default().with_dedicated_thread(true);
+
+            let network_a = Network::bind(None).await.unwrap();
+            let network_b = Network::bind(None).await.unwrap();
+            let addr_b = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), network_b.port().unwrap());
+
+            let mut communicator_a = startup(network_a, conf);
+            let mut communicator_b = startup(network_b, conf);
+
+            let sent = Instant::now();
+            communicator_a
+                .send(OutMessage::new(
+                    vec![1, 2, 3],
+                    true,
+                    Peers::Players,
+                    vec![addr_b],
+                ))
+                .await
+                .unwrap();
+
+            let message = communicator_b.recv().await.unwrap();
+            assert!(sent <= message.received());
+            assert!(message.received() <= Instant::now());
+            assert!(message.reliable());
+            assert_eq!(message.data(), vec![1, 2, 3]);
+
+            // Dropping joins the networking threads.
+            drop(communicator_a);
+            drop(communicator_b);
+        });
+    }
 }
@@ -1,3 +1,5 @@
+use std::net::SocketAddrV4;
+
 use bincode::{Decode, Encode};
 
 /// Message item to be sent from a player/client to a main server (outside of a
@@ -24,10 +26,17 @@ pub enum FromServer {
 /// game).
 #[derive(Encode, Decode)]
 pub enum ToGame {
+    /// Requests a player slot in the game. The server responds with
+    /// [`FromGame::Joined`] or [`FromGame::GameFull`].
+    Join,
     /// Requests closure of the game.
     CloseGame,
     /// Prompts the server to respond [`FromGame::Pong`] with the same ping ID.
     Ping(u32),
+    /// Asks whether the server port is forwarded by the gateway (home
+    /// router) of the server's local network. The server responds with
+    /// [`FromGame::PortMapping`].
+    PortMapping,
 }
 
 /// Message item to be sent from a game server to a player/client (inside of a
@@ -39,4 +48,19 @@ pub enum FromGame {
     GameClosed,
     /// Response to [`ToGame::Ping`].
     Pong(u32),
+    /// Response to [`ToGame::Join`], informs the client about its
+    /// authoritative player slot. A client re-joining from the same address
+    /// is assigned the same slot.
+    Joined {
+        /// Zero based index of the assigned player slot.
+        player: u8,
+    },
+    /// Response to [`ToGame::Join`], the game has no free player slot.
+    GameFull,
+    /// Response to [`ToGame::PortMapping`] with the external address
+    /// forwarded to the server. It is None if the port is not mapped, for
+    /// example when the gateway does not support port mapping. Players
+    /// outside of the server's local network might not be able to connect
+    /// in such a case.
+    PortMapping(Option<SocketAddrV4>),
 }
@@ -0,0 +1,500 @@
+//! Tick stamping of state snapshots, typically sent unreliably.
+//!
+//! Each snapshot message is prefixed with the simulation tick at which the
+//! snapshot was taken. The receiver applies a snapshot of an entity (or of any
+//! other independently updated state) only if its tick is newer than the tick
+//! of the last applied snapshot of the same entity. Thus reordered or
+//! duplicated datagrams never rewind the state.
+//!
+//! Ticks wrap around, a tick is considered newer than another tick if it is
+//! ahead by less than 2^31 ticks.
+//!
+//! Snapshots sent to a peer can be produced at a reduced level of detail (see
+//! [`SnapshotDetail`] and [`produce_snapshot`]) so that they fit the number
+//! of bytes the server is willing to send to the peer and so that peers with
+//! poor links receive less data.
+//!
+//! Received snapshots might be kept in a [`SnapshotHistory`] so that remote
+//! state is rendered interpolated between two snapshots, a configured
+//! [`InterpolationDelay`] in the past.
+
+use std::{collections::VecDeque, hash::Hash, time::Duration};
+
+use ahash::AHashMap;
+use thiserror::Error;
+
+use crate::DeliveryStats;
+
+/// Loss of confirmed unreliable datagrams above which at most
+/// [`SnapshotDetail::Reduced`] snapshots are sent.
+const REDUCED_LOSS: f64 = 0.1;
+/// Loss of confirmed unreliable datagrams above which only
+/// [`SnapshotDetail::Minimal`] snapshots are sent.
+const MINIMAL_LOSS: f64 = 0.3;
+
+/// Minimum interpolation delay in ticks. Interpolation needs at least one
+/// snapshot newer than the rendered point in time.
+const MIN_INTERPOLATION_TICKS: f64 = 1.;
+/// Maximum interpolation delay in ticks.
+const MAX_INTERPOLATION_TICKS: f64 = 16.;
+/// Maximum number of snapshots kept in a [`SnapshotHistory`]. It covers the
+/// maximum interpolation delay even with some snapshots lost.
+const HISTORY_CAPACITY: usize = 32;
+
+/// Number of bytes prepended to each snapshot message.
+pub const TICK_HEADER_SIZE: usize = 4;
+
+/// Returns a message consisting of the tick followed by the snapshot data.
+pub fn stamp_tick(tick: u32, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(TICK_HEADER_SIZE + data.len());
+    message.extend_from_slice(&tick.to_be_bytes());
+    message.extend_from_slice(data);
+    message
+}
+
+/// Splits a message created with [`stamp_tick`] to the tick and the
+/// snapshot data.
+pub fn read_tick(message: &[u8]) -> Result<(u32, &[u8]), SnapshotError> {
+    if message.len() < TICK_HEADER_SIZE {
+        return Err(SnapshotError::Truncated);
+    }
+    let (tick, data) = message.split_at(TICK_HEADER_SIZE);
+    Ok((u32::from_be_bytes(tick.try_into().unwrap()), data))
+}
+
+/// Filter of stale snapshots. See the [module level](self) documentation.
+pub struct TickFilter<K> {
+    latest: AHashMap<K, u32>,
+    rejected: u64,
+}
+
+impl<K> TickFilter<K>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            latest: AHashMap::new(),
+            rejected: 0,
+        }
+    }
+
+    /// Returns true if a snapshot of an entity with the given tick is to be
+    /// applied, i.e. if it is the first snapshot of the entity or if its tick
+    /// is newer than the tick of the last applied snapshot of the entity.
+    /// The tick is then remembered as the last applied one.
+    ///
+    /// Otherwise, the snapshot is counted as rejected and false is returned.
+    pub fn accept(&mut self, key: K, tick: u32) -> bool {
+        match self.latest.get_mut(&key) {
+            Some(latest) if !is_newer(tick, *latest) => {
+                self.rejected += 1;
+                false
+            }
+            Some(latest) => {
+                *latest = tick;
+                true
+            }
+            None => {
+                self.latest.insert(key, tick);
+                true
+            }
+        }
+    }
+
+    /// Forgets the last applied tick of an entity, for example after the
+    /// entity was despawned.
+    pub fn remove(&mut self, key: &K) {
+        self.latest.remove(key);
+    }
+
+    /// Returns the total number of rejected stale snapshots.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+}
+
+impl<K> Default for TickFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Level of detail of a state snapshot. The meaning of each level is up to the
+/// game, a lower level is expected to serialize to fewer bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SnapshotDetail {
+    /// Only the most important state, e.g. positions of nearby entities
+    /// quantized to a coarse grid.
+    Minimal,
+    /// A subset of the state, e.g. entities with changed state only, or all
+    /// entities with reduced precision.
+    Reduced,
+    /// The complete state.
+    Full,
+}
+
+impl SnapshotDetail {
+    /// Returns the next lower level of detail or None if this is the lowest
+    /// level.
+    pub fn lower(self) -> Option<Self> {
+        match self {
+            Self::Full => Some(Self::Reduced),
+            Self::Reduced => Some(Self::Minimal),
+            Self::Minimal => None,
+        }
+    }
+
+    /// Returns the highest level of detail appropriate for the quality of a
+    /// link characterized by delivery statistics of confirmed unreliable
+    /// messages. Full detail is chosen if no statistics are available.
+    pub fn for_link(stats: Option<&DeliveryStats>) -> Self {
+        match stats.and_then(DeliveryStats::loss) {
+            Some(loss) if loss > MINIMAL_LOSS => Self::Minimal,
+            Some(loss) if loss > REDUCED_LOSS => Self::Reduced,
+            _ => Self::Full,
+        }
+    }
+}
+
+/// Game state which can be serialized to a snapshot at various levels of
+/// detail.
+pub trait SnapshotSource {
+    /// Serializes the state at the given level of detail.
+    fn serialize(&self, detail: SnapshotDetail) -> Vec<u8>;
+}
+
+/// Produces a tick stamped snapshot (see [`stamp_tick`]) for a single peer.
+///
+/// The highest level of detail appropriate for the link quality (see
+/// [`SnapshotDetail::for_link`]) is tried first. Lower levels are tried as
+/// long as the stamped snapshot does not fit `budget` bytes. A snapshot of
+/// the lowest level is returned if no level fits.
+///
+/// # Arguments
+///
+/// * `tick` - the tick at which the snapshot is taken.
+///
+/// * `source` - the game state.
+///
+/// * `budget` - maximum number of bytes to be sent to the peer.
+///
+/// * `stats` - delivery statistics of the peer (see
+///   [`crate::Communicator::delivery_stats`]).
+pub fn produce_snapshot<S: SnapshotSource>(
+    tick: u32,
+    source: &S,
+    budget: usize,
+    stats: Option<&DeliveryStats>,
+) -> (SnapshotDetail, Vec<u8>) {
+    let mut detail = SnapshotDetail::for_link(stats);
+    loop {
+        let data = source.serialize(detail);
+        if TICK_HEADER_SIZE + data.len() <= budget {
+            return (detail, stamp_tick(tick, &data));
+        }
+        match detail.lower() {
+            Some(lower) => detail = lower,
+            None => return (detail, stamp_tick(tick, &data)),
+        }
+    }
+}
+
+/// Delay by which remote state is rendered in the past. A larger delay
+/// tolerates more jitter and more lost snapshots before there is no newer
+/// snapshot to interpolate towards (i.e. before a visual stutter), a smaller
+/// delay makes the rendered state more recent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterpolationDelay(f64);
+
+impl InterpolationDelay {
+    /// Creates the delay clamped to between 1 and 16 ticks.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - desired delay, e.g. from the game configuration.
+    ///
+    /// * `tick_rate` - number of server ticks per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick_rate` is zero.
+    pub fn new(delay: Duration, tick_rate: u32) -> Self {
+        assert!(tick_rate > 0, "Tick rate must be positive.");
+        let ticks = delay.as_secs_f64() * f64::from(tick_rate);
+        Self(ticks.clamp(MIN_INTERPOLATION_TICKS, MAX_INTERPOLATION_TICKS))
+    }
+
+    /// Returns the delay in (fractional) ticks.
+    pub fn ticks(self) -> f64 {
+        self.0
+    }
+}
+
+/// Recently received snapshots of a state ordered by their ticks.
+pub struct SnapshotHistory<S> {
+    snapshots: VecDeque<(u32, S)>,
+}
+
+impl<S> SnapshotHistory<S> {
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(HISTORY_CAPACITY),
+        }
+    }
+
+    /// Stores a snapshot and returns true. Snapshots not newer than the
+    /// newest stored snapshot are ignored and false is returned. The oldest
+    /// snapshot is dropped once the history is full.
+    pub fn push(&mut self, tick: u32, snapshot: S) -> bool {
+        if let Some(&(newest, _)) = self.snapshots.back() {
+            if !is_newer(tick, newest) {
+                return false;
+            }
+        }
+
+        if self.snapshots.len() >= HISTORY_CAPACITY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((tick, snapshot));
+        true
+    }
+
+    /// Returns the two snapshots between which the state is to be
+    /// interpolated, i.e. the snapshots right before and right after the
+    /// point in time lagging `delay` behind `tick`.
+    ///
+    /// None is returned if there is no snapshot newer than the point in time
+    /// (the state would have to be extrapolated) or no snapshot at or before
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - current (estimated) tick of the server.
+    ///
+    /// * `delay` - interpolation delay.
+    pub fn bracket(&self, tick: u32, delay: InterpolationDelay) -> Option<Bracket<'_, S>> {
+        // Snapshots newer than `tick` have negative age.
+        let age = |snapshot: u32| f64::from(tick.wrapping_sub(snapshot) as i32);
+
+        let index = self
+            .snapshots
+            .iter()
+            .position(|&(snapshot, _)| age(snapshot) < delay.ticks())?;
+        if index == 0 {
+            return None;
+        }
+
+        let (before, after) = (&self.snapshots[index - 1], &self.snapshots[index]);
+        let span = age(before.0) - age(after.0);
+        Some(Bracket {
+            before: (before.0, &before.1),
+            after: (after.0, &after.1),
+            fraction: (age(before.0) - delay.ticks()) / span,
+        })
+    }
+}
+
+impl<S> Default for SnapshotHistory<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Two consecutive stored snapshots surrounding a point in time. See
+/// [`SnapshotHistory::bracket`].
+pub struct Bracket<'a, S> {
+    before: (u32, &'a S),
+    after: (u32, &'a S),
+    fraction: f64,
+}
+
+impl<'a, S> Bracket<'a, S> {
+    /// The tick and the snapshot right before (or at) the point in time.
+    pub fn before(&self) -> (u32, &'a S) {
+        self.before
+    }
+
+    /// The tick and the snapshot right after the point in time.
+    pub fn after(&self) -> (u32, &'a S) {
+        self.after
+    }
+
+    /// Position of the point in time between the two snapshots, 0 at the
+    /// older snapshot and approaching 1 at the newer snapshot.
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("the message is shorter than the tick header")]
+    Truncated,
+}
+
+/// Returns true if `tick` is newer than `other` (with wraparound).
+fn is_newer(tick: u32, other: u32) -> bool {
+    let diff = tick.wrapping_sub(other);
+    diff != 0 && diff < 1 << 31
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::delivery::DeliveryLog;
+
+    /// Entity positions, full detail entities are serialized as 3 f32
+    /// coordinates, reduced as 2 u16 coordinates and minimal as only the
+    /// number of entities.
+    struct Positions(Vec<[f32; 3]>);
+
+    impl SnapshotSource for Positions {
+        fn serialize(&self, detail: SnapshotDetail) -> Vec<u8> {
+            let mut data = Vec::new();
+            data.extend_from_slice(&(self.0.len() as u16).to_be_bytes());
+            for position in &self.0 {
+                match detail {
+                    SnapshotDetail::Full => {
+                        for coordinate in position {
+                            data.extend_from_slice(&coordinate.to_be_bytes());
+                        }
+                    }
+                    SnapshotDetail::Reduced => {
+                        for coordinate in &position[..2] {
+                            data.extend_from_slice(&(*coordinate as u16).to_be_bytes());
+                        }
+                    }
+                    SnapshotDetail::Minimal => (),
+                }
+            }
+            data
+        }
+    }
+
+    #[test]
+    fn test_stamp() {
+        let message = stamp_tick(0x01020304, &[7, 8]);
+        assert_eq!(message, vec![1, 2, 3, 4, 7, 8]);
+        assert_eq!(read_tick(&message).unwrap(), (0x01020304, &[7, 8][..]));
+        assert_eq!(read_tick(&[1, 2]), Err(SnapshotError::Truncated));
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = TickFilter::new();
+
+        assert!(filter.accept(1, 10));
+        assert!(filter.accept(1, 12));
+        // Out of order older snapshot.
+        assert!(!filter.accept(1, 11));
+        assert!(!filter.accept(1, 12));
+        assert!(filter.accept(1, 13));
+        assert_eq!(filter.rejected(), 2);
+
+        // Entities are filtered independently.
+        assert!(filter.accept(2, 5));
+
+        assert!(filter.accept(3, u32::MAX - 1));
+        assert!(filter.accept(3, 1));
+        assert!(!filter.accept(3, u32::MAX));
+        assert_eq!(filter.rejected(), 3);
+
+        filter.remove(&1);
+        assert!(filter.accept(1, 0));
+    }
+
+    #[test]
+    fn test_produce() {
+        let state = Positions(vec![[1., 2., 3.]; 10]);
+
+        let (detail, ample) = produce_snapshot(7, &state, 1200, None);
+        assert_eq!(detail, SnapshotDetail::Full);
+        assert_eq!(ample.len(), 4 + 2 + 120);
+        assert_eq!(read_tick(&ample).unwrap().0, 7);
+
+        let (detail, small) = produce_snapshot(7, &state, 100, None);
+        assert_eq!(detail, SnapshotDetail::Reduced);
+        assert_eq!(small.len(), 4 + 2 + 40);
+
+        let (detail, tiny) = produce_snapshot(7, &state, 2, None);
+        assert_eq!(detail, SnapshotDetail::Minimal);
+        assert_eq!(tiny.len(), 4 + 2);
+
+        let addr: SocketAddr = "1.2.3.4:1111".parse().unwrap();
+        let log = DeliveryLog::default();
+        for _ in 0..3 {
+            log.sent(addr);
+            log.delivered(addr, Duration::from_millis(10));
+        }
+        log.sent(addr);
+        log.lost(addr);
+        let stats = log.get(addr).unwrap();
+        // A poor link limits the detail even with an ample budget.
+        let (detail, _) = produce_snapshot(7, &state, 1200, Some(&stats));
+        assert_eq!(detail, SnapshotDetail::Reduced);
+    }
+
+    #[test]
+    fn test_interpolation_delay() {
+        let delay = InterpolationDelay::new(Duration::from_millis(250), 20);
+        assert_eq!(delay.ticks(), 5.);
+        let delay = InterpolationDelay::new(Duration::ZERO, 20);
+        assert_eq!(delay.ticks(), 1.);
+        let delay = InterpolationDelay::new(Duration::from_secs(10), 20);
+        assert_eq!(delay.ticks(), 16.);
+    }
+
+    #[test]
+    fn test_bracket() {
+        let mut history = SnapshotHistory::new();
+        assert!(history.push(10, "a"));
+        assert!(history.push(11, "b"));
+        assert!(history.push(12, "c"));
+        // Snapshot of tick 13 is lost.
+        assert!(history.push(14, "e"));
+        assert!(!history.push(12, "c"));
+
+        let bracket = history
+            .bracket(15, InterpolationDelay::new(Duration::from_millis(250), 10))
+            .unwrap();
+        assert_eq!(bracket.before(), (12, &"c"));
+        assert_eq!(bracket.after(), (14, &"e"));
+        assert_eq!(bracket.fraction(), 0.25);
+
+        let bracket = history
+            .bracket(15, InterpolationDelay::new(Duration::from_millis(350), 10))
+            .unwrap();
+        assert_eq!(bracket.before(), (11, &"b"));
+        assert_eq!(bracket.after(), (12, &"c"));
+        assert!((bracket.fraction() - 0.5).abs() < 1e-9);
+
+        // No snapshot newer than tick 14 has been received.
+        assert!(history
+            .bracket(15, InterpolationDelay::new(Duration::ZERO, 10))
+            .is_none());
+        // 16 ticks in the past is older than the oldest stored snapshot.
+        assert!(history
+            .bracket(15, InterpolationDelay::new(Duration::from_secs(5), 10))
+            .is_none());
+
+        // A larger delay tolerates loss of the newest snapshot.
+        let mut lossy = SnapshotHistory::new();
+        lossy.push(u32::MAX, "y");
+        lossy.push(0, "z");
+        assert!(lossy
+            .bracket(2, InterpolationDelay::new(Duration::from_millis(150), 10))
+            .is_none());
+        let bracket = lossy
+            .bracket(2, InterpolationDelay::new(Duration::from_millis(250), 10))
+            .unwrap();
+        assert_eq!(bracket.before(), (u32::MAX, &"y"));
+        assert_eq!(bracket.after(), (0, &"z"));
+        assert_eq!(bracket.fraction(), 0.5);
+    }
+}
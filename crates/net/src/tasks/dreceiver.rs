@@ -1,31 +1,86 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-use async_std::{channel::Sender, future::timeout};
-use tracing::{error, info, log::warn};
+use async_std::{channel::Sender, future::timeout, task};
+use futures::{future::BoxFuture, FutureExt};
+use tracing::{debug, error, info, warn};
 
 use crate::{
+    counters::Counters,
+    fault::FaultLog,
     header::DatagramHeader,
     messages::{Messages, MsgRecvError},
+    net::RecvError,
     MAX_DATAGRAM_SIZE,
 };
 
+/// Maximum number of consecutive transient receive errors. Reaching the
+/// limit is considered a fatal error, the socket is most likely broken.
+const MAX_TRANSIENT_ERRORS: usize = 64;
+/// Receiving is retried after this delay after a transient error.
+const TRANSIENT_ERROR_DELAY: Duration = Duration::from_millis(1);
+
 pub(crate) struct InDatagram {
     pub(crate) source: SocketAddr,
-    pub(crate) header: DatagramHeader,
+    /// None if the datagram is malformed, i.e. if it has an invalid header.
+    pub(crate) header: Option<DatagramHeader>,
     pub(crate) data: Vec<u8>,
+    /// Time when the datagram was received from the socket.
+    pub(crate) time: Instant,
+}
+
+/// Source of received messages, i.e. [`Messages`] apart from tests.
+pub(crate) trait MessageSource {
+    fn port(&self) -> io::Result<u16>;
+
+    /// See [`Messages::recv`].
+    #[allow(clippy::type_complexity)]
+    fn recv<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, Result<(SocketAddr, DatagramHeader, &'a [u8]), MsgRecvError>>;
 }
 
-pub(crate) async fn run(datagrams: Sender<InDatagram>, messages: Messages) {
+impl MessageSource for Messages {
+    fn port(&self) -> io::Result<u16> {
+        Messages::port(self)
+    }
+
+    fn recv<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, Result<(SocketAddr, DatagramHeader, &'a [u8]), MsgRecvError>> {
+        Messages::recv(self, buf).boxed()
+    }
+}
+
+/// Receives datagrams and sends them to a channel until the channel or the
+/// network is closed.
+///
+/// Transient receive errors (e.g. interrupted system calls) are retried. The
+/// task terminates after a fatal receive error, the error is recorded to
+/// `faults`. Dropped datagrams of foreign protocols are counted in `counters`.
+pub(crate) async fn run<S: MessageSource>(
+    datagrams: Sender<InDatagram>,
+    messages: S,
+    faults: FaultLog,
+    counters: Counters,
+) {
     let port = match messages.port() {
         Ok(port) => port,
         Err(err) => {
             error!("Cannot obtain port: {:?}", err);
+            faults.fault(Instant::now(), format!("cannot obtain port: {err}"));
             return;
         }
     };
 
     info!("Starting datagram receiver on port {port}...");
     let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+    let mut transient = 0;
 
     loop {
         let Ok(result) = timeout(Duration::from_millis(500), messages.recv(&mut buffer)).await else {
@@ -35,24 +90,50 @@ pub(crate) async fn run(datagrams: Sender<InDatagram>, messages: Messages) {
                 continue;
             }
         };
+        // Captured as early as possible so that the age of the message is
+        // not inflated by the processing below.
+        let time = Instant::now();
 
         let (addr, header, data) = match result {
-            Ok(msg) => msg,
-            Err(err @ MsgRecvError::InvalidHeader(_)) => {
-                warn!("Invalid message received on port {port}: {err:?}");
+            Ok((addr, header, data)) => (addr, Some(header), data),
+            Err(err @ MsgRecvError::ForeignProtocol(_)) => {
+                transient = 0;
+                let foreign = counters.foreign_datagram();
+                debug!("Dropping datagram on port {port} ({foreign} dropped in total): {err}");
                 continue;
             }
-            Err(err @ MsgRecvError::RecvError(_)) => {
+            Err(MsgRecvError::InvalidHeader(source, err)) => {
+                warn!("Invalid message received on port {port} from {source}: {err:?}");
+                // Passed on so that the processing loop can act on peers
+                // sending malformed datagrams repeatedly.
+                (source, None, &buffer[..0])
+            }
+            Err(MsgRecvError::RecvError(RecvError::Closed)) => {
+                info!("Network on port {port} was closed.");
+                break;
+            }
+            Err(MsgRecvError::RecvError(RecvError::Io(err)))
+                if is_transient(&err) && transient < MAX_TRANSIENT_ERRORS =>
+            {
+                transient += 1;
+                warn!("Transient data receiving error on port {port}, retrying: {err:?}");
+                task::sleep(TRANSIENT_ERROR_DELAY).await;
+                continue;
+            }
+            Err(MsgRecvError::RecvError(RecvError::Io(err))) => {
                 error!("Data receiving failed on port {port}: {err:?}");
+                faults.fault(time, format!("data receiving failed on port {port}: {err}"));
                 break;
             }
         };
+        transient = 0;
 
         let result = datagrams
             .send(InDatagram {
                 source: addr,
                 header,
                 data: data.to_vec(),
+                time,
             })
             .await;
         if result.is_err() {
@@ -62,3 +143,117 @@ pub(crate) async fn run(datagrams: Sender<InDatagram>, messages: Messages) {
 
     info!("Datagram receiver on port {port} finished.");
 }
+
+/// Returns true if receiving may succeed when retried after the error.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            // Some platforms report ICMP port unreachable messages, caused by
+            // previously sent datagrams, as receive errors.
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex};
+
+    use async_std::channel::bounded;
+
+    use super::*;
+    use crate::header::{DatagramId, Peers};
+
+    /// Message source returning predefined results. It fails with a fatal
+    /// error once all results are returned.
+    struct MockSource(Mutex<VecDeque<Result<u8, MsgRecvError>>>);
+
+    fn io_error(kind: io::ErrorKind) -> Result<u8, MsgRecvError> {
+        Err(RecvError::from(io::Error::from(kind)).into())
+    }
+
+    impl MessageSource for MockSource {
+        fn port(&self) -> io::Result<u16> {
+            Ok(1111)
+        }
+
+        fn recv<'a>(
+            &'a self,
+            buf: &'a mut [u8],
+        ) -> BoxFuture<'a, Result<(SocketAddr, DatagramHeader, &'a [u8]), MsgRecvError>> {
+            let result = self
+                .0
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| io_error(io::ErrorKind::PermissionDenied));
+            async move {
+                let byte = result?;
+                buf[0] = byte;
+                Ok((
+                    "1.2.3.4:2222".parse().unwrap(),
+                    DatagramHeader::new_data(false, Peers::Players, DatagramId::zero()),
+                    &buf[..1],
+                ))
+            }
+            .boxed()
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let source = MockSource(Mutex::new(VecDeque::from([
+            io_error(io::ErrorKind::Interrupted),
+            Ok(1),
+            io_error(io::ErrorKind::WouldBlock),
+            io_error(io::ErrorKind::ConnectionReset),
+            Ok(2),
+        ])));
+        let (sender, receiver) = bounded(16);
+        let faults = FaultLog::default();
+
+        task::block_on(run(sender, source, faults.clone(), Counters::default()));
+
+        let received: Vec<u8> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|datagram| datagram.data[0])
+            .collect();
+        assert_eq!(received, vec![1, 2]);
+
+        let fault = faults.get().unwrap();
+        assert!(fault.reason().contains("port 1111"));
+    }
+
+    #[test]
+    fn test_persistent_transient_errors() {
+        let errors = (0..=MAX_TRANSIENT_ERRORS).map(|_| io_error(io::ErrorKind::Interrupted));
+        let source = MockSource(Mutex::new(errors.chain([Ok(1)]).collect()));
+        let (sender, receiver) = bounded(16);
+        let faults = FaultLog::default();
+
+        task::block_on(run(sender, source, faults.clone(), Counters::default()));
+        assert!(receiver.try_recv().is_err());
+        assert!(faults.get().is_some());
+    }
+
+    #[test]
+    fn test_foreign_datagrams() {
+        let source = "1.2.3.4:2222".parse().unwrap();
+        let foreign = || Err(MsgRecvError::ForeignProtocol(source));
+        let source = MockSource(Mutex::new(VecDeque::from([
+            foreign(),
+            Ok(1),
+            foreign(),
+            foreign(),
+        ])));
+        let (sender, receiver) = bounded(16);
+        let counters = Counters::default();
+
+        task::block_on(run(sender, source, FaultLog::default(), counters.clone()));
+        assert_eq!(receiver.try_recv().unwrap().data, vec![1]);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(counters.foreign_datagrams(), 3);
+    }
+}
@@ -1,16 +1,17 @@
 use async_std::channel::Receiver;
-use tracing::{error, info};
+use futures::{select_biased, FutureExt};
+use tracing::{error, info, warn};
 
 use crate::{
     header::DatagramHeader,
     messages::{Messages, Targets},
-    MAX_DATAGRAM_SIZE,
+    SendError, MAX_DATAGRAM_SIZE,
 };
 
 pub(crate) struct OutDatagram {
-    header: DatagramHeader,
-    data: Vec<u8>,
-    targets: Targets<'static>,
+    pub(crate) header: DatagramHeader,
+    pub(crate) data: Vec<u8>,
+    pub(crate) targets: Targets<'static>,
 }
 
 impl OutDatagram {
@@ -27,7 +28,22 @@ impl OutDatagram {
     }
 }
 
-pub(crate) async fn run(datagrams: Receiver<OutDatagram>, messages: Messages) {
+/// Sends datagrams received from the channels.
+///
+/// # Arguments
+///
+/// * `confirms` - channel with confirmation datagrams. These are sent before
+///   any waiting datagrams from `datagrams`. It might be the same channel as
+///   `datagrams`.
+///
+/// * `datagrams` - channel with all other datagrams.
+///
+/// * `messages` - the datagrams are sent via this.
+pub(crate) async fn run(
+    confirms: Receiver<OutDatagram>,
+    datagrams: Receiver<OutDatagram>,
+    messages: Messages,
+) {
     let port = match messages.port() {
         Ok(port) => port,
         Err(err) => {
@@ -38,10 +54,11 @@ pub(crate) async fn run(datagrams: Receiver<OutDatagram>, messages: Messages) {
 
     info!("Starting datagram sender on port {port}...");
     let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+    let mut partial_sends = 0;
 
     loop {
-        let Ok(datagram) = datagrams.recv().await else { break };
-        if let Err(err) = messages
+        let Some(datagram) = next(&confirms, &datagrams).await else { break };
+        match messages
             .send(
                 &mut buffer,
                 datagram.header,
@@ -50,10 +67,85 @@ pub(crate) async fn run(datagrams: Receiver<OutDatagram>, messages: Messages) {
             )
             .await
         {
-            error!("Error while sending a datagram: {err:?}");
-            break;
+            Ok(()) => (),
+            // Partially sent datagram is treated as lost: reliable datagrams
+            // are re-sent once unconfirmed for too long.
+            Err(err @ SendError::PartialSend(..)) => {
+                partial_sends += 1;
+                warn!(
+                    "Datagram {} not fully sent ({partial_sends} partial sends in total): {err}",
+                    datagram.header
+                );
+            }
+            Err(err) => {
+                error!("Error while sending a datagram: {err:?}");
+                break;
+            }
         }
     }
 
     info!("Datagram sender on port {port} finished.");
 }
+
+/// Waits for and returns the next datagram to be sent, preferring datagrams
+/// from `confirms`. None is returned once a channel is closed.
+async fn next(
+    confirms: &Receiver<OutDatagram>,
+    datagrams: &Receiver<OutDatagram>,
+) -> Option<OutDatagram> {
+    select_biased! {
+        datagram = confirms.recv().fuse() => datagram.ok(),
+        datagram = datagrams.recv().fuse() => datagram.ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use async_std::{channel::bounded, task};
+
+    use super::*;
+    use crate::header::{DatagramId, Peers};
+
+    #[test]
+    fn test_confirm_priority() {
+        let (confirms_sender, confirms) = bounded(4);
+        let (datagrams_sender, datagrams) = bounded(8);
+        let target = "1.2.3.4:1111".parse::<SocketAddr>().unwrap();
+
+        for i in 0..8 {
+            let header = DatagramHeader::new_data(true, Peers::Players, DatagramId::zero());
+            datagrams_sender
+                .try_send(OutDatagram::new(header, vec![i], target))
+                .unwrap();
+        }
+        assert!(datagrams_sender.is_full());
+        for i in 0..2 {
+            confirms_sender
+                .try_send(OutDatagram::new(
+                    DatagramHeader::Confirmation,
+                    vec![0, 0, i],
+                    target,
+                ))
+                .unwrap();
+        }
+
+        task::block_on(async {
+            for i in 0..2 {
+                let datagram = next(&confirms, &datagrams).await.unwrap();
+                assert!(matches!(datagram.header, DatagramHeader::Confirmation));
+                assert_eq!(datagram.data, vec![0, 0, i]);
+            }
+            for i in 0..8 {
+                let datagram = next(&confirms, &datagrams).await.unwrap();
+                assert!(matches!(datagram.header, DatagramHeader::Data(_)));
+                assert_eq!(datagram.data, vec![i]);
+            }
+
+            drop(confirms_sender);
+            drop(datagrams_sender);
+            assert!(next(&confirms, &datagrams).await.is_none());
+        });
+    }
+}
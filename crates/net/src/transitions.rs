@@ -0,0 +1,199 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{counters::Counters, peerlog::PeerLog};
+
+/// A single recorded transition of the protocol state (see
+/// [`crate::NetConf::with_transition_log`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    time: Instant,
+    kind: TransitionKind,
+}
+
+impl Transition {
+    /// Time of the transition.
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn kind(&self) -> TransitionKind {
+        self.kind
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// A data datagram was sent (for the first time).
+    Sent {
+        target: SocketAddr,
+        id: u32,
+        reliable: bool,
+    },
+    /// A data datagram was received and passed to the application.
+    /// Duplicates of reliable datagrams are not recorded.
+    Received {
+        source: SocketAddr,
+        id: u32,
+        reliable: bool,
+    },
+    /// Delivery of a reliable datagram was confirmed by its recipient.
+    Confirmed { source: SocketAddr, id: u32 },
+    /// A confirmation of a datagram not waiting for one was received and
+    /// ignored, for example a late confirmation of an already failed
+    /// datagram or a redundant confirmation.
+    StaleConfirmation { source: SocketAddr, id: u32 },
+    /// A reliable datagram was re-sent because it was not confirmed in time.
+    Resent { target: SocketAddr, id: u32 },
+    /// Re-sending to a connection started to be throttled due to a re-send
+    /// storm.
+    ThrottleStarted { target: SocketAddr },
+    /// Re-send storm on a connection ended.
+    ThrottleEnded { target: SocketAddr },
+    /// Re-sending to a connection was paused due to congestion.
+    Paused { target: SocketAddr },
+    /// A connection failed because a reliable datagram could not be
+    /// delivered.
+    Failed { target: SocketAddr },
+}
+
+impl fmt::Display for TransitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sent {
+                target,
+                id,
+                reliable,
+            } => write!(f, "sent {id} to {target} (reliable: {reliable})"),
+            Self::Received {
+                source,
+                id,
+                reliable,
+            } => write!(f, "received {id} from {source} (reliable: {reliable})"),
+            Self::Confirmed { source, id } => write!(f, "{id} confirmed by {source}"),
+            Self::StaleConfirmation { source, id } => {
+                write!(f, "stale confirmation of {id} by {source}")
+            }
+            Self::Resent { target, id } => write!(f, "re-sent {id} to {target}"),
+            Self::ThrottleStarted { target } => write!(f, "throttling re-sends to {target}"),
+            Self::ThrottleEnded { target } => write!(f, "throttling of {target} ended"),
+            Self::Paused { target } => write!(f, "re-sends to {target} paused"),
+            Self::Failed { target } => write!(f, "connection to {target} failed"),
+        }
+    }
+}
+
+/// Bounded log of protocol state transitions shared between the networking
+/// tasks and the application. Clones of the log share the records.
+///
+/// A disabled log does not record anything.
+#[derive(Clone, Default)]
+pub(crate) struct TransitionLog(Option<Arc<Mutex<Records>>>);
+
+impl TransitionLog {
+    /// # Arguments
+    ///
+    /// * `capacity` - maximum number of kept records, the oldest records are
+    ///   dropped once it is reached. The log is disabled if None.
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self(capacity.map(|capacity| {
+            Arc::new(Mutex::new(Records {
+                capacity,
+                records: VecDeque::with_capacity(capacity),
+            }))
+        }))
+    }
+
+    pub(crate) fn record(&self, time: Instant, kind: TransitionKind) {
+        let Some(records) = self.0.as_ref() else { return };
+        let mut records = records.lock().expect("Transition log lock is poisoned");
+        if records.records.len() >= records.capacity {
+            records.records.pop_front();
+        }
+        records.records.push_back(Transition { time, kind });
+    }
+
+    /// Returns all kept records in the order of recording.
+    pub(crate) fn snapshot(&self) -> Vec<Transition> {
+        let Some(records) = self.0.as_ref() else { return Vec::new() };
+        let records = records.lock().expect("Transition log lock is poisoned");
+        records.records.iter().cloned().collect()
+    }
+}
+
+struct Records {
+    capacity: usize,
+    records: VecDeque<Transition>,
+}
+
+/// Records protocol state transitions to a transition log and the
+/// corresponding peer events to a per peer event log. Ignored anomalies are
+/// counted.
+#[derive(Clone, Default)]
+pub(crate) struct Recorder {
+    transitions: TransitionLog,
+    peers: PeerLog,
+    counters: Counters,
+}
+
+impl Recorder {
+    pub(crate) fn new(transitions: TransitionLog, peers: PeerLog) -> Self {
+        Self {
+            transitions,
+            peers,
+            counters: Counters::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, time: Instant, kind: TransitionKind) {
+        self.transitions.record(time, kind);
+        self.peers.record_transition(time, kind);
+    }
+
+    pub(crate) fn transitions(&self) -> &TransitionLog {
+        &self.transitions
+    }
+
+    pub(crate) fn peers(&self) -> &PeerLog {
+        &self.peers
+    }
+
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log() {
+        let target = "1.2.3.4:1111".parse().unwrap();
+        let time = Instant::now();
+
+        let disabled = TransitionLog::new(None);
+        disabled.record(time, TransitionKind::Failed { target });
+        assert!(disabled.snapshot().is_empty());
+
+        let log = TransitionLog::new(Some(2));
+        for id in 0..3 {
+            log.clone()
+                .record(time, TransitionKind::Resent { target, id });
+        }
+        let kinds: Vec<TransitionKind> = log.snapshot().iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TransitionKind::Resent { target, id: 1 },
+                TransitionKind::Resent { target, id: 2 }
+            ]
+        );
+        assert_eq!(kinds[0].to_string(), "re-sent 1 to 1.2.3.4:1111");
+    }
+}
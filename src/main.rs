@@ -19,7 +19,7 @@ use de_index::IndexPluginGroup;
 use de_loader::LoaderPluginGroup;
 use de_lobby_client::LobbyClientPluginGroup;
 use de_log::LogPluginGroup;
-use de_menu::MenuPluginGroup;
+use de_menu::{BuildInfo, MenuPluginGroup};
 use de_movement::MovementPluginGroup;
 use de_objects::ObjectsPluginGroup;
 use de_pathing::PathingPluginGroup;
@@ -46,6 +46,7 @@ fn main() {
         let _enter = span.enter();
 
         app.insert_resource(Msaa::Sample4)
+            .insert_resource(BuildInfo::new(CARGO_PKG_VERSION, GIT_SHA))
             .add_plugins(
                 DefaultPlugins
                     .set(WindowPlugin {